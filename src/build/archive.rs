@@ -0,0 +1,336 @@
+use std::{
+    borrow::Cow,
+    env,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use indicatif::HumanBytes;
+use nanoid::nanoid;
+
+use super::{
+    ArchiveArtifact, BuildOptions, Discovered, TimedProgress, discover, hooks::run_hook, run_phase,
+};
+use crate::{
+    RumkinstError,
+    config::Config,
+    error_log::{Diagnostics, ErrorCode},
+    installer_gen::{
+        ArchiveWriter, DigestWriter, Digester, ListingWriter, RumkinstFiles, new_archive_writer,
+        parse_listing, read_archive_entries, to_hex,
+    },
+    lockfile::{LOCKFILE_NAME, LockfileMode},
+    progress_log::{NoProgress, Phase, ProgressSink},
+};
+
+/// An archive written by [`write_archive`]
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct StreamedArchive {
+    /// Number of source files found, including any that were skipped
+    pub files: usize,
+    /// Number of bytes written
+    pub bytes: u64,
+    /// Lowercase hex digest of the archive, from the [`Digester`] in the options
+    pub digest: String,
+    /// Number of warnings reported while writing it, see [`BuildOptions::with_diagnostics`]
+    pub warnings: usize,
+    /// Number of errors skipped past while writing it, see
+    /// [`FileErrorPolicy::KeepGoing`](crate::error_log::FileErrorPolicy::KeepGoing)
+    pub skipped: usize,
+}
+
+/// Write the archive described by `config` into `destination` instead of a file
+///
+/// The archive is hashed as it is written, so nothing needs to be read back from
+/// `destination`. The hooks run and the lockfile is checked or updated as in [`build`](super::build), with
+/// the output directory of `opts` given to the hooks, but its signer goes unused and nothing
+/// else is written there. Unlike [`build`](super::build), an empty archive is written when there are no
+/// source files.
+pub fn write_archive<W: Write>(
+    config: &Config,
+    destination: W,
+    opts: &BuildOptions,
+) -> Result<StreamedArchive, RumkinstError> {
+    if !config.targets.is_empty() {
+        return Err(RumkinstError::TargetsUnsupported {
+            feature: "a single streamed archive",
+        });
+    }
+    let timed = TimedProgress::new(opts.progress.as_ref());
+    let progress: &dyn ProgressSink = &timed;
+
+    let Discovered {
+        files: all_files,
+        vcs,
+        lockfile,
+        ..
+    } = discover(config, opts, &timed, true)?;
+
+    let (digest, bytes) = run_phase(
+        progress,
+        Phase::Archiving,
+        all_files.total_files() as u64,
+        || archive_into(config, None, &all_files, destination, None, opts),
+    )?;
+
+    run_hook(
+        config,
+        "postbuild",
+        &config.build.postbuild,
+        &opts.out_dir,
+        vcs.as_ref(),
+        &timed,
+        true,
+    )?;
+    if let Some(lockfile) = lockfile
+        && opts.lockfile == LockfileMode::Update
+    {
+        lockfile.write(&config.base_dir().join(LOCKFILE_NAME))?;
+    }
+
+    Ok(StreamedArchive {
+        files: all_files.total_files(),
+        bytes,
+        digest: to_hex(&digest),
+        warnings: opts.diagnostics.warnings().len(),
+        skipped: opts.diagnostics.errors().len(),
+    })
+}
+
+/// File name of the archive for `config`, or for its target called `target`
+pub(crate) fn archive_name(config: &Config, target: Option<&str>) -> String {
+    let extension = config.archive_format().extension();
+    match target {
+        Some(target) => format!("{}-{target}.{extension}", config.get_name()),
+        None => format!("{}.{extension}", config.get_name()),
+    }
+}
+
+/// Archive `all_files` into `destination`, returning the digest and size of the archive
+///
+/// A listing of its entries is written to `listing_path` if set, which is signed and embedded
+/// into the archive when the build has a signer.
+pub(crate) fn archive_into<W: Write>(
+    config: &Config,
+    target: Option<&str>,
+    all_files: &RumkinstFiles,
+    destination: W,
+    listing_path: Option<&Path>,
+    opts: &BuildOptions,
+) -> Result<(Vec<u8>, u64), RumkinstError> {
+    let mut writer = DigestWriter::new(destination, opts.digester.as_ref());
+    let mut archive = new_archive_writer(
+        config.archive_format(),
+        &mut writer,
+        &archive_name(config, target),
+    );
+    let write = |archive: &mut dyn ArchiveWriter| {
+        all_files.write_archive(
+            archive,
+            opts.file_policy(),
+            opts.progress.as_ref(),
+            &opts.cancel,
+        )
+    };
+    match listing_path {
+        Some(listing_path) => write(
+            &mut ListingWriter::new(archive.as_mut(), opts.digester.as_ref(), listing_path)
+                .with_signer(opts.signer.as_deref()),
+        )?,
+        None => write(archive.as_mut())?,
+    }
+    drop(archive);
+    writer
+        .flush()
+        .map_err(|source| RumkinstError::Archive { path: None, source })?;
+
+    Ok(writer.finish())
+}
+
+/// Path, digest and size of a written archive, and the path of its listing
+pub(crate) type MadeArchive = (PathBuf, Vec<u8>, u64, PathBuf);
+
+pub(crate) fn make_archive(
+    config: &Config,
+    out_dir: &Path,
+    target: Option<&str>,
+    all_files: &RumkinstFiles,
+    opts: &BuildOptions,
+) -> Result<MadeArchive, RumkinstError> {
+    let archive_name = archive_name(config, target);
+    let archive_path = out_dir.join(&archive_name);
+
+    log::info!("Making archive \"{archive_name}\"");
+
+    let archive_file =
+        File::create_new(&archive_path).map_err(|source| RumkinstError::Package {
+            path: archive_path.clone(),
+            source,
+        })?;
+    let listing_path = out_dir.join(format!("{archive_name}.list"));
+    let (digest, len) = archive_into(
+        config,
+        target,
+        all_files,
+        archive_file,
+        Some(&listing_path),
+        opts,
+    )?;
+
+    Ok((archive_path, digest, len, listing_path))
+}
+
+/// Warn that the archive at `archive_path` is over `output.warn-size`, listing the largest of
+/// the files in it
+pub(crate) fn warn_archive_size(
+    archive_path: &Path,
+    archive_len: u64,
+    limit: u64,
+    files: &RumkinstFiles,
+    diagnostics: &Diagnostics,
+) {
+    /// Number of files listed as taking up the most space
+    const LARGEST_FILES: usize = 10;
+
+    let mut message = format!(
+        "archive {:?} is {}, over the `output.warn-size` of {}; its largest files are:",
+        archive_path.file_name().unwrap_or(archive_path.as_os_str()),
+        HumanBytes(archive_len),
+        HumanBytes(limit)
+    );
+    for (name, size) in files.largest_files(LARGEST_FILES) {
+        message.push_str(&format!("\n  {:>10}  {name}", HumanBytes(size).to_string()));
+    }
+    diagnostics.warn(ErrorCode::ARCHIVE_TOO_LARGE, message);
+}
+
+/// Archive every one of `archived` again into a temporary directory, failing if it does not
+/// match the archive made from it the first time, in `archives`
+///
+/// A signed archive embeds the signature of its listing, which a signer need not make the same
+/// way twice, so with a signer two unsigned archives are made and compared instead. Skipped
+/// errors and warnings of the second pass are not collected again.
+pub(crate) fn check_reproducible(
+    config: &Config,
+    archived: &[(Option<&str>, Cow<RumkinstFiles>)],
+    archives: &[(Option<&str>, ArchiveArtifact)],
+    opts: &BuildOptions,
+) -> Result<(), RumkinstError> {
+    let dir = env::temp_dir().join(format!("rumkinst-reproducible-{}", nanoid!()));
+    fs::create_dir(&dir).map_err(|source| RumkinstError::OutputDir {
+        path: dir.clone(),
+        source,
+    })?;
+    let signed = opts.signer.is_some();
+    let opts = BuildOptions {
+        progress: Arc::new(NoProgress),
+        diagnostics: Arc::default(),
+        signer: None,
+        ..opts.clone()
+    };
+    let make_unsigned = |name: &str, target: Option<&str>, files: &RumkinstFiles| {
+        let out_dir = dir.join(name);
+        fs::create_dir_all(&out_dir).map_err(|source| RumkinstError::OutputDir {
+            path: out_dir.clone(),
+            source,
+        })?;
+        make_archive(config, &out_dir, target, files, &opts)
+    };
+    let result = archived
+        .iter()
+        .zip(archives)
+        .try_for_each(|((target, files), (_, first))| {
+            opts.cancel.check()?;
+            let (first_path, first_digest, first_listing) = match signed {
+                true => {
+                    let (path, digest, _, listing_path) = make_unsigned("first", *target, files)?;
+                    (path, to_hex(&digest), Some(listing_path))
+                }
+                false => (
+                    first.path.clone(),
+                    first.digest.clone(),
+                    first.listing_path.clone(),
+                ),
+            };
+            let (path, digest, _, listing_path) = make_unsigned("second", *target, files)?;
+            if to_hex(&digest) == first_digest {
+                return Ok(());
+            }
+            let listing = |path: &Path| fs::read_to_string(path).unwrap_or_default();
+            Err(RumkinstError::NotReproducible {
+                path: first.path.clone(),
+                differences: archive_differences(
+                    &first_path,
+                    &first_listing.as_deref().map(listing).unwrap_or_default(),
+                    &path,
+                    &listing(&listing_path),
+                    opts.digester.as_ref(),
+                ),
+            })
+        });
+    if let Err(err) = fs::remove_dir_all(&dir) {
+        log::warn!("failed to remove the reproducibility check directory {dir:?}: {err}");
+    }
+    result
+}
+
+/// What differs between the archive at `first` and the one at `second`, made from the same
+/// files, going by their listings
+pub(crate) fn archive_differences(
+    first: &Path,
+    first_listing: &str,
+    second: &Path,
+    second_listing: &str,
+    digester: &dyn Digester,
+) -> Vec<String> {
+    let first_entries = parse_listing(first_listing).unwrap_or_default();
+    let second_entries = parse_listing(second_listing).unwrap_or_default();
+    let mut differences = vec![];
+    for entry in &first_entries {
+        match second_entries.iter().find(|other| other.name == entry.name) {
+            None => differences.push(format!("`{}` was only archived the first time", entry.name)),
+            Some(other) => {
+                let changed = [
+                    (entry.size != other.size, "size"),
+                    (entry.mode != other.mode, "mode"),
+                    (entry.digest != other.digest, "contents"),
+                ];
+                for (_, what) in changed.iter().filter(|(changed, _)| *changed) {
+                    differences.push(format!("the {what} of `{}`", entry.name));
+                }
+            }
+        }
+    }
+    for entry in &second_entries {
+        if !first_entries.iter().any(|other| other.name == entry.name) {
+            differences.push(format!(
+                "`{}` was only archived the second time",
+                entry.name
+            ));
+        }
+    }
+    if !differences.is_empty() {
+        return differences;
+    }
+
+    // Every entry is the same, so the order they were archived in or their headers differ
+    let order = |path: &Path| {
+        read_archive_entries(path, digester)
+            .map(|entries| {
+                entries
+                    .into_iter()
+                    .map(|entry| entry.name)
+                    .collect::<Vec<_>>()
+            })
+            .ok()
+    };
+    match order(first) != order(second) {
+        true => vec!["the order of the entries".to_string()],
+        false => {
+            vec!["the archive metadata, such as timestamps, with every entry the same".to_string()]
+        }
+    }
+}
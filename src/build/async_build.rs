@@ -0,0 +1,130 @@
+use std::{io, io::Write, sync::Arc};
+
+use super::{BuildOptions, BuildOutput, StreamedArchive, build, write_archive};
+use crate::{CancelHandle, RumkinstError, config::Config};
+
+/// Cancels a build if it is still running when dropped
+struct CancelOnDrop(Option<CancelHandle>);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(cancel) = &self.0 {
+            cancel.cancel();
+        }
+    }
+}
+
+/// The result of a build that ran on tokio's blocking thread pool
+fn joined<T>(
+    result: Result<Result<T, RumkinstError>, tokio::task::JoinError>,
+) -> Result<T, RumkinstError> {
+    match result {
+        Ok(result) => result,
+        Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+        Err(_) => Err(RumkinstError::Cancelled),
+    }
+}
+
+/// Run [`build`] on tokio's blocking thread pool, so it does not hold up any async tasks
+///
+/// Every step of a build walks the file system or runs other programs, so the whole build runs
+/// there rather than only parts of it. Dropping the returned future cancels the build through
+/// the [`CancelHandle`] in `opts`, its partial output is left for the caller to remove.
+pub async fn build_async(
+    config: Arc<Config>,
+    opts: BuildOptions,
+) -> Result<BuildOutput, RumkinstError> {
+    let mut guard = CancelOnDrop(Some(opts.cancel.clone()));
+    let result = tokio::task::spawn_blocking(move || build(&config, opts)).await;
+    guard.0 = None;
+    joined(result)
+}
+
+/// Like [`write_archive`], but writes the archive into an async `destination`, such as the body
+/// of an HTTP response, as it is made
+///
+/// Discovery and archiving run on tokio's blocking thread pool and hand the archive over in
+/// chunks, which are written to `destination` from the calling task. Only a few chunks are
+/// held at once, so a slow `destination` slows archiving down instead of buffering the whole
+/// archive. Dropping the returned future cancels the archive through the [`CancelHandle`] in
+/// `opts`.
+pub async fn write_archive_async<W: tokio::io::AsyncWrite + Unpin>(
+    config: Arc<Config>,
+    mut destination: W,
+    opts: BuildOptions,
+) -> Result<StreamedArchive, RumkinstError> {
+    use tokio::io::AsyncWriteExt;
+
+    /// Chunks written before the archive waits for `destination` to catch up
+    const QUEUED_CHUNKS: usize = 4;
+
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(QUEUED_CHUNKS);
+    let mut guard = CancelOnDrop(Some(opts.cancel.clone()));
+    let task = tokio::task::spawn_blocking(move || {
+        let mut writer = ChunkWriter {
+            sender,
+            chunk: Vec::with_capacity(CHUNK_SIZE),
+        };
+        let streamed = write_archive(&config, &mut writer, &opts)?;
+        writer
+            .send()
+            .map_err(|source| RumkinstError::Archive { path: None, source })?;
+        Ok(streamed)
+    });
+
+    let mut written = Ok(());
+    while let Some(chunk) = receiver.recv().await {
+        written = destination.write_all(&chunk).await;
+        if written.is_err() {
+            break;
+        }
+    }
+    if written.is_ok() {
+        written = destination.flush().await;
+    }
+    // The archive stops at its next write once nothing receives its chunks
+    drop(receiver);
+    let result = joined(task.await);
+    guard.0 = None;
+    written.map_err(|source| RumkinstError::Archive { path: None, source })?;
+    result
+}
+
+/// Size of the chunks [`write_archive_async`] hands over
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Collects what is written into chunks of [`CHUNK_SIZE`], sent to the task writing them out
+struct ChunkWriter {
+    sender: tokio::sync::mpsc::Sender<Vec<u8>>,
+    chunk: Vec<u8>,
+}
+
+impl ChunkWriter {
+    /// Send the chunk collected so far, waiting for room if too many are queued
+    fn send(&mut self) -> io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.chunk, Vec::with_capacity(CHUNK_SIZE));
+        self.sender.blocking_send(chunk).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "the archive destination stopped accepting data",
+            )
+        })
+    }
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.chunk.extend_from_slice(buf);
+        if self.chunk.len() >= CHUNK_SIZE {
+            self.send()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send()
+    }
+}
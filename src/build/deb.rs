@@ -0,0 +1,60 @@
+use std::{fs::File, path::Path};
+
+use super::{ArchiveArtifact, BuildOptions, make_checksum, sign_artifact};
+use crate::{
+    RumkinstError,
+    config::Config,
+    installer_gen::{DigestWriter, RumkinstFiles, deb_file_name, to_hex, write_deb},
+};
+
+/// Write the `.deb` for `config`, along with its checksum and signature
+pub(crate) fn make_deb(
+    config: &Config,
+    out_dir: &Path,
+    all_files: &RumkinstFiles,
+    opts: &BuildOptions,
+) -> Result<ArchiveArtifact, RumkinstError> {
+    let deb_name = deb_file_name(
+        config,
+        config
+            .deb
+            .as_ref()
+            .expect("a .deb should only be made when [deb] is set"),
+    )?;
+    let deb_path = out_dir.join(&deb_name);
+
+    log::info!("Making Debian package \"{deb_name}\"");
+
+    let deb_file = File::create_new(&deb_path).map_err(|source| RumkinstError::Package {
+        path: deb_path.clone(),
+        source,
+    })?;
+    let mut writer = DigestWriter::new(deb_file, opts.digester.as_ref());
+    write_deb(
+        config,
+        all_files,
+        &mut writer,
+        &deb_path,
+        opts.file_policy(),
+        opts.progress.as_ref(),
+        &opts.cancel,
+    )?;
+    let (digest, _) = writer.finish();
+    opts.cancel.check()?;
+
+    let checksum_path = make_checksum(&deb_path, opts.digester.algorithm(), &digest)?;
+    let signature_path = opts
+        .signer
+        .as_ref()
+        .map(|signer| sign_artifact(&deb_path, &digest, signer.as_ref()))
+        .transpose()?;
+
+    Ok(ArchiveArtifact {
+        path: deb_path,
+        checksum_path,
+        digest: to_hex(&digest),
+        algorithm: opts.digester.algorithm().to_string(),
+        signature_path,
+        listing_path: None,
+    })
+}
@@ -0,0 +1,103 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use serde_json::json;
+
+use super::{ArchiveArtifact, BuildOptions, archive::archive_name, make_checksum, sign_artifact};
+use crate::{
+    RumkinstError,
+    config::{ArchiveFormat, Config},
+    installer_gen::{DigestWriter, RumkinstFiles, new_archive_writer, to_hex, write_delta},
+};
+
+/// Write the delta from the archive of an earlier build at `base` to this build, along with its
+/// manifest, checksum and signature
+pub(crate) fn make_delta(
+    config: &Config,
+    out_dir: &Path,
+    base: &Path,
+    all_files: &RumkinstFiles,
+    opts: &BuildOptions,
+) -> Result<(ArchiveArtifact, PathBuf), RumkinstError> {
+    let base = find_delta_base(config, base)?;
+    let delta_name = format!(
+        "{}.delta.{}",
+        config.get_name(),
+        config.archive_format().extension()
+    );
+    let delta_path = out_dir.join(&delta_name);
+    let delta_error = |source| RumkinstError::Package {
+        path: delta_path.clone(),
+        source,
+    };
+
+    log::info!("Making delta \"{delta_name}\" from {base:?}");
+
+    let delta_file = File::create_new(&delta_path).map_err(delta_error)?;
+    let mut writer = DigestWriter::new(delta_file, opts.digester.as_ref());
+    let mut archive = new_archive_writer(config.archive_format(), &mut writer, &delta_name);
+    let mut manifest = write_delta(
+        all_files,
+        &base,
+        archive.as_mut(),
+        opts.digester.as_ref(),
+        opts.file_policy(),
+        &opts.cancel,
+    )?;
+    drop(archive);
+    writer.flush().map_err(delta_error)?;
+    let (digest, _) = writer.finish();
+    opts.cancel.check()?;
+
+    manifest["delta"] = json!(delta_name);
+    manifest["target"] = json!({
+        "archive": archive_name(config, None),
+        "version": config.version(),
+    });
+    let manifest_path = out_dir.join(format!("{}.delta.json", config.get_name()));
+    let manifest = serde_json::to_string_pretty(&manifest)
+        .expect("delta manifest should always serialize to json")
+        + "\n";
+    fs::write(&manifest_path, manifest).map_err(|source| RumkinstError::Package {
+        path: manifest_path.clone(),
+        source,
+    })?;
+
+    let checksum_path = make_checksum(&delta_path, opts.digester.algorithm(), &digest)?;
+    let signature_path = opts
+        .signer
+        .as_ref()
+        .map(|signer| sign_artifact(&delta_path, &digest, signer.as_ref()))
+        .transpose()?;
+
+    let delta = ArchiveArtifact {
+        path: delta_path,
+        checksum_path,
+        digest: to_hex(&digest),
+        algorithm: opts.digester.algorithm().to_string(),
+        signature_path,
+        listing_path: None,
+    };
+    Ok((delta, manifest_path))
+}
+
+/// The archive to make a delta from, `base` itself or the archive in the run directory `base`
+pub(crate) fn find_delta_base(config: &Config, base: &Path) -> Result<PathBuf, RumkinstError> {
+    if !base.is_dir() {
+        return Ok(base.to_path_buf());
+    }
+    [ArchiveFormat::TarGz, ArchiveFormat::Zip]
+        .into_iter()
+        .map(|format| base.join(format!("{}.{}", config.get_name(), format.extension())))
+        .find(|path| path.is_file())
+        .ok_or_else(|| RumkinstError::DeltaBase {
+            path: base.to_path_buf(),
+            source: io::Error::new(
+                io::ErrorKind::NotFound,
+                "the directory holds no archive of this package",
+            ),
+        })
+}
@@ -0,0 +1,64 @@
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use super::{ArchiveArtifact, BuildOptions};
+use crate::{
+    RumkinstError,
+    config::Config,
+    error_log::ErrorCode,
+    installer_gen::{Digester, Sha256Digester, flatpak_manifest, to_hex},
+    progress_log::NoProgress,
+};
+
+/// Write the Flatpak manifest for `config`, which points at `archive`
+pub(crate) fn make_flatpak_manifest(
+    config: &Config,
+    out_dir: &Path,
+    archive: Option<&ArchiveArtifact>,
+    opts: &BuildOptions,
+) -> Result<Option<PathBuf>, RumkinstError> {
+    let Some(archive) = archive else {
+        opts.diagnostics.warn(
+            ErrorCode::NO_SOURCE_FILES,
+            "no archive was made, skipping the Flatpak manifest",
+        );
+        return Ok(None);
+    };
+
+    // Flatpak only accepts SHA-256, which the configured digester may not be
+    let sha256 = if opts.digester.algorithm() == Sha256Digester.algorithm() {
+        archive.digest.clone()
+    } else {
+        File::open(&archive.path)
+            .and_then(|mut file| Sha256Digester.digest(&mut file, &NoProgress))
+            .map(|digest| to_hex(&digest))
+            .map_err(|source| RumkinstError::Checksum {
+                path: archive.path.clone(),
+                source,
+            })?
+    };
+
+    let archive_name = archive
+        .path
+        .file_name()
+        .expect("archive path should always have a file name")
+        .to_string_lossy();
+    let root_is_file = config.resolve(config.root.path()).is_file();
+    let manifest = flatpak_manifest(config, &archive_name, &sha256, root_is_file);
+
+    let app_id = &config
+        .flatpak
+        .as_ref()
+        .expect("a flatpak manifest should only be made when [flatpak] is set")
+        .app_id;
+    let manifest_path = out_dir.join(format!("{app_id}.json"));
+    log::info!("Writing Flatpak manifest \"{app_id}.json\"");
+    fs::write(&manifest_path, manifest).map_err(|source| RumkinstError::Package {
+        path: manifest_path.clone(),
+        source,
+    })?;
+
+    Ok(Some(manifest_path))
+}
@@ -0,0 +1,91 @@
+use std::{io, path::Path, process::Command, time::Instant};
+
+use super::TimedProgress;
+use crate::{RumkinstError, VcsInfo, config::Config};
+
+/// Run the hook script at `path` from the base directory of `config`, if the script exists
+///
+/// The output directory is passed to the script in `RUMKINST_OUT_DIR`, along with the state of
+/// the git repository in `RUMKINST_GIT_*` if there is one. When `streaming`, the script's stdout
+/// is sent to stderr instead.
+pub(crate) fn run_hook(
+    config: &Config,
+    name: &str,
+    path: &Path,
+    out_dir: &Path,
+    vcs: Option<&VcsInfo>,
+    timed: &TimedProgress,
+    streaming: bool,
+) -> Result<(), RumkinstError> {
+    let hook_error = |source: Box<dyn std::error::Error + Send + Sync>| RumkinstError::Hook {
+        hook: name.to_string(),
+        source,
+    };
+
+    let script = config.resolve(path);
+    if !script.exists() {
+        log::debug!("no {name} hook at {script:?}, skipping");
+        return Ok(());
+    }
+
+    log::info!("Running {name} hook");
+    // Absolute paths, so neither is looked up in PATH or resolved against the wrong directory
+    let script = std::path::absolute(&script).map_err(|err| hook_error(err.into()))?;
+    let out_dir = std::path::absolute(out_dir).map_err(|err| hook_error(err.into()))?;
+
+    let mut command = hook_command(&script);
+    command.env("RUMKINST_OUT_DIR", out_dir);
+    if let Some(vcs) = vcs {
+        command.envs(vcs.env_vars());
+    }
+    if !config.base_dir().as_os_str().is_empty() {
+        command.current_dir(config.base_dir());
+    }
+    // The archive is being written to stdout, so anything the hook prints goes to stderr
+    if streaming {
+        command.stdout(io::stderr());
+    }
+
+    let hook_start = Instant::now();
+    let status = command.status().map_err(|err| hook_error(err.into()))?;
+    timed.record(format!("Running {name} hook"), hook_start.elapsed());
+    if !status.success() {
+        return Err(hook_error(format!("{script:?} {status}").into()));
+    }
+    Ok(())
+}
+
+/// The command running the hook script at `script`, through an interpreter for scripts that
+/// can't be run directly
+///
+/// PowerShell scripts always go through PowerShell, and shell scripts go through `sh` on Windows,
+/// such as the one Git for Windows installs. Batch files and executables are run as they are.
+fn hook_command(script: &Path) -> Command {
+    let extension = script
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("ps1") => {
+            let mut command = Command::new(match cfg!(windows) {
+                true => "powershell",
+                false => "pwsh",
+            });
+            command
+                .args([
+                    "-NoProfile",
+                    "-NonInteractive",
+                    "-ExecutionPolicy",
+                    "Bypass",
+                ])
+                .arg("-File")
+                .arg(script);
+            command
+        }
+        Some("sh") if cfg!(windows) => {
+            let mut command = Command::new("sh");
+            command.arg(script);
+            command
+        }
+        _ => Command::new(script),
+    }
+}
@@ -0,0 +1,1365 @@
+mod archive;
+#[cfg(feature = "tokio")]
+mod async_build;
+mod deb;
+mod delta;
+mod flatpak;
+mod hooks;
+mod nsis;
+mod oci;
+
+use std::{
+    borrow::Cow,
+    collections::BTreeMap,
+    fs,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use archive::{MadeArchive, check_reproducible, make_archive, warn_archive_size};
+
+pub use archive::{StreamedArchive, write_archive};
+
+#[cfg(feature = "tokio")]
+pub use async_build::{build_async, write_archive_async};
+use deb::make_deb;
+use delta::make_delta;
+use flatpak::make_flatpak_manifest;
+use hooks::run_hook;
+use nsis::make_nsis_installer;
+use oci::make_oci_image;
+
+use crate::{
+    AssumeYes, CancelHandle, Confirm, RumkinstError, VcsInfo,
+    cache::{CacheKind, default_cache_dir},
+    changelog::version_section,
+    command_source::run_command_sources,
+    config::{Config, PluginStage},
+    download::{FetchedFile, cached_path, fetch_remote_sources, git_cached},
+    error_log::{Diagnostics, ErrorCode, FileErrorPolicy, FilePolicy},
+    installer_gen::{
+        Digester, HookMetadata, RumkinstFiles, Sha256Digester, Signer, check_wasm_hooks,
+        find_files, to_hex,
+    },
+    jobs::{Task, default_jobs, run_tasks},
+    lint::{preflight_hooks, preflight_scripts},
+    lockfile::{LOCKFILE_NAME, Lockfile, LockfileMode},
+    plugin::{PluginArtifact, has_plugins, run_plugins},
+    progress_log::{NoProgress, Phase, ProgressSink},
+    publish::{
+        publish_github_release, publish_gitlab_release, published_files, push_to_registry,
+        update_feed, upload_over_http, upload_to_s3, verify_before_publish,
+    },
+};
+
+/// Options for a single [`build`]
+#[derive(Clone)]
+pub struct BuildOptions {
+    out_dir: PathBuf,
+    policy: FileErrorPolicy,
+    diagnostics: Arc<Diagnostics>,
+    progress: Arc<dyn ProgressSink>,
+    digester: Arc<dyn Digester>,
+    signer: Option<Arc<dyn Signer>>,
+    cancel: CancelHandle,
+    confirm: Arc<dyn Confirm>,
+    publish: bool,
+    vcs: bool,
+    delta_base: Option<PathBuf>,
+    allow_outside_sources: bool,
+    jobs: Option<NonZeroUsize>,
+    offline: bool,
+    cache_dir: Option<PathBuf>,
+    refresh: bool,
+    check_reproducible: bool,
+    lockfile: LockfileMode,
+}
+
+impl std::fmt::Debug for BuildOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BuildOptions")
+            .field("out_dir", &self.out_dir)
+            .field("policy", &self.policy)
+            .field("diagnostics", &self.diagnostics)
+            .field("digester", &self.digester.algorithm())
+            .field(
+                "signer",
+                &self.signer.as_ref().map(|signer| signer.extension()),
+            )
+            .field("cancel", &self.cancel)
+            .field("publish", &self.publish)
+            .field("vcs", &self.vcs)
+            .field("delta_base", &self.delta_base)
+            .field("allow_outside_sources", &self.allow_outside_sources)
+            .field("jobs", &self.jobs)
+            .field("offline", &self.offline)
+            .field("cache_dir", &self.cache_dir)
+            .field("refresh", &self.refresh)
+            .field("check_reproducible", &self.check_reproducible)
+            .field("lockfile", &self.lockfile)
+            .finish_non_exhaustive()
+    }
+}
+
+impl BuildOptions {
+    /// Build into `out_dir`, which is created if it does not exist yet
+    pub fn new(out_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            out_dir: out_dir.into(),
+            policy: FileErrorPolicy::default(),
+            diagnostics: Arc::default(),
+            progress: Arc::new(NoProgress),
+            digester: Arc::new(Sha256Digester),
+            signer: None,
+            cancel: CancelHandle::new(),
+            confirm: Arc::new(AssumeYes),
+            publish: false,
+            vcs: true,
+            delta_base: None,
+            allow_outside_sources: false,
+            jobs: None,
+            offline: false,
+            cache_dir: None,
+            refresh: false,
+            check_reproducible: false,
+            lockfile: LockfileMode::default(),
+        }
+    }
+
+    /// What to do when a single source file can't be read, aborting by default
+    pub fn with_file_error_policy(mut self, policy: FileErrorPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Where the warnings and skipped errors of the build are collected, a new collector for each
+    /// [`BuildOptions::new`] by default. Builds given clones of the same options share it.
+    pub fn with_diagnostics(mut self, diagnostics: Arc<Diagnostics>) -> Self {
+        self.diagnostics = diagnostics;
+        self
+    }
+
+    /// Where to send progress updates, which are dropped by default
+    pub fn with_progress(mut self, progress: Arc<dyn ProgressSink>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// How checksums are computed, SHA-256 by default
+    pub fn with_digester(mut self, digester: Arc<dyn Digester>) -> Self {
+        self.digester = digester;
+        self
+    }
+
+    /// Sign every artifact with `signer`, artifacts are left unsigned by default
+    ///
+    /// Source archives also get their listing signed and embedded as `.rumkinst/manifest`, next
+    /// to its signature, so single files can be checked without the signature files.
+    pub fn with_signer(mut self, signer: Arc<dyn Signer>) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// Stop the build early once `cancel` is cancelled
+    pub fn with_cancel(mut self, cancel: CancelHandle) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Ask `confirm` before replacing assets already on a release, which are replaced without
+    /// asking by default
+    pub fn with_confirm(mut self, confirm: Arc<dyn Confirm>) -> Self {
+        self.confirm = confirm;
+        self
+    }
+
+    /// Whether [`build`] also [`publish`]es the artifacts once they are made, off by default
+    ///
+    /// A failed publish then fails the build even though its artifacts are already in the output
+    /// directory, call [`publish`] after the build instead to keep them apart.
+    pub fn with_publish(mut self, publish: bool) -> Self {
+        self.publish = publish;
+        self
+    }
+
+    /// Whether to look for a git repository enclosing the base directory, on by default
+    pub fn with_vcs(mut self, vcs: bool) -> Self {
+        self.vcs = vcs;
+        self
+    }
+
+    /// Also make a delta holding the files that changed since an earlier build, from its archive
+    /// at `base` or the output directory of its run
+    ///
+    /// The delta is file-level, changed files are included whole rather than diffed, and
+    /// rumkinst doesn't apply it. Its manifest has what an updater needs to check and apply it.
+    pub fn with_delta_base(mut self, base: impl Into<PathBuf>) -> Self {
+        self.delta_base = Some(base.into());
+        self
+    }
+
+    /// Whether sources may be outside the base directory, and symlinks in them may point outside
+    /// of the source, off by default so a stray symlink can't pull host files into the artifacts
+    pub fn with_allow_outside_sources(mut self, allow: bool) -> Self {
+        self.allow_outside_sources = allow;
+        self
+    }
+
+    /// Make artifacts on at most `jobs` threads, overriding `[build] jobs` in the config. Both
+    /// default to the available parallelism.
+    pub fn with_jobs(mut self, jobs: NonZeroUsize) -> Self {
+        self.jobs = Some(jobs);
+        self
+    }
+
+    /// Fail before doing anything if the build would need network access, off by default
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Cache downloaded sources in `dir`, instead of the user's cache directory
+    pub fn with_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Download every download source again even when it is cached, replacing the cached copy,
+    /// off by default
+    pub fn with_refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Archive the sources a second time in a temporary directory, failing with what differs if
+    /// any archive is not the same both times, off by default
+    ///
+    /// The second pass reuses the files found for the first one, but reads and transforms every
+    /// one of them again.
+    pub fn with_reproducibility_check(mut self, check: bool) -> Self {
+        self.check_reproducible = check;
+        self
+    }
+
+    /// What to do with the [`LOCKFILE_NAME`](crate::LOCKFILE_NAME) in the base directory, which
+    /// is left alone by default
+    ///
+    /// Every file is read an extra time to digest it when it is used.
+    pub fn with_lockfile(mut self, mode: LockfileMode) -> Self {
+        self.lockfile = mode;
+        self
+    }
+
+    fn cache_dir(&self) -> PathBuf {
+        self.cache_dir.clone().unwrap_or_else(default_cache_dir)
+    }
+
+    /// The file error policy, collecting into the diagnostics of these options
+    fn file_policy(&self) -> FilePolicy<'_> {
+        FilePolicy::new(self.policy, &self.diagnostics)
+    }
+
+    /// Most threads to make artifacts on, from these options, then `config`
+    fn jobs(&self, config: &Config) -> NonZeroUsize {
+        self.jobs.or(config.jobs()).unwrap_or_else(default_jobs)
+    }
+}
+
+/// Everything produced by a successful [`build`]
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct BuildOutput {
+    /// The directory every artifact was written into
+    pub out_dir: PathBuf,
+    /// The archive of all source files, `None` if no source files were found or `[targets]` is
+    /// set in the config
+    pub archive: Option<ArchiveArtifact>,
+    /// The archive made for each of `[targets]` in the config, in the order of their names,
+    /// leaving out targets without any source files
+    pub target_archives: Vec<TargetArchive>,
+    /// The files that changed since the build given to [`BuildOptions::with_delta_base`], `None`
+    /// if there was none
+    pub delta: Option<ArchiveArtifact>,
+    /// The manifest listing what [`BuildOutput::delta`] adds, changes and removes
+    pub delta_manifest: Option<PathBuf>,
+    /// The Debian package, `None` unless `[deb]` is set in the config
+    pub deb: Option<ArchiveArtifact>,
+    /// The Flatpak manifest, `None` unless `[flatpak]` is set in the config and an archive was
+    /// made for it to point at
+    pub flatpak_manifest: Option<PathBuf>,
+    /// The OCI image layout directory, `None` unless `[oci]` is set in the config
+    pub oci_layout: Option<PathBuf>,
+    /// The NSIS script for the Windows installer, `None` unless `[nsis]` is set in the config
+    pub nsis_script: Option<PathBuf>,
+    /// The Windows installer compiled from [`BuildOutput::nsis_script`], `None` unless `[nsis]`
+    /// is set in the config with `compile` left on
+    pub windows_installer: Option<ArchiveArtifact>,
+    /// The registry reference the artifacts were pushed to, `None` unless `[publish.oci]` is set
+    pub registry_reference: Option<String>,
+    /// URLs of the GitHub and GitLab releases the artifacts were added to, empty unless
+    /// `[publish.github]` or `[publish.gitlab]` is set
+    pub release_urls: Vec<String>,
+    /// URLs of every artifact uploaded to S3 or over HTTP, empty unless `[publish.s3]` or
+    /// `[publish.http]` is set
+    pub uploaded_urls: Vec<String>,
+    /// Files made by `[[plugins]]` at the `artifacts` stage, in the order they were made
+    pub plugin_artifacts: Vec<PluginArtifact>,
+    /// Metadata appended by each of `[[wasm-hooks]]`, by hook name
+    pub hook_metadata: BTreeMap<String, BTreeMap<String, String>>,
+    /// The update feed the artifacts were added to, `None` unless `[feed]` is set
+    pub feed: Option<PathBuf>,
+    /// The changelog section for the package version, `None` unless `package.changelog` and
+    /// `package.version` are set and the changelog has a section for it
+    pub release_notes: Option<PathBuf>,
+    /// The git repository the build ran in, `None` if there is none or it was not looked for
+    pub vcs: Option<VcsInfo>,
+    /// Entry names of the license files bundled into the archive, empty unless `[licenses]` is
+    /// set
+    pub licenses: Vec<String>,
+    pub stats: BuildStats,
+}
+
+impl BuildOutput {
+    /// Where every path of this output ends up once `staging` is moved into `out_dir`
+    fn finalized(self, staging: &Path, out_dir: &Path) -> Self {
+        let finalize = |artifact: Option<ArchiveArtifact>| {
+            artifact.map(|artifact| artifact.finalized(staging, out_dir))
+        };
+        let finalize_path =
+            |path: Option<PathBuf>| path.map(|path| finalized_path(&path, staging, out_dir));
+        Self {
+            out_dir: out_dir.to_path_buf(),
+            archive: finalize(self.archive),
+            target_archives: self
+                .target_archives
+                .into_iter()
+                .map(|target| TargetArchive {
+                    archive: target.archive.finalized(staging, out_dir),
+                    ..target
+                })
+                .collect(),
+            delta: finalize(self.delta),
+            delta_manifest: finalize_path(self.delta_manifest),
+            deb: finalize(self.deb),
+            flatpak_manifest: finalize_path(self.flatpak_manifest),
+            oci_layout: finalize_path(self.oci_layout),
+            nsis_script: finalize_path(self.nsis_script),
+            windows_installer: finalize(self.windows_installer),
+            plugin_artifacts: self
+                .plugin_artifacts
+                .into_iter()
+                .map(|artifact| PluginArtifact {
+                    path: finalized_path(&artifact.path, staging, out_dir),
+                    ..artifact
+                })
+                .collect(),
+            release_notes: finalize_path(self.release_notes),
+            ..self
+        }
+    }
+}
+
+/// A written archive or package, along with its checksum and signature files
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ArchiveArtifact {
+    pub path: PathBuf,
+    pub checksum_path: PathBuf,
+    /// Lowercase hex digest of the archive, from the [`Digester`] the build used
+    pub digest: String,
+    /// Name of the algorithm behind [`ArchiveArtifact::digest`], see [`Digester::algorithm`]
+    pub algorithm: String,
+    /// `None` unless a [`Signer`] was given to the build
+    pub signature_path: Option<PathBuf>,
+    /// Text file listing the mode, size and name of every entry, `None` unless this is an
+    /// archive of the source files
+    pub listing_path: Option<PathBuf>,
+}
+
+impl ArchiveArtifact {
+    /// The same artifact, once its files were moved from `staging` into `out_dir`
+    fn finalized(self, staging: &Path, out_dir: &Path) -> Self {
+        Self {
+            path: finalized_path(&self.path, staging, out_dir),
+            checksum_path: finalized_path(&self.checksum_path, staging, out_dir),
+            signature_path: self
+                .signature_path
+                .map(|path| finalized_path(&path, staging, out_dir)),
+            listing_path: self
+                .listing_path
+                .map(|path| finalized_path(&path, staging, out_dir)),
+            ..self
+        }
+    }
+}
+
+/// The archive of the source files packaged for one of `[targets]`
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct TargetArchive {
+    /// Name of the target, as in `[targets.<name>]`
+    pub target: String,
+    pub archive: ArchiveArtifact,
+}
+
+/// Name of the directory inside the output directory that artifacts are written into, before
+/// they are all moved out of it once every one of them is complete
+const STAGING_DIR_NAME: &str = ".staging";
+
+/// Name of the release notes written from the changelog into the output directory
+const RELEASE_NOTES_FILE_NAME: &str = "release-notes.md";
+
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct BuildStats {
+    /// Number of source files found, including any that were skipped
+    pub files: usize,
+    /// Size of the archive in bytes, `0` if none was written
+    pub archive_bytes: u64,
+    /// Number of warnings reported by the build, see [`BuildOptions::with_diagnostics`]
+    pub warnings: usize,
+    /// Number of errors the build skipped past, see [`FileErrorPolicy::KeepGoing`]
+    pub skipped: usize,
+    pub elapsed: Duration,
+    /// How long each phase and hook that ran took, in the order they started
+    pub phases: Vec<PhaseTiming>,
+}
+
+/// The wall-clock time one phase of a build took
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct PhaseTiming {
+    /// Title of the phase, as shown on its progress bar, or the name of the hook
+    pub title: String,
+    pub elapsed: Duration,
+}
+
+/// Make every rumkinst artifact described by `config`
+///
+/// Source and hook paths in `config` are resolved against [`Config::base_dir`], the process
+/// working directory is never changed. Warnings and skipped errors are collected into the
+/// [`Diagnostics`] of `opts`, and counted in [`BuildOutput::stats`].
+///
+/// Artifacts only appear in the output directory once all of them, with their checksums and
+/// signatures, were written. A cancelled build returns [`RumkinstError::Cancelled`] and leaves
+/// whatever it already wrote in the `.staging` directory inside the output directory, it is up
+/// to the caller to remove it.
+pub fn build(config: &Config, opts: BuildOptions) -> Result<BuildOutput, RumkinstError> {
+    let start = Instant::now();
+    check_targets(config, &opts)?;
+    let out_dir = opts.out_dir.clone();
+    fs::create_dir_all(&out_dir).map_err(|source| RumkinstError::OutputDir {
+        path: out_dir.clone(),
+        source,
+    })?;
+    let staging = out_dir.join(STAGING_DIR_NAME);
+    if staging.exists() {
+        log::debug!("removing stale staging directory {staging:?}");
+        fs::remove_dir_all(&staging).map_err(|source| RumkinstError::OutputDir {
+            path: staging.clone(),
+            source,
+        })?;
+    }
+    fs::create_dir(&staging).map_err(|source| RumkinstError::OutputDir {
+        path: staging.clone(),
+        source,
+    })?;
+
+    let timed = TimedProgress::new(opts.progress.as_ref());
+    let progress: &dyn ProgressSink = &timed;
+    let cancel = &opts.cancel;
+
+    let Discovered {
+        files: all_files,
+        vcs,
+        lockfile,
+        hook_metadata,
+    } = discover(config, &opts, &timed, false)?;
+
+    log::info!("Making rumkinst artifacts...");
+
+    // Discovery is shared, each target only archives the files packaged for it
+    let archived = match config.targets.is_empty() {
+        true => vec![(None, Cow::Borrowed(&all_files))],
+        false => config
+            .targets
+            .iter()
+            .map(|target| {
+                (
+                    Some(target.name.as_str()),
+                    Cow::Owned(all_files.for_target(target)),
+                )
+            })
+            .collect(),
+    };
+    let archived = archived
+        .into_iter()
+        .filter(|(target, files)| {
+            if files.total_files() > 0 {
+                return true;
+            }
+            match target {
+                Some(target) => opts.diagnostics.warn(
+                    ErrorCode::NO_SOURCE_FILES,
+                    format!(
+                        "no source files included for target `{target}`, skipping making its \
+                         archive file"
+                    ),
+                ),
+                None => opts.diagnostics.warn(
+                    ErrorCode::NO_SOURCE_FILES,
+                    "no source files included, skipping making archive file",
+                ),
+            }
+            false
+        })
+        .collect::<Vec<_>>();
+
+    let (archives, archive_bytes) = if !archived.is_empty() {
+        let length = archived
+            .iter()
+            .map(|(_, files)| files.total_files() as u64)
+            .sum();
+        // Each target's archive is written and hashed on its own thread, up to the job limit
+        let made = run_phase(progress, Phase::Archiving, length, || {
+            let tasks = archived
+                .iter()
+                .map(|(target, files)| {
+                    let (staging, opts) = (&staging, &opts);
+                    Box::new(move || {
+                        cancel.check()?;
+                        make_archive(config, staging, *target, files, opts)
+                    }) as Task<'_, Result<MadeArchive, RumkinstError>>
+                })
+                .collect();
+            run_tasks(opts.jobs(config), tasks)
+                .into_iter()
+                .collect::<Result<Vec<_>, RumkinstError>>()
+        })?;
+        cancel.check()?;
+
+        let checksum_paths = run_phase(progress, Phase::Checksum, made.len() as u64, || {
+            made.iter()
+                .map(|(archive_path, digest, ..)| {
+                    make_checksum(archive_path, opts.digester.algorithm(), digest)
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+        cancel.check()?;
+
+        let signature_paths = match &opts.signer {
+            Some(signer) => run_phase(progress, Phase::Signing, made.len() as u64, || {
+                let tasks = made
+                    .iter()
+                    .map(|(archive_path, digest, ..)| {
+                        Box::new(move || {
+                            sign_artifact(archive_path, digest, signer.as_ref()).map(Some)
+                        })
+                            as Task<'_, Result<Option<PathBuf>, RumkinstError>>
+                    })
+                    .collect();
+                run_tasks(opts.jobs(config), tasks)
+                    .into_iter()
+                    .collect::<Result<Vec<_>, _>>()
+            })?,
+            None => {
+                progress.phase_skipped(Phase::Signing);
+                vec![None; made.len()]
+            }
+        };
+
+        if let Some(limit) = config.warn_size() {
+            for ((_, files), (archive_path, _, archive_len, _)) in archived.iter().zip(&made) {
+                if *archive_len > limit {
+                    warn_archive_size(archive_path, *archive_len, limit, files, &opts.diagnostics);
+                }
+            }
+        }
+
+        let archive_bytes = made.iter().map(|(_, _, archive_len, _)| archive_len).sum();
+        let archives = archived
+            .iter()
+            .zip(made)
+            .zip(checksum_paths.into_iter().zip(signature_paths))
+            .map(
+                |(
+                    ((target, _), (archive_path, digest, _, listing_path)),
+                    (checksum_path, signature_path),
+                )| {
+                    let archive = ArchiveArtifact {
+                        path: archive_path,
+                        checksum_path,
+                        digest: to_hex(&digest),
+                        algorithm: opts.digester.algorithm().to_string(),
+                        signature_path,
+                        listing_path: Some(listing_path),
+                    };
+                    (*target, archive)
+                },
+            )
+            .collect::<Vec<_>>();
+        (archives, archive_bytes)
+    } else {
+        progress.phase_skipped(Phase::Archiving);
+        progress.phase_skipped(Phase::Checksum);
+        progress.phase_skipped(Phase::Signing);
+        (vec![], 0)
+    };
+    if opts.check_reproducible && !archives.is_empty() {
+        log::info!("Archiving again to check the archives are reproducible");
+        check_reproducible(config, &archived, &archives, &opts)?;
+        cancel.check()?;
+    }
+    drop(archived);
+    let (mut archive, mut target_archives) = (None, vec![]);
+    for (target, made) in archives {
+        match target {
+            Some(target) => target_archives.push(TargetArchive {
+                target: target.to_string(),
+                archive: made,
+            }),
+            None => archive = Some(made),
+        }
+    }
+
+    let (delta, delta_manifest) = match (&opts.delta_base, &archive) {
+        (Some(base), Some(_)) => {
+            let (delta, manifest) = make_delta(config, &staging, base, &all_files, &opts)?;
+            cancel.check()?;
+            (Some(delta), Some(manifest))
+        }
+        _ => (None, None),
+    };
+
+    let (mut deb, mut flatpak_manifest, mut oci_layout) = (None, None, None);
+    let root_files = all_files.root_files().len() as u64;
+    {
+        // Each package with the progress it reports, so the phase is as long as what runs in it
+        let mut tasks: Vec<(u64, Task<Result<(), RumkinstError>>)> = vec![];
+        if config.deb.is_some() {
+            tasks.push((
+                root_files,
+                Box::new(|| {
+                    cancel.check()?;
+                    deb = Some(make_deb(config, &staging, &all_files, &opts)?);
+                    Ok(())
+                }),
+            ));
+        }
+        if config.flatpak.is_some() {
+            tasks.push((
+                1,
+                Box::new(|| {
+                    cancel.check()?;
+                    flatpak_manifest =
+                        make_flatpak_manifest(config, &staging, archive.as_ref(), &opts)?;
+                    progress.increment(1);
+                    Ok(())
+                }),
+            ));
+        }
+        if config.oci.is_some() {
+            tasks.push((
+                root_files,
+                Box::new(|| {
+                    cancel.check()?;
+                    oci_layout = Some(make_oci_image(config, &staging, &all_files, &opts)?);
+                    Ok(())
+                }),
+            ));
+        }
+        if tasks.is_empty() {
+            progress.phase_skipped(Phase::Packaging);
+        } else {
+            let length = tasks.iter().map(|(length, _)| length).sum();
+            let tasks = tasks.into_iter().map(|(_, task)| task).collect();
+            let jobs = opts.jobs(config);
+            // Every package is finished even if an earlier one failed, the first error is kept
+            run_phase(progress, Phase::Packaging, length, || {
+                run_tasks(jobs, tasks)
+                    .into_iter()
+                    .collect::<Result<Vec<()>, _>>()
+            })?;
+        }
+    }
+
+    let (nsis_script, windows_installer) = match config.nsis {
+        Some(_) => {
+            cancel.check()?;
+            let (script, installer) =
+                run_phase(progress, Phase::InstallerGeneration, root_files, || {
+                    make_nsis_installer(config, &staging, &all_files, &opts)
+                })?;
+            (Some(script), installer)
+        }
+        None => {
+            progress.phase_skipped(Phase::InstallerGeneration);
+            (None, None)
+        }
+    };
+
+    let release_notes = make_release_notes(config, &staging, &opts.diagnostics)?;
+
+    let mut output = BuildOutput {
+        out_dir: staging.clone(),
+        archive,
+        target_archives,
+        delta,
+        delta_manifest,
+        deb,
+        flatpak_manifest,
+        oci_layout,
+        nsis_script,
+        windows_installer,
+        registry_reference: None,
+        release_urls: vec![],
+        uploaded_urls: vec![],
+        plugin_artifacts: vec![],
+        hook_metadata,
+        feed: None,
+        release_notes,
+        vcs,
+        licenses: all_files.license_entries(),
+        stats: BuildStats {
+            files: all_files.total_files(),
+            archive_bytes,
+            warnings: 0,
+            skipped: 0,
+            elapsed: Duration::ZERO,
+            phases: vec![],
+        },
+    };
+
+    // Plugins write into the staging directory, so what they make is only finalized, and later
+    // verified before publishing, like every other artifact
+    if has_plugins(config, PluginStage::Artifacts) {
+        cancel.check()?;
+        let plugins_start = Instant::now();
+        run_plugins(
+            config,
+            PluginStage::Artifacts,
+            &mut output,
+            opts.digester.as_ref(),
+            &opts.diagnostics,
+            opts.offline,
+        )?;
+        timed.record("Running plugins".to_string(), plugins_start.elapsed());
+    }
+
+    cancel.check()?;
+    let artifacts = [
+        &output.archive,
+        &output.delta,
+        &output.deb,
+        &output.windows_installer,
+    ];
+    let targeted = output.target_archives.iter().map(|target| &target.archive);
+    finalize_artifacts(
+        &staging,
+        &out_dir,
+        artifacts.into_iter().flatten().chain(targeted),
+    )?;
+    let mut output = output.finalized(&staging, &out_dir);
+
+    run_hook(
+        config,
+        "postbuild",
+        &config.build.postbuild,
+        &out_dir,
+        output.vcs.as_ref(),
+        &timed,
+        false,
+    )?;
+
+    if let Some(lockfile) = lockfile
+        && opts.lockfile == LockfileMode::Update
+    {
+        lockfile.write(&config.base_dir().join(LOCKFILE_NAME))?;
+    }
+
+    output.stats.warnings = opts.diagnostics.warnings().len();
+    output.stats.skipped = opts.diagnostics.errors().len();
+    output.stats.elapsed = start.elapsed();
+    output.stats.phases = timed.into_timings();
+    if opts.publish {
+        publish(config, &mut output, &opts)?;
+    }
+    Ok(output)
+}
+
+/// Publish the artifacts of a finished [`build`] to every target under `[publish]` and the
+/// publish-stage `[[plugins]]`, then update the `[feed]`, recording where they ended up in
+/// `output`
+///
+/// Nothing in the output directory is changed when this fails, other than the feed, so it can
+/// be called again with the same `output` once the problem is fixed. Uploads already on their
+/// target are skipped where the target can tell.
+pub fn publish(
+    config: &Config,
+    output: &mut BuildOutput,
+    opts: &BuildOptions,
+) -> Result<(), RumkinstError> {
+    let start = Instant::now();
+    let opts = &opts.clone().with_publish(true);
+    if opts.offline {
+        check_offline(config, opts)?;
+    }
+    let timed = TimedProgress::new(opts.progress.as_ref());
+    let progress: &dyn ProgressSink = &timed;
+
+    if config.publish.any() || has_plugins(config, PluginStage::Publish) {
+        opts.cancel.check()?;
+        let notes = match &output.release_notes {
+            Some(path) => {
+                Some(
+                    fs::read_to_string(path).map_err(|source| RumkinstError::SourceRead {
+                        path: path.clone(),
+                        source,
+                    })?,
+                )
+            }
+            None => None,
+        };
+        let targets = [
+            config.publish.oci.is_some(),
+            config.publish.github.is_some(),
+            config.publish.s3.is_some(),
+            config.publish.http.is_some(),
+            config.publish.gitlab.is_some(),
+            has_plugins(config, PluginStage::Publish),
+        ];
+        let length = targets.iter().filter(|enabled| **enabled).count() as u64;
+        run_phase(progress, Phase::Publishing, length, || {
+            publish_artifacts(config, output, notes.as_deref(), opts)
+        })?;
+    } else {
+        progress.phase_skipped(Phase::Publishing);
+    }
+
+    if config.feed.is_some() {
+        opts.cancel.check()?;
+        output.feed = update_feed(config, output)?;
+    }
+
+    output.stats.warnings = opts.diagnostics.warnings().len();
+    output.stats.skipped = opts.diagnostics.errors().len();
+    output.stats.elapsed += start.elapsed();
+    output.stats.phases.extend(timed.into_timings());
+    Ok(())
+}
+
+/// The files of a build, found once its prebuild hook ran
+struct Discovered {
+    files: RumkinstFiles,
+    vcs: Option<VcsInfo>,
+    /// The lockfile of the files, already checked for a locked build, `None` when it is ignored
+    lockfile: Option<Lockfile>,
+    hook_metadata: HookMetadata,
+}
+
+/// Run the prebuild hook, then find and transform every file `config` packages and hash them
+/// into its lockfile, as [`build`] and [`write_archive`] both start with
+///
+/// When `streaming`, the archive is being written to stdout, so the hook prints to stderr.
+fn discover(
+    config: &Config,
+    opts: &BuildOptions,
+    timed: &TimedProgress,
+    streaming: bool,
+) -> Result<Discovered, RumkinstError> {
+    check_wasm_hooks(config)?;
+    if opts.offline {
+        check_offline(config, opts)?;
+    }
+    let progress: &dyn ProgressSink = timed;
+    let cancel = &opts.cancel;
+
+    let vcs = match opts.vcs {
+        true => VcsInfo::detect(config.base_dir()),
+        false => None,
+    };
+    match &vcs {
+        Some(vcs) => log::debug!("building from git commit {}", vcs.commit),
+        None => log::debug!("not building from a git repository"),
+    }
+
+    preflight_hooks(config, &opts.diagnostics);
+    run_hook(
+        config,
+        "prebuild",
+        &config.build.prebuild,
+        &opts.out_dir,
+        vcs.as_ref(),
+        timed,
+        streaming,
+    )?;
+    cancel.check()?;
+
+    log::info!("Reading source directories");
+    let (mut files, lockfile) = run_phase(progress, Phase::Discovery, 3, || {
+        let fetched = fetch_sources(config, opts)?;
+        let lockfile = (opts.lockfile != LockfileMode::Ignore)
+            .then(|| Lockfile::new(config, &fetched, opts.digester.algorithm()));
+        let mut files = find_files(
+            config,
+            fetched,
+            opts.file_policy(),
+            opts.allow_outside_sources,
+            progress,
+            cancel,
+        )?;
+        files.cache_transforms_in(CacheKind::Transforms.dir(&opts.cache_dir()));
+        files.add_build_info(config, vcs.as_ref())?;
+        Ok((files, lockfile))
+    })?;
+    cancel.check()?;
+    preflight_scripts(config, &files, &opts.diagnostics);
+
+    let mut hook_metadata = HookMetadata::new();
+    if !config.wasm_hooks.is_empty() {
+        let hooks_start = Instant::now();
+        hook_metadata = files.run_wasm_hooks(config)?;
+        timed.record("Running wasm hooks".to_string(), hooks_start.elapsed());
+        cancel.check()?;
+    }
+
+    let lockfile = match lockfile {
+        Some(mut lockfile) => {
+            lockfile.add_files(&files, opts.digester.as_ref(), opts.jobs(config), cancel)?;
+            if opts.lockfile == LockfileMode::Locked {
+                lockfile.check(&config.base_dir().join(LOCKFILE_NAME))?;
+            }
+            Some(lockfile)
+        }
+        None => None,
+    };
+
+    Ok(Discovered {
+        files,
+        vcs,
+        lockfile,
+        hook_metadata,
+    })
+}
+
+/// Download, check out and make every source in `[sources]` of `config`
+fn fetch_sources(config: &Config, opts: &BuildOptions) -> Result<Vec<FetchedFile>, RumkinstError> {
+    let cache_dir = opts.cache_dir();
+    let mut fetched =
+        fetch_remote_sources(config, &cache_dir, opts.offline, opts.refresh, &opts.cancel)?;
+    fetched.extend(run_command_sources(config, &cache_dir, &opts.cancel)?);
+    Ok(fetched)
+}
+
+/// Fail if a source is limited to a target missing from `[targets]`, or if `[targets]` is set
+/// along with an artifact that is made from every source file
+fn check_targets(config: &Config, opts: &BuildOptions) -> Result<(), RumkinstError> {
+    let sources = &config.sources;
+    let limited = [
+        (
+            sources
+                .download
+                .iter()
+                .map(|source| &source.targets)
+                .collect::<Vec<_>>(),
+            "[[sources.download]]",
+        ),
+        (
+            sources.git.iter().map(|source| &source.targets).collect(),
+            "[[sources.git]]",
+        ),
+        (
+            sources
+                .command
+                .iter()
+                .map(|source| &source.targets)
+                .collect(),
+            "[[sources.command]]",
+        ),
+    ];
+    for (targets, source_name) in limited {
+        if let Some(target) = targets
+            .into_iter()
+            .flatten()
+            .find(|target| !config.targets.iter().any(|known| &known.name == *target))
+        {
+            return Err(RumkinstError::UnknownTarget {
+                target: target.clone(),
+                source_name,
+            });
+        }
+    }
+
+    if config.targets.is_empty() {
+        return Ok(());
+    }
+    let features = [
+        (opts.delta_base.is_some(), "a delta"),
+        (config.deb.is_some(), "the Debian package in [deb]"),
+        (
+            config.flatpak.is_some(),
+            "the Flatpak manifest in [flatpak]",
+        ),
+        (config.oci.is_some(), "the OCI image in [oci]"),
+        (config.nsis.is_some(), "the Windows installer in [nsis]"),
+    ];
+    match features.into_iter().find(|(needed, _)| *needed) {
+        Some((_, feature)) => Err(RumkinstError::TargetsUnsupported { feature }),
+        None => Ok(()),
+    }
+}
+
+/// Fail with the first part of the build that would need network access
+fn check_offline(config: &Config, opts: &BuildOptions) -> Result<(), RumkinstError> {
+    let publish = &config.publish;
+    let features = [
+        (
+            publish.oci.is_some(),
+            "pushing to the registry in [publish.oci]",
+        ),
+        (
+            publish.github.is_some(),
+            "publishing to the release in [publish.github]",
+        ),
+        (
+            publish.s3.is_some(),
+            "uploading to the bucket in [publish.s3]",
+        ),
+        (
+            publish.http.is_some(),
+            "uploading to the URL in [publish.http]",
+        ),
+        (
+            publish.gitlab.is_some(),
+            "publishing to the release in [publish.gitlab]",
+        ),
+        (
+            has_plugins(config, PluginStage::Publish),
+            "running the [[plugins]] of the publish stage",
+        ),
+    ];
+    if let Some((_, feature)) = features
+        .into_iter()
+        .find(|(needed, _)| opts.publish && *needed)
+    {
+        return Err(RumkinstError::Offline { feature });
+    }
+
+    let cache_dir = opts.cache_dir();
+    if config
+        .sources
+        .download
+        .iter()
+        .any(|download| opts.refresh || !cached_path(&cache_dir, download).exists())
+    {
+        return Err(RumkinstError::Offline {
+            feature: "downloading the sources in [[sources.download]]",
+        });
+    }
+    match config
+        .sources
+        .git
+        .iter()
+        .any(|source| !git_cached(&cache_dir, source))
+    {
+        true => Err(RumkinstError::Offline {
+            feature: "cloning the repositories in [[sources.git]]",
+        }),
+        false => Ok(()),
+    }
+}
+
+/// Passes every update on to `inner`, timing each phase from its start to its end
+struct TimedProgress<'a> {
+    inner: &'a dyn ProgressSink,
+    started: Mutex<Option<Instant>>,
+    timings: Mutex<Vec<PhaseTiming>>,
+}
+
+impl<'a> TimedProgress<'a> {
+    fn new(inner: &'a dyn ProgressSink) -> Self {
+        Self {
+            inner,
+            started: Mutex::new(None),
+            timings: Mutex::new(vec![]),
+        }
+    }
+
+    /// Add the timing of something other than a phase, such as a hook
+    fn record(&self, title: String, elapsed: Duration) {
+        self.timings
+            .lock()
+            .expect("phase timings mutex is poisoned")
+            .push(PhaseTiming { title, elapsed });
+    }
+
+    fn into_timings(self) -> Vec<PhaseTiming> {
+        self.timings
+            .into_inner()
+            .expect("phase timings mutex is poisoned")
+    }
+}
+
+impl ProgressSink for TimedProgress<'_> {
+    fn phase_started(&self, phase: Phase, length: u64) {
+        self.started
+            .lock()
+            .expect("phase start mutex is poisoned")
+            .replace(Instant::now());
+        self.inner.phase_started(phase, length);
+    }
+
+    fn phase_finished(&self, phase: Phase) {
+        self.inner.phase_finished(phase);
+        if let Some(started) = self
+            .started
+            .lock()
+            .expect("phase start mutex is poisoned")
+            .take()
+        {
+            self.record(phase.title().to_string(), started.elapsed());
+        }
+    }
+
+    fn phase_skipped(&self, phase: Phase) {
+        self.inner.phase_skipped(phase);
+    }
+
+    fn increment(&self, amount: u64) {
+        self.inner.increment(amount);
+    }
+
+    fn set_message(&self, message: &str) {
+        self.inner.set_message(message);
+    }
+}
+
+/// Run `logic` as `phase` of `length` steps, telling `progress` when it starts and stops
+fn run_phase<R>(
+    progress: &dyn ProgressSink,
+    phase: Phase,
+    length: u64,
+    logic: impl FnOnce() -> R,
+) -> R {
+    progress.phase_started(phase, length);
+    let result = logic();
+    progress.phase_finished(phase);
+    result
+}
+
+/// Write the changelog section for the package version into `out_dir` as release notes. Returns
+/// their path, `None` if there is no changelog, version or section for it.
+fn make_release_notes(
+    config: &Config,
+    out_dir: &Path,
+    diagnostics: &Diagnostics,
+) -> Result<Option<PathBuf>, RumkinstError> {
+    let Some(changelog) = config.changelog() else {
+        return Ok(None);
+    };
+    let Some(version) = config.version() else {
+        log::debug!("package.version is not set, skipping release notes");
+        return Ok(None);
+    };
+
+    let changelog = config.resolve(changelog);
+    let text = fs::read_to_string(&changelog).map_err(|source| RumkinstError::SourceRead {
+        path: changelog.clone(),
+        source,
+    })?;
+    let Some(notes) = version_section(&text, version) else {
+        diagnostics.warn(
+            ErrorCode::CHANGELOG_SECTION.at(&changelog),
+            format!("{changelog:?} has no section for version {version}, skipping release notes"),
+        );
+        return Ok(None);
+    };
+
+    let notes_path = out_dir.join(RELEASE_NOTES_FILE_NAME);
+    log::info!("Writing release notes \"{RELEASE_NOTES_FILE_NAME}\"");
+    fs::write(&notes_path, &notes).map_err(|source| RumkinstError::Package {
+        path: notes_path.clone(),
+        source,
+    })?;
+    Ok(Some(notes_path))
+}
+
+/// Publish the artifacts of `output` to every configured target, with `notes` as the body of new
+/// releases instead of the package description
+fn publish_artifacts(
+    config: &Config,
+    output: &mut BuildOutput,
+    notes: Option<&str>,
+    opts: &BuildOptions,
+) -> Result<(), RumkinstError> {
+    let notes = notes.unwrap_or(config.package.description.as_deref().unwrap_or_default());
+    let progress = opts.progress.as_ref();
+    let cancel = &opts.cancel;
+    let files = published_files(config, output);
+    if files.is_empty() {
+        opts.diagnostics.warn(
+            ErrorCode::NO_SOURCE_FILES,
+            "no artifacts were made, skipping publishing",
+        );
+        return Ok(());
+    }
+
+    log::info!("Verifying artifacts before publishing");
+    verify_before_publish(
+        config,
+        output,
+        opts.digester.as_ref(),
+        opts.signer.as_deref(),
+    )?;
+    cancel.check()?;
+
+    if config.publish.oci.is_some() {
+        output.registry_reference = Some(push_to_registry(
+            config,
+            &output.out_dir,
+            &files,
+            output.vcs.as_ref(),
+        )?);
+        progress.increment(1);
+        cancel.check()?;
+    }
+    if config.publish.github.is_some() {
+        let url = publish_github_release(
+            config,
+            &output.out_dir,
+            &files,
+            notes,
+            opts.confirm.as_ref(),
+        )?;
+        output.release_urls.push(url);
+        progress.increment(1);
+        cancel.check()?;
+    }
+    if config.publish.s3.is_some() {
+        let urls = upload_to_s3(config, &output.out_dir, &files, cancel)?;
+        output.uploaded_urls.extend(urls);
+        progress.increment(1);
+        cancel.check()?;
+    }
+    if config.publish.http.is_some() {
+        let urls = upload_over_http(config, &output.out_dir, &files, cancel)?;
+        output.uploaded_urls.extend(urls);
+        progress.increment(1);
+        cancel.check()?;
+    }
+    if config.publish.gitlab.is_some() {
+        let url = publish_gitlab_release(
+            config,
+            &output.out_dir,
+            &files,
+            notes,
+            opts.confirm.as_ref(),
+            cancel,
+        )?;
+        output.release_urls.push(url);
+        progress.increment(1);
+        cancel.check()?;
+    }
+    if has_plugins(config, PluginStage::Publish) {
+        run_plugins(
+            config,
+            PluginStage::Publish,
+            output,
+            opts.digester.as_ref(),
+            &opts.diagnostics,
+            opts.offline,
+        )?;
+        progress.increment(1);
+    }
+    Ok(())
+}
+
+/// Move everything written into `staging` into `out_dir`, then remove `staging`
+///
+/// Each file is renamed, so it appears complete or not at all. Every other file is moved before
+/// `artifacts`, so their checksums and signatures are already in place once they appear.
+fn finalize_artifacts<'a>(
+    staging: &Path,
+    out_dir: &Path,
+    artifacts: impl Iterator<Item = &'a ArchiveArtifact>,
+) -> Result<(), RumkinstError> {
+    let finalize_error = |path: &Path, source| RumkinstError::Finalize {
+        path: path.to_path_buf(),
+        source,
+    };
+    let artifacts = artifacts
+        .map(|artifact| artifact.path.clone())
+        .collect::<Vec<_>>();
+
+    let mut others = vec![];
+    let entries = fs::read_dir(staging).map_err(|err| finalize_error(staging, err))?;
+    for entry in entries {
+        let path = entry.map_err(|err| finalize_error(staging, err))?.path();
+        if !artifacts.contains(&path) {
+            others.push(path);
+        }
+    }
+    for path in others.iter().chain(&artifacts) {
+        let destination = finalized_path(path, staging, out_dir);
+        log::debug!("moving {path:?} to {destination:?}");
+        fs::rename(path, &destination).map_err(|err| finalize_error(path, err))?;
+    }
+    fs::remove_dir(staging).map_err(|err| finalize_error(staging, err))
+}
+
+/// Where `path` inside `staging` ends up once it was moved into `out_dir`
+fn finalized_path(path: &Path, staging: &Path, out_dir: &Path) -> PathBuf {
+    let relative = path
+        .strip_prefix(staging)
+        .expect("artifacts should be written inside the staging directory");
+    out_dir.join(relative)
+}
+
+fn make_checksum(
+    archive_path: &Path,
+    algorithm: &str,
+    digest: &[u8],
+) -> Result<PathBuf, RumkinstError> {
+    let archive_name = archive_path
+        .file_name()
+        .expect("archive path should always have a file name")
+        .to_string_lossy();
+    let checksum_path = archive_path.with_file_name(format!("{archive_name}.{algorithm}"));
+
+    fs::write(
+        &checksum_path,
+        format!("{}  {archive_name}", to_hex(digest)),
+    )
+    .map_err(|source| RumkinstError::Checksum {
+        path: checksum_path.clone(),
+        source,
+    })?;
+
+    Ok(checksum_path)
+}
+
+fn sign_artifact(
+    path: &Path,
+    digest: &[u8],
+    signer: &dyn Signer,
+) -> Result<PathBuf, RumkinstError> {
+    let signature = signer
+        .sign(path, digest)
+        .map_err(|source| RumkinstError::Signing {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+    let mut signature_path = path.as_os_str().to_os_string();
+    signature_path.push(format!(".{}", signer.extension()));
+    let signature_path = PathBuf::from(signature_path);
+
+    fs::write(&signature_path, signature).map_err(|source| RumkinstError::Signing {
+        path: path.to_path_buf(),
+        source: source.into(),
+    })?;
+
+    Ok(signature_path)
+}
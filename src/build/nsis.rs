@@ -0,0 +1,63 @@
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use super::{ArchiveArtifact, BuildOptions, make_checksum, sign_artifact};
+use crate::{
+    RumkinstError,
+    config::Config,
+    installer_gen::{RumkinstFiles, compile_nsis_script, nsis_installer_name, nsis_script, to_hex},
+    progress_log::NoProgress,
+};
+
+/// Write the NSIS script for `config`, and compile it into a Windows installer unless asked not to
+pub(crate) fn make_nsis_installer(
+    config: &Config,
+    out_dir: &Path,
+    all_files: &RumkinstFiles,
+    opts: &BuildOptions,
+) -> Result<(PathBuf, Option<ArchiveArtifact>), RumkinstError> {
+    let installer_name = nsis_installer_name(config);
+    let script_path = out_dir.join(format!("{}.nsi", config.get_name()));
+
+    log::info!("Writing NSIS script for \"{installer_name}\"");
+    let script = nsis_script(config, all_files, opts.progress.as_ref())?;
+    fs::write(&script_path, script).map_err(|source| RumkinstError::Package {
+        path: script_path.clone(),
+        source,
+    })?;
+
+    let compile = config.nsis.as_ref().is_some_and(|nsis| nsis.compile);
+    if !compile {
+        return Ok((script_path, None));
+    }
+
+    opts.cancel.check()?;
+    log::info!("Compiling Windows installer \"{installer_name}\"");
+    compile_nsis_script(&script_path)?;
+
+    let installer_path = out_dir.join(&installer_name);
+    let digest = File::open(&installer_path)
+        .and_then(|mut file| opts.digester.digest(&mut file, &NoProgress))
+        .map_err(|source| RumkinstError::Checksum {
+            path: installer_path.clone(),
+            source,
+        })?;
+    let checksum_path = make_checksum(&installer_path, opts.digester.algorithm(), &digest)?;
+    let signature_path = opts
+        .signer
+        .as_ref()
+        .map(|signer| sign_artifact(&installer_path, &digest, signer.as_ref()))
+        .transpose()?;
+
+    let installer = ArchiveArtifact {
+        path: installer_path,
+        checksum_path,
+        digest: to_hex(&digest),
+        algorithm: opts.digester.algorithm().to_string(),
+        signature_path,
+        listing_path: None,
+    };
+    Ok((script_path, Some(installer)))
+}
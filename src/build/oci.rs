@@ -0,0 +1,37 @@
+use std::path::{Path, PathBuf};
+
+use super::BuildOptions;
+use crate::{
+    RumkinstError,
+    config::Config,
+    installer_gen::{RumkinstFiles, image_reference, load_into_docker, write_oci_layout},
+};
+
+/// Write the OCI image layout for `config`, and load it into docker if asked to
+pub(crate) fn make_oci_image(
+    config: &Config,
+    out_dir: &Path,
+    all_files: &RumkinstFiles,
+    opts: &BuildOptions,
+) -> Result<PathBuf, RumkinstError> {
+    let layout_dir = out_dir.join("oci");
+    let reference = image_reference(config);
+
+    log::info!("Making OCI image \"{reference}\"");
+    write_oci_layout(
+        config,
+        all_files,
+        &layout_dir,
+        opts.file_policy(),
+        opts.progress.as_ref(),
+        &opts.cancel,
+    )?;
+
+    if config.oci.as_ref().is_some_and(|oci| oci.load) {
+        opts.cancel.check()?;
+        log::info!("Loading \"{reference}\" into docker");
+        load_into_docker(&layout_dir)?;
+    }
+
+    Ok(layout_dir)
+}
@@ -0,0 +1,172 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result, bail};
+use rumkinst::{
+    config::{Config, find_config_file_at},
+    error_log::{ErrorCode, WithCode},
+};
+use semver::{BuildMetadata, Prerelease, Version};
+use toml_edit::{DocumentMut, Item, Value};
+
+use crate::{
+    cli::{VersionArgs, VersionBump},
+    git::git,
+};
+
+/// Set `package.version` in rumkinst.toml as asked by `args`, and print the new version
+pub fn bump_version(args: VersionArgs) -> Result<()> {
+    let config_path =
+        find_config_file_at(args.path).context("could not find `rumkinst.toml` config file")?;
+    let base_dir = config_path
+        .parent()
+        .context("could not find the parent directory of rumkinst.toml")?;
+    let text = fs::read_to_string(&config_path)
+        .code_at(ErrorCode::CONFIG_UNREADABLE, &config_path)
+        .with_context(|| format!("failed to read {config_path:?}"))?;
+    let mut document = text
+        .parse::<DocumentMut>()
+        .code_at(ErrorCode::CONFIG_INVALID, &config_path)
+        .with_context(|| format!("failed to parse {config_path:?}"))?;
+
+    let package = document
+        .get_mut("package")
+        .and_then(Item::as_table_like_mut)
+        .ok_or(ErrorCode::CONFIG_INVALID.at(&config_path))
+        .context("rumkinst.toml has no `[package]` table")?;
+    let current = package
+        .get("version")
+        .and_then(Item::as_str)
+        .map(str::to_string);
+    let version = next_version(current.as_deref(), &args.bump)
+        .code_at(ErrorCode::VERSION_INVALID, &config_path)?;
+    let tag = format!("v{version}");
+    if args.tag {
+        check_tag_free(base_dir, &tag)?;
+    }
+
+    let mut new_version = Value::from(version.to_string());
+    match package.get_mut("version").and_then(Item::as_value_mut) {
+        Some(old_version) => {
+            // Keeps the spacing and any comment after the old version
+            *new_version.decor_mut() = old_version.decor().clone();
+            *old_version = new_version;
+        }
+        None => {
+            package.insert("version", Item::Value(new_version));
+        }
+    }
+
+    let text = document.to_string();
+    Config::read_named(text.as_bytes(), &config_path.to_string_lossy())
+        .with_context(|| format!("{config_path:?} would be invalid with the new version"))?;
+    fs::write(&config_path, text)
+        .code_at(ErrorCode::CONFIG_WRITE, &config_path)
+        .with_context(|| format!("failed to write {config_path:?}"))?;
+    match current {
+        Some(current) => log::info!("Changed the version from {current} to {version}"),
+        None => log::info!("Set the version to {version}"),
+    }
+
+    if args.tag {
+        let message = format!("Release {version}");
+        // `git` runs in the directory holding rumkinst.toml
+        let config_arg = config_path
+            .file_name()
+            .expect("the config path should always have a file name")
+            .to_string_lossy();
+        git(base_dir, &["add", "--", &config_arg])?;
+        git(
+            base_dir,
+            &["commit", "--only", "-m", &message, "--", &config_arg],
+        )?;
+        git(base_dir, &["tag", &tag])?;
+        log::info!("Committed the new version and tagged it \"{tag}\"");
+    }
+
+    println!("{version}");
+    Ok(())
+}
+
+/// The version `bump` asks for, given the `current` one
+fn next_version(current: Option<&str>, bump: &VersionBump) -> Result<Version> {
+    if let VersionBump::Exact(version) = bump {
+        return Ok(version.clone());
+    }
+    let Some(current) = current else {
+        bail!("`package.version` is not set, give the new version instead of the part to increase");
+    };
+    let mut version = Version::parse(current).with_context(|| {
+        format!("the current version {current:?} can't be increased, give the new version instead")
+    })?;
+
+    // A prerelease comes before its release, so bumping it to the part it is a prerelease of
+    // only drops the prerelease, as `1.2.3-beta` is followed by `1.2.3`
+    let prerelease = !version.pre.is_empty();
+    match bump {
+        VersionBump::Major if prerelease && version.minor == 0 && version.patch == 0 => {}
+        VersionBump::Major => {
+            version.major += 1;
+            version.minor = 0;
+            version.patch = 0;
+        }
+        VersionBump::Minor if prerelease && version.patch == 0 => {}
+        VersionBump::Minor => {
+            version.minor += 1;
+            version.patch = 0;
+        }
+        VersionBump::Patch if prerelease => {}
+        VersionBump::Patch => version.patch += 1,
+        VersionBump::Exact(_) => unreachable!("exact versions should be returned above"),
+    }
+    version.pre = Prerelease::EMPTY;
+    version.build = BuildMetadata::EMPTY;
+    Ok(version)
+}
+
+/// Fail before anything is changed if `tag` can't be created in the repository enclosing `dir`
+fn check_tag_free(dir: &Path, tag: &str) -> Result<()> {
+    git(dir, &["rev-parse", "--verify", "HEAD"])
+        .context("`--tag` needs the project to be in a git repository with a commit")?;
+    let existing = git(dir, &["tag", "--list", tag])?;
+    if !existing.trim().is_empty() {
+        return Err(ErrorCode::GIT_FAILED).context(format!("the tag \"{tag}\" already exists"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn next(current: &str, bump: VersionBump) -> String {
+        next_version(Some(current), &bump).unwrap().to_string()
+    }
+
+    #[test]
+    fn next_version_increases_the_part() {
+        assert_eq!(next("1.2.3", VersionBump::Major), "2.0.0");
+        assert_eq!(next("1.2.3", VersionBump::Minor), "1.3.0");
+        assert_eq!(next("1.2.3", VersionBump::Patch), "1.2.4");
+        assert_eq!(next("1.2.3+build.5", VersionBump::Patch), "1.2.4");
+    }
+
+    #[test]
+    fn next_version_releases_prereleases() {
+        assert_eq!(next("2.0.0-rc.1", VersionBump::Major), "2.0.0");
+        assert_eq!(next("1.2.0-beta", VersionBump::Major), "2.0.0");
+        assert_eq!(next("1.3.0-beta", VersionBump::Minor), "1.3.0");
+        assert_eq!(next("1.2.3-beta", VersionBump::Minor), "1.3.0");
+        assert_eq!(next("1.2.3-beta", VersionBump::Patch), "1.2.3");
+    }
+
+    #[test]
+    fn next_version_needs_a_valid_current_version() {
+        assert!(next_version(None, &VersionBump::Patch).is_err());
+        assert!(next_version(Some("1.2"), &VersionBump::Patch).is_err());
+        let exact = Version::new(3, 0, 0);
+        assert_eq!(
+            next_version(None, &VersionBump::Exact(exact.clone())).unwrap(),
+            exact
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//! The cache shared by every project, holding downloaded sources, git checkouts, the outputs of
+//! source commands and transformed files
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+/// A part of the cache, each in its own directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CacheKind {
+    /// Files of `[[sources.download]]`, by their sha256
+    Downloads,
+    /// Clones of the repositories of `[[sources.git]]`, and checkouts of their commits
+    Git,
+    /// Outputs of `[[sources.command]]`, by the command and its inputs
+    Commands,
+    /// Files after the slow transforms of `[[transforms]]`, such as commands and `strip`, by
+    /// their contents and transforms
+    Transforms,
+}
+
+impl CacheKind {
+    pub const ALL: [CacheKind; 4] = [
+        CacheKind::Downloads,
+        CacheKind::Git,
+        CacheKind::Commands,
+        CacheKind::Transforms,
+    ];
+
+    /// The directory of this part of the cache in `cache_dir`
+    pub fn dir(&self, cache_dir: &Path) -> PathBuf {
+        cache_dir.join(match self {
+            CacheKind::Downloads => "downloads",
+            CacheKind::Git => "git",
+            CacheKind::Commands => "command",
+            CacheKind::Transforms => "transform",
+        })
+    }
+}
+
+/// How much of the cache one [`CacheKind`] takes up
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct CacheUsage {
+    pub kind: CacheKind,
+    /// Cached files, repositories or outputs, the entries directly in its directory
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+/// Where the cache is when the build options name no other directory: under `XDG_CACHE_HOME`,
+/// `LOCALAPPDATA` or `~/.cache`, falling back to the temporary directory
+pub fn default_cache_dir() -> PathBuf {
+    let cache_home = env::var_os("XDG_CACHE_HOME")
+        .or_else(|| env::var_os("LOCALAPPDATA"))
+        .map(PathBuf::from)
+        .or_else(|| env::home_dir().map(|home| home.join(".cache")))
+        .filter(|dir| dir.is_absolute());
+    match cache_home {
+        Some(dir) => dir.join("rumkinst"),
+        None => env::temp_dir().join("rumkinst-cache"),
+    }
+}
+
+/// How much every part of the cache in `cache_dir` takes up, in the order of [`CacheKind::ALL`]
+pub fn cache_usage(cache_dir: &Path) -> io::Result<Vec<CacheUsage>> {
+    CacheKind::ALL
+        .iter()
+        .map(|kind| {
+            let dir = kind.dir(cache_dir);
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries.count(),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+                Err(err) => return Err(err),
+            };
+            Ok(CacheUsage {
+                kind: *kind,
+                entries,
+                bytes: size_of(&dir)?,
+            })
+        })
+        .collect()
+}
+
+/// Remove every entry of `kinds` from the cache in `cache_dir`, returning how many bytes that
+/// freed
+pub fn clear_cache(cache_dir: &Path, kinds: &[CacheKind]) -> io::Result<u64> {
+    let mut freed = 0;
+    for kind in kinds {
+        let dir = kind.dir(cache_dir);
+        let bytes = size_of(&dir)?;
+        match fs::remove_dir_all(&dir) {
+            Ok(()) => log::debug!("cleared {dir:?}"),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        freed += bytes;
+    }
+    Ok(freed)
+}
+
+/// Combined size of every file under `path`, without following symlinks
+fn size_of(path: &Path) -> io::Result<u64> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err),
+    };
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        size += size_of(&entry?.path())?;
+    }
+    Ok(size)
+}
@@ -0,0 +1,84 @@
+use anyhow::{Context, Result};
+use indicatif::HumanBytes;
+use rumkinst::cache::{CacheKind, cache_usage, clear_cache, default_cache_dir};
+
+use crate::cli::{CacheArgs, CacheClearArgs, CacheCommand, CachePart, CacheStatsArgs};
+
+/// Run the `cache` subcommand given by `args`
+pub fn command_cache(args: CacheArgs) -> Result<()> {
+    match args.command {
+        CacheCommand::Stats(args) => cache_stats(args).context("failed to read the cache"),
+        CacheCommand::Clear(args) => cache_clear(args).context("failed to clear the cache"),
+    }
+}
+
+/// Print how much each part of the cache takes up
+fn cache_stats(args: CacheStatsArgs) -> Result<()> {
+    let cache_dir = default_cache_dir();
+    let usage = cache_usage(&cache_dir).with_context(|| format!("failed to read {cache_dir:?}"))?;
+    if args.json {
+        let report = serde_json::json!({ "dir": cache_dir, "usage": usage });
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).expect("cache usage should always serialize")
+        );
+        return Ok(());
+    }
+
+    println!("Cache at {cache_dir:?}");
+    for part in &usage {
+        println!(
+            "  {:<12} {:>6} entries  {:>10}",
+            kind_name(part.kind),
+            part.entries,
+            HumanBytes(part.bytes).to_string()
+        );
+    }
+    let total = usage.iter().map(|part| part.bytes).sum();
+    println!(
+        "  {:<12} {:>6}          {:>10}",
+        "total",
+        "",
+        HumanBytes(total).to_string()
+    );
+    Ok(())
+}
+
+/// Remove the parts of the cache named by `args`, or all of it
+fn cache_clear(args: CacheClearArgs) -> Result<()> {
+    let cache_dir = default_cache_dir();
+    let kinds = match args.kind.is_empty() {
+        true => CacheKind::ALL.to_vec(),
+        false => args.kind.into_iter().map(cache_kind).collect(),
+    };
+    let freed = clear_cache(&cache_dir, &kinds)
+        .with_context(|| format!("failed to clear {cache_dir:?}"))?;
+    let names = kinds
+        .iter()
+        .map(|kind| kind_name(*kind))
+        .collect::<Vec<_>>();
+    println!(
+        "Cleared {} from the cache, freeing {}",
+        names.join(", "),
+        HumanBytes(freed)
+    );
+    Ok(())
+}
+
+fn cache_kind(part: CachePart) -> CacheKind {
+    match part {
+        CachePart::Downloads => CacheKind::Downloads,
+        CachePart::Git => CacheKind::Git,
+        CachePart::Commands => CacheKind::Commands,
+        CachePart::Transforms => CacheKind::Transforms,
+    }
+}
+
+fn kind_name(kind: CacheKind) -> &'static str {
+    match kind {
+        CacheKind::Downloads => "downloads",
+        CacheKind::Git => "git",
+        CacheKind::Commands => "commands",
+        CacheKind::Transforms => "transforms",
+    }
+}
@@ -0,0 +1,64 @@
+use std::{
+    io::{self, Read},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use crate::RumkinstError;
+
+/// A flag shared between a build and whoever may want to stop it early
+///
+/// Every clone refers to the same flag. A cancelled build stops at the next file or chunk it
+/// handles, and returns [`RumkinstError::Cancelled`].
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Ask every build using this handle to stop
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Fail with [`RumkinstError::Cancelled`] once cancelled
+    pub fn check(&self) -> Result<(), RumkinstError> {
+        if self.is_cancelled() {
+            Err(RumkinstError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Wrap `reader` so reads fail once cancelled
+    pub(crate) fn reader<R: Read>(&self, reader: R) -> CancellableReader<'_, R> {
+        CancellableReader {
+            inner: reader,
+            cancel: self,
+        }
+    }
+}
+
+pub(crate) struct CancellableReader<'a, R> {
+    inner: R,
+    cancel: &'a CancelHandle,
+}
+
+impl<R: Read> Read for CancellableReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.cancel.is_cancelled() {
+            return Err(io::Error::other(RumkinstError::Cancelled));
+        }
+        self.inner.read(buf)
+    }
+}
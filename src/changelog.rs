@@ -0,0 +1,131 @@
+/// The section of the markdown `changelog` for `version`, without its heading, `None` if there is
+/// none or it is empty
+///
+/// A section starts at a heading naming the version, such as `## [1.2.0] - 2026-01-14` or
+/// `# v1.2.0`, and runs until the next heading of the same or a higher level. Lines in fenced
+/// code blocks are never taken as headings.
+pub(crate) fn version_section(changelog: &str, version: &str) -> Option<String> {
+    let mut lines = Lines::new(changelog);
+    let level = loop {
+        let (line, fenced) = lines.next()?;
+        if !fenced
+            && let Some((level, title)) = heading(line)
+            && names_version(title, version)
+        {
+            break level;
+        }
+    };
+
+    let mut section = vec![];
+    for (line, fenced) in lines {
+        if !fenced && heading(line).is_some_and(|(next, _)| next <= level) {
+            break;
+        }
+        section.push(line);
+    }
+    let section = section.join("\n");
+    let section = section.trim();
+    (!section.is_empty()).then(|| format!("{section}\n"))
+}
+
+/// The level and title of the ATX heading on `line`, such as `## Title`
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let line = line.trim_start();
+    let level = line.bytes().take_while(|byte| *byte == b'#').count();
+    let title = line[level..].strip_prefix([' ', '\t'])?;
+    (1..=6).contains(&level).then_some((level, title.trim()))
+}
+
+/// Whether a word of `title` is `version`, optionally prefixed with `v` or in brackets
+fn names_version(title: &str, version: &str) -> bool {
+    title
+        .split(|char: char| char.is_whitespace() || "[]()".contains(char))
+        .any(|word| word == version || word.strip_prefix('v') == Some(version))
+}
+
+/// Lines of a markdown document, along with whether each is inside a fenced code block
+struct Lines<'a> {
+    lines: std::str::Lines<'a>,
+    fence: Option<&'static str>,
+}
+
+impl<'a> Lines<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            lines: text.lines(),
+            fence: None,
+        }
+    }
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = (&'a str, bool);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = self.lines.next()?;
+        let trimmed = line.trim_start();
+        let fenced = match self.fence {
+            Some(fence) => {
+                if trimmed.starts_with(fence) {
+                    self.fence = None;
+                }
+                true
+            }
+            None => {
+                self.fence = ["```", "~~~"]
+                    .into_iter()
+                    .find(|fence| trimmed.starts_with(fence));
+                self.fence.is_some()
+            }
+        };
+        Some((line, fenced))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHANGELOG: &str = "\
+# Changelog
+
+## [Unreleased]
+
+- Nothing yet
+
+## [1.2.0] - 2026-01-14
+
+### Added
+
+- Deltas
+
+```md
+## 1.1.0
+```
+
+## v1.1.0
+
+- Fixes
+
+## 1.0.0
+";
+
+    #[test]
+    fn version_section_runs_until_the_next_heading_of_its_level() {
+        assert_eq!(
+            version_section(CHANGELOG, "1.2.0").as_deref(),
+            Some("### Added\n\n- Deltas\n\n```md\n## 1.1.0\n```\n")
+        );
+        assert_eq!(
+            version_section(CHANGELOG, "1.1.0").as_deref(),
+            Some("- Fixes\n")
+        );
+    }
+
+    #[test]
+    fn version_section_is_none_when_missing_or_empty() {
+        assert_eq!(version_section(CHANGELOG, "1.0.0"), None);
+        assert_eq!(version_section(CHANGELOG, "2.0.0"), None);
+        assert_eq!(version_section(CHANGELOG, "1.2"), None);
+    }
+}
@@ -1,6 +1,6 @@
-use std::path::PathBuf;
+use std::{ffi::OsString, num::NonZeroUsize, path::PathBuf, str::FromStr};
 
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
 use rumkinst::config::identifier::Identifier;
 
 #[derive(Debug, Parser)]
@@ -10,14 +10,59 @@ pub struct Rumkinst {
     #[arg(global = true, value_enum, long, default_value = "auto")]
     pub color: ColorDisplay,
 
-    /// What log level to use. Can also be set using environment variables
-    #[arg(global = true, value_enum, long, default_value = "info")]
-    pub log_level: LogLevel,
+    /// What log level to use, `info` by default. Can also be set using environment variables.
+    /// Takes precedence over `-v` and `-q`
+    #[arg(global = true, value_enum, long)]
+    pub log_level: Option<LogLevel>,
+
+    /// Log more, `-vv` to log everything
+    #[arg(global = true, short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Log less, `-qq` to only log errors
+    #[arg(global = true, short, long, action = ArgAction::Count)]
+    pub quiet: u8,
+
+    /// How warnings, errors and every other log record are written to the terminal
+    #[arg(global = true, value_enum, long, default_value = "human")]
+    pub format: OutputFormat,
+
+    /// Where warnings and errors are logged to, besides the terminal
+    #[arg(global = true, value_enum, long, default_value = "terminal")]
+    pub log_target: LogTarget,
+
+    /// Fail straight away if anything would need network access, such as publishing or cloning
+    /// a remote template
+    #[arg(global = true, long)]
+    pub offline: bool,
+
+    /// Also copy every debug log record into this file, kept across runs and rotated once it
+    /// grows large. Overrides `[log] file` in rumkinst.toml
+    #[arg(global = true, long, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
 
     #[command(subcommand)]
     pub subcommand: Command,
 }
 
+impl Rumkinst {
+    /// The `--log-level` given, otherwise `info` moved one level per `-v` or `-q`
+    pub fn log_level(&self) -> LogLevel {
+        if let Some(level) = &self.log_level {
+            return level.clone();
+        }
+        const LEVELS: [LogLevel; 5] = [
+            LogLevel::Error,
+            LogLevel::Warn,
+            LogLevel::Info,
+            LogLevel::Debug,
+            LogLevel::Trace,
+        ];
+        let index = (2 + i16::from(self.verbose) - i16::from(self.quiet)).clamp(0, 4);
+        LEVELS[index as usize].clone()
+    }
+}
+
 #[derive(Debug, Clone, ValueEnum)]
 pub enum ColorDisplay {
     Auto,
@@ -34,14 +79,26 @@ pub enum LogLevel {
     Error,
 }
 
+#[derive(Debug, Clone, ValueEnum)]
+pub enum LogTarget {
+    /// Only the terminal
+    Terminal,
+    /// The local syslog daemon
+    Syslog,
+    /// The systemd journal
+    Journald,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum OutputFormat {
+    Human,
+    Json,
+}
+
 #[derive(Debug, Subcommand)]
 pub enum Command {
     /// Make all installer artifacts
-    Make {
-        /// Path to rumkinst.toml
-        #[arg(short, long)]
-        path: Option<PathBuf>,
-    },
+    Make(MakeArgs),
     /// Create a new rumkinst directory, with some defaults
     New {
         /// Name of the package
@@ -50,5 +107,358 @@ pub enum Command {
         /// Name of rumkinst
         #[arg(long, default_value = "rumkinst")]
         dir_name: Identifier,
+
+        /// Clone the project from a template git repository instead, filling in `{{name}}` and
+        /// `{{authors}}` in its files
+        #[arg(long, value_name = "URL")]
+        template_git: Option<String>,
     },
+    /// Change `package.version` in rumkinst.toml, keeping its comments and layout, and print the
+    /// new version
+    Version(VersionArgs),
+    /// Show what takes up space in an archive, to find out why it is bigger than expected
+    Stats(StatsArgs),
+    /// Inspect the configuration in rumkinst.toml
+    Config(ConfigArgs),
+    /// Show the runs recorded in `out/history.json`, to spot growing artifacts and slower builds
+    History(HistoryArgs),
+    /// Install an archive into a throwaway prefix, running the installer scripts with `sh`, check
+    /// the installed files against its listing, then uninstall it again
+    Test(TestArgs),
+    /// Check rumkinst.toml and the source directories for likely mistakes, with the rules set in
+    /// `[lint]`
+    Lint(LintArgs),
+    /// Record the files of an archive in a snapshot meant to be committed, or check an archive
+    /// against it, so changes to what is packaged show up in review
+    Snapshot(SnapshotArgs),
+    /// Show or clear the cache of downloaded sources, git checkouts, source command outputs and
+    /// transformed files shared by every project
+    Cache(CacheArgs),
+    /// Any other subcommand runs the plugin `rumkinst-<name>` from `PATH`, with the arguments
+    /// after it
+    #[command(external_subcommand)]
+    Plugin(Vec<OsString>),
+}
+
+#[derive(Debug, Args)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub command: ConfigCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Print every setting with its defaults filled in, noting whether each was set in
+    /// rumkinst.toml or is a default
+    Show(ConfigShowArgs),
+    /// Print the value of a setting, or its default when it is not set
+    Get(ConfigGetArgs),
+    /// Change a setting in rumkinst.toml, keeping its comments and layout
+    Set(ConfigSetArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct ConfigShowArgs {
+    /// Path to rumkinst.toml
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ConfigGetArgs {
+    /// Dotted key of the setting, such as `package.version` or `output.run-id.scheme`
+    pub key: String,
+
+    /// Path to rumkinst.toml
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct ConfigSetArgs {
+    /// Dotted key of the setting, such as `package.version` or `output.run-id.scheme`
+    pub key: String,
+
+    /// New value, read as a toml value such as `true`, `4` or `["a", "b"]`. Anything that is not
+    /// valid toml is taken as a string, quote it to always get a string
+    pub value: String,
+
+    /// Path to rumkinst.toml
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct CacheArgs {
+    #[command(subcommand)]
+    pub command: CacheCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CacheCommand {
+    /// Show how many entries each part of the cache holds and how much space it takes up
+    Stats(CacheStatsArgs),
+    /// Remove cached entries, so they are downloaded, checked out or generated again by the next
+    /// build
+    Clear(CacheClearArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct CacheStatsArgs {
+    /// Print the usage as JSON instead
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct CacheClearArgs {
+    /// Only clear this part of the cache, can be given more than once. Defaults to all of it
+    #[arg(value_enum, long)]
+    pub kind: Vec<CachePart>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CachePart {
+    /// Files of `[[sources.download]]`
+    Downloads,
+    /// Repositories and checkouts of `[[sources.git]]`
+    Git,
+    /// Outputs of `[[sources.command]]`
+    Commands,
+    /// Files after commands and `strip` in `[[transforms]]`
+    Transforms,
+}
+
+#[derive(Debug, Args)]
+pub struct StatsArgs {
+    /// Archive, or run directory holding one. Defaults to the latest run of the project
+    pub archive: Option<PathBuf>,
+
+    /// Path to rumkinst.toml, whose latest run is used when no archive is given
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+
+    /// How many of the largest files and groups of duplicate files to list
+    #[arg(long, default_value_t = 10)]
+    pub top: usize,
+
+    /// How many leading path components directories are grouped by
+    #[arg(long, default_value_t = 2)]
+    pub depth: usize,
+
+    /// Print the report as JSON instead
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct TestArgs {
+    /// Archive, or run directory holding one. Defaults to the latest run of the project
+    pub archive: Option<PathBuf>,
+
+    /// Path to rumkinst.toml
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+
+    /// Keep the throwaway prefix and extracted archive instead of removing them, to look around
+    /// in them
+    #[arg(long)]
+    pub keep: bool,
+
+    /// Run the installer scripts in a fresh container of IMAGE instead, once for each time it is
+    /// given
+    #[arg(long, value_name = "IMAGE")]
+    pub in_container: Vec<String>,
+
+    /// What runs the containers of `--in-container`
+    #[arg(value_enum, long, default_value = "docker")]
+    pub engine: ContainerEngine,
+
+    /// Print the report as JSON instead, a list of reports with `--in-container`
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ContainerEngine {
+    Docker,
+    Podman,
+}
+
+#[derive(Debug, Args)]
+pub struct SnapshotArgs {
+    /// Archive, or run directory holding one. Defaults to the latest run of the project
+    pub archive: Option<PathBuf>,
+
+    /// Path to rumkinst.toml
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+
+    /// Snapshot file, `rumkinst.snap` next to rumkinst.toml by default
+    #[arg(long)]
+    pub file: Option<PathBuf>,
+
+    /// Fail if the archive differs from the snapshot, instead of recording it
+    #[arg(long)]
+    pub check: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct LintArgs {
+    /// Path to rumkinst.toml
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct HistoryArgs {
+    /// Path to rumkinst.toml
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+
+    /// How many of the latest runs to show
+    #[arg(long, default_value_t = 20)]
+    pub limit: usize,
+
+    /// Compare the files packaged by the successful runs among them instead, and suggest
+    /// excluding the ones that look generated, or are large and change between runs
+    #[arg(long)]
+    pub suggest_excludes: bool,
+
+    /// Size in bytes from which files changing between runs are suggested with
+    /// `--suggest-excludes`
+    #[arg(long, default_value_t = 1024 * 1024, value_name = "BYTES")]
+    pub min_size: u64,
+
+    /// Print the runs, or suggested excludes, as JSON instead
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct VersionArgs {
+    /// `major`, `minor` or `patch` to increase that part of the current version, or the new
+    /// version itself
+    pub bump: VersionBump,
+
+    /// Path to rumkinst.toml
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+
+    /// Commit the change to rumkinst.toml and tag the commit `v` followed by the new version
+    #[arg(long)]
+    pub tag: bool,
+}
+
+/// A new package version, or which part of the current one to increase
+#[derive(Debug, Clone)]
+pub enum VersionBump {
+    Major,
+    Minor,
+    Patch,
+    Exact(semver::Version),
+}
+
+impl FromStr for VersionBump {
+    type Err = semver::Error;
+
+    fn from_str(bump: &str) -> Result<Self, Self::Err> {
+        match bump {
+            "major" => Ok(Self::Major),
+            "minor" => Ok(Self::Minor),
+            "patch" => Ok(Self::Patch),
+            version => version.parse().map(Self::Exact),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct MakeArgs {
+    /// Path to rumkinst.toml
+    #[arg(short, long)]
+    pub path: Option<PathBuf>,
+
+    /// Skip files that can't be read instead of aborting, and report them at the end
+    #[arg(short, long)]
+    pub keep_going: bool,
+
+    /// Fail the run if any warnings were reported. Can also be set in rumkinst.toml
+    #[arg(long)]
+    pub deny_warnings: bool,
+
+    /// Keep the output directory of a failed run instead of removing it, for debugging
+    #[arg(long)]
+    pub keep_partial: bool,
+
+    /// Go ahead without asking when a run would overwrite an earlier run, remove old runs or
+    /// replace assets already on a release
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// Build the artifacts without publishing them to the targets under `[publish]`
+    #[arg(long)]
+    pub no_publish: bool,
+
+    /// Don't look for an enclosing git repository to record in the run metadata and hooks
+    #[arg(long)]
+    pub no_vcs: bool,
+
+    /// Also make a delta holding the files that changed since an earlier build, given as its
+    /// archive or the output directory of its run. Changed files are included whole, and
+    /// applying the delta is left to your updater
+    #[arg(long, value_name = "PATH")]
+    pub delta_from: Option<PathBuf>,
+
+    /// Allow sources outside the directory holding rumkinst.toml, and symlinks in sources that
+    /// point outside of them
+    #[arg(long)]
+    pub allow_outside_sources: bool,
+
+    /// Allow sources at absolute paths, as if each one set `allow-absolute = true`
+    #[arg(long)]
+    pub allow_absolute: bool,
+
+    /// Wait for another run making artifacts in the same project to finish, instead of failing
+    #[arg(long)]
+    pub wait: bool,
+
+    /// Most threads used to make artifacts. Can also be set in rumkinst.toml, defaults to the
+    /// available parallelism
+    #[arg(short, long, value_name = "N")]
+    pub jobs: Option<NonZeroUsize>,
+
+    /// Archive the sources a second time and fail if the archives differ, to catch
+    /// nondeterminism such as timestamps or ordering
+    #[arg(long)]
+    pub verify_reproducible: bool,
+
+    /// Download every `[[sources.download]]` again, even when it is in the download cache
+    #[arg(long)]
+    pub refresh: bool,
+
+    /// Fail before making any artifact if the files to package, or the downloads and commits
+    /// they come from, differ from `rumkinst.lock`, instead of recording them in it
+    #[arg(long)]
+    pub locked: bool,
+
+    /// Print how long each phase of the build took once it finishes
+    #[arg(long)]
+    pub timings: bool,
+
+    /// Write only the archive, to stdout, to pipe it into another program without a run
+    /// directory. Its checksum is logged once it is written
+    #[arg(long, conflicts_with_all = ["delta_from", "verify_reproducible"])]
+    pub stdout: bool,
+
+    /// Also write the checksum of the archive written by `--stdout` to PATH, in the format of
+    /// `sha256sum`
+    #[arg(long, value_name = "PATH", requires = "stdout")]
+    pub checksum_file: Option<PathBuf>,
+
+    /// Send a desktop notification once the build finishes or fails, or run COMMAND instead
+    ///
+    /// COMMAND runs through the shell with `RUMKINST_NOTIFY_TITLE`, `RUMKINST_NOTIFY_BODY`,
+    /// `RUMKINST_NOTIFY_STATUS`, `RUMKINST_NOTIFY_ELAPSED_SECONDS` and `RUMKINST_OUT_DIR` set.
+    #[arg(long, value_name = "COMMAND", num_args = 0..=1, require_equals = true)]
+    pub notify: Option<Option<String>>,
 }
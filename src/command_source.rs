@@ -0,0 +1,158 @@
+use std::{
+    fs::{self, File},
+    io,
+    path::{self, Path},
+    process::Command,
+};
+
+use crate::{
+    CancelHandle, RumkinstError,
+    cache::CacheKind,
+    config::{CommandSource, Config},
+    download::FetchedFile,
+    installer_gen::{Digester, Sha256Digester, to_hex},
+    progress_log::NoProgress,
+};
+
+/// Run the command of every `[[sources.command]]` of `config` whose inputs changed, keeping
+/// their outputs in `cache_dir`
+///
+/// The command runs from the base directory, with an empty directory to write its outputs into
+/// in `RUMKINST_SOURCE_OUT`. Outputs are cached by the command, the outputs and the contents of
+/// every input, and commands without inputs run in every build.
+pub(crate) fn run_command_sources(
+    config: &Config,
+    cache_dir: &Path,
+    cancel: &CancelHandle,
+) -> Result<Vec<FetchedFile>, RumkinstError> {
+    let mut fetched = vec![];
+    for source in &config.sources.command {
+        cancel.check()?;
+        let command_error =
+            |err: Box<dyn std::error::Error + Send + Sync>| RumkinstError::SourceCommand {
+                command: source.run.join(" "),
+                source: err,
+            };
+
+        let key = cache_key(config, source).map_err(|err| command_error(err.into()))?;
+        let dir = CacheKind::Commands.dir(cache_dir).join(&key);
+        if source.inputs.is_some() && dir.exists() {
+            log::debug!(
+                "inputs of `{}` are unchanged, using {dir:?}",
+                source.run.join(" ")
+            );
+        } else {
+            let partial = dir.with_file_name(format!(".{key}.{}.part", std::process::id()));
+            let result = run_into(config, source, &partial).and_then(|()| {
+                if dir.exists() {
+                    fs::remove_dir_all(&dir)?;
+                }
+                fs::rename(&partial, &dir)?;
+                Ok(())
+            });
+            if result.is_err() {
+                fs::remove_dir_all(&partial).ok();
+            }
+            result.map_err(|err| command_error(err.into()))?;
+        }
+
+        fetched.extend(source.outputs.iter().map(|output| FetchedFile {
+            dest: source.dest.join(output),
+            path: dir.join(output),
+            targets: source.targets.clone(),
+            commit: None,
+        }));
+    }
+    Ok(fetched)
+}
+
+/// Run the command of `source` with `out` as its output directory, checking it wrote every output
+fn run_into(config: &Config, source: &CommandSource, out: &Path) -> io::Result<()> {
+    let Some((program, args)) = source.run.split_first() else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "`run` names no program",
+        ));
+    };
+    fs::create_dir_all(out)?;
+
+    log::info!("Running source command `{}`", source.run.join(" "));
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .env("RUMKINST_SOURCE_OUT", path::absolute(out)?)
+        // Kept off stdout, which may be the archive itself with `make --stdout`
+        .stdout(io::stderr());
+    if !config.base_dir().as_os_str().is_empty() {
+        command.current_dir(config.base_dir());
+    }
+    let status = command.status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("`{program}` {status}")));
+    }
+
+    match source
+        .outputs
+        .iter()
+        .find(|output| !out.join(output).exists())
+    {
+        Some(missing) => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("it did not write the output {missing:?}"),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Name of the directory the outputs of `source` are cached in
+///
+/// Without inputs, the name only depends on the command, so each run replaces the outputs of
+/// the one before.
+fn cache_key(config: &Config, source: &CommandSource) -> io::Result<String> {
+    let mut key = String::new();
+    for part in [&source.run, &source.outputs.iter().map(path_str).collect()] {
+        key.push_str(&part.join("\0"));
+        key.push('\n');
+    }
+    let prefix = match &source.inputs {
+        Some(inputs) => {
+            for input in inputs {
+                hash_input(&config.resolve(input), input, &mut key)?;
+            }
+            "inputs"
+        }
+        None => {
+            key.push_str(&path_str(&path::absolute(config.base_dir())?));
+            "latest"
+        }
+    };
+    let digest = Sha256Digester.digest(&mut key.as_bytes(), &NoProgress)?;
+    Ok(format!("{prefix}-{}", &to_hex(&digest)[..32]))
+}
+
+/// Add a line with the name and digest of every file at `resolved` to `key`, in a stable order
+fn hash_input(resolved: &Path, name: &Path, key: &mut String) -> io::Result<()> {
+    let metadata = fs::metadata(resolved).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!("failed to read input {resolved:?}: {err}"),
+        )
+    })?;
+    if metadata.is_dir() {
+        let mut entries = fs::read_dir(resolved)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort();
+        for entry in entries {
+            hash_input(&resolved.join(&entry), &name.join(&entry), key)?;
+        }
+    } else {
+        let digest = Sha256Digester.digest(&mut File::open(resolved)?, &NoProgress)?;
+        key.push_str(&format!("{}\t{}\n", path_str(name), to_hex(&digest)));
+    }
+    Ok(())
+}
+
+fn path_str(path: impl AsRef<Path>) -> String {
+    path.as_ref().to_string_lossy().into_owned()
+}
@@ -1,43 +1,81 @@
+mod app_id;
+mod builder;
+mod byte_size;
+mod file_mode;
+mod glob_pattern;
 pub mod identifier;
 mod relativepathbuf;
+mod secret;
+mod sha256;
+mod source_path;
+mod suggest;
 
 use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    env, fs,
     io::{Read, Write},
+    num::NonZeroUsize,
+    ops::Range,
     path::{Path, PathBuf},
 };
 
-use anyhow::{Context, Result};
+use app_id::AppId;
+use byte_size::ByteSize;
+use file_mode::FileMode;
+use glob_pattern::GlobPattern;
 use identifier::Identifier;
-use log::{debug, trace};
+use log::{debug, info, trace};
 use relativepathbuf::RelativePathBuf;
 use serde::{Deserialize, Serialize};
+use sha256::Sha256Hex;
+use source_path::SourcePath;
+
+use crate::{
+    RumkinstError,
+    error_log::{ErrorCode, SourceSnippet},
+    installer_gen::DEFAULT_WASM_FUEL,
+    progress_log::{DEFAULT_LOG_FILE_KEEP, DEFAULT_LOG_FILE_SIZE},
+};
+
+pub use builder::{ConfigBuilder, SourceKind};
+pub(crate) use secret::SecretSource;
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 struct InternalPackageDetails {
     name: Identifier,
+    version: Option<String>,
     description: Option<String>,
     authors: Option<Vec<String>>,
+    changelog: Option<RelativePathBuf>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub(crate) struct PackageDetails {
     pub(crate) name: String,
+    pub(crate) version: Option<String>,
     pub(crate) description: Option<String>,
     pub(crate) authors: Vec<String>,
+    /// Markdown changelog, archived with the sources and used for release notes
+    pub(crate) changelog: Option<PathBuf>,
 }
 
 impl PackageDetails {
     fn init(source: InternalPackageDetails) -> Self {
         Self {
             name: source.name.into_string(),
+            version: source.version,
             description: source.description,
             authors: source.authors.unwrap_or_default(),
+            changelog: source.changelog.map(RelativePathBuf::into_pathbuf),
         }
     }
 }
 
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
-pub(crate) enum ThemeType {
+pub enum ThemeType {
     #[default]
     #[serde(rename = "plain")]
     Plain,
@@ -48,6 +86,7 @@ pub(crate) enum ThemeType {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 struct InternalInstallerConfig {
     #[serde(rename = "allow-user-install")]
     allow_user_install: Option<bool>,
@@ -57,7 +96,8 @@ struct InternalInstallerConfig {
     postinstall: Option<RelativePathBuf>,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub(crate) struct InstallerConfig {
     pub(crate) allow_user_install: bool,
     pub(crate) theme: ThemeType,
@@ -79,16 +119,124 @@ impl InstallerConfig {
     }
 }
 
+/// The format the packaged files are archived in
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    #[default]
+    #[serde(rename = "tar.gz")]
+    TarGz,
+    #[serde(rename = "zip")]
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// File extension of archives in this format, without a leading `.`
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            ArchiveFormat::TarGz => "application/gzip",
+            ArchiveFormat::Zip => "application/zip",
+        }
+    }
+}
+
+/// What to do with source files whose names aren't valid UTF-8, or contain newlines or other
+/// control characters
+///
+/// Tarballs store such names exactly, zip files can only store UTF-8 names.
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UnusualNames {
+    /// Package them like any other file
+    #[default]
+    #[serde(rename = "allow")]
+    Allow,
+    /// Package them, and report a warning for each
+    #[serde(rename = "warn")]
+    Warn,
+    /// Fail the run, or skip them when keeping going
+    #[serde(rename = "reject")]
+    Reject,
+}
+
+/// What to do with packaged paths that only differ by case, such as `README` and `ReadMe`, which
+/// overwrite each other when extracted on the default filesystems of macOS and Windows
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CaseCollisions {
+    /// Package them all
+    #[serde(rename = "allow")]
+    Allow,
+    /// Package them all, and report a warning for each collision
+    #[default]
+    #[serde(rename = "warn")]
+    Warn,
+    /// Fail the run, or skip all but the first when keeping going
+    #[serde(rename = "reject")]
+    Reject,
+}
+
+/// What to do with different files archived under the same entry path, such as one found by
+/// two overlapping sources, or files moved onto each other by `strip-prefix`
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateEntries {
+    /// Fail the run, or keep the first file when keeping going
+    #[default]
+    #[serde(rename = "reject")]
+    Reject,
+    /// Keep the file from the source archived first, in the order `root`, `env`, `scripts`
+    #[serde(rename = "first")]
+    First,
+    /// Keep the file from the source archived last
+    #[serde(rename = "last")]
+    Last,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 struct InternalBuildConfig {
     prebuild: Option<RelativePathBuf>,
     postbuild: Option<RelativePathBuf>,
+    #[serde(rename = "deny-warnings")]
+    deny_warnings: Option<bool>,
+    #[serde(rename = "archive-format")]
+    archive_format: Option<ArchiveFormat>,
+    #[serde(rename = "unusual-names")]
+    unusual_names: Option<UnusualNames>,
+    #[serde(rename = "case-collisions")]
+    case_collisions: Option<CaseCollisions>,
+    #[serde(rename = "duplicate-entries")]
+    duplicate_entries: Option<DuplicateEntries>,
+    #[serde(rename = "windows-names")]
+    windows_names: Option<bool>,
+    jobs: Option<NonZeroUsize>,
+    xattrs: Option<bool>,
+    acls: Option<bool>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub(crate) struct BuildConfig {
     pub(crate) prebuild: PathBuf,
     pub(crate) postbuild: PathBuf,
+    pub(crate) deny_warnings: bool,
+    pub(crate) archive_format: ArchiveFormat,
+    pub(crate) unusual_names: UnusualNames,
+    pub(crate) case_collisions: CaseCollisions,
+    pub(crate) duplicate_entries: DuplicateEntries,
+    /// Whether to warn about paths that can't be extracted on Windows, `None` to only warn when
+    /// a Windows installer or a `windows-*` target is made
+    pub(crate) windows_names: Option<bool>,
+    /// Most threads used to make artifacts, `None` for the available parallelism
+    pub(crate) jobs: Option<NonZeroUsize>,
+    /// Whether extended attributes of source files are stored in tarballs
+    pub(crate) xattrs: bool,
+    /// Whether POSIX ACLs of source files are stored in tarballs
+    pub(crate) acls: bool,
 }
 
 impl BuildConfig {
@@ -103,26 +251,867 @@ impl BuildConfig {
                     .postbuild
                     .map(RelativePathBuf::into_pathbuf)
                     .unwrap_or(PathBuf::from("./postbuild.sh")),
+                deny_warnings: source.deny_warnings.unwrap_or(false),
+                archive_format: source.archive_format.unwrap_or_default(),
+                unusual_names: source.unusual_names.unwrap_or_default(),
+                case_collisions: source.case_collisions.unwrap_or_default(),
+                duplicate_entries: source.duplicate_entries.unwrap_or_default(),
+                windows_names: source.windows_names,
+                jobs: source.jobs,
+                xattrs: source.xattrs.unwrap_or(false),
+                acls: source.acls.unwrap_or(false),
             })
             .unwrap_or(Self {
                 prebuild: PathBuf::from("./prebuild.sh"),
                 postbuild: PathBuf::from("./postbuild.sh"),
+                deny_warnings: false,
+                archive_format: ArchiveFormat::default(),
+                unusual_names: UnusualNames::default(),
+                case_collisions: CaseCollisions::default(),
+                duplicate_entries: DuplicateEntries::default(),
+                windows_names: None,
+                jobs: None,
+                xattrs: false,
+                acls: false,
+            })
+    }
+}
+
+/// How the directory of each run under `out/` is named
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RunIdScheme {
+    /// A random id, such as `V1StGXR8_Z5jdHi6B-myT`
+    #[default]
+    #[serde(rename = "nanoid")]
+    Nanoid,
+    /// The UTC time the run started, such as `20260114T093012Z`
+    #[serde(rename = "timestamp")]
+    Timestamp,
+    /// `package.version`
+    #[serde(rename = "version")]
+    Version,
+    /// The short hash of the checked out git commit, with `-dirty` added when tracked files
+    /// have uncommitted changes
+    #[serde(rename = "git")]
+    Git,
+}
+
+/// What to do when the directory for a new run already exists
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RunIdCollision {
+    /// Add `-2`, `-3` and so on to the id until it is unused
+    #[default]
+    #[serde(rename = "suffix")]
+    Suffix,
+    /// Remove the existing run directory
+    #[serde(rename = "overwrite")]
+    Overwrite,
+    /// Fail the run
+    #[serde(rename = "error")]
+    Error,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalOutputConfig {
+    #[serde(rename = "keep-runs")]
+    keep_runs: Option<usize>,
+    #[serde(rename = "max-size")]
+    max_size: Option<ByteSize>,
+    #[serde(rename = "run-id")]
+    run_id: Option<InternalRunIdConfig>,
+    #[serde(rename = "extra-files")]
+    extra_files: Option<Vec<InternalExtraFile>>,
+    #[serde(rename = "build-info")]
+    build_info: Option<RelativePathBuf>,
+    #[serde(rename = "warn-size")]
+    warn_size: Option<ByteSize>,
+    environment: Option<InternalEnvironmentConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalEnvironmentConfig {
+    record: Option<bool>,
+    hostname: Option<bool>,
+    env: Option<Vec<String>>,
+    tools: Option<Vec<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalExtraFile {
+    src: RelativePathBuf,
+    dest: RelativePathBuf,
+    mode: Option<FileMode>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalRunIdConfig {
+    scheme: Option<RunIdScheme>,
+    #[serde(rename = "on-collision")]
+    on_collision: Option<RunIdCollision>,
+}
+
+/// How run directories under `out/` are named, and limits on the ones kept. The oldest are
+/// removed after each successful `make` until both limits hold. The newest run is always kept.
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct OutputConfig {
+    pub(crate) keep_runs: Option<usize>,
+    /// Combined size of every kept run, in bytes
+    pub(crate) max_size: Option<u64>,
+    pub(crate) run_id: RunIdConfig,
+    pub(crate) extra_files: Vec<ExtraFile>,
+    /// Entry path of the generated file describing the build, `None` to leave it out
+    pub(crate) build_info: Option<PathBuf>,
+    /// Size in bytes an archive can grow to before a warning is reported
+    pub(crate) warn_size: Option<u64>,
+    pub(crate) environment: EnvironmentConfig,
+}
+
+/// What the metadata of a run records about the machine it was made on, configured by
+/// `[output.environment]`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct EnvironmentConfig {
+    /// Whether anything is recorded at all
+    pub(crate) record: bool,
+    /// Whether the hostname is recorded, off by default as it may identify a person
+    pub(crate) hostname: bool,
+    /// Names of the environment variables recorded when they are set
+    pub(crate) env: Vec<String>,
+    /// Programs whose `--version` is recorded, on top of the ones rumkinst runs itself
+    pub(crate) tools: Vec<String>,
+}
+
+/// Environment variables recorded when `[output.environment] env` is not set, naming the CI
+/// job and the settings that change what is packaged
+const DEFAULT_RECORDED_ENV: [&str; 6] = [
+    "CI",
+    "GITHUB_RUN_ID",
+    "CI_PIPELINE_ID",
+    "BUILD_NUMBER",
+    "SOURCE_DATE_EPOCH",
+    "STRIP",
+];
+
+impl Default for EnvironmentConfig {
+    fn default() -> Self {
+        Self::init(None)
+    }
+}
+
+impl EnvironmentConfig {
+    fn init(source: Option<InternalEnvironmentConfig>) -> Self {
+        let source = source.unwrap_or(InternalEnvironmentConfig {
+            record: None,
+            hostname: None,
+            env: None,
+            tools: None,
+        });
+        Self {
+            record: source.record.unwrap_or(true),
+            hostname: source.hostname.unwrap_or(false),
+            env: source
+                .env
+                .unwrap_or_else(|| DEFAULT_RECORDED_ENV.map(String::from).to_vec()),
+            tools: source.tools.unwrap_or_default(),
+        }
+    }
+}
+
+/// A single file archived after the sources, configured by `[[output.extra-files]]`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct ExtraFile {
+    /// The file to archive, relative to the base directory
+    pub(crate) src: PathBuf,
+    /// Entry path of the file in the archive
+    pub(crate) dest: PathBuf,
+    /// Unix permissions to archive the file with, instead of its own
+    pub(crate) mode: Option<u32>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct RunIdConfig {
+    pub(crate) scheme: RunIdScheme,
+    pub(crate) on_collision: RunIdCollision,
+}
+
+impl OutputConfig {
+    fn init(source: Option<InternalOutputConfig>) -> Self {
+        source
+            .map(|source| Self {
+                keep_runs: source.keep_runs,
+                max_size: source.max_size.map(|size| size.bytes()),
+                run_id: source
+                    .run_id
+                    .map(|run_id| RunIdConfig {
+                        scheme: run_id.scheme.unwrap_or_default(),
+                        on_collision: run_id.on_collision.unwrap_or_default(),
+                    })
+                    .unwrap_or_default(),
+                extra_files: source
+                    .extra_files
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|extra| ExtraFile {
+                        src: extra.src.into_pathbuf(),
+                        dest: extra.dest.into_pathbuf(),
+                        mode: extra.mode.map(FileMode::bits),
+                    })
+                    .collect(),
+                build_info: source.build_info.map(RelativePathBuf::into_pathbuf),
+                warn_size: source.warn_size.map(|size| size.bytes()),
+                environment: EnvironmentConfig::init(source.environment),
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalDebConfig {
+    architecture: Option<String>,
+    maintainer: Option<String>,
+    section: Option<String>,
+    depends: Option<Vec<String>>,
+}
+
+/// Settings for the `.deb` output, which is only built when `[deb]` is present
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct DebConfig {
+    pub(crate) architecture: String,
+    pub(crate) maintainer: Option<String>,
+    pub(crate) section: Option<String>,
+    pub(crate) depends: Vec<String>,
+}
+
+impl DebConfig {
+    fn init(source: Option<InternalDebConfig>) -> Option<Self> {
+        source.map(|source| Self {
+            architecture: source.architecture.unwrap_or_else(|| "all".to_string()),
+            maintainer: source.maintainer,
+            section: source.section,
+            depends: source.depends.unwrap_or_default(),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalFlatpakConfig {
+    #[serde(rename = "app-id")]
+    app_id: AppId,
+    runtime: Option<String>,
+    #[serde(rename = "runtime-version")]
+    runtime_version: Option<String>,
+    sdk: Option<String>,
+    command: Option<String>,
+    #[serde(rename = "finish-args")]
+    finish_args: Option<Vec<String>>,
+    #[serde(rename = "archive-url")]
+    archive_url: Option<String>,
+}
+
+/// Settings for the Flatpak manifest, which is only written when `[flatpak]` is present
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct FlatpakConfig {
+    pub(crate) app_id: String,
+    pub(crate) runtime: String,
+    pub(crate) runtime_version: String,
+    pub(crate) sdk: String,
+    pub(crate) command: Option<String>,
+    pub(crate) finish_args: Vec<String>,
+    /// Where the archive will be downloaded from, it is expected next to the manifest if unset
+    pub(crate) archive_url: Option<String>,
+}
+
+impl FlatpakConfig {
+    fn init(source: Option<InternalFlatpakConfig>) -> Option<Self> {
+        source.map(|source| Self {
+            app_id: source.app_id.as_str().to_string(),
+            runtime: source
+                .runtime
+                .unwrap_or_else(|| "org.freedesktop.Platform".to_string()),
+            runtime_version: source
+                .runtime_version
+                .unwrap_or_else(|| "24.08".to_string()),
+            sdk: source
+                .sdk
+                .unwrap_or_else(|| "org.freedesktop.Sdk".to_string()),
+            command: source.command,
+            finish_args: source.finish_args.unwrap_or_default(),
+            archive_url: source.archive_url,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalOciConfig {
+    base: Option<RelativePathBuf>,
+    entrypoint: Option<Vec<String>>,
+    cmd: Option<Vec<String>>,
+    tag: Option<String>,
+    architecture: Option<String>,
+    load: Option<bool>,
+}
+
+/// Settings for the OCI image, which is only built when `[oci]` is present
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct OciConfig {
+    /// An OCI image layout directory holding the base image, `None` to build from scratch
+    pub(crate) base: Option<PathBuf>,
+    pub(crate) entrypoint: Option<Vec<String>>,
+    pub(crate) cmd: Option<Vec<String>>,
+    pub(crate) tag: Option<String>,
+    /// Only used without a base image, which decides the architecture otherwise
+    pub(crate) architecture: String,
+    /// Load the image into the local docker daemon once it is built
+    pub(crate) load: bool,
+}
+
+impl OciConfig {
+    fn init(source: Option<InternalOciConfig>) -> Option<Self> {
+        source.map(|source| Self {
+            base: source.base.map(RelativePathBuf::into_pathbuf),
+            entrypoint: source.entrypoint,
+            cmd: source.cmd,
+            tag: source.tag,
+            architecture: source.architecture.unwrap_or_else(|| "amd64".to_string()),
+            load: source.load.unwrap_or(false),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalNsisConfig {
+    #[serde(rename = "install-dir")]
+    install_dir: Option<String>,
+    publisher: Option<String>,
+    compile: Option<bool>,
+}
+
+/// Settings for the Windows installer, which is only built when `[nsis]` is present
+///
+/// The `root` source is installed into the install directory. `preinstall` and `postinstall` are
+/// shell scripts, so the Windows installer does not run them.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct NsisConfig {
+    /// Name of the directory made under `Program Files`, or under the user's local app data for
+    /// per-user installs
+    pub(crate) install_dir: Option<String>,
+    pub(crate) publisher: Option<String>,
+    /// Run `makensis` on the generated script, instead of only writing the script
+    pub(crate) compile: bool,
+}
+
+impl NsisConfig {
+    fn init(source: Option<InternalNsisConfig>) -> Option<Self> {
+        source.map(|source| Self {
+            install_dir: source.install_dir,
+            publisher: source.publisher,
+            compile: source.compile.unwrap_or(true),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalPublishConfig {
+    oci: Option<InternalOciPublishConfig>,
+    github: Option<InternalGithubPublishConfig>,
+    s3: Option<InternalS3PublishConfig>,
+    http: Option<InternalHttpPublishConfig>,
+    gitlab: Option<InternalGitlabPublishConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalOciPublishConfig {
+    reference: String,
+    #[serde(rename = "artifact-type")]
+    artifact_type: Option<String>,
+    #[serde(rename = "plain-http")]
+    plain_http: Option<bool>,
+}
+
+/// Where finished artifacts are published, nothing is published when `[publish]` is absent
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct PublishConfig {
+    pub(crate) oci: Option<OciPublishConfig>,
+    pub(crate) github: Option<GithubPublishConfig>,
+    pub(crate) s3: Option<S3PublishConfig>,
+    pub(crate) http: Option<HttpPublishConfig>,
+    pub(crate) gitlab: Option<GitlabPublishConfig>,
+}
+
+impl PublishConfig {
+    fn init(source: Option<InternalPublishConfig>) -> Self {
+        source
+            .map(|source| Self {
+                oci: source.oci.map(OciPublishConfig::init),
+                github: source.github.map(GithubPublishConfig::init),
+                s3: source.s3.map(S3PublishConfig::init),
+                http: source.http.map(HttpPublishConfig::init),
+                gitlab: source.gitlab.map(GitlabPublishConfig::init),
             })
+            .unwrap_or_default()
+    }
+
+    /// Whether any publishing target is configured
+    pub(crate) fn any(&self) -> bool {
+        self.oci.is_some()
+            || self.github.is_some()
+            || self.s3.is_some()
+            || self.http.is_some()
+            || self.gitlab.is_some()
+    }
+}
+
+/// Settings for pushing artifacts to an OCI registry, configured by `[publish.oci]`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct OciPublishConfig {
+    /// Repository to push to, e.g. `ghcr.io/owner/name`, optionally with a tag
+    pub(crate) reference: String,
+    pub(crate) artifact_type: String,
+    /// Talk to the registry over plain HTTP, for local test registries
+    pub(crate) plain_http: bool,
+}
+
+impl OciPublishConfig {
+    fn init(source: InternalOciPublishConfig) -> Self {
+        Self {
+            reference: source.reference,
+            artifact_type: source
+                .artifact_type
+                .unwrap_or_else(|| "application/vnd.rumkinst.artifact.v1".to_string()),
+            plain_http: source.plain_http.unwrap_or(false),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalGithubPublishConfig {
+    repository: String,
+    tag: Option<String>,
+    draft: Option<bool>,
+    prerelease: Option<bool>,
+}
+
+/// Settings for uploading artifacts to a GitHub release, configured by `[publish.github]`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct GithubPublishConfig {
+    /// Repository owning the release, as `owner/name`
+    pub(crate) repository: String,
+    /// Tag of the release, `v` followed by the package version if unset
+    pub(crate) tag: Option<String>,
+    /// Only used when the release is created, an existing release keeps its state
+    pub(crate) draft: bool,
+    /// Only used when the release is created, an existing release keeps its state
+    pub(crate) prerelease: bool,
+}
+
+impl GithubPublishConfig {
+    fn init(source: InternalGithubPublishConfig) -> Self {
+        Self {
+            repository: source.repository,
+            tag: source.tag,
+            draft: source.draft.unwrap_or(false),
+            prerelease: source.prerelease.unwrap_or(false),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalS3PublishConfig {
+    bucket: String,
+    prefix: Option<String>,
+    endpoint: Option<String>,
+    region: Option<String>,
+    public: Option<bool>,
+}
+
+/// Settings for uploading artifacts to S3-compatible object storage, configured by
+/// `[publish.s3]`
+///
+/// Credentials are read from the environment, the same way the `aws` CLI reads them.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct S3PublishConfig {
+    pub(crate) bucket: String,
+    /// Key prefix every artifact is uploaded under, without leading or trailing `/`
+    pub(crate) prefix: String,
+    /// Endpoint of a non-AWS storage service, such as `https://minio.example.com`
+    pub(crate) endpoint: Option<String>,
+    /// Falls back to `AWS_REGION` and `AWS_DEFAULT_REGION`
+    pub(crate) region: Option<String>,
+    /// Upload with the `public-read` ACL
+    pub(crate) public: bool,
+}
+
+impl S3PublishConfig {
+    fn init(source: InternalS3PublishConfig) -> Self {
+        Self {
+            bucket: source.bucket,
+            prefix: source
+                .prefix
+                .map(|prefix| prefix.trim_matches('/').to_string())
+                .unwrap_or_default(),
+            endpoint: source
+                .endpoint
+                .map(|endpoint| endpoint.trim_end_matches('/').to_string()),
+            region: source.region,
+            public: source.public.unwrap_or(false),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalHttpPublishConfig {
+    url: String,
+    method: Option<String>,
+    headers: Option<BTreeMap<String, String>>,
+    token: Option<SecretSource>,
+    username: Option<SecretSource>,
+    password: Option<SecretSource>,
+    #[serde(rename = "token-env")]
+    token_env: Option<String>,
+    #[serde(rename = "username-env")]
+    username_env: Option<String>,
+    #[serde(rename = "password-env")]
+    password_env: Option<String>,
+}
+
+/// Settings for uploading each artifact with an HTTP request, configured by `[publish.http]`
+///
+/// `{name}`, `{version}` and `{file}` in the URL and header values are replaced with the package
+/// name, the package version and the file name of the artifact.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct HttpPublishConfig {
+    pub(crate) url: String,
+    pub(crate) method: String,
+    pub(crate) headers: BTreeMap<String, String>,
+    /// Token sent as `Authorization: Bearer <token>`. `token-env = "NAME"` is short for
+    /// `token = { env = "NAME" }`, and likewise for the username and password
+    pub(crate) token: Option<SecretSource>,
+    /// Username for basic auth, along with `password`
+    pub(crate) username: Option<SecretSource>,
+    pub(crate) password: Option<SecretSource>,
+}
+
+impl HttpPublishConfig {
+    fn init(source: InternalHttpPublishConfig) -> Self {
+        Self {
+            url: source.url,
+            method: source
+                .method
+                .map(|method| method.to_ascii_uppercase())
+                .unwrap_or_else(|| "PUT".to_string()),
+            headers: source.headers.unwrap_or_default(),
+            token: source.token.or(source.token_env.map(SecretSource::Env)),
+            username: source
+                .username
+                .or(source.username_env.map(SecretSource::Env)),
+            password: source
+                .password
+                .or(source.password_env.map(SecretSource::Env)),
+        }
     }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalGitlabPublishConfig {
+    project: String,
+    url: Option<String>,
+    package: Option<String>,
+    tag: Option<String>,
+    #[serde(rename = "ref")]
+    git_ref: Option<String>,
+    token: Option<SecretSource>,
+    #[serde(rename = "token-env")]
+    token_env: Option<String>,
+}
+
+/// Settings for uploading artifacts to GitLab's generic package registry and linking them from
+/// a release, configured by `[publish.gitlab]`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct GitlabPublishConfig {
+    /// Numeric ID or full path of the project, such as `group/name`
+    pub(crate) project: String,
+    /// The GitLab instance, `https://gitlab.com` if unset
+    pub(crate) url: String,
+    /// Name of the generic package, the package name if unset
+    pub(crate) package: Option<String>,
+    /// Tag of the release, `v` followed by the package version if unset
+    pub(crate) tag: Option<String>,
+    /// Branch or commit to create the tag from, when it does not exist yet
+    #[serde(rename = "ref")]
+    pub(crate) git_ref: Option<String>,
+    /// Personal, project or group access token, `GITLAB_TOKEN` by default. Without one,
+    /// `CI_JOB_TOKEN` is used when running in GitLab CI. `token-env = "NAME"` is short for
+    /// `token = { env = "NAME" }`
+    pub(crate) token: SecretSource,
+}
+
+impl GitlabPublishConfig {
+    fn init(source: InternalGitlabPublishConfig) -> Self {
+        Self {
+            project: source.project,
+            url: source
+                .url
+                .map(|url| url.trim_end_matches('/').to_string())
+                .unwrap_or_else(|| "https://gitlab.com".to_string()),
+            package: source.package,
+            tag: source.tag,
+            git_ref: source.git_ref,
+            token: source.token.unwrap_or_else(|| {
+                SecretSource::Env(
+                    source
+                        .token_env
+                        .unwrap_or_else(|| "GITLAB_TOKEN".to_string()),
+                )
+            }),
+        }
+    }
+}
+
+/// The format of the update feed written for `[feed]`
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FeedFormat {
+    /// A JSON document listing every release
+    #[default]
+    #[serde(rename = "json")]
+    Json,
+    /// An RSS appcast, as read by Sparkle and WinSparkle
+    #[serde(rename = "appcast")]
+    Appcast,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalFeedConfig {
+    path: RelativePathBuf,
+    format: Option<FeedFormat>,
+    url: Option<String>,
+    channel: Option<String>,
+}
+
+/// Settings for the update feed kept up to date with every published release, configured by
+/// `[feed]`
+///
+/// `{name}`, `{version}` and `{file}` in the URL are replaced the same way as in
+/// `[publish.http]`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct FeedConfig {
+    /// The feed file, relative to the base directory, read back and updated by every build
+    pub(crate) path: PathBuf,
+    pub(crate) format: FeedFormat,
+    /// Where each artifact can be downloaded, the URL it was uploaded to if unset
+    pub(crate) url: Option<String>,
+    /// Release channel, such as `beta`, the default channel if unset
+    pub(crate) channel: Option<String>,
+}
+
+impl FeedConfig {
+    fn init(source: Option<InternalFeedConfig>) -> Option<Self> {
+        source.map(|source| Self {
+            path: source.path.into_pathbuf(),
+            format: source.format.unwrap_or_default(),
+            url: source.url,
+            channel: source.channel,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalLogConfig {
+    file: RelativePathBuf,
+    #[serde(rename = "max-size")]
+    max_size: Option<ByteSize>,
+    keep: Option<usize>,
+}
+
+/// A log file kept across runs, configured by `[log]`
+///
+/// Once it grows past `max_size` it is renamed to `{file}.1`, moving earlier ones up to
+/// `{file}.{keep}`, and a new one is started.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct LogConfig {
+    pub(crate) file: PathBuf,
+    pub(crate) max_size: u64,
+    pub(crate) keep: usize,
+}
+
+impl LogConfig {
+    fn init(source: Option<InternalLogConfig>) -> Option<Self> {
+        source.map(|source| Self {
+            file: source.file.into_pathbuf(),
+            max_size: source
+                .max_size
+                .map_or(DEFAULT_LOG_FILE_SIZE, |size| size.bytes()),
+            keep: source.keep.unwrap_or(DEFAULT_LOG_FILE_KEEP),
+        })
+    }
+}
+
+/// A check run by `rumkinst lint` over the config and the source directories
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+pub enum LintRule {
+    /// `installer.preinstall` or `installer.postinstall` is set, but the script does not exist
+    ScriptMissing,
+    /// An installer script is not executable
+    ScriptNotExecutable,
+    /// An installer script or build hook does not start with a `#!` line
+    ScriptMissingShebang,
+    /// A path in the `exclude` of a source does not exist, so it excludes nothing
+    ExcludeUnused,
+    /// A source directory is inside another one, so its files are packaged twice
+    SourceOverlap,
+}
+
+impl LintRule {
+    /// Name of the rule, as written in `[lint]`
+    pub fn name(&self) -> &'static str {
+        match self {
+            LintRule::ScriptMissing => "script-missing",
+            LintRule::ScriptNotExecutable => "script-not-executable",
+            LintRule::ScriptMissingShebang => "script-missing-shebang",
+            LintRule::ExcludeUnused => "exclude-unused",
+            LintRule::SourceOverlap => "source-overlap",
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            LintRule::ScriptMissing => ErrorCode::LINT_SCRIPT_MISSING,
+            LintRule::ScriptNotExecutable => ErrorCode::LINT_SCRIPT_NOT_EXECUTABLE,
+            LintRule::ScriptMissingShebang => ErrorCode::LINT_SCRIPT_SHEBANG,
+            LintRule::ExcludeUnused => ErrorCode::LINT_EXCLUDE_UNUSED,
+            LintRule::SourceOverlap => ErrorCode::LINT_SOURCE_OVERLAP,
+        }
+    }
+}
+
+/// How a finding of a [`LintRule`] is reported
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LintLevel {
+    /// Not reported at all
+    Allow,
+    /// Reported as a warning
+    Warn,
+    /// Reported as an error, failing `rumkinst lint`
+    Deny,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalLintConfig {
+    allow: Option<Vec<LintRule>>,
+    deny: Option<Vec<LintRule>>,
+}
+
+/// Levels of the lint rules, configured by `[lint]`
+///
+/// Rules are warnings unless they are allowed or denied, and denying a rule takes precedence
+/// over allowing it.
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct LintConfig {
+    pub(crate) allow: Vec<LintRule>,
+    pub(crate) deny: Vec<LintRule>,
+}
+
+impl LintConfig {
+    fn init(source: Option<InternalLintConfig>) -> Self {
+        source
+            .map(|source| Self {
+                allow: source.allow.unwrap_or_default(),
+                deny: source.deny.unwrap_or_default(),
+            })
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn level(&self, rule: LintRule) -> LintLevel {
+        if self.deny.contains(&rule) {
+            LintLevel::Deny
+        } else if self.allow.contains(&rule) {
+            LintLevel::Allow
+        } else {
+            LintLevel::Warn
+        }
+    }
+}
+
+/// What to do with sockets, fifos, device nodes and anything else in a source that is not a
+/// regular file, directory or symlink
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SpecialFiles {
+    /// Leave them out, and report a warning for each
+    #[default]
+    #[serde(rename = "skip-with-warning")]
+    SkipWithWarning,
+    /// Fail the run, or skip them when keeping going
+    #[serde(rename = "error")]
+    Error,
+    /// Package them as empty regular files, without reading them
+    #[serde(rename = "include-as-empty")]
+    IncludeAsEmpty,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 struct InternalSourceConfig {
     disable: Option<bool>,
-    path: Option<RelativePathBuf>,
+    path: Option<toml::Spanned<SourcePath>>,
     exclude: Option<Vec<RelativePathBuf>>,
+    #[serde(rename = "special-files")]
+    special_files: Option<SpecialFiles>,
+    #[serde(rename = "strip-prefix")]
+    strip_prefix: Option<RelativePathBuf>,
+    #[serde(rename = "allow-absolute")]
+    allow_absolute: Option<bool>,
+    #[serde(rename = "archive-prefix")]
+    archive_prefix: Option<RelativePathBuf>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub(crate) struct SourceConfig {
     pub(crate) disable: bool,
     pub(crate) path: PathBuf,
     pub(crate) exclude: Vec<PathBuf>,
+    pub(crate) special_files: SpecialFiles,
+    /// Leading directories left out of the archive entries of every file in the source,
+    /// relative to the base directory
+    pub(crate) strip_prefix: Option<PathBuf>,
+    /// Whether `path` may be absolute
+    pub(crate) allow_absolute: bool,
+    /// Directory the files of a source at an absolute path are archived under, instead of
+    /// their path on disk
+    pub(crate) archive_prefix: PathBuf,
 }
 
 impl SourceConfig {
@@ -132,17 +1121,28 @@ impl SourceConfig {
                 disable: source.disable.unwrap_or(false),
                 path: source
                     .path
-                    .map(|rel| rel.into_pathbuf())
+                    .map(|path| path.into_inner().into_pathbuf())
                     .unwrap_or(PathBuf::from(default_path)),
                 exclude: source
                     .exclude
                     .map(|exclude| exclude.into_iter().map(|rel| rel.into_pathbuf()).collect())
                     .unwrap_or(vec![]),
+                special_files: source.special_files.unwrap_or_default(),
+                strip_prefix: source.strip_prefix.map(|rel| rel.into_pathbuf()),
+                allow_absolute: source.allow_absolute.unwrap_or(false),
+                archive_prefix: source
+                    .archive_prefix
+                    .map(|rel| rel.into_pathbuf())
+                    .unwrap_or(PathBuf::from(default_path)),
             },
             None => Self {
                 disable: false,
                 path: PathBuf::from(default_path),
                 exclude: vec![],
+                special_files: SpecialFiles::default(),
+                strip_prefix: None,
+                allow_absolute: false,
+                archive_prefix: PathBuf::from(default_path),
             },
         }
     }
@@ -155,26 +1155,524 @@ impl SourceConfig {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalSourcesConfig {
+    download: Option<Vec<InternalDownloadSource>>,
+    git: Option<Vec<InternalGitSource>>,
+    command: Option<Vec<InternalCommandSource>>,
+    #[serde(rename = "cache-max-age")]
+    cache_max_age: Option<u64>,
+    #[serde(rename = "cache-max-size")]
+    cache_max_size: Option<ByteSize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalDownloadSource {
+    url: String,
+    sha256: Sha256Hex,
+    dest: RelativePathBuf,
+    targets: Option<Vec<Identifier>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalGitSource {
+    git: String,
+    rev: String,
+    path: Option<RelativePathBuf>,
+    dest: RelativePathBuf,
+    targets: Option<Vec<Identifier>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalCommandSource {
+    run: Vec<String>,
+    outputs: Vec<RelativePathBuf>,
+    inputs: Option<Vec<RelativePathBuf>>,
+    dest: RelativePathBuf,
+    targets: Option<Vec<Identifier>>,
+}
+
+/// Days a cached download can go unused before it is removed from the cache
+const DEFAULT_CACHE_MAX_AGE: u64 = 30;
+
+/// Files packaged from somewhere other than the source directories
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct SourcesConfig {
+    pub(crate) download: Vec<DownloadSource>,
+    pub(crate) git: Vec<GitSource>,
+    pub(crate) command: Vec<CommandSource>,
+    /// Days a cached download can go unused before it is removed, `0` to keep them
+    pub(crate) cache_max_age: u64,
+    /// Combined size in bytes of the cached downloads, past which the least recently used ones
+    /// are removed
+    pub(crate) cache_max_size: Option<u64>,
+}
+
+impl Default for SourcesConfig {
+    fn default() -> Self {
+        Self {
+            download: vec![],
+            git: vec![],
+            command: vec![],
+            cache_max_age: DEFAULT_CACHE_MAX_AGE,
+            cache_max_size: None,
+        }
+    }
+}
+
+impl SourcesConfig {
+    fn init(source: Option<InternalSourcesConfig>) -> Self {
+        source
+            .map(|source| Self {
+                download: source
+                    .download
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(DownloadSource::init)
+                    .collect(),
+                git: source
+                    .git
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(GitSource::init)
+                    .collect(),
+                command: source
+                    .command
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(CommandSource::init)
+                    .collect(),
+                cache_max_age: source.cache_max_age.unwrap_or(DEFAULT_CACHE_MAX_AGE),
+                cache_max_size: source.cache_max_size.map(|size| size.bytes()),
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// A file downloaded by every build, configured by `[[sources.download]]`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct DownloadSource {
+    pub(crate) url: String,
+    /// Lowercase hex digest the download has to match
+    pub(crate) sha256: String,
+    /// Where the file is packaged, relative to the base directory and inside the `root`, `env`
+    /// or `scripts` source
+    pub(crate) dest: PathBuf,
+    /// Names of the `[targets]` the file is only packaged for, every target if empty
+    pub(crate) targets: Vec<String>,
+}
+
+impl DownloadSource {
+    fn init(source: InternalDownloadSource) -> Self {
+        Self {
+            url: source.url,
+            sha256: source.sha256.into_string(),
+            dest: source.dest.into_pathbuf(),
+            targets: target_names(source.targets),
+        }
+    }
+}
+
+/// A directory checked out from a git repository by every build, configured by
+/// `[[sources.git]]`
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct GitSource {
+    pub(crate) git: String,
+    /// Commit, tag or branch to check out
+    pub(crate) rev: String,
+    /// Directory inside the repository to package, the whole repository if unset
+    pub(crate) path: Option<PathBuf>,
+    /// Where the directory is packaged, relative to the base directory and inside the `root`,
+    /// `env` or `scripts` source
+    pub(crate) dest: PathBuf,
+    /// Names of the `[targets]` the directory is only packaged for, every target if empty
+    pub(crate) targets: Vec<String>,
+}
+
+impl GitSource {
+    fn init(source: InternalGitSource) -> Self {
+        Self {
+            git: source.git,
+            rev: source.rev,
+            path: source.path.map(RelativePathBuf::into_pathbuf),
+            dest: source.dest.into_pathbuf(),
+            targets: target_names(source.targets),
+        }
+    }
+}
+
+/// Files made by a command in every build, configured by `[[sources.command]]`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct CommandSource {
+    /// The program and its arguments, run from the base directory
+    pub(crate) run: Vec<String>,
+    /// Files and directories the command writes into `RUMKINST_SOURCE_OUT`, which are the only
+    /// ones packaged
+    pub(crate) outputs: Vec<PathBuf>,
+    /// Files and directories the outputs are made from, relative to the base directory. The
+    /// outputs are reused until one of them changes, the command runs in every build if unset
+    pub(crate) inputs: Option<Vec<PathBuf>>,
+    /// Where the outputs are packaged, relative to the base directory and inside the `root`,
+    /// `env` or `scripts` source
+    pub(crate) dest: PathBuf,
+    /// Names of the `[targets]` the outputs are only packaged for, every target if empty
+    pub(crate) targets: Vec<String>,
+}
+
+impl CommandSource {
+    fn init(source: InternalCommandSource) -> Self {
+        Self {
+            run: source.run,
+            outputs: source
+                .outputs
+                .into_iter()
+                .map(RelativePathBuf::into_pathbuf)
+                .collect(),
+            inputs: source.inputs.map(|inputs| {
+                inputs
+                    .into_iter()
+                    .map(RelativePathBuf::into_pathbuf)
+                    .collect()
+            }),
+            dest: source.dest.into_pathbuf(),
+            targets: target_names(source.targets),
+        }
+    }
+}
+
+fn target_names(targets: Option<Vec<Identifier>>) -> Vec<String> {
+    targets
+        .unwrap_or_default()
+        .into_iter()
+        .map(Identifier::into_string)
+        .collect()
+}
+
+/// Writes `targets` keyed by name, as `[targets.<name>]` is written in `rumkinst.toml`
+fn serialize_targets<S: serde::Serializer>(
+    targets: &[TargetConfig],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(targets.iter().map(|target| (&target.name, target)))
+}
+
+/// A change made to the contents of a file before it is archived
+///
+/// Written in `rumkinst.toml` as the name of a built-in transform, or as the program and
+/// arguments of a command that reads the file on stdin and writes the result to stdout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transform {
+    /// Compress the file with gzip, adding `.gz` to its name
+    Gzip,
+    /// Remove spaces and tabs from the end of every line
+    TrimWhitespace,
+    /// Turn CRLF line endings into LF
+    Lf,
+    /// Turn LF line endings into CRLF
+    Crlf,
+    /// Remove every symbol from ELF and Mach-O executables and libraries with `strip`, or the
+    /// program in `STRIP`. Other files are left as they are
+    Strip,
+    /// Remove only debug symbols, like [`Transform::Strip`]
+    StripDebug,
+    /// Run a command from the base directory, with the path of the file in
+    /// `RUMKINST_TRANSFORM_PATH`
+    Run(Vec<String>),
+}
+
+impl Transform {
+    const BUILTIN: &[(&str, Transform)] = &[
+        ("gzip", Transform::Gzip),
+        ("trim-whitespace", Transform::TrimWhitespace),
+        ("lf", Transform::Lf),
+        ("crlf", Transform::Crlf),
+        ("strip", Transform::Strip),
+        ("strip-debug", Transform::StripDebug),
+    ];
+}
+
+impl<'de> Deserialize<'de> for Transform {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct TransformVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for TransformVisitor {
+            type Value = Transform;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("the name of a built-in transform, or a command")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                Transform::BUILTIN
+                    .iter()
+                    .find(|(name, _)| *name == v)
+                    .map(|(_, transform)| transform.clone())
+                    .ok_or_else(|| {
+                        let names = Transform::BUILTIN
+                            .iter()
+                            .map(|(name, _)| format!("`{name}`"))
+                            .collect::<Vec<_>>();
+                        E::custom(format!(
+                            "unknown transform `{v}`, expected one of {} or a command",
+                            names.join(", ")
+                        ))
+                    })
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: serde::de::SeqAccess<'de>,
+            {
+                let mut run = vec![];
+                while let Some(arg) = seq.next_element::<String>()? {
+                    run.push(arg);
+                }
+                if run.is_empty() {
+                    return Err(serde::de::Error::custom(
+                        "a transform command needs at least a program",
+                    ));
+                }
+                Ok(Transform::Run(run))
+            }
+        }
+
+        deserializer.deserialize_any(TransformVisitor)
+    }
+}
+
+impl Serialize for Transform {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            Transform::Run(run) => run.serialize(serializer),
+            builtin => Transform::BUILTIN
+                .iter()
+                .find(|(_, transform)| transform == builtin)
+                .map(|(name, _)| *name)
+                .expect("every built-in transform should have a name")
+                .serialize(serializer),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalLicensesConfig {
+    scan: Option<bool>,
+    include: Option<Vec<RelativePathBuf>>,
+    dir: Option<RelativePathBuf>,
+}
+
+/// Settings for bundling license files into the archive, which only happens when `[licenses]`
+/// is present
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) struct LicensesConfig {
+    /// Whether to bundle the `LICENSE`, `NOTICE` and `COPYING` files found in the sources
+    pub(crate) scan: bool,
+    /// More license files and directories to bundle, such as the dependency licenses written by
+    /// `cargo about`, relative to the base directory
+    pub(crate) include: Vec<PathBuf>,
+    /// Directory of the archive the license files are bundled into
+    pub(crate) dir: PathBuf,
+}
+
+impl LicensesConfig {
+    fn init(source: Option<InternalLicensesConfig>) -> Option<Self> {
+        source.map(|source| Self {
+            scan: source.scan.unwrap_or(true),
+            include: source
+                .include
+                .unwrap_or_default()
+                .into_iter()
+                .map(RelativePathBuf::into_pathbuf)
+                .collect(),
+            dir: source
+                .dir
+                .map(RelativePathBuf::into_pathbuf)
+                .unwrap_or(PathBuf::from("licenses")),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalTransformRule {
+    files: Vec<GlobPattern>,
+    transform: Transform,
+}
+
+/// Files run through a transform before they are archived, configured by `[[transforms]]`
+///
+/// Every rule matching a file applies, in the order they are written.
+#[derive(Debug, Serialize)]
+pub(crate) struct TransformRule {
+    /// Patterns matched against the paths of files relative to the base directory, before any
+    /// `strip-prefix`
+    pub(crate) files: Vec<GlobPattern>,
+    pub(crate) transform: Transform,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalTargetConfig {
+    exclude: Option<Vec<GlobPattern>>,
+}
+
+/// A stage of the build `[[plugins]]` can be called at
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PluginStage {
+    /// Once every built-in artifact is made, to make more of them
+    #[serde(rename = "artifacts")]
+    Artifacts,
+    /// While publishing, after the built-in targets, to publish the artifacts elsewhere
+    #[serde(rename = "publish")]
+    Publish,
+}
+
+impl PluginStage {
+    /// Name of the stage, as written in the config and sent to plugins
+    pub fn name(&self) -> &'static str {
+        match self {
+            PluginStage::Artifacts => "artifacts",
+            PluginStage::Publish => "publish",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalPluginConfig {
+    name: Identifier,
+    command: Option<String>,
+    stages: Vec<PluginStage>,
+    options: Option<toml::Table>,
+}
+
+/// An external program called with the context of the build at some of its stages, configured
+/// by `[[plugins]]`
+#[derive(Debug, Serialize)]
+pub(crate) struct PluginConfig {
+    pub(crate) name: String,
+    /// Program to run, looked up on `PATH` unless it is a path relative to the base directory
+    pub(crate) command: String,
+    pub(crate) stages: Vec<PluginStage>,
+    /// Handed to the plugin as they are
+    pub(crate) options: toml::Table,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalWasmHookConfig {
+    module: RelativePathBuf,
+    options: Option<toml::Table>,
+    fuel: Option<u64>,
+}
+
+/// A sandboxed build step shipped as a WebAssembly module, run once the sources are found,
+/// configured by `[[wasm-hooks]]`
+///
+/// Hooks can only read the source files, add files to the archive and add metadata to the
+/// build, through the `rumkinst` imports. They are only run with the `wasm` feature.
+#[derive(Debug, Serialize)]
+pub(crate) struct WasmHookConfig {
+    /// The module, relative to the base directory
+    pub(crate) module: PathBuf,
+    /// Handed to the hook as JSON
+    pub(crate) options: toml::Table,
+    /// Fuel the hook may use up before it is stopped, roughly one per instruction
+    pub(crate) fuel: u64,
+}
+
+/// A platform that gets its own archive, configured by `[targets.<name>]`
+///
+/// Only archives are made per target: a delta, `--stdout` and the packages of `[deb]`,
+/// `[flatpak]`, `[oci]` and `[nsis]` each describe one set of files, so a build with targets
+/// refuses them rather than picking one target to make them from.
+#[derive(Debug, Serialize)]
+pub(crate) struct TargetConfig {
+    /// Name of the target, such as `linux-x86_64`, added to the names of its artifacts
+    #[serde(skip)]
+    pub(crate) name: String,
+    /// Patterns of the files left out of the archive for this target, matched like the files of
+    /// `[[transforms]]`
+    pub(crate) exclude: Vec<GlobPattern>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct InternalConfig {
     package: InternalPackageDetails,
 
     installer: Option<InternalInstallerConfig>,
     build: Option<InternalBuildConfig>,
+    output: Option<InternalOutputConfig>,
+    deb: Option<InternalDebConfig>,
+    flatpak: Option<InternalFlatpakConfig>,
+    oci: Option<InternalOciConfig>,
+    nsis: Option<InternalNsisConfig>,
+    publish: Option<InternalPublishConfig>,
+    feed: Option<InternalFeedConfig>,
+    log: Option<InternalLogConfig>,
+    lint: Option<InternalLintConfig>,
 
     root: Option<InternalSourceConfig>,
     env: Option<InternalSourceConfig>,
     scripts: Option<InternalSourceConfig>,
+    sources: Option<InternalSourcesConfig>,
+    transforms: Option<Vec<InternalTransformRule>>,
+    licenses: Option<InternalLicensesConfig>,
+    targets: Option<BTreeMap<Identifier, InternalTargetConfig>>,
+    plugins: Option<Vec<InternalPluginConfig>>,
+    #[serde(rename = "wasm-hooks")]
+    wasm_hooks: Option<Vec<InternalWasmHookConfig>>,
 }
 
+#[derive(Serialize)]
 pub struct Config {
     pub(crate) package: PackageDetails,
 
     pub(crate) installer: InstallerConfig,
     pub(crate) build: BuildConfig,
+    pub(crate) output: OutputConfig,
+    pub(crate) deb: Option<DebConfig>,
+    pub(crate) flatpak: Option<FlatpakConfig>,
+    pub(crate) oci: Option<OciConfig>,
+    pub(crate) nsis: Option<NsisConfig>,
+    pub(crate) publish: PublishConfig,
+    pub(crate) feed: Option<FeedConfig>,
+    pub(crate) log: Option<LogConfig>,
+    pub(crate) lint: LintConfig,
 
     pub(crate) root: SourceConfig,
     pub(crate) env: SourceConfig,
     pub(crate) scripts: SourceConfig,
+    pub(crate) sources: SourcesConfig,
+    pub(crate) transforms: Vec<TransformRule>,
+    pub(crate) licenses: Option<LicensesConfig>,
+    #[serde(serialize_with = "serialize_targets")]
+    pub(crate) targets: Vec<TargetConfig>,
+    pub(crate) plugins: Vec<PluginConfig>,
+    #[serde(rename = "wasm-hooks")]
+    pub(crate) wasm_hooks: Vec<WasmHookConfig>,
+
+    #[serde(skip)]
+    pub(crate) base_dir: PathBuf,
 }
 
 impl From<InternalConfig> for Config {
@@ -184,36 +1682,126 @@ impl From<InternalConfig> for Config {
 
             installer: InstallerConfig::init(value.installer),
             build: BuildConfig::init(value.build),
+            output: OutputConfig::init(value.output),
+            deb: DebConfig::init(value.deb),
+            flatpak: FlatpakConfig::init(value.flatpak),
+            oci: OciConfig::init(value.oci),
+            nsis: NsisConfig::init(value.nsis),
+            publish: PublishConfig::init(value.publish),
+            feed: FeedConfig::init(value.feed),
+            log: LogConfig::init(value.log),
+            lint: LintConfig::init(value.lint),
 
             root: SourceConfig::init(value.root, "./root/"),
             env: SourceConfig::init(value.env, "./env/"),
             scripts: SourceConfig::init(value.scripts, "./scripts/"),
+            sources: SourcesConfig::init(value.sources),
+            transforms: value
+                .transforms
+                .unwrap_or_default()
+                .into_iter()
+                .map(|rule| TransformRule {
+                    files: rule.files,
+                    transform: rule.transform,
+                })
+                .collect(),
+            licenses: LicensesConfig::init(value.licenses),
+            targets: value
+                .targets
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(name, target)| TargetConfig {
+                    name: name.into_string(),
+                    exclude: target.exclude.unwrap_or_default(),
+                })
+                .collect(),
+            plugins: value
+                .plugins
+                .unwrap_or_default()
+                .into_iter()
+                .map(|plugin| {
+                    let name = plugin.name.into_string();
+                    PluginConfig {
+                        command: plugin.command.unwrap_or_else(|| format!("rumkinst-{name}")),
+                        name,
+                        stages: plugin.stages,
+                        options: plugin.options.unwrap_or_default(),
+                    }
+                })
+                .collect(),
+            wasm_hooks: value
+                .wasm_hooks
+                .unwrap_or_default()
+                .into_iter()
+                .map(|hook| WasmHookConfig {
+                    module: hook.module.into_pathbuf(),
+                    options: hook.options.unwrap_or_default(),
+                    fuel: hook.fuel.unwrap_or(DEFAULT_WASM_FUEL),
+                })
+                .collect(),
+
+            base_dir: PathBuf::new(),
         }
     }
 }
 
 impl Config {
-    pub fn read<R: Read>(mut readable: R) -> Result<Self> {
+    pub fn read<R: Read>(readable: R) -> Result<Self, RumkinstError> {
+        Self::read_named(readable, "rumkinst.toml")
+    }
+
+    /// Read a config, using `name` to refer to it in diagnostics
+    ///
+    /// Sources at an absolute path are rejected unless they set `allow-absolute`.
+    pub fn read_named<R: Read>(readable: R, name: &str) -> Result<Self, RumkinstError> {
+        Self::read_checked(readable, name, false)
+    }
+
+    /// Read a config like [`read_named`](Self::read_named), allowing every source at an
+    /// absolute path as if it set `allow-absolute`
+    pub fn read_named_allowing_absolute<R: Read>(
+        readable: R,
+        name: &str,
+    ) -> Result<Self, RumkinstError> {
+        Self::read_checked(readable, name, true)
+            .map(|config| config.with_allow_absolute_sources(true))
+    }
+
+    fn read_checked<R: Read>(
+        mut readable: R,
+        name: &str,
+        allow_absolute: bool,
+    ) -> Result<Self, RumkinstError> {
         trace!("reading config reader to config type");
         let mut config_str = String::new();
         readable
             .read_to_string(&mut config_str)
-            .context("failed to finish reading reader to string")?;
+            .map_err(|source| RumkinstError::ConfigRead {
+                name: name.to_string(),
+                source,
+            })?;
 
-        toml::from_str::<InternalConfig>(&config_str)
-            .context("failed to parse rumkinst config from file text")
-            .map(|cfg| {
-                debug!("successfully parsed config");
-                cfg.into()
-            })
+        REFUSED.take();
+        let config = toml::from_str::<InternalConfig>(&config_str)
+            .map_err(|err| config_error(err, name, &config_str))?;
+        if !allow_absolute {
+            check_absolute_sources(&config, name, &config_str)?;
+        }
+        debug!("successfully parsed config");
+        Ok(config.into())
     }
 
-    pub fn write_default<W: Write>(mut writable: W, package_name: Identifier) -> Result<()> {
+    pub fn write_default<W: Write>(
+        mut writable: W,
+        package_name: Identifier,
+    ) -> Result<(), RumkinstError> {
         let config_str = toml::to_string_pretty(&InternalConfig {
             package: InternalPackageDetails {
                 name: package_name,
+                version: Some("0.1.0".to_string()),
                 description: Some(String::new()),
                 authors: Some(vec![]),
+                changelog: None,
             },
             installer: Some(InternalInstallerConfig {
                 allow_user_install: Some(false),
@@ -223,27 +1811,329 @@ impl Config {
                 postinstall: None,
             }),
             build: None,
+            output: None,
+            deb: None,
+            flatpak: None,
+            oci: None,
+            nsis: None,
+            publish: None,
+            feed: None,
+            log: None,
+            lint: None,
             root: None,
             env: None,
             scripts: None,
+            sources: None,
+            transforms: None,
+            licenses: None,
+            targets: None,
+            plugins: None,
+            wasm_hooks: None,
         })
-        .context("failed to convert default config to toml string")?;
+        .expect("default config should always serialize to toml");
 
         writable
             .write_fmt(format_args!("{config_str}"))
-            .context("failed to write default toml to writer")
+            .map_err(RumkinstError::ConfigWrite)
     }
 
     pub fn get_name(&self) -> &str {
         &self.package.name
     }
+
+    /// `installer.theme`, also used to draw the summary after `make`
+    pub fn theme(&self) -> &ThemeType {
+        &self.installer.theme
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.package.version.as_deref()
+    }
+
+    /// `package.changelog`, relative to the base directory
+    pub fn changelog(&self) -> Option<&Path> {
+        self.package.changelog.as_deref()
+    }
+
+    /// `installer.preinstall`, relative to the base directory
+    pub fn preinstall(&self) -> Option<&Path> {
+        self.installer.preinstall.as_deref()
+    }
+
+    /// `installer.postinstall`, relative to the base directory
+    pub fn postinstall(&self) -> Option<&Path> {
+        self.installer.postinstall.as_deref()
+    }
+
+    /// Path of the `root` source, whose files are installed on the target system, `None` if it
+    /// is disabled
+    pub fn root_source(&self) -> Option<&Path> {
+        (!self.root.disable).then(|| self.root.path())
+    }
+
+    pub fn deny_warnings(&self) -> bool {
+        self.build.deny_warnings
+    }
+
+    pub fn archive_format(&self) -> ArchiveFormat {
+        self.build.archive_format
+    }
+
+    /// Whether the artifacts may be extracted on Windows, so packaged paths have to follow its
+    /// naming rules
+    ///
+    /// Unless `build.windows-names` is set, this is when `[nsis]` is set or a target is named
+    /// `windows-*`.
+    pub fn targets_windows(&self) -> bool {
+        self.build.windows_names.unwrap_or_else(|| {
+            self.nsis.is_some()
+                || self
+                    .targets
+                    .iter()
+                    .any(|target| target.name.starts_with("windows"))
+        })
+    }
+
+    /// `[build] jobs`, the most threads used to make artifacts, `None` if it is not set
+    pub fn jobs(&self) -> Option<NonZeroUsize> {
+        self.build.jobs
+    }
+
+    /// How many run directories to keep under `out/`, `None` to keep every run
+    pub fn keep_runs(&self) -> Option<usize> {
+        self.output.keep_runs
+    }
+
+    /// Combined size in bytes the run directories under `out/` may take, `None` for no limit
+    pub fn max_output_size(&self) -> Option<u64> {
+        self.output.max_size
+    }
+
+    /// Size in bytes an archive can grow to before a warning is reported, `None` for no limit
+    pub fn warn_size(&self) -> Option<u64> {
+        self.output.warn_size
+    }
+
+    /// How the run directories under `out/` are named
+    pub fn run_id_scheme(&self) -> RunIdScheme {
+        self.output.run_id.scheme
+    }
+
+    /// What to do when a new run would reuse the directory of an earlier one
+    pub fn run_id_collision(&self) -> RunIdCollision {
+        self.output.run_id.on_collision
+    }
+
+    /// `[log] file`, relative to the base directory, `None` if no log file is kept
+    pub fn log_file(&self) -> Option<&Path> {
+        self.log.as_ref().map(|log| log.file.as_path())
+    }
+
+    /// Size in bytes the log file is rotated at, and how many rotated files are kept
+    pub fn log_rotation(&self) -> (u64, usize) {
+        self.log
+            .as_ref()
+            .map_or((DEFAULT_LOG_FILE_SIZE, DEFAULT_LOG_FILE_KEEP), |log| {
+                (log.max_size, log.keep)
+            })
+    }
+
+    /// Entry path of the generated file describing the build, `None` if it is not archived
+    pub fn build_info(&self) -> Option<&Path> {
+        self.output.build_info.as_deref()
+    }
+
+    /// Allow every source to be at an absolute path, as if each one set `allow-absolute`
+    pub fn with_allow_absolute_sources(mut self, allow: bool) -> Self {
+        for source in [&mut self.root, &mut self.env, &mut self.scripts] {
+            source.allow_absolute |= allow;
+        }
+        self
+    }
+
+    /// Resolve source and hook paths against `dir`, usually the directory holding
+    /// `rumkinst.toml`, instead of the current working directory
+    pub fn with_base_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.base_dir = dir.into();
+        self
+    }
+
+    /// The directory paths in this config are relative to, empty for the current directory
+    pub fn base_dir(&self) -> &Path {
+        &self.base_dir
+    }
+
+    /// Every setting with its defaults filled in, laid out the same way as in `rumkinst.toml`
+    ///
+    /// Optional settings and tables that are unset are left out, except `build.windows-names`, which is
+    /// filled in with whether Windows naming rules are checked.
+    pub fn effective(&self) -> toml::Table {
+        let mut table =
+            toml::Table::try_from(self).expect("config should always serialize to toml");
+        if let Some(toml::Value::Table(build)) = table.get_mut("build") {
+            build
+                .entry("windows-names")
+                .or_insert(toml::Value::Boolean(self.targets_windows()));
+        }
+        table.retain(|_, value| value.as_table().is_none_or(|table| !table.is_empty()));
+        table
+    }
+
+    /// The dotted `key` with its first part that names no setting in
+    /// [`effective`](Self::effective) replaced by the closest one that does, to suggest when
+    /// `key` is not set, or `None` if every part names a setting or nothing is close
+    pub fn suggest_key(&self, key: &str) -> Option<String> {
+        let effective = self.effective();
+        let mut table = &effective;
+        let mut parts = key.split('.').map(String::from).collect::<Vec<_>>();
+        for part in &mut parts {
+            match table.get(part.as_str()) {
+                Some(toml::Value::Table(inner)) => table = inner,
+                Some(_) => return None,
+                None => {
+                    *part = suggest::closest(part, table.keys().map(String::as_str))?.to_string();
+                    return Some(parts.join("."));
+                }
+            }
+        }
+        None
+    }
+
+    pub(crate) fn resolve(&self, path: &Path) -> PathBuf {
+        self.base_dir.join(path)
+    }
+}
+
+/// Fail if a source of `config` is at an absolute path without setting `allow-absolute`, pointing
+/// at the path in `config_str`
+fn check_absolute_sources(
+    config: &InternalConfig,
+    name: &str,
+    config_str: &str,
+) -> Result<(), RumkinstError> {
+    let sources = [
+        ("root", &config.root),
+        ("env", &config.env),
+        ("scripts", &config.scripts),
+    ];
+    for (source_name, source) in sources {
+        let Some(source) = source else {
+            continue;
+        };
+        let Some(path) = &source.path else {
+            continue;
+        };
+        if source.allow_absolute != Some(true) && path.get_ref().as_path().is_absolute() {
+            let snippet = SourceSnippet::new(
+                name,
+                config_str,
+                path.span(),
+                format!("`[{source_name}]` is at an absolute path"),
+            )
+            .with_help(Some(
+                "set `allow-absolute = true` in this source to package files from outside the \
+                 project, or pass `--allow-absolute`"
+                    .to_string(),
+            ));
+            return Err(RumkinstError::ConfigParse {
+                name: name.to_string(),
+                source: snippet.into(),
+            });
+        }
+    }
+    Ok(())
+}
+
+thread_local! {
+    /// The error the last value refused by a type of the config was refused with, on this thread
+    static REFUSED: RefCell<Option<RumkinstError>> = const { RefCell::new(None) };
+}
+
+/// Refuse a value while deserializing the config, keeping `err` to pick help for the error toml
+/// reports once it knows where the value is
+pub(crate) fn refuse<E: serde::de::Error>(err: RumkinstError) -> E {
+    let refused = E::custom(&err);
+    REFUSED.set(Some(err));
+    refused
+}
+
+/// Attach the offending snippet of the config to a parse error, when toml knows where it is
+fn config_error(err: toml::de::Error, name: &str, config_str: &str) -> RumkinstError {
+    let message = err.message().trim().to_string();
+    let refused = REFUSED.take();
+    let source = match err.span() {
+        Some(span) => {
+            let help = config_help(&message, &span, refused.as_ref(), config_str);
+            SourceSnippet::new(name, config_str, span, message)
+                .with_help(help)
+                .into()
+        }
+        None => err.into(),
+    };
+    RumkinstError::ConfigParse {
+        name: name.to_string(),
+        source,
+    }
 }
 
-pub fn find_config_file_at(path: Option<PathBuf>) -> Result<PathBuf> {
+/// Help for the error toml reported at `span` of `config_str`, from the error a type of the
+/// config `refused` a value with, or the table the error is about
+fn config_help(
+    message: &str,
+    span: &Range<usize>,
+    refused: Option<&RumkinstError>,
+    config_str: &str,
+) -> Option<String> {
+    if let Some(refused) = refused {
+        return match refused {
+            RumkinstError::InvalidIdentifier { .. } => {
+                Some("identifiers may only contain ASCII letters, digits, `-` and `_`".to_string())
+            }
+            RumkinstError::InvalidRelativePath { .. } => Some(
+                "paths in rumkinst.toml are relative to the directory containing it".to_string(),
+            ),
+            _ => None,
+        };
+    }
+    // Errors about a whole table, such as a field missing from it, point at its header, or at
+    // the start of the config for the top-level table
+    if span.is_empty() && span.start == 0 {
+        return Some("add the missing table to the config".to_string());
+    }
+    let document = toml_edit::Document::parse(config_str).ok()?;
+    match table_at(document.as_table(), span) {
+        Some(keys) => Some(format!("add the missing field to `[{}]`", keys.join("."))),
+        None => suggest::suggest_for_message(message),
+    }
+}
+
+/// Keys of the table in `table` whose header is at `span`, such as `["package"]` for `[package]`
+fn table_at(table: &toml_edit::Table, span: &Range<usize>) -> Option<Vec<String>> {
+    let in_table = |inner: &toml_edit::Table| match inner.span().as_ref() == Some(span) {
+        true => Some(vec![]),
+        false => table_at(inner, span),
+    };
+    table.iter().find_map(|(key, item)| {
+        let mut keys = match item {
+            toml_edit::Item::Table(inner) => in_table(inner),
+            toml_edit::Item::ArrayOfTables(array) => array.iter().find_map(in_table),
+            _ => None,
+        }?;
+        keys.insert(0, key.to_string());
+        Some(keys)
+    })
+}
+
+/// Find `rumkinst.toml` at `path`, or search the current directory and its parents for it,
+/// logging which file was found
+///
+/// A symlinked config file is resolved to the file it links to, whose directory is the base
+/// directory of the project.
+pub fn find_config_file_at(path: Option<PathBuf>) -> Result<PathBuf, RumkinstError> {
     trace!("searching for config file");
     debug!("provided path to search is `{path:?}`");
 
-    match path {
+    let found = match path {
         Some(path) => {
             if path.is_file() {
                 debug!("provided path was a file");
@@ -253,21 +2143,118 @@ pub fn find_config_file_at(path: Option<PathBuf>) -> Result<PathBuf> {
                 Ok(path.join("rumkinst.toml"))
             } else {
                 debug!("provided path was not, or did not contain `rumkinst.toml`");
-                anyhow::bail!("could not find `rumkinst.toml` in `{path:?}`");
+                Err(RumkinstError::ConfigNotFound { path: Some(path) })
             }
         }
-        None => find_default_config_file()
-            .context("could not find `rumkinst.toml` in `./` or in `./rumkinst/`"),
-    }
+        None => {
+            let found = find_default_config_file();
+            if let Some(found) = &found {
+                info!("Using the config file {found:?}");
+            }
+            found.ok_or(RumkinstError::ConfigNotFound { path: None })
+        }
+    }?;
+    Ok(resolve_symlink(found))
 }
 
+/// Search `./` and `./rumkinst/`, then every parent directory
+///
+/// The `rumkinst/` of a parent directory is never searched, so a project made by `rumkinst new`
+/// somewhere above, such as in the home directory, isn't picked up by accident.
 fn find_default_config_file() -> Option<PathBuf> {
     trace!("searching default file paths for `rumkinst.toml`");
-    config_exists("./").or_else(|| config_exists("./rumkinst/"))
+    config_exists(Path::new("./"))
+        .or_else(|| config_exists(Path::new("./rumkinst/")))
+        .or_else(|| {
+            let current = env::current_dir().ok()?;
+            current.ancestors().skip(1).find_map(|dir| {
+                trace!("searching parent directory {dir:?} for `rumkinst.toml`");
+                config_exists(dir)
+            })
+        })
 }
 
-fn config_exists(path: &str) -> Option<PathBuf> {
-    let path = PathBuf::from(path).join("rumkinst.toml");
+fn config_exists(path: &Path) -> Option<PathBuf> {
+    let path = path.join("rumkinst.toml");
 
     path.is_file().then_some(path)
 }
+
+/// The file `path` links to if it is a symlink, otherwise `path` itself
+fn resolve_symlink(path: PathBuf) -> PathBuf {
+    if !path.is_symlink() {
+        return path;
+    }
+    match fs::canonicalize(&path) {
+        Ok(real) => {
+            debug!("config file {path:?} links to {real:?}");
+            real
+        }
+        Err(err) => {
+            debug!("could not resolve the config file symlink {path:?}: {err}");
+            path
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The help given with the error `config` fails to read with
+    fn help_for(config: &str) -> Option<String> {
+        let Err(RumkinstError::ConfigParse { source, .. }) = Config::read(config.as_bytes()) else {
+            panic!("{config:?} should fail to parse");
+        };
+        source
+            .downcast_ref::<SourceSnippet>()
+            .expect("the error should point into the config")
+            .help()
+            .map(String::from)
+    }
+
+    #[test]
+    fn config_help_for_invalid_identifiers() {
+        assert_eq!(
+            help_for("[package]\nname = \"my app\"\n").as_deref(),
+            Some("identifiers may only contain ASCII letters, digits, `-` and `_`")
+        );
+    }
+
+    #[test]
+    fn config_help_for_absolute_paths() {
+        assert_eq!(
+            help_for("[package]\nname = \"demo\"\nchangelog = \"/CHANGELOG.md\"\n").as_deref(),
+            Some("paths in rumkinst.toml are relative to the directory containing it")
+        );
+    }
+
+    #[test]
+    fn config_help_for_missing_fields_names_the_table() {
+        assert_eq!(
+            help_for("[package]\nversion = \"1.0.0\"\n").as_deref(),
+            Some("add the missing field to `[package]`")
+        );
+        assert_eq!(
+            help_for("[package]\nname = \"demo\"\n\n[[sources.download]]\nurl = \"https://example.com\"\n")
+                .as_deref(),
+            Some("add the missing field to `[sources.download]`")
+        );
+    }
+
+    #[test]
+    fn config_help_for_missing_tables() {
+        assert_eq!(
+            help_for("[installer]\ntheme = \"plain\"\n").as_deref(),
+            Some("add the missing table to the config")
+        );
+    }
+
+    #[test]
+    fn config_help_suggests_close_names() {
+        assert_eq!(
+            help_for("[package]\nname = \"demo\"\nverison = \"1.0.0\"\n").as_deref(),
+            Some("did you mean `version`?")
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use serde::{
+    de::{Deserialize, Deserializer, Visitor},
+    ser::Serialize,
+};
+
+use super::refuse;
+use crate::RumkinstError;
+
+/// A reverse-DNS application id, as used by Flatpak, e.g. `org.example.App`
+#[derive(Debug, Clone)]
+pub(crate) struct AppId(String);
+
+impl AppId {
+    pub(crate) fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<&str> for AppId {
+    type Error = RumkinstError;
+    fn try_from(value: &str) -> Result<Self, RumkinstError> {
+        let invalid = |reason: &str| RumkinstError::InvalidAppId {
+            id: value.to_string(),
+            reason: reason.to_string(),
+        };
+
+        if value.len() > 255 {
+            return Err(invalid("longer than 255 characters"));
+        }
+
+        let elements: Vec<_> = value.split('.').collect();
+        if elements.len() < 3 {
+            return Err(invalid("needs at least three `.` separated elements"));
+        }
+
+        for (index, element) in elements.iter().enumerate() {
+            let is_last = index == elements.len() - 1;
+            if element.is_empty() {
+                return Err(invalid("contains an empty element"));
+            }
+            if element.starts_with(|ch: char| ch.is_ascii_digit()) {
+                return Err(invalid("an element starts with a digit"));
+            }
+            if let Some(ch) = element
+                .chars()
+                .find(|ch| !(ch.is_ascii_alphanumeric() || *ch == '_' || (is_last && *ch == '-')))
+            {
+                return Err(invalid(&format!("contains invalid character `{ch}`")));
+            }
+        }
+
+        Ok(Self(value.to_string()))
+    }
+}
+
+impl TryFrom<String> for AppId {
+    type Error = RumkinstError;
+    fn try_from(value: String) -> Result<Self, RumkinstError> {
+        Self::try_from(value.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for AppId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct AppIdVisitor;
+
+        impl<'de> Visitor<'de> for AppIdVisitor {
+            type Value = AppId;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("reverse-DNS app id")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                AppId::try_from(v).map_err(refuse)
+            }
+        }
+
+        deserializer.deserialize_str(AppIdVisitor)
+    }
+}
+
+impl Serialize for AppId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.0.serialize(serializer)
+    }
+}
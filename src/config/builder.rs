@@ -0,0 +1,855 @@
+use std::{collections::BTreeMap, num::NonZeroUsize, path::PathBuf};
+
+use crate::RumkinstError;
+
+use super::{
+    ArchiveFormat, CaseCollisions, CommandSource, Config, DuplicateEntries, FeedFormat,
+    InternalBuildConfig, InternalCommandSource, InternalConfig, InternalDebConfig,
+    InternalDownloadSource, InternalExtraFile, InternalFeedConfig, InternalFlatpakConfig,
+    InternalGitSource, InternalGithubPublishConfig, InternalGitlabPublishConfig,
+    InternalHttpPublishConfig, InternalInstallerConfig, InternalLicensesConfig, InternalLintConfig,
+    InternalLogConfig, InternalNsisConfig, InternalOciConfig, InternalOciPublishConfig,
+    InternalOutputConfig, InternalPackageDetails, InternalPluginConfig, InternalPublishConfig,
+    InternalS3PublishConfig, InternalSourceConfig, InternalSourcesConfig, InternalTargetConfig,
+    InternalTransformRule, InternalWasmHookConfig, LintLevel, LintRule, PluginStage, SpecialFiles,
+    ThemeType, Transform, UnusualNames, app_id::AppId, file_mode::FileMode,
+    glob_pattern::GlobPattern, identifier::Identifier, relativepathbuf::RelativePathBuf,
+    sha256::Sha256Hex, source_path::SourcePath,
+};
+
+/// One of the three source directories packaged by rumkinst
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceKind {
+    Root,
+    Env,
+    Scripts,
+}
+
+/// Builds a [`Config`] without going through `rumkinst.toml`
+///
+/// Anything left unset takes the same default it would have when missing from the toml file.
+/// Values are only validated once [`ConfigBuilder::build`] is called.
+#[derive(Debug, Clone)]
+pub struct ConfigBuilder {
+    name: String,
+    version: Option<String>,
+    description: Option<String>,
+    authors: Vec<String>,
+    changelog: Option<PathBuf>,
+
+    allow_user_install: Option<bool>,
+    theme: Option<ThemeType>,
+    preinstall: Option<PathBuf>,
+    postinstall: Option<PathBuf>,
+
+    prebuild: Option<PathBuf>,
+    postbuild: Option<PathBuf>,
+    deny_warnings: Option<bool>,
+    archive_format: Option<ArchiveFormat>,
+    unusual_names: Option<UnusualNames>,
+    case_collisions: Option<CaseCollisions>,
+    duplicate_entries: Option<DuplicateEntries>,
+    windows_names: Option<bool>,
+    jobs: Option<NonZeroUsize>,
+    xattrs: Option<bool>,
+    acls: Option<bool>,
+
+    deb: Option<DebBuilder>,
+    flatpak: Option<FlatpakBuilder>,
+    oci: Option<OciBuilder>,
+    nsis: bool,
+    oci_registry: Option<String>,
+    github_repository: Option<String>,
+    s3_bucket: Option<String>,
+    http_url: Option<String>,
+    gitlab_project: Option<String>,
+    feed: Option<(PathBuf, FeedFormat)>,
+    log_file: Option<PathBuf>,
+    lint_levels: Vec<(LintRule, LintLevel)>,
+
+    root: SourceBuilder,
+    env: SourceBuilder,
+    scripts: SourceBuilder,
+    downloads: Vec<(String, String, PathBuf)>,
+    git_sources: Vec<(String, String, Option<PathBuf>, PathBuf)>,
+    command_sources: Vec<CommandSource>,
+    transforms: Vec<(Vec<String>, Transform)>,
+    licenses: Option<(bool, Vec<PathBuf>)>,
+    targets: Vec<(String, Vec<String>)>,
+    plugins: Vec<(String, Vec<PluginStage>)>,
+    wasm_hooks: Vec<PathBuf>,
+    extra_files: Vec<(PathBuf, PathBuf, Option<u32>)>,
+    build_info: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct DebBuilder {
+    architecture: Option<String>,
+    depends: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct FlatpakBuilder {
+    app_id: String,
+    command: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct OciBuilder {
+    base: Option<PathBuf>,
+    entrypoint: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct SourceBuilder {
+    disable: Option<bool>,
+    path: Option<PathBuf>,
+    exclude: Vec<PathBuf>,
+    special_files: Option<SpecialFiles>,
+    strip_prefix: Option<PathBuf>,
+    allow_absolute: Option<bool>,
+    archive_prefix: Option<PathBuf>,
+}
+
+impl SourceBuilder {
+    fn build(self) -> Result<InternalSourceConfig, RumkinstError> {
+        Ok(InternalSourceConfig {
+            disable: self.disable,
+            path: self
+                .path
+                .map(SourcePath::try_from)
+                .transpose()?
+                .map(|path| toml::Spanned::new(0..0, path)),
+            exclude: Some(
+                self.exclude
+                    .into_iter()
+                    .map(RelativePathBuf::try_from)
+                    .collect::<Result<_, _>>()?,
+            ),
+            special_files: self.special_files,
+            strip_prefix: self
+                .strip_prefix
+                .map(RelativePathBuf::try_from)
+                .transpose()?,
+            allow_absolute: self.allow_absolute,
+            archive_prefix: self
+                .archive_prefix
+                .map(RelativePathBuf::try_from)
+                .transpose()?,
+        })
+    }
+}
+
+impl ConfigBuilder {
+    /// Start a config for the package `name`, which must be a valid identifier
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            version: None,
+            description: None,
+            authors: vec![],
+            changelog: None,
+            allow_user_install: None,
+            theme: None,
+            preinstall: None,
+            postinstall: None,
+            prebuild: None,
+            postbuild: None,
+            deny_warnings: None,
+            archive_format: None,
+            unusual_names: None,
+            case_collisions: None,
+            duplicate_entries: None,
+            windows_names: None,
+            jobs: None,
+            xattrs: None,
+            acls: None,
+            deb: None,
+            flatpak: None,
+            oci: None,
+            nsis: false,
+            oci_registry: None,
+            github_repository: None,
+            s3_bucket: None,
+            http_url: None,
+            gitlab_project: None,
+            feed: None,
+            log_file: None,
+            lint_levels: vec![],
+            root: SourceBuilder::default(),
+            env: SourceBuilder::default(),
+            scripts: SourceBuilder::default(),
+            downloads: vec![],
+            git_sources: vec![],
+            command_sources: vec![],
+            transforms: vec![],
+            licenses: None,
+            targets: vec![],
+            plugins: vec![],
+            wasm_hooks: vec![],
+            extra_files: vec![],
+            build_info: None,
+        }
+    }
+
+    pub fn with_version(mut self, version: impl Into<String>) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn with_author(mut self, author: impl Into<String>) -> Self {
+        self.authors.push(author.into());
+        self
+    }
+
+    /// Archive the markdown changelog at `path` and make release notes from its section for the
+    /// package version
+    pub fn with_changelog(mut self, path: impl Into<PathBuf>) -> Self {
+        self.changelog = Some(path.into());
+        self
+    }
+
+    pub fn with_allow_user_install(mut self, allow: bool) -> Self {
+        self.allow_user_install = Some(allow);
+        self
+    }
+
+    pub fn with_theme(mut self, theme: ThemeType) -> Self {
+        self.theme = Some(theme);
+        self
+    }
+
+    /// Script run by the installer before any files are installed
+    pub fn with_preinstall(mut self, path: impl Into<PathBuf>) -> Self {
+        self.preinstall = Some(path.into());
+        self
+    }
+
+    /// Script run by the installer after every file is installed
+    pub fn with_postinstall(mut self, path: impl Into<PathBuf>) -> Self {
+        self.postinstall = Some(path.into());
+        self
+    }
+
+    /// Hook run before building, defaults to `./prebuild.sh`
+    pub fn with_prebuild(mut self, path: impl Into<PathBuf>) -> Self {
+        self.prebuild = Some(path.into());
+        self
+    }
+
+    /// Hook run after building, defaults to `./postbuild.sh`
+    pub fn with_postbuild(mut self, path: impl Into<PathBuf>) -> Self {
+        self.postbuild = Some(path.into());
+        self
+    }
+
+    /// Report findings of the lint `rule` at `level`, replacing any level set for it before
+    pub fn with_lint_level(mut self, rule: LintRule, level: LintLevel) -> Self {
+        self.lint_levels.retain(|(set, _)| *set != rule);
+        self.lint_levels.push((rule, level));
+        self
+    }
+
+    /// Fail builds that report any warnings
+    pub fn with_deny_warnings(mut self, deny: bool) -> Self {
+        self.deny_warnings = Some(deny);
+        self
+    }
+
+    /// Archive packaged files as `format`, defaults to a gzip compressed tarball
+    pub fn with_archive_format(mut self, format: ArchiveFormat) -> Self {
+        self.archive_format = Some(format);
+        self
+    }
+
+    /// What to do with source files whose names aren't UTF-8 or have control characters,
+    /// defaults to packaging them
+    pub fn with_unusual_names(mut self, unusual_names: UnusualNames) -> Self {
+        self.unusual_names = Some(unusual_names);
+        self
+    }
+
+    /// What to do with packaged paths that only differ by case, defaults to a warning
+    pub fn with_case_collisions(mut self, case_collisions: CaseCollisions) -> Self {
+        self.case_collisions = Some(case_collisions);
+        self
+    }
+
+    /// Which of the files archived under the same entry path to keep, defaults to failing
+    pub fn with_duplicate_entries(mut self, duplicate_entries: DuplicateEntries) -> Self {
+        self.duplicate_entries = Some(duplicate_entries);
+        self
+    }
+
+    /// Warn about packaged paths that can't be extracted on Windows, defaults to only warning
+    /// when a Windows installer is made
+    pub fn with_windows_names(mut self, check: bool) -> Self {
+        self.windows_names = Some(check);
+        self
+    }
+
+    /// Make artifacts on at most `jobs` threads, defaults to the available parallelism
+    pub fn with_jobs(mut self, jobs: NonZeroUsize) -> Self {
+        self.jobs = Some(jobs);
+        self
+    }
+
+    /// Store the extended attributes of packaged files in tarballs, defaults to `false`
+    pub fn with_xattrs(mut self, xattrs: bool) -> Self {
+        self.xattrs = Some(xattrs);
+        self
+    }
+
+    /// Store the POSIX ACLs of packaged files in tarballs, defaults to `false`
+    pub fn with_acls(mut self, acls: bool) -> Self {
+        self.acls = Some(acls);
+        self
+    }
+
+    /// Also build a `.deb` for `architecture`, which needs a version to be set
+    pub fn with_deb(mut self, architecture: impl Into<String>) -> Self {
+        self.deb.get_or_insert_default().architecture = Some(architecture.into());
+        self
+    }
+
+    /// Add a dependency to the `.deb`, in the syntax of a `Depends` field
+    pub fn with_deb_depends(mut self, dependency: impl Into<String>) -> Self {
+        self.deb
+            .get_or_insert_default()
+            .depends
+            .push(dependency.into());
+        self
+    }
+
+    /// Also write a Flatpak manifest for `app_id`, a reverse-DNS id such as `org.example.App`
+    pub fn with_flatpak(mut self, app_id: impl Into<String>, command: Option<String>) -> Self {
+        self.flatpak = Some(FlatpakBuilder {
+            app_id: app_id.into(),
+            command,
+        });
+        self
+    }
+
+    /// Also build an OCI image, running `entrypoint` when started
+    pub fn with_oci_entrypoint(mut self, entrypoint: Vec<String>) -> Self {
+        self.oci.get_or_insert_default().entrypoint = Some(entrypoint);
+        self
+    }
+
+    /// Also build an OCI image, on top of the OCI image layout directory at `path`
+    pub fn with_oci_base(mut self, path: impl Into<PathBuf>) -> Self {
+        self.oci.get_or_insert_default().base = Some(path.into());
+        self
+    }
+
+    /// Also build a Windows installer with NSIS
+    pub fn with_nsis(mut self) -> Self {
+        self.nsis = true;
+        self
+    }
+
+    /// Push the finished artifacts to the OCI registry repository `reference`
+    pub fn with_oci_registry(mut self, reference: impl Into<String>) -> Self {
+        self.oci_registry = Some(reference.into());
+        self
+    }
+
+    /// Upload the finished artifacts to a release of the GitHub repository `owner/name`
+    pub fn with_github_release(mut self, repository: impl Into<String>) -> Self {
+        self.github_repository = Some(repository.into());
+        self
+    }
+
+    /// Upload the finished artifacts to the S3 bucket `bucket`
+    pub fn with_s3_bucket(mut self, bucket: impl Into<String>) -> Self {
+        self.s3_bucket = Some(bucket.into());
+        self
+    }
+
+    /// Upload each finished artifact with a `PUT` to `url`, see `[publish.http]` for its
+    /// placeholders
+    pub fn with_http_upload(mut self, url: impl Into<String>) -> Self {
+        self.http_url = Some(url.into());
+        self
+    }
+
+    /// Upload the finished artifacts to the generic package registry of the GitLab project
+    /// `project`, and link them from a release
+    pub fn with_gitlab_release(mut self, project: impl Into<String>) -> Self {
+        self.gitlab_project = Some(project.into());
+        self
+    }
+
+    /// Copy every log record of a build into the file at `path`, rotated at the default size
+    pub fn with_log_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.log_file = Some(path.into());
+        self
+    }
+
+    /// Keep the update feed at `path` up to date with every published release
+    pub fn with_update_feed(mut self, path: impl Into<PathBuf>, format: FeedFormat) -> Self {
+        self.feed = Some((path.into(), format));
+        self
+    }
+
+    /// Read `kind` from `path` instead of its default directory
+    pub fn with_source_path(mut self, kind: SourceKind, path: impl Into<PathBuf>) -> Self {
+        self.source(kind).path = Some(path.into());
+        self
+    }
+
+    /// Leave `path` out when searching `kind`
+    pub fn with_exclude(mut self, kind: SourceKind, path: impl Into<PathBuf>) -> Self {
+        self.source(kind).exclude.push(path.into());
+        self
+    }
+
+    /// Skip `kind` entirely
+    pub fn with_source_disabled(mut self, kind: SourceKind) -> Self {
+        self.source(kind).disable = Some(true);
+        self
+    }
+
+    /// What to do with sockets, fifos and device nodes found in `kind`, defaults to skipping
+    /// them with a warning
+    pub fn with_special_files(mut self, kind: SourceKind, special_files: SpecialFiles) -> Self {
+        self.source(kind).special_files = Some(special_files);
+        self
+    }
+
+    /// Archive the files of `kind` without the leading directories in `prefix`, so
+    /// `root/opt/app/bin` is archived as `opt/app/bin` with a prefix of `root`
+    pub fn with_strip_prefix(mut self, kind: SourceKind, prefix: impl Into<PathBuf>) -> Self {
+        self.source(kind).strip_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Allow the path of `kind` to be absolute, for files kept outside of the project
+    pub fn with_allow_absolute(mut self, kind: SourceKind) -> Self {
+        self.source(kind).allow_absolute = Some(true);
+        self
+    }
+
+    /// Archive the files of `kind` under `prefix` when its path is absolute, instead of under
+    /// its default directory
+    pub fn with_archive_prefix(mut self, kind: SourceKind, prefix: impl Into<PathBuf>) -> Self {
+        self.source(kind).archive_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Download `url` in every build and package it at `dest`, which has to be inside one of the
+    /// sources. The download has to match `sha256`, given in hex.
+    pub fn with_download(
+        mut self,
+        url: impl Into<String>,
+        sha256: impl Into<String>,
+        dest: impl Into<PathBuf>,
+    ) -> Self {
+        self.downloads
+            .push((url.into(), sha256.into(), dest.into()));
+        self
+    }
+
+    /// Check out `rev` of the git repository at `url` in every build, and package its directory
+    /// `path`, or all of it, at `dest` inside one of the sources
+    pub fn with_git_source(
+        mut self,
+        url: impl Into<String>,
+        rev: impl Into<String>,
+        path: Option<PathBuf>,
+        dest: impl Into<PathBuf>,
+    ) -> Self {
+        self.git_sources
+            .push((url.into(), rev.into(), path, dest.into()));
+        self
+    }
+
+    /// Run `run` in every build, unless none of `inputs` changed since it last ran, and package
+    /// the `outputs` it writes into `RUMKINST_SOURCE_OUT` at `dest` inside one of the sources
+    pub fn with_command_source(
+        mut self,
+        run: Vec<String>,
+        outputs: Vec<PathBuf>,
+        inputs: Option<Vec<PathBuf>>,
+        dest: impl Into<PathBuf>,
+    ) -> Self {
+        self.command_sources.push(CommandSource {
+            run,
+            outputs,
+            inputs,
+            dest: dest.into(),
+            targets: vec![],
+        });
+        self
+    }
+
+    /// Run every file matching one of the glob patterns in `files` through `transform` before
+    /// archiving it, after the transforms added before
+    pub fn with_transform(mut self, files: Vec<String>, transform: Transform) -> Self {
+        self.transforms.push((files, transform));
+        self
+    }
+
+    /// Bundle license files into the `licenses` directory of the archive, the ones found in the
+    /// sources if `scan` is set, and every file in `include`
+    pub fn with_licenses(mut self, scan: bool, include: Vec<PathBuf>) -> Self {
+        self.licenses = Some((scan, include));
+        self
+    }
+
+    /// Make a separate archive for the target called `name`, leaving out every file matching
+    /// one of the glob patterns in `exclude`
+    pub fn with_target(mut self, name: impl Into<String>, exclude: Vec<String>) -> Self {
+        self.targets.push((name.into(), exclude));
+        self
+    }
+
+    /// Call the plugin `rumkinst-<name>` from `PATH` at each of `stages`, after the plugins added
+    /// before
+    pub fn with_plugin(mut self, name: impl Into<String>, stages: Vec<PluginStage>) -> Self {
+        self.plugins.push((name.into(), stages));
+        self
+    }
+
+    /// Run the WebAssembly module at `module` once the sources are found, after the hooks added
+    /// before
+    pub fn with_wasm_hook(mut self, module: impl Into<PathBuf>) -> Self {
+        self.wasm_hooks.push(module.into());
+        self
+    }
+
+    /// Archive the file at `src` as `dest` after the sources, with the unix permissions `mode`
+    /// if set
+    pub fn with_extra_file(
+        mut self,
+        src: impl Into<PathBuf>,
+        dest: impl Into<PathBuf>,
+        mode: Option<u32>,
+    ) -> Self {
+        self.extra_files.push((src.into(), dest.into(), mode));
+        self
+    }
+
+    /// Archive a generated file describing the build as `entry`
+    pub fn with_build_info(mut self, entry: impl Into<PathBuf>) -> Self {
+        self.build_info = Some(entry.into());
+        self
+    }
+
+    fn source(&mut self, kind: SourceKind) -> &mut SourceBuilder {
+        match kind {
+            SourceKind::Root => &mut self.root,
+            SourceKind::Env => &mut self.env,
+            SourceKind::Scripts => &mut self.scripts,
+        }
+    }
+
+    /// Validate every value, the same way they would be when read from `rumkinst.toml`
+    pub fn build(self) -> Result<Config, RumkinstError> {
+        let relative = |path: Option<PathBuf>| path.map(RelativePathBuf::try_from).transpose();
+
+        let config = InternalConfig {
+            package: InternalPackageDetails {
+                name: Identifier::try_from(self.name)?,
+                version: self.version,
+                description: self.description,
+                authors: Some(self.authors),
+                changelog: relative(self.changelog)?,
+            },
+            installer: Some(InternalInstallerConfig {
+                allow_user_install: self.allow_user_install,
+                theme: self.theme,
+                preinstall: relative(self.preinstall)?,
+                postinstall: relative(self.postinstall)?,
+            }),
+            build: Some(InternalBuildConfig {
+                prebuild: relative(self.prebuild)?,
+                postbuild: relative(self.postbuild)?,
+                deny_warnings: self.deny_warnings,
+                archive_format: self.archive_format,
+                unusual_names: self.unusual_names,
+                case_collisions: self.case_collisions,
+                duplicate_entries: self.duplicate_entries,
+                windows_names: self.windows_names,
+                jobs: self.jobs,
+                xattrs: self.xattrs,
+                acls: self.acls,
+            }),
+            output: match self.extra_files.is_empty() && self.build_info.is_none() {
+                true => None,
+                false => Some(InternalOutputConfig {
+                    keep_runs: None,
+                    max_size: None,
+                    run_id: None,
+                    extra_files: Some(
+                        self.extra_files
+                            .into_iter()
+                            .map(|(src, dest, mode)| {
+                                Ok::<_, RumkinstError>(InternalExtraFile {
+                                    src: RelativePathBuf::try_from(src)?,
+                                    dest: RelativePathBuf::try_from(dest)?,
+                                    mode: mode
+                                        .map(|mode| {
+                                            FileMode::try_from(format!("{mode:o}").as_str())
+                                        })
+                                        .transpose()?,
+                                })
+                            })
+                            .collect::<Result<_, _>>()?,
+                    ),
+                    build_info: relative(self.build_info)?,
+                    warn_size: None,
+                    environment: None,
+                }),
+            },
+            deb: self.deb.map(|deb| InternalDebConfig {
+                architecture: deb.architecture,
+                maintainer: None,
+                section: None,
+                depends: Some(deb.depends),
+            }),
+            flatpak: self
+                .flatpak
+                .map(|flatpak| {
+                    Ok::<_, RumkinstError>(InternalFlatpakConfig {
+                        app_id: AppId::try_from(flatpak.app_id)?,
+                        runtime: None,
+                        runtime_version: None,
+                        sdk: None,
+                        command: flatpak.command,
+                        finish_args: None,
+                        archive_url: None,
+                    })
+                })
+                .transpose()?,
+            oci: self
+                .oci
+                .map(|oci| {
+                    Ok::<_, RumkinstError>(InternalOciConfig {
+                        base: relative(oci.base)?,
+                        entrypoint: oci.entrypoint,
+                        cmd: None,
+                        tag: None,
+                        architecture: None,
+                        load: None,
+                    })
+                })
+                .transpose()?,
+            nsis: self.nsis.then_some(InternalNsisConfig {
+                install_dir: None,
+                publisher: None,
+                compile: None,
+            }),
+            publish: Some(InternalPublishConfig {
+                oci: self.oci_registry.map(|reference| InternalOciPublishConfig {
+                    reference,
+                    artifact_type: None,
+                    plain_http: None,
+                }),
+                github: self
+                    .github_repository
+                    .map(|repository| InternalGithubPublishConfig {
+                        repository,
+                        tag: None,
+                        draft: None,
+                        prerelease: None,
+                    }),
+                s3: self.s3_bucket.map(|bucket| InternalS3PublishConfig {
+                    bucket,
+                    prefix: None,
+                    endpoint: None,
+                    region: None,
+                    public: None,
+                }),
+                http: self.http_url.map(|url| InternalHttpPublishConfig {
+                    url,
+                    method: None,
+                    headers: None,
+                    token: None,
+                    username: None,
+                    password: None,
+                    token_env: None,
+                    username_env: None,
+                    password_env: None,
+                }),
+                gitlab: self
+                    .gitlab_project
+                    .map(|project| InternalGitlabPublishConfig {
+                        project,
+                        url: None,
+                        package: None,
+                        tag: None,
+                        git_ref: None,
+                        token: None,
+                        token_env: None,
+                    }),
+            }),
+            feed: self
+                .feed
+                .map(|(path, format)| {
+                    Ok::<_, RumkinstError>(InternalFeedConfig {
+                        path: RelativePathBuf::try_from(path)?,
+                        format: Some(format),
+                        url: None,
+                        channel: None,
+                    })
+                })
+                .transpose()?,
+            log: self
+                .log_file
+                .map(|file| {
+                    Ok::<_, RumkinstError>(InternalLogConfig {
+                        file: RelativePathBuf::try_from(file)?,
+                        max_size: None,
+                        keep: None,
+                    })
+                })
+                .transpose()?,
+            lint: Some(InternalLintConfig {
+                allow: Some(rules_at(&self.lint_levels, LintLevel::Allow)),
+                deny: Some(rules_at(&self.lint_levels, LintLevel::Deny)),
+            }),
+            root: Some(self.root.build()?),
+            env: Some(self.env.build()?),
+            scripts: Some(self.scripts.build()?),
+            sources: Some(InternalSourcesConfig {
+                download: Some(
+                    self.downloads
+                        .into_iter()
+                        .map(|(url, sha256, dest)| {
+                            Ok::<_, RumkinstError>(InternalDownloadSource {
+                                url,
+                                sha256: Sha256Hex::try_from(sha256.as_str())?,
+                                dest: RelativePathBuf::try_from(dest)?,
+                                targets: None,
+                            })
+                        })
+                        .collect::<Result<_, _>>()?,
+                ),
+                git: Some(
+                    self.git_sources
+                        .into_iter()
+                        .map(|(git, rev, path, dest)| {
+                            Ok::<_, RumkinstError>(InternalGitSource {
+                                git,
+                                rev,
+                                path: relative(path)?,
+                                dest: RelativePathBuf::try_from(dest)?,
+                                targets: None,
+                            })
+                        })
+                        .collect::<Result<_, _>>()?,
+                ),
+                command: Some(
+                    self.command_sources
+                        .into_iter()
+                        .map(|source| {
+                            let relative_all = |paths: Vec<PathBuf>| {
+                                paths
+                                    .into_iter()
+                                    .map(RelativePathBuf::try_from)
+                                    .collect::<Result<Vec<_>, _>>()
+                            };
+                            Ok::<_, RumkinstError>(InternalCommandSource {
+                                run: source.run,
+                                outputs: relative_all(source.outputs)?,
+                                inputs: source.inputs.map(relative_all).transpose()?,
+                                dest: RelativePathBuf::try_from(source.dest)?,
+                                targets: None,
+                            })
+                        })
+                        .collect::<Result<_, _>>()?,
+                ),
+                cache_max_age: None,
+                cache_max_size: None,
+            }),
+            transforms: Some(
+                self.transforms
+                    .into_iter()
+                    .map(|(files, transform)| {
+                        Ok::<_, RumkinstError>(InternalTransformRule {
+                            files: files
+                                .iter()
+                                .map(|pattern| GlobPattern::try_from(pattern.as_str()))
+                                .collect::<Result<_, _>>()?,
+                            transform,
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            licenses: self
+                .licenses
+                .map(|(scan, include)| {
+                    Ok::<_, RumkinstError>(InternalLicensesConfig {
+                        scan: Some(scan),
+                        include: Some(
+                            include
+                                .into_iter()
+                                .map(RelativePathBuf::try_from)
+                                .collect::<Result<_, _>>()?,
+                        ),
+                        dir: None,
+                    })
+                })
+                .transpose()?,
+            targets: Some(
+                self.targets
+                    .into_iter()
+                    .map(|(name, exclude)| {
+                        let exclude = exclude
+                            .iter()
+                            .map(|pattern| GlobPattern::try_from(pattern.as_str()))
+                            .collect::<Result<_, _>>()?;
+                        Ok::<_, RumkinstError>((
+                            Identifier::try_from(name)?,
+                            InternalTargetConfig {
+                                exclude: Some(exclude),
+                            },
+                        ))
+                    })
+                    .collect::<Result<BTreeMap<_, _>, _>>()?,
+            ),
+            plugins: Some(
+                self.plugins
+                    .into_iter()
+                    .map(|(name, stages)| {
+                        Ok::<_, RumkinstError>(InternalPluginConfig {
+                            name: Identifier::try_from(name)?,
+                            command: None,
+                            stages,
+                            options: None,
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            wasm_hooks: Some(
+                self.wasm_hooks
+                    .into_iter()
+                    .map(|module| {
+                        Ok::<_, RumkinstError>(InternalWasmHookConfig {
+                            module: RelativePathBuf::try_from(module)?,
+                            options: None,
+                            fuel: None,
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+        };
+
+        Ok(config.into())
+    }
+}
+
+/// The rules set to `level` in `levels`
+fn rules_at(levels: &[(LintRule, LintLevel)], level: LintLevel) -> Vec<LintRule> {
+    levels
+        .iter()
+        .filter(|(_, set)| *set == level)
+        .map(|(rule, _)| *rule)
+        .collect()
+}
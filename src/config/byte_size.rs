@@ -0,0 +1,102 @@
+use serde::{
+    de::{Deserialize, Deserializer, Visitor},
+    ser::Serialize,
+};
+
+use super::refuse;
+use crate::RumkinstError;
+
+/// A number of bytes, written either as a plain integer or with a unit, e.g. `500MB` or `2GiB`
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ByteSize(u64);
+
+impl ByteSize {
+    pub(crate) fn bytes(&self) -> u64 {
+        self.0
+    }
+}
+
+impl TryFrom<&str> for ByteSize {
+    type Error = RumkinstError;
+    fn try_from(value: &str) -> Result<Self, RumkinstError> {
+        let invalid = |reason: &str| RumkinstError::InvalidByteSize {
+            size: value.to_string(),
+            reason: reason.to_string(),
+        };
+
+        let trimmed = value.trim();
+        let unit_start = trimmed
+            .find(|ch: char| !(ch.is_ascii_digit() || ch == '.'))
+            .unwrap_or(trimmed.len());
+        let (number, unit) = trimmed.split_at(unit_start);
+
+        let number: f64 = number
+            .parse()
+            .map_err(|_| invalid("must start with a number"))?;
+        let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "k" | "kb" => 1000,
+            "m" | "mb" => 1000_u64.pow(2),
+            "g" | "gb" => 1000_u64.pow(3),
+            "t" | "tb" => 1000_u64.pow(4),
+            "kib" => 1 << 10,
+            "mib" => 1 << 20,
+            "gib" => 1 << 30,
+            "tib" => 1 << 40,
+            _ => return Err(invalid(&format!("unknown unit `{}`", unit.trim()))),
+        };
+
+        Ok(Self((number * multiplier as f64) as u64))
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct ByteSizeVisitor;
+
+        impl<'de> Visitor<'de> for ByteSizeVisitor {
+            type Value = ByteSize;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a number of bytes, or a size such as \"2GiB\"")
+            }
+
+            fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                Ok(ByteSize(v))
+            }
+
+            fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                u64::try_from(v)
+                    .map(ByteSize)
+                    .map_err(|_| serde::de::Error::custom("size cannot be negative"))
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                ByteSize::try_from(v).map_err(refuse)
+            }
+        }
+
+        deserializer.deserialize_any(ByteSizeVisitor)
+    }
+}
+
+impl Serialize for ByteSize {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.0.serialize(serializer)
+    }
+}
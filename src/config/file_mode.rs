@@ -0,0 +1,78 @@
+use serde::{
+    de::{Deserialize, Deserializer, Visitor},
+    ser::Serialize,
+};
+
+use super::refuse;
+use crate::RumkinstError;
+
+/// Unix permissions of a file, written as an octal string such as `"0755"`
+///
+/// Plain integers are refused, since `755` in toml is a decimal number.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FileMode(u32);
+
+impl FileMode {
+    pub(crate) fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl TryFrom<&str> for FileMode {
+    type Error = RumkinstError;
+    fn try_from(value: &str) -> Result<Self, RumkinstError> {
+        let invalid = |reason: &str| RumkinstError::InvalidFileMode {
+            mode: value.to_string(),
+            reason: reason.to_string(),
+        };
+
+        let digits = value.strip_prefix("0o").unwrap_or(value);
+        if digits.is_empty() {
+            return Err(invalid("is empty"));
+        }
+        if let Some(invalid_char) = digits.chars().find(|ch| !('0'..='7').contains(ch)) {
+            return Err(invalid(&format!(
+                "contains `{invalid_char}`, which is not an octal digit"
+            )));
+        }
+        match u32::from_str_radix(digits, 8) {
+            Ok(mode) if mode <= 0o7777 => Ok(Self(mode)),
+            _ => Err(invalid("is larger than 7777")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for FileMode {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct FileModeVisitor;
+
+        impl<'de> Visitor<'de> for FileModeVisitor {
+            type Value = FileMode;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("unix permissions as an octal string, such as \"0755\"")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                FileMode::try_from(v).map_err(refuse)
+            }
+        }
+
+        deserializer.deserialize_str(FileModeVisitor)
+    }
+}
+
+impl Serialize for FileMode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        format!("{:04o}", self.0).serialize(serializer)
+    }
+}
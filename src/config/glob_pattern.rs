@@ -0,0 +1,92 @@
+use std::path::{Component, Path};
+
+use glob::{MatchOptions, Pattern};
+use serde::{
+    de::{Deserialize, Deserializer, Visitor},
+    ser::Serialize,
+};
+
+use super::refuse;
+use crate::RumkinstError;
+
+/// A glob pattern matched against paths relative to the base directory, such as
+/// `root/share/**/*.css`
+///
+/// `*` and `?` never match a `/`, while `**` matches any number of directories. Paths are
+/// matched with `/` separators on every platform.
+#[derive(Debug, Clone)]
+pub(crate) struct GlobPattern(Pattern);
+
+impl GlobPattern {
+    pub(crate) fn matches(&self, path: &Path) -> bool {
+        let path = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(part) => Some(part.to_string_lossy()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        let options = MatchOptions {
+            case_sensitive: true,
+            require_literal_separator: true,
+            require_literal_leading_dot: false,
+        };
+        self.0.matches_with(&path, options)
+    }
+}
+
+impl TryFrom<&str> for GlobPattern {
+    type Error = RumkinstError;
+    fn try_from(value: &str) -> Result<Self, RumkinstError> {
+        let invalid = |reason: &str| RumkinstError::InvalidGlob {
+            pattern: value.to_string(),
+            reason: reason.to_string(),
+        };
+
+        if value.starts_with('/') {
+            return Err(invalid(
+                "it is absolute, patterns are relative to the base directory",
+            ));
+        }
+        let trimmed = value.trim_start_matches("./");
+        Pattern::new(trimmed)
+            .map(Self)
+            .map_err(|err| invalid(err.msg))
+    }
+}
+
+impl<'de> Deserialize<'de> for GlobPattern {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct GlobPatternVisitor;
+
+        impl<'de> Visitor<'de> for GlobPatternVisitor {
+            type Value = GlobPattern;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a glob pattern")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                GlobPattern::try_from(v).map_err(refuse)
+            }
+        }
+
+        deserializer.deserialize_str(GlobPatternVisitor)
+    }
+}
+
+impl Serialize for GlobPattern {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.0.as_str().serialize(serializer)
+    }
+}
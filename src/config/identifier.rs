@@ -1,11 +1,13 @@
-use anyhow::{Context, Result};
 use clap::builder::ValueParserFactory;
 use serde::{
     de::{Deserialize, Deserializer, Visitor},
     ser::Serialize,
 };
 
-#[derive(Debug, Clone)]
+use super::refuse;
+use crate::RumkinstError;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Identifier(String);
 
 impl Identifier {
@@ -19,19 +21,21 @@ impl Identifier {
 }
 
 impl TryFrom<String> for Identifier {
-    type Error = anyhow::Error;
-    fn try_from(value: String) -> Result<Self> {
+    type Error = RumkinstError;
+    fn try_from(value: String) -> Result<Self, RumkinstError> {
         if value.is_empty() {
-            anyhow::bail!("cannot create Identifier: source string is empty")
+            return Err(RumkinstError::InvalidIdentifier {
+                reason: "source string is empty".to_string(),
+            });
         }
 
         if let Some(invalid_char) = value
             .chars()
             .find(|ch| !(ch.is_ascii_alphanumeric() || ch == &'-' || ch == &'_'))
         {
-            anyhow::bail!(
-                "cannot create Identifier: source string contains invalid character `{invalid_char}`"
-            )
+            return Err(RumkinstError::InvalidIdentifier {
+                reason: format!("source string contains invalid character `{invalid_char}`"),
+            });
         }
 
         Ok(Self(value))
@@ -39,14 +43,14 @@ impl TryFrom<String> for Identifier {
 }
 
 impl TryFrom<&str> for Identifier {
-    type Error = anyhow::Error;
-    fn try_from(value: &str) -> Result<Self> {
+    type Error = RumkinstError;
+    fn try_from(value: &str) -> Result<Self, RumkinstError> {
         Self::try_from(value.to_string())
     }
 }
 
 impl<'de> Deserialize<'de> for Identifier {
-    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
@@ -59,18 +63,18 @@ impl<'de> Deserialize<'de> for Identifier {
                 formatter.write_str("identifer string (a-z, -, _)")
             }
 
-            fn visit_str<E>(self, v: &str) -> std::result::Result<Self::Value, E>
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
             where
                 E: serde::de::Error,
             {
-                Identifier::try_from(v).map_err(|err| serde::de::Error::custom(format!("{err}")))
+                Identifier::try_from(v).map_err(refuse)
             }
 
-            fn visit_string<E>(self, v: String) -> std::result::Result<Self::Value, E>
+            fn visit_string<E>(self, v: String) -> Result<Self::Value, E>
             where
                 E: serde::de::Error,
             {
-                Identifier::try_from(v).map_err(|err| serde::de::Error::custom(format!("{err}")))
+                Identifier::try_from(v).map_err(refuse)
             }
         }
 
@@ -79,18 +83,18 @@ impl<'de> Deserialize<'de> for Identifier {
 }
 
 impl ValueParserFactory for Identifier {
-    type Parser = fn(&str) -> Result<Identifier>;
+    type Parser = fn(&str) -> Result<Identifier, RumkinstError>;
     fn value_parser() -> Self::Parser {
         validate_identifier
     }
 }
 
-fn validate_identifier(value: &str) -> Result<Identifier> {
-    Identifier::try_from(value).context("invalid character in identifier string")
+fn validate_identifier(value: &str) -> Result<Identifier, RumkinstError> {
+    Identifier::try_from(value)
 }
 
 impl Serialize for Identifier {
-    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
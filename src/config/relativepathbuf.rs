@@ -1,37 +1,63 @@
-use std::path::{Path, PathBuf};
+use std::path::{Component, PathBuf};
 
-use anyhow::{Context, Result};
 use serde::{
     de::{Deserialize, Deserializer, Visitor},
     ser::Serialize,
 };
 
+use super::refuse;
+use crate::RumkinstError;
+
+/// A path without a root or drive prefix, resolved against the base directory
+///
+/// Config files are shared between platforms, so both `/` and `\` are separators when parsing
+/// from a string, whatever the current platform.
 #[derive(Debug, Clone)]
 pub(crate) struct RelativePathBuf(PathBuf);
 
 impl RelativePathBuf {
-    pub(crate) fn as_path(&self) -> &Path {
-        &self.0
-    }
-
     pub(crate) fn into_pathbuf(self) -> PathBuf {
         self.0
     }
 }
 
 impl TryFrom<&str> for RelativePathBuf {
-    type Error = anyhow::Error;
-    fn try_from(value: &str) -> Result<Self> {
-        let path = PathBuf::from(value);
+    type Error = RumkinstError;
+    fn try_from(value: &str) -> Result<Self, RumkinstError> {
+        let drive = matches!(value.as_bytes(), [letter, b':', ..] if letter.is_ascii_alphabetic());
+        if drive || value.starts_with(['/', '\\']) {
+            return Err(RumkinstError::InvalidRelativePath {
+                path: PathBuf::from(value),
+            });
+        }
+        Self::try_from(
+            value
+                .split(['/', '\\'])
+                .filter(|part| !part.is_empty())
+                .collect::<PathBuf>(),
+        )
+    }
+}
 
-        path.is_relative()
-            .then_some(Self(path))
-            .context("cannot create RelativePathBuf: Path is not relative")
+impl TryFrom<PathBuf> for RelativePathBuf {
+    type Error = RumkinstError;
+    fn try_from(path: PathBuf) -> Result<Self, RumkinstError> {
+        // A Windows path like `\dir` or `C:dir` is not absolute, but is not relative to the base
+        // directory either
+        let rooted = path.has_root()
+            || path
+                .components()
+                .any(|component| matches!(component, Component::Prefix(_)));
+        if path.is_relative() && !rooted {
+            Ok(Self(path))
+        } else {
+            Err(RumkinstError::InvalidRelativePath { path })
+        }
     }
 }
 
 impl<'de> Deserialize<'de> for RelativePathBuf {
-    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
@@ -44,20 +70,18 @@ impl<'de> Deserialize<'de> for RelativePathBuf {
                 formatter.write_str("relative path")
             }
 
-            fn visit_str<E>(self, v: &str) -> std::result::Result<Self::Value, E>
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
             where
                 E: serde::de::Error,
             {
-                RelativePathBuf::try_from(v)
-                    .map_err(|err| serde::de::Error::custom(format!("{err}")))
+                RelativePathBuf::try_from(v).map_err(refuse)
             }
 
-            fn visit_string<E>(self, v: String) -> std::result::Result<Self::Value, E>
+            fn visit_string<E>(self, v: String) -> Result<Self::Value, E>
             where
                 E: serde::de::Error,
             {
-                RelativePathBuf::try_from(v.as_str())
-                    .map_err(|err| serde::de::Error::custom(format!("{err}")))
+                RelativePathBuf::try_from(v.as_str()).map_err(refuse)
             }
         }
 
@@ -66,7 +90,7 @@ impl<'de> Deserialize<'de> for RelativePathBuf {
 }
 
 impl Serialize for RelativePathBuf {
-    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: serde::Serializer,
     {
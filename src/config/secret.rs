@@ -0,0 +1,100 @@
+use std::{fmt::Display, path::PathBuf};
+
+use serde::{
+    Deserialize, Serialize,
+    de::{Deserializer, MapAccess, Visitor, value::MapAccessDeserializer},
+};
+
+use crate::secrets::register_secret;
+
+/// Where a secret such as an access token is read from, as the secret itself is never written
+/// in rumkinst.toml
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum SecretSource {
+    /// An environment variable, `{ env = "NAME" }`
+    Env(String),
+    /// A file, relative to the base directory, without its trailing newline,
+    /// `{ file = "path" }`
+    File(PathBuf),
+    /// An entry of the OS keyring, `{ keyring = { service = "...", user = "..." } }`
+    Keyring { service: String, user: String },
+}
+
+impl Display for SecretSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SecretSource::Env(name) => write!(f, "environment variable `{name}`"),
+            SecretSource::File(path) => write!(f, "file {path:?}"),
+            SecretSource::Keyring { service, user } => {
+                write!(f, "keyring entry `{user}` of `{service}`")
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalSecretSource {
+    env: Option<String>,
+    file: Option<PathBuf>,
+    keyring: Option<InternalKeyringEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InternalKeyringEntry {
+    service: String,
+    user: String,
+}
+
+impl<'de> Deserialize<'de> for SecretSource {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct SecretSourceVisitor;
+
+        impl<'de> Visitor<'de> for SecretSourceVisitor {
+            type Value = SecretSource;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a table with one of `env`, `file` or `keyring`")
+            }
+
+            // The value is likely the secret itself, so it is never echoed back, and is redacted
+            // from the snippet of rumkinst.toml shown with the error
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                register_secret(v);
+                Err(E::custom(
+                    "secrets can't be written in rumkinst.toml, read them with \
+                     `{ env = \"NAME\" }`, `{ file = \"path\" }` or \
+                     `{ keyring = { service = \"...\", user = \"...\" } }` instead",
+                ))
+            }
+
+            fn visit_map<A>(self, map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let source = InternalSecretSource::deserialize(MapAccessDeserializer::new(map))?;
+                match (source.env, source.file, source.keyring) {
+                    (Some(name), None, None) => Ok(SecretSource::Env(name)),
+                    (None, Some(path), None) => Ok(SecretSource::File(path)),
+                    (None, None, Some(entry)) => Ok(SecretSource::Keyring {
+                        service: entry.service,
+                        user: entry.user,
+                    }),
+                    _ => Err(serde::de::Error::custom(
+                        "a secret is read from exactly one of `env`, `file` or `keyring`",
+                    )),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(SecretSourceVisitor)
+    }
+}
@@ -0,0 +1,75 @@
+use serde::{
+    de::{Deserialize, Deserializer, Visitor},
+    ser::Serialize,
+};
+
+use super::refuse;
+use crate::RumkinstError;
+
+/// A SHA-256 digest, written as 64 hex digits and kept in lowercase
+#[derive(Debug, Clone)]
+pub(crate) struct Sha256Hex(String);
+
+impl Sha256Hex {
+    pub(crate) fn into_string(self) -> String {
+        self.0
+    }
+}
+
+impl TryFrom<&str> for Sha256Hex {
+    type Error = RumkinstError;
+    fn try_from(value: &str) -> Result<Self, RumkinstError> {
+        let invalid = |reason: &str| RumkinstError::InvalidChecksum {
+            checksum: value.to_string(),
+            reason: reason.to_string(),
+        };
+
+        if value.len() != 64 {
+            return Err(invalid(&format!(
+                "must be 64 hex digits, not {}",
+                value.len()
+            )));
+        }
+        if let Some(invalid_char) = value.chars().find(|ch| !ch.is_ascii_hexdigit()) {
+            return Err(invalid(&format!(
+                "contains `{invalid_char}`, which is not a hex digit"
+            )));
+        }
+        Ok(Self(value.to_ascii_lowercase()))
+    }
+}
+
+impl<'de> Deserialize<'de> for Sha256Hex {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct Sha256HexVisitor;
+
+        impl<'de> Visitor<'de> for Sha256HexVisitor {
+            type Value = Sha256Hex;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("a SHA-256 digest in hex")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                Sha256Hex::try_from(v).map_err(refuse)
+            }
+        }
+
+        deserializer.deserialize_str(Sha256HexVisitor)
+    }
+}
+
+impl Serialize for Sha256Hex {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.0.serialize(serializer)
+    }
+}
@@ -0,0 +1,81 @@
+use std::path::{Path, PathBuf};
+
+use serde::{
+    de::{Deserialize, Deserializer, Visitor},
+    ser::Serialize,
+};
+
+use super::{refuse, relativepathbuf::RelativePathBuf};
+use crate::RumkinstError;
+
+/// The path of a source, either a [`RelativePathBuf`] or an absolute path on this platform
+///
+/// Absolute paths are only accepted when their source sets `allow-absolute`, which is checked
+/// once the whole config is read, and again when the source is searched.
+#[derive(Debug, Clone)]
+pub(crate) struct SourcePath(PathBuf);
+
+impl SourcePath {
+    pub(crate) fn into_pathbuf(self) -> PathBuf {
+        self.0
+    }
+
+    pub(crate) fn as_path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl TryFrom<&str> for SourcePath {
+    type Error = RumkinstError;
+    fn try_from(value: &str) -> Result<Self, RumkinstError> {
+        match Path::new(value).is_absolute() {
+            true => Ok(Self(PathBuf::from(value))),
+            false => RelativePathBuf::try_from(value).map(|path| Self(path.into_pathbuf())),
+        }
+    }
+}
+
+impl TryFrom<PathBuf> for SourcePath {
+    type Error = RumkinstError;
+    fn try_from(path: PathBuf) -> Result<Self, RumkinstError> {
+        match path.is_absolute() {
+            true => Ok(Self(path)),
+            false => RelativePathBuf::try_from(path).map(|path| Self(path.into_pathbuf())),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SourcePath {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct SourcePathVisitor;
+
+        impl<'de> Visitor<'de> for SourcePathVisitor {
+            type Value = SourcePath;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("relative or absolute path")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: serde::de::Error,
+            {
+                SourcePath::try_from(v).map_err(refuse)
+            }
+        }
+
+        deserializer.deserialize_str(SourcePathVisitor)
+    }
+}
+
+impl Serialize for SourcePath {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.0.serialize(serializer)
+    }
+}
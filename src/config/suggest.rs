@@ -0,0 +1,32 @@
+/// Minimum Jaro-Winkler similarity for a candidate to be suggested
+const SIMILARITY_THRESHOLD: f64 = 0.7;
+
+/// The candidate closest to `input`, if any is close enough to be a likely typo.
+///
+/// Short values score poorly on Jaro-Winkler, so anything a single edit away also counts.
+pub(crate) fn closest<'a>(
+    input: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    candidates
+        .into_iter()
+        .map(|candidate| (strsim::jaro_winkler(input, candidate), candidate))
+        .filter(|(similarity, candidate)| {
+            *similarity >= SIMILARITY_THRESHOLD || strsim::osa_distance(input, candidate) <= 1
+        })
+        .max_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, candidate)| candidate)
+}
+
+/// Suggest a fix for serde's "unknown variant" and "unknown field" messages, which name the
+/// rejected value first and then every accepted value, all in backticks
+pub(crate) fn suggest_for_message(message: &str) -> Option<String> {
+    if !(message.starts_with("unknown variant") || message.starts_with("unknown field")) {
+        return None;
+    }
+
+    let mut quoted = message.split('`').skip(1).step_by(2);
+    let input = quoted.next()?;
+
+    closest(input, quoted).map(|candidate| format!("did you mean `{candidate}`?"))
+}
@@ -0,0 +1,204 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use rumkinst::{
+    config::{Config, find_config_file_at},
+    error_log::{ErrorCode, WithCode},
+};
+use toml_edit::{DocumentMut, Item, Key, Table, Value};
+
+use crate::cli::{ConfigArgs, ConfigCommand, ConfigGetArgs, ConfigSetArgs, ConfigShowArgs};
+
+/// Run the `config` subcommand given by `args`
+pub fn command_config(args: ConfigArgs) -> Result<()> {
+    match args.command {
+        ConfigCommand::Show(args) => {
+            show_config(args).context("failed to show the effective config")
+        }
+        ConfigCommand::Get(args) => get_config(args).context("failed to get the config value"),
+        ConfigCommand::Set(args) => set_config(args).context("failed to set the config value"),
+    }
+}
+
+/// Print the effective config, with a comment after each value saying where it came from
+fn show_config(args: ConfigShowArgs) -> Result<()> {
+    let config_path =
+        find_config_file_at(args.path).context("could not find `rumkinst.toml` config file")?;
+    let text = fs::read_to_string(&config_path)
+        .code_at(ErrorCode::CONFIG_UNREADABLE, &config_path)
+        .with_context(|| format!("failed to read {config_path:?}"))?;
+    let config = Config::read_named(text.as_bytes(), &config_path.to_string_lossy())
+        .with_context(|| format!("could not read rumkinst config at {config_path:?}"))?;
+    let written = text
+        .parse::<toml::Table>()
+        .code_at(ErrorCode::CONFIG_INVALID, &config_path)
+        .with_context(|| format!("failed to parse {config_path:?}"))?;
+
+    let mut effective = toml::to_string(&config.effective())
+        .expect("effective config should always serialize to toml")
+        .parse::<DocumentMut>()
+        .expect("serialized toml should always parse");
+    annotate(effective.as_table_mut(), Some(&written));
+
+    println!("# Effective config of {config_path:?}");
+    println!("# Unset optional settings are left out\n");
+    print!("{effective}");
+    Ok(())
+}
+
+/// Print the effective value of the key given by `args`, strings without quotes and tables as
+/// toml
+fn get_config(args: ConfigGetArgs) -> Result<()> {
+    let config_path =
+        find_config_file_at(args.path).context("could not find `rumkinst.toml` config file")?;
+    let keys = parse_key(&args.key)?;
+    let text = fs::read_to_string(&config_path)
+        .code_at(ErrorCode::CONFIG_UNREADABLE, &config_path)
+        .with_context(|| format!("failed to read {config_path:?}"))?;
+    let config = Config::read_named(text.as_bytes(), &config_path.to_string_lossy())
+        .with_context(|| format!("could not read rumkinst config at {config_path:?}"))?;
+
+    let effective = toml::Value::Table(config.effective());
+    let value = keys
+        .iter()
+        .try_fold(&effective, |value, key| value.get(key.get()))
+        .ok_or(ErrorCode::CONFIG_KEY)
+        .with_context(|| match config.suggest_key(&join_keys(&keys)) {
+            Some(suggestion) => format!(
+                "`{}` is not set and has no default, did you mean `{suggestion}`?",
+                args.key
+            ),
+            None => format!("`{}` is not set and has no default", args.key),
+        })?;
+    match value {
+        toml::Value::String(value) => println!("{value}"),
+        toml::Value::Table(table) => print!(
+            "{}",
+            toml::to_string(table).expect("config tables should always serialize to toml")
+        ),
+        value => println!("{value}"),
+    }
+    Ok(())
+}
+
+/// Change the key given by `args` in rumkinst.toml, keeping the comments and spacing around it,
+/// once the changed config is known to be valid
+fn set_config(args: ConfigSetArgs) -> Result<()> {
+    let config_path =
+        find_config_file_at(args.path).context("could not find `rumkinst.toml` config file")?;
+    let keys = parse_key(&args.key)?;
+    let mut document = read_document(&config_path)?;
+    // Only used to suggest another key, so a config that is already invalid suggests nothing
+    let suggestion = Config::read_named(
+        document.to_string().as_bytes(),
+        &config_path.to_string_lossy(),
+    )
+    .ok()
+    .and_then(|config| config.suggest_key(&join_keys(&keys)));
+
+    let (last, parents) = keys
+        .split_last()
+        .expect("parsed keys should never be empty");
+    let mut table = document.as_table_mut() as &mut dyn toml_edit::TableLike;
+    for (depth, key) in parents.iter().enumerate() {
+        let mut new_table = Table::new();
+        new_table.set_implicit(true);
+        table = table
+            .entry_format(key)
+            .or_insert(Item::Table(new_table))
+            .as_table_like_mut()
+            .ok_or(ErrorCode::CONFIG_KEY.at(&config_path))
+            .with_context(|| format!("`{}` is not a table", join_keys(&keys[..=depth])))?;
+    }
+
+    // Anything that isn't a toml value, such as `1.2.0`, is meant as a string
+    let mut new_value = args
+        .value
+        .parse::<Value>()
+        .unwrap_or_else(|_| Value::from(args.value.as_str()));
+    match table.get_mut(last.get()) {
+        Some(Item::Value(old_value)) => {
+            // Keeps the spacing and any comment after the old value
+            *new_value.decor_mut() = old_value.decor().clone();
+            *old_value = new_value;
+        }
+        Some(Item::None) | None => {
+            new_value.decor_mut().clear();
+            table.insert(last.get(), Item::Value(new_value));
+        }
+        Some(_) => {
+            return Err(ErrorCode::CONFIG_KEY.at(&config_path)).context(format!(
+                "`{}` is a table, set the keys in it instead",
+                args.key
+            ));
+        }
+    }
+
+    let text = document.to_string();
+    Config::read_named(text.as_bytes(), &config_path.to_string_lossy()).with_context(|| {
+        match suggestion {
+            Some(suggestion) => format!(
+                "{config_path:?} would be invalid with the new value, did you mean to set \
+                 `{suggestion}`?"
+            ),
+            None => format!("{config_path:?} would be invalid with the new value"),
+        }
+    })?;
+    fs::write(&config_path, text)
+        .code_at(ErrorCode::CONFIG_WRITE, &config_path)
+        .with_context(|| format!("failed to write {config_path:?}"))?;
+    log::info!("Set `{}` in {config_path:?}", args.key);
+    Ok(())
+}
+
+fn read_document(config_path: &Path) -> Result<DocumentMut> {
+    let text = fs::read_to_string(config_path)
+        .code_at(ErrorCode::CONFIG_UNREADABLE, config_path)
+        .with_context(|| format!("failed to read {config_path:?}"))?;
+    text.parse::<DocumentMut>()
+        .code_at(ErrorCode::CONFIG_INVALID, config_path)
+        .with_context(|| format!("failed to parse {config_path:?}"))
+}
+
+/// The parts of a dotted key, such as `publish.http.headers."X-Token"`
+fn parse_key(key: &str) -> Result<Vec<Key>> {
+    Key::parse(key)
+        .ok()
+        .filter(|keys| !keys.is_empty())
+        .ok_or(ErrorCode::CONFIG_KEY)
+        .with_context(|| format!("`{key}` is not a valid dotted key"))
+}
+
+fn join_keys(keys: &[Key]) -> String {
+    keys.iter()
+        .map(|key| key.display_repr().into_owned())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Note after every value in `table` whether it was set in rumkinst.toml, where the same table
+/// is `written`, or is a default
+fn annotate(table: &mut Table, written: Option<&toml::Table>) {
+    for (key, item) in table.iter_mut() {
+        let written = written.and_then(|written| written.get(key.get()));
+        match item {
+            Item::Value(value) => {
+                let origin = match written {
+                    Some(_) => "rumkinst.toml",
+                    None => "default",
+                };
+                value.decor_mut().set_suffix(format!(" # {origin}"));
+            }
+            Item::Table(table) => annotate(table, written.and_then(toml::Value::as_table)),
+            Item::ArrayOfTables(tables) => {
+                for (index, table) in tables.iter_mut().enumerate() {
+                    let written = written
+                        .and_then(|written| written.get(index))
+                        .and_then(toml::Value::as_table);
+                    annotate(table, written);
+                }
+            }
+            Item::None => {}
+        }
+    }
+}
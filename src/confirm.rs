@@ -0,0 +1,16 @@
+/// Decides whether to go ahead with an operation that replaces or removes something that already
+/// exists, such as an asset on a release that is published again
+pub trait Confirm: Send + Sync {
+    /// Whether to go ahead with what `question` describes
+    fn confirm(&self, question: &str) -> bool;
+}
+
+/// Goes ahead with every operation without asking, which is what builds do by default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AssumeYes;
+
+impl Confirm for AssumeYes {
+    fn confirm(&self, _question: &str) -> bool {
+        true
+    }
+}
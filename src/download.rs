@@ -0,0 +1,467 @@
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    CancelHandle, RumkinstError,
+    cache::CacheKind,
+    config::{Config, DownloadSource, GitSource, SourcesConfig},
+    installer_gen::{Digester, Sha256Digester, to_hex},
+    progress_log::NoProgress,
+    publish::run_tool_with_retries,
+};
+
+/// A file from `[[sources.download]]` or a directory from `[[sources.git]]`, fetched into the
+/// cache
+pub(crate) struct FetchedFile {
+    /// Where the file or directory is packaged, relative to the base directory
+    pub(crate) dest: PathBuf,
+    /// The file or directory in the cache
+    pub(crate) path: PathBuf,
+    /// Names of the targets it is only packaged for, every target if empty
+    pub(crate) targets: Vec<String>,
+    /// Full hash of the commit a `[[sources.git]]` directory was checked out from
+    pub(crate) commit: Option<String>,
+}
+
+/// Age of a partial download after which it is taken to be left behind by a build that was
+/// killed
+const PARTIAL_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The cached copy of `download` in `cache_dir`, which may not exist yet
+///
+/// Downloads are cached by their digest, so the same file is only downloaded once however many
+/// projects or URLs name it.
+pub(crate) fn cached_path(cache_dir: &Path, download: &DownloadSource) -> PathBuf {
+    CacheKind::Downloads.dir(cache_dir).join(&download.sha256)
+}
+
+/// Fetch every `[[sources.download]]` and `[[sources.git]]` of `config` into `cache_dir`
+///
+/// With `refresh`, every download is downloaded again even when it is cached.
+pub(crate) fn fetch_remote_sources(
+    config: &Config,
+    cache_dir: &Path,
+    offline: bool,
+    refresh: bool,
+    cancel: &CancelHandle,
+) -> Result<Vec<FetchedFile>, RumkinstError> {
+    let mut fetched = fetch_downloads(config, cache_dir, offline, refresh, cancel)?;
+    let used = fetched
+        .iter()
+        .map(|file| file.path.clone())
+        .collect::<Vec<_>>();
+    evict_downloads(cache_dir, &config.sources, &used);
+    fetched.extend(fetch_git_sources(config, cache_dir, offline, cancel)?);
+    Ok(fetched)
+}
+
+/// Download every `[[sources.download]]` of `config` that is not cached in `cache_dir` yet, and
+/// check each one against its digest
+///
+/// Cached copies are checked again before they are used, and downloaded again when they no
+/// longer match. When `offline`, only cached downloads can be used.
+fn fetch_downloads(
+    config: &Config,
+    cache_dir: &Path,
+    offline: bool,
+    refresh: bool,
+    cancel: &CancelHandle,
+) -> Result<Vec<FetchedFile>, RumkinstError> {
+    let mut fetched = vec![];
+    for download in &config.sources.download {
+        cancel.check()?;
+        let path = cached_path(cache_dir, download);
+        let cached = !refresh
+            && path.exists()
+            && match sha256_of(&path) {
+                Ok(digest) if digest == download.sha256 => true,
+                Ok(_) => {
+                    log::warn!(
+                        "cached download of {} at {path:?} is corrupted, downloading it again",
+                        download.url
+                    );
+                    false
+                }
+                Err(err) => {
+                    log::debug!("failed to read cached download {path:?}: {err}");
+                    false
+                }
+            };
+        if cached {
+            log::debug!("using cached download of {} at {path:?}", download.url);
+            mark_used(&path);
+        } else {
+            if offline {
+                return Err(RumkinstError::Offline {
+                    feature: "downloading the sources in [[sources.download]]",
+                });
+            }
+            download_to(download, &path, cancel)?;
+        }
+        fetched.push(FetchedFile {
+            dest: download.dest.clone(),
+            path,
+            targets: download.targets.clone(),
+            commit: None,
+        });
+    }
+    Ok(fetched)
+}
+
+/// Record that the cached file at `path` was used now, so it is evicted last
+fn mark_used(path: &Path) {
+    let touched = File::options()
+        .append(true)
+        .open(path)
+        .and_then(|file| file.set_modified(SystemTime::now()));
+    if let Err(err) = touched {
+        log::debug!("failed to mark cached download {path:?} as used: {err}");
+    }
+}
+
+/// Remove the downloads in `cache_dir` unused for longer than the `cache-max-age` of `sources`,
+/// then the least recently used ones until the rest fit in its `cache-max-size`
+///
+/// The downloads in `used` are never removed, and neither are partial downloads less than a day
+/// old, which another build may still be writing. Eviction is best effort, failing to remove a
+/// download is only logged.
+fn evict_downloads(cache_dir: &Path, sources: &SourcesConfig, used: &[PathBuf]) {
+    let dir = CacheKind::Downloads.dir(cache_dir);
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return;
+    };
+    let now = SystemTime::now();
+    let age = |modified: SystemTime| now.duration_since(modified).unwrap_or_default();
+
+    let mut cached = vec![];
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        let Ok(modified) = metadata.modified() else {
+            continue;
+        };
+        if used.contains(&path) || !metadata.is_file() {
+            continue;
+        }
+        let partial = entry.file_name().to_string_lossy().ends_with(".part");
+        let expired = match partial {
+            true => age(modified) > PARTIAL_MAX_AGE,
+            false => {
+                sources.cache_max_age > 0
+                    && age(modified) > Duration::from_secs(sources.cache_max_age * 24 * 60 * 60)
+            }
+        };
+        if expired {
+            remove_cached(&path, "it went unused for too long");
+        } else if !partial {
+            cached.push((modified, metadata.len(), path));
+        }
+    }
+
+    let Some(max_size) = sources.cache_max_size else {
+        return;
+    };
+    let used_size = used
+        .iter()
+        .filter_map(|path| fs::metadata(path).ok())
+        .map(|metadata| metadata.len())
+        .sum::<u64>();
+    let mut size = used_size + cached.iter().map(|(_, len, _)| len).sum::<u64>();
+    cached.sort();
+    for (_, len, path) in cached {
+        if size <= max_size {
+            break;
+        }
+        remove_cached(&path, "the cache is larger than `sources.cache-max-size`");
+        size -= len;
+    }
+}
+
+fn remove_cached(path: &Path, reason: &str) {
+    match fs::remove_file(path) {
+        Ok(()) => log::debug!("removed cached download {path:?}, {reason}"),
+        Err(err) => log::debug!("failed to remove cached download {path:?}: {err}"),
+    }
+}
+
+/// Download `download` to `path`, only moving it there once it matches its digest
+fn download_to(
+    download: &DownloadSource,
+    path: &Path,
+    cancel: &CancelHandle,
+) -> Result<(), RumkinstError> {
+    let url = &download.url;
+    log::info!("Downloading {url}");
+    let dir = path
+        .parent()
+        .expect("cached downloads should always be in a directory");
+    let download_error = |source: io::Error| RumkinstError::Download {
+        url: url.clone(),
+        source: source.into(),
+    };
+    fs::create_dir_all(dir).map_err(download_error)?;
+
+    let partial = dir.join(format!(".{}.{}.part", download.sha256, std::process::id()));
+    let command = || {
+        let mut command = Command::new("curl");
+        command
+            .arg("--fail")
+            .arg("--silent")
+            .arg("--show-error")
+            .arg("--location")
+            .arg("--output")
+            .arg(&partial)
+            .arg(url);
+        command
+    };
+    let result = run_tool_with_retries(command, dir, None, cancel)
+        .map_err(|err| match cancel.is_cancelled() {
+            true => RumkinstError::Cancelled,
+            false => RumkinstError::Download {
+                url: url.clone(),
+                source: err.into(),
+            },
+        })
+        .and_then(|_| sha256_of(&partial).map_err(download_error))
+        .and_then(|actual| match actual == download.sha256 {
+            true => fs::rename(&partial, path).map_err(download_error),
+            false => Err(RumkinstError::DownloadMismatch {
+                url: url.clone(),
+                expected: download.sha256.clone(),
+                actual,
+            }),
+        });
+    if result.is_err() {
+        fs::remove_file(&partial).ok();
+    }
+    result
+}
+
+/// Directory in `cache_dir` holding the clone of the repository of `source`, and a checkout of
+/// every commit used from it
+fn git_cache_dir(cache_dir: &Path, source: &GitSource) -> PathBuf {
+    let digest = Sha256Digester
+        .digest(&mut source.git.as_bytes(), &NoProgress)
+        .expect("hashing bytes in memory should never fail");
+    CacheKind::Git.dir(cache_dir).join(&to_hex(&digest)[..16])
+}
+
+/// Whether the repository of `source` was cloned into `cache_dir` already
+pub(crate) fn git_cached(cache_dir: &Path, source: &GitSource) -> bool {
+    git_cache_dir(cache_dir, source).join("repo.git").exists()
+}
+
+/// Check out every `[[sources.git]]` of `config` into `cache_dir`, cloning and fetching their
+/// repositories as needed
+///
+/// Full commit hashes that were fetched before are used without asking the remote. When
+/// `offline`, tags and branches resolve to where they were when last fetched.
+fn fetch_git_sources(
+    config: &Config,
+    cache_dir: &Path,
+    offline: bool,
+    cancel: &CancelHandle,
+) -> Result<Vec<FetchedFile>, RumkinstError> {
+    let mut fetched = vec![];
+    for source in &config.sources.git {
+        cancel.check()?;
+        let checkout = check_out(source, &git_cache_dir(cache_dir, source), offline, cancel)?;
+        let commit = checkout
+            .file_name()
+            .expect("checkouts should be named by their commit")
+            .to_string_lossy()
+            .into_owned();
+        let path = match &source.path {
+            Some(path) if !checkout.join(path).is_dir() => {
+                return Err(git_error(
+                    source,
+                    format!("{path:?} is not a directory in the repository"),
+                ));
+            }
+            Some(path) => checkout.join(path),
+            None => checkout,
+        };
+        fetched.push(FetchedFile {
+            dest: source.dest.clone(),
+            path,
+            targets: source.targets.clone(),
+            commit: Some(commit),
+        });
+    }
+    Ok(fetched)
+}
+
+/// The checkout of `source.rev` in `dir`, made from the clone of the repository next to it
+fn check_out(
+    source: &GitSource,
+    dir: &Path,
+    offline: bool,
+    cancel: &CancelHandle,
+) -> Result<PathBuf, RumkinstError> {
+    let repo = dir.join("repo.git");
+    let cloned = !repo.exists();
+    if cloned {
+        if offline {
+            return Err(RumkinstError::Offline {
+                feature: "cloning the repositories in [[sources.git]]",
+            });
+        }
+        log::info!("Cloning {}", source.git);
+        fs::create_dir_all(dir).map_err(|err| git_error(source, err))?;
+        let partial = dir.join(format!(".repo.git.{}.part", std::process::id()));
+        let result = run_git(
+            source,
+            dir,
+            &["clone", "--bare", "--quiet", "--", &source.git],
+            Some(&partial),
+            cancel,
+        )
+        .and_then(|_| fs::rename(&partial, &repo).map_err(|err| git_error(source, err)));
+        if result.is_err() {
+            fs::remove_dir_all(&partial).ok();
+        }
+        result?;
+    }
+
+    let pinned = source.rev.len() == 40 && source.rev.chars().all(|ch| ch.is_ascii_hexdigit());
+    let mut commit = resolve_rev(&repo, &source.rev);
+    // A fresh clone already has every branch and tag
+    if !offline && (commit.is_none() || (!pinned && !cloned)) {
+        log::info!("Fetching {}", source.git);
+        run_git(
+            source,
+            &repo,
+            &[
+                "fetch",
+                "--quiet",
+                "--force",
+                "--tags",
+                "--",
+                &source.git,
+                "+refs/heads/*:refs/heads/*",
+            ],
+            None,
+            cancel,
+        )?;
+        commit = resolve_rev(&repo, &source.rev);
+        if commit.is_none() && pinned {
+            // Commits no branch or tag points at can only be fetched by their hash
+            run_git(
+                source,
+                &repo,
+                &["fetch", "--quiet", "--", &source.git, &source.rev],
+                None,
+                cancel,
+            )?;
+            commit = resolve_rev(&repo, &source.rev);
+        }
+    }
+    let commit = commit.ok_or_else(|| {
+        git_error(
+            source,
+            format!("revision `{}` is not in the repository", source.rev),
+        )
+    })?;
+
+    let checkout = dir.join(&commit);
+    if !checkout.exists() {
+        log::debug!("checking out {commit} of {}", source.git);
+        let partial = dir.join(format!(".{commit}.{}.part", std::process::id()));
+        let extracted = extract_commit(&repo, &commit, &partial)
+            .and_then(|()| fs::rename(&partial, &checkout))
+            .map_err(|err| git_error(source, err));
+        if extracted.is_err() {
+            fs::remove_dir_all(&partial).ok();
+        }
+        extracted?;
+    }
+    Ok(checkout)
+}
+
+/// The full hash of the commit `rev` names in `repo`, `None` if it names none
+fn resolve_rev(repo: &Path, rev: &str) -> Option<String> {
+    let output = Command::new("git")
+        .arg("--git-dir")
+        .arg(repo)
+        .args(["rev-parse", "--verify", "--quiet", "--end-of-options"])
+        .arg(format!("{rev}^{{commit}}"))
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Write the files of `commit` in `repo` into the new directory `dir`, without any git metadata
+fn extract_commit(repo: &Path, commit: &str, dir: &Path) -> io::Result<()> {
+    let mut child = Command::new("git")
+        .arg("--git-dir")
+        .arg(repo)
+        .args(["archive", "--format=tar", commit])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = child
+        .stdout
+        .take()
+        .expect("stdout of `git archive` should be piped");
+    fs::create_dir(dir)?;
+    let unpacked = tar::Archive::new(stdout).unpack(dir);
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "`git archive` {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    unpacked
+}
+
+/// Run `git` for `source` in `dir` with `args`, and `target` as its last argument
+fn run_git(
+    source: &GitSource,
+    dir: &Path,
+    args: &[&str],
+    target: Option<&Path>,
+    cancel: &CancelHandle,
+) -> Result<String, RumkinstError> {
+    let command = || {
+        let mut command = Command::new("git");
+        command.args(args);
+        if let Some(target) = target {
+            command.arg(target);
+        }
+        command
+    };
+    run_tool_with_retries(command, dir, None, cancel).map_err(|err| match cancel.is_cancelled() {
+        true => RumkinstError::Cancelled,
+        false => git_error(source, err),
+    })
+}
+
+fn git_error(
+    source: &GitSource,
+    err: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+) -> RumkinstError {
+    RumkinstError::Download {
+        url: source.git.clone(),
+        source: err.into(),
+    }
+}
+
+fn sha256_of(path: &Path) -> io::Result<String> {
+    let digest = Sha256Digester.digest(&mut File::open(path)?, &NoProgress)?;
+    Ok(to_hex(&digest))
+}
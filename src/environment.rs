@@ -0,0 +1,122 @@
+//! The machine a build ran on, recorded in the metadata of its run so artifacts can be traced
+//! back to where they were made
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    process::{Command, Stdio},
+};
+
+use serde::Serialize;
+
+use crate::{
+    config::{Config, Transform},
+    secrets::redact,
+};
+
+/// The machine and tools a build ran with, as set in `[output.environment]`
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct BuildEnvironment {
+    /// Operating system, as in [`std::env::consts::OS`]
+    pub os: &'static str,
+    /// CPU architecture, as in [`std::env::consts::ARCH`]
+    pub arch: &'static str,
+    /// Only recorded when `hostname = true`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// The recorded environment variables that were set, with any secrets redacted
+    pub env: BTreeMap<String, String>,
+    /// First line of the `--version` of each program, `None` if it could not be run
+    pub tools: BTreeMap<String, Option<String>>,
+}
+
+/// The environment of the current build of `config`, or `None` if `[output.environment]`
+/// turns recording off
+///
+/// Tools are the programs of `[[sources.command]]` and `[[transforms]]`, `git` and `strip`
+/// when they are used, and the extra `tools` in the config.
+pub fn capture_environment(config: &Config) -> Option<BuildEnvironment> {
+    let settings = &config.output.environment;
+    if !settings.record {
+        return None;
+    }
+
+    let env = settings
+        .env
+        .iter()
+        .filter_map(|name| {
+            let value = env::var(name).ok()?;
+            Some((name.clone(), redact(&value).into_owned()))
+        })
+        .collect();
+
+    let mut programs = config
+        .sources
+        .command
+        .iter()
+        .filter_map(|source| source.run.first().cloned())
+        .collect::<Vec<_>>();
+    for rule in &config.transforms {
+        match &rule.transform {
+            Transform::Run(run) => programs.extend(run.first().cloned()),
+            Transform::Strip | Transform::StripDebug => {
+                programs.push(env::var("STRIP").unwrap_or("strip".to_string()));
+            }
+            _ => {}
+        }
+    }
+    if !config.sources.git.is_empty() {
+        programs.push("git".to_string());
+    }
+    programs.extend(settings.tools.iter().cloned());
+    let tools = programs
+        .into_iter()
+        .map(|program| {
+            let version = tool_version(&program);
+            (program, version)
+        })
+        .collect();
+
+    Some(BuildEnvironment {
+        os: env::consts::OS,
+        arch: env::consts::ARCH,
+        hostname: settings.hostname.then(hostname).flatten(),
+        env,
+        tools,
+    })
+}
+
+/// First line `program --version` prints, on stdout or else on stderr
+fn tool_version(program: &str) -> Option<String> {
+    let output = Command::new(program)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .output()
+        .inspect_err(|err| log::debug!("failed to run `{program} --version`: {err}"))
+        .ok()?;
+    if !output.status.success() {
+        log::debug!("`{program} --version` {}", output.status);
+        return None;
+    }
+    [output.stdout, output.stderr]
+        .iter()
+        .map(|printed| String::from_utf8_lossy(printed).trim().to_string())
+        .find(|printed| !printed.is_empty())
+        .and_then(|printed| printed.lines().next().map(str::to_string))
+}
+
+/// Name of this machine, from the kernel on Linux and the `hostname` program elsewhere
+fn hostname() -> Option<String> {
+    let name = match fs::read_to_string("/proc/sys/kernel/hostname") {
+        Ok(name) => name,
+        Err(_) => {
+            let output = Command::new("hostname")
+                .stdin(Stdio::null())
+                .output()
+                .ok()?;
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        }
+    };
+    Some(name.trim().to_string()).filter(|name| !name.is_empty())
+}
@@ -0,0 +1,419 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::error_log::ErrorCode;
+
+/// Any error returned by the rumkinst library
+///
+/// Each variant maps onto an [`ErrorCode`], see [`RumkinstError::code`].
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum RumkinstError {
+    #[error("could not find `rumkinst.toml` {}", match path {
+        Some(path) => format!("in {path:?}"),
+        None => "in `./`, `./rumkinst/`, or any parent directory".to_string(),
+    })]
+    ConfigNotFound { path: Option<PathBuf> },
+
+    #[error("failed to read rumkinst config {name}")]
+    ConfigRead {
+        name: String,
+        #[source]
+        source: io::Error,
+    },
+
+    /// The config is not valid toml, or does not match the expected schema
+    ///
+    /// When the position of the error is known, `source` is a
+    /// [`SourceSnippet`](crate::error_log::SourceSnippet) pointing at it.
+    #[error("failed to parse rumkinst config {name}")]
+    ConfigParse {
+        name: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[error("failed to write rumkinst config")]
+    ConfigWrite(#[source] io::Error),
+
+    #[error("`{key}` must be set in rumkinst.toml to build {needed_by}")]
+    ConfigMissing {
+        key: &'static str,
+        needed_by: &'static str,
+    },
+
+    #[error("cannot create Identifier: {reason}")]
+    InvalidIdentifier { reason: String },
+
+    #[error("invalid app id `{id}`: {reason}")]
+    InvalidAppId { id: String, reason: String },
+
+    #[error("invalid size `{size}`: {reason}")]
+    InvalidByteSize { size: String, reason: String },
+
+    #[error("cannot create RelativePathBuf: {path:?} is not relative")]
+    InvalidRelativePath { path: PathBuf },
+
+    #[error("invalid checksum `{checksum}`: {reason}")]
+    InvalidChecksum { checksum: String, reason: String },
+
+    #[error("invalid glob pattern `{pattern}`: {reason}")]
+    InvalidGlob { pattern: String, reason: String },
+
+    #[error("invalid file mode `{mode}`: {reason}")]
+    InvalidFileMode { mode: String, reason: String },
+
+    #[error("source path {path:?} does not exist")]
+    SourceMissing { path: PathBuf },
+
+    #[error("source path {path:?} is not a regular file or directory")]
+    SourceUnsupported { path: PathBuf },
+
+    #[error("symlink at {path:?} points to a missing target")]
+    SourceBrokenLink { path: PathBuf },
+
+    #[error("source path {path:?} is absolute, set `allow-absolute = true` on its source")]
+    AbsoluteSource { path: PathBuf },
+
+    /// A source path, or the target of a symlink in one, is outside the directory it may be in
+    #[error("source path {path:?} resolves outside of {allowed:?}")]
+    SourceEscape { path: PathBuf, allowed: PathBuf },
+
+    #[error("source path {path:?} {reason}")]
+    UnusualName { path: PathBuf, reason: &'static str },
+
+    #[error(
+        "source paths {path:?} and {other:?} only differ by case, and overwrite each other on \
+         case-insensitive filesystems"
+    )]
+    CaseCollision { path: PathBuf, other: PathBuf },
+
+    #[error(
+        "{path:?} from {origin} and {other:?} from {other_origin} are both archived as {entry:?}"
+    )]
+    DuplicateEntry {
+        path: PathBuf,
+        origin: &'static str,
+        other: PathBuf,
+        other_origin: &'static str,
+        entry: String,
+    },
+
+    #[error("{path:?} and the file generated by {origin} are both archived as {entry:?}")]
+    GeneratedDuplicate {
+        path: PathBuf,
+        origin: &'static str,
+        entry: String,
+    },
+
+    #[error("source path {path:?} is not under {prefix:?}, the `strip-prefix` of its source")]
+    StripPrefix { path: PathBuf, prefix: PathBuf },
+
+    #[error("target `{target}` of {source_name} is not in [targets]")]
+    UnknownTarget {
+        target: String,
+        source_name: &'static str,
+    },
+
+    #[error(
+        "{feature} can't be made when [targets] is set, only archives are made per target; \
+         build it from a config without [targets]"
+    )]
+    TargetsUnsupported { feature: &'static str },
+
+    #[error("failed to read source {path:?}")]
+    SourceRead {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("failed to download source {url}")]
+    Download {
+        url: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[error("download of {url} has SHA-256 {actual}, but {expected} was expected")]
+    DownloadMismatch {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+
+    /// The command of a `[[sources.command]]` could not be run, or did not write its outputs
+    #[error("source command `{command}` failed")]
+    SourceCommand {
+        command: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// A download, git checkout or command output can't be packaged at `path`, inside the `dest`
+    /// it was given
+    #[error("cannot package a remote source at {path:?}, {reason}")]
+    DownloadDest { path: PathBuf, reason: &'static str },
+
+    #[error("failed to create output directory {path:?}")]
+    OutputDir {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    /// Writing the archive failed, `path` is the entry being added if there was one
+    #[error("{}", match path {
+        Some(path) => format!("failed to add {path:?} to the archive"),
+        None => "failed to write the archive".to_string(),
+    })]
+    Archive {
+        path: Option<PathBuf>,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("failed to write checksum for {path:?}")]
+    Checksum {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("failed to write package {path:?}")]
+    Package {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("failed to read previous archive {path:?} to make a delta from")]
+    DeltaBase {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("failed to move {path:?} into the output directory")]
+    Finalize {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("failed to update feed {path:?}")]
+    Feed {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("build hook `{hook}` failed")]
+    Hook {
+        hook: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[error("wasm hook `{hook}` failed")]
+    WasmHook {
+        hook: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// `needed_by` is set in the config, but rumkinst was built without `feature`
+    #[error("{needed_by} needs rumkinst to be built with the `{feature}` feature")]
+    FeatureDisabled {
+        feature: &'static str,
+        needed_by: &'static str,
+    },
+
+    /// The secret in `secret`, such as an environment variable, could not be read
+    #[error("failed to read the secret in {secret}")]
+    Secret {
+        secret: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[error("plugin `{plugin}` failed at the {stage} stage")]
+    Plugin {
+        plugin: String,
+        stage: &'static str,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[error("failed to sign {path:?}")]
+    Signing {
+        path: PathBuf,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// An artifact no longer matches the checksum or signature made for it
+    #[error("{path:?} failed verification: {reason}")]
+    Verification { path: PathBuf, reason: String },
+
+    /// An archive was made differently when the same files were archived a second time
+    #[error("{path:?} is not reproducible, archiving it again changed {}", differences.join(", "))]
+    NotReproducible {
+        path: PathBuf,
+        differences: Vec<String>,
+    },
+
+    #[error("failed to read lockfile {path:?}")]
+    LockfileRead {
+        path: PathBuf,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[error("failed to write lockfile {path:?}")]
+    LockfileWrite {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The files to package, or the remote sources they come from, differ from the lockfile
+    #[error("packaged inputs differ from {path:?}: {}", differences.join(", "))]
+    LockfileMismatch {
+        path: PathBuf,
+        differences: Vec<String>,
+    },
+
+    /// Publishing finished artifacts to `target`, such as a registry reference, failed
+    #[error("failed to publish artifacts to {target}")]
+    Publish {
+        target: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    /// `feature` needs network access, but the build was made offline
+    #[error("{feature} needs network access, but this build is offline")]
+    Offline { feature: &'static str },
+
+    #[error("{count} file{} could not be packaged and {} skipped",
+        if *count == 1 { "" } else { "s" },
+        if *count == 1 { "was" } else { "were" })]
+    FilesSkipped { count: usize },
+
+    #[error("{count} warning{} reported, and warnings are denied",
+        if *count == 1 { " was" } else { "s were" })]
+    WarningsDenied { count: usize },
+
+    #[error("{count} lint finding{} denied in [lint]",
+        if *count == 1 { " is" } else { "s are" })]
+    LintsDenied { count: usize },
+
+    /// The build was stopped through a [`CancelHandle`](crate::CancelHandle)
+    #[error("the build was cancelled")]
+    Cancelled,
+
+    /// An operation replacing something that already exists was turned down by the
+    /// [`Confirm`](crate::Confirm) of the build
+    #[error("declined to {action}")]
+    Declined { action: String },
+}
+
+impl RumkinstError {
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            RumkinstError::ConfigNotFound { .. } => ErrorCode::CONFIG_NOT_FOUND,
+            RumkinstError::ConfigRead { .. } => ErrorCode::CONFIG_UNREADABLE,
+            RumkinstError::ConfigParse { .. }
+            | RumkinstError::InvalidIdentifier { .. }
+            | RumkinstError::InvalidAppId { .. }
+            | RumkinstError::InvalidByteSize { .. }
+            | RumkinstError::InvalidRelativePath { .. }
+            | RumkinstError::InvalidChecksum { .. }
+            | RumkinstError::InvalidGlob { .. }
+            | RumkinstError::InvalidFileMode { .. }
+            | RumkinstError::DownloadDest { .. }
+            | RumkinstError::StripPrefix { .. }
+            | RumkinstError::UnknownTarget { .. }
+            | RumkinstError::TargetsUnsupported { .. }
+            | RumkinstError::FeatureDisabled { .. } => ErrorCode::CONFIG_INVALID,
+            RumkinstError::ConfigMissing { .. } => ErrorCode::CONFIG_INCOMPLETE,
+            RumkinstError::Secret { .. } => ErrorCode::SECRET_UNAVAILABLE,
+            RumkinstError::ConfigWrite(_) => ErrorCode::PROJECT_CREATE,
+            RumkinstError::SourceMissing { .. } => ErrorCode::SOURCE_MISSING,
+            RumkinstError::SourceUnsupported { .. } => ErrorCode::SOURCE_UNSUPPORTED,
+            RumkinstError::SourceBrokenLink { .. } => ErrorCode::SOURCE_BROKEN_LINK,
+            RumkinstError::SourceEscape { .. } | RumkinstError::AbsoluteSource { .. } => {
+                ErrorCode::SOURCE_ESCAPE
+            }
+            RumkinstError::UnusualName { .. } => ErrorCode::SOURCE_UNUSUAL_NAME,
+            RumkinstError::CaseCollision { .. } => ErrorCode::SOURCE_CASE_COLLISION,
+            RumkinstError::DuplicateEntry { .. } | RumkinstError::GeneratedDuplicate { .. } => {
+                ErrorCode::SOURCE_DUPLICATE_ENTRY
+            }
+            RumkinstError::SourceRead { .. } => ErrorCode::SOURCE_UNREADABLE,
+            RumkinstError::Download { .. } => ErrorCode::SOURCE_DOWNLOAD,
+            RumkinstError::DownloadMismatch { .. } => ErrorCode::SOURCE_CHECKSUM,
+            RumkinstError::SourceCommand { .. } => ErrorCode::SOURCE_COMMAND,
+            RumkinstError::OutputDir { .. } => ErrorCode::OUTPUT_DIR,
+            RumkinstError::Archive { .. } => ErrorCode::ARCHIVE_WRITE,
+            RumkinstError::Checksum { .. } => ErrorCode::CHECKSUM_WRITE,
+            RumkinstError::Package { .. } => ErrorCode::PACKAGE_WRITE,
+            RumkinstError::Feed { .. } => ErrorCode::FEED_WRITE,
+            RumkinstError::DeltaBase { .. } => ErrorCode::DELTA_BASE,
+            RumkinstError::Finalize { .. } => ErrorCode::FINALIZE,
+            RumkinstError::Hook { .. } | RumkinstError::WasmHook { .. } => ErrorCode::HOOK_FAILED,
+            RumkinstError::Plugin { .. } => ErrorCode::PLUGIN_FAILED,
+            RumkinstError::Signing { .. } => ErrorCode::SIGNING_FAILED,
+            RumkinstError::Verification { .. } => ErrorCode::ARTIFACT_MISMATCH,
+            RumkinstError::NotReproducible { .. } => ErrorCode::NOT_REPRODUCIBLE,
+            RumkinstError::LockfileRead { .. } | RumkinstError::LockfileWrite { .. } => {
+                ErrorCode::LOCKFILE
+            }
+            RumkinstError::LockfileMismatch { .. } => ErrorCode::LOCKFILE_MISMATCH,
+            RumkinstError::Publish { .. } => ErrorCode::PUBLISH_FAILED,
+            RumkinstError::Offline { .. } => ErrorCode::OFFLINE,
+            RumkinstError::FilesSkipped { .. } => ErrorCode::FILES_SKIPPED,
+            RumkinstError::WarningsDenied { .. } => ErrorCode::WARNINGS_DENIED,
+            RumkinstError::LintsDenied { .. } => ErrorCode::LINTS_DENIED,
+            RumkinstError::Cancelled | RumkinstError::Declined { .. } => ErrorCode::CANCELLED,
+        }
+    }
+
+    /// The file or directory this error concerns, if there is one
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            RumkinstError::ConfigNotFound { path } | RumkinstError::Archive { path, .. } => {
+                path.as_deref()
+            }
+            RumkinstError::InvalidRelativePath { path }
+            | RumkinstError::SourceMissing { path }
+            | RumkinstError::SourceUnsupported { path }
+            | RumkinstError::SourceBrokenLink { path }
+            | RumkinstError::SourceEscape { path, .. }
+            | RumkinstError::AbsoluteSource { path }
+            | RumkinstError::UnusualName { path, .. }
+            | RumkinstError::CaseCollision { path, .. }
+            | RumkinstError::DuplicateEntry { path, .. }
+            | RumkinstError::GeneratedDuplicate { path, .. }
+            | RumkinstError::StripPrefix { path, .. }
+            | RumkinstError::SourceRead { path, .. }
+            | RumkinstError::DownloadDest { path, .. }
+            | RumkinstError::OutputDir { path, .. }
+            | RumkinstError::Checksum { path, .. }
+            | RumkinstError::Package { path, .. }
+            | RumkinstError::Feed { path, .. }
+            | RumkinstError::DeltaBase { path, .. }
+            | RumkinstError::Finalize { path, .. }
+            | RumkinstError::Signing { path, .. }
+            | RumkinstError::Verification { path, .. }
+            | RumkinstError::NotReproducible { path, .. }
+            | RumkinstError::LockfileRead { path, .. }
+            | RumkinstError::LockfileWrite { path, .. }
+            | RumkinstError::LockfileMismatch { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+}
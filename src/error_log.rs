@@ -1,10 +1,394 @@
+pub mod report;
+
+use std::{
+    fmt::Display,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
 use log::{error, warn};
+use serde::Serialize;
+
+use crate::{RumkinstError, progress_log::print_above_progress, secrets::redact, tr};
+
+static DIAGNOSTIC_FORMAT: OnceLock<DiagnosticFormat> = OnceLock::new();
+
+/// How errors reported through [`Log`] are rendered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiagnosticFormat {
+    /// Formatted anyhow chains, through the regular logger
+    #[default]
+    Human,
+    /// One JSON object per diagnostic, written to stderr
+    Json,
+}
+
+pub fn set_diagnostic_format(format: DiagnosticFormat) {
+    if DIAGNOSTIC_FORMAT.set(format).is_err() {
+        panic!("set_diagnostic_format should only be called once");
+    }
+}
+
+fn diagnostic_format() -> DiagnosticFormat {
+    DIAGNOSTIC_FORMAT.get().copied().unwrap_or_default()
+}
+
+/// A stable, machine-readable identifier for a class of failure, displayed as `RK0102`.
+///
+/// Codes are grouped by the area they come from:
+///
+/// | Range    | Area                       | [`FailureClass`]                   |
+/// |----------|----------------------------|------------------------------------|
+/// | `RK00xx` | project setup              | [`FailureClass::Io`]               |
+/// | `RK01xx` | sources and file discovery | [`FailureClass::Io`]               |
+/// | `RK02xx` | configuration              | [`FailureClass::Config`]           |
+/// | `RK03xx` | artifact output            | [`FailureClass::Io`]               |
+/// | `RK04xx` | build hooks                | [`FailureClass::Hook`]             |
+/// | `RK05xx` | artifact verification      | [`FailureClass::Verification`]     |
+/// | `RK06xx` | interruption               | [`FailureClass::UserAbort`]        |
+/// | `RK07xx` | strict-mode checks         | [`FailureClass::DeniedWarnings`]   |
+/// | `RK08xx` | publishing                 | [`FailureClass::Io`]               |
+/// | `RK09xx` | git                        | [`FailureClass::Io`]               |
+/// | `RK10xx` | lint rules                 | [`FailureClass::DeniedWarnings`]   |
+///
+/// Codes are never renumbered or reused once published.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ErrorCode {
+    number: u16,
+    summary: &'static str,
+}
+
+impl ErrorCode {
+    pub const PROJECT_EXISTS: Self = Self::new(1, "project directory already exists");
+    pub const PROJECT_CREATE: Self = Self::new(2, "failed to create project files");
+    pub const PROJECT_LOCKED: Self = Self::new(3, "another run is making artifacts in the project");
+
+    pub const SOURCE_UNREADABLE: Self = Self::new(101, "source path could not be read");
+    pub const SOURCE_MISSING: Self = Self::new(102, "source path does not exist");
+    pub const SOURCE_UNSUPPORTED: Self =
+        Self::new(103, "source path is not a regular file or directory");
+    pub const EXCLUDE_UNUSED: Self = Self::new(104, "exclude path did not match anything");
+    pub const NO_SOURCE_FILES: Self = Self::new(105, "no source files were found");
+    pub const SOURCE_BROKEN_LINK: Self = Self::new(106, "symlink target does not exist");
+    pub const SOURCE_ESCAPE: Self =
+        Self::new(107, "source path resolves outside its source directory");
+    pub const SOURCE_UNUSUAL_NAME: Self = Self::new(
+        108,
+        "source path is not valid UTF-8 or has control characters",
+    );
+    pub const SOURCE_CASE_COLLISION: Self = Self::new(109, "source paths only differ by case");
+    pub const SOURCE_WINDOWS_NAME: Self =
+        Self::new(110, "source path can't be extracted on Windows");
+    pub const SOURCE_DOWNLOAD: Self = Self::new(111, "source could not be downloaded");
+    pub const SOURCE_CHECKSUM: Self =
+        Self::new(112, "downloaded source does not match its checksum");
+    pub const SOURCE_COMMAND: Self = Self::new(113, "source command failed");
+    pub const SOURCE_DUPLICATE_ENTRY: Self =
+        Self::new(114, "source paths are archived under the same name");
+    pub const SCRIPT_NOT_EXECUTABLE: Self = Self::new(115, "script is not executable");
+    pub const SCRIPT_SHEBANG: Self = Self::new(116, "script does not start with `#!`");
+    pub const SCRIPT_CRLF: Self = Self::new(117, "script has CRLF line endings");
+
+    pub const CONFIG_NOT_FOUND: Self = Self::new(201, "config file not found");
+    pub const CONFIG_UNREADABLE: Self = Self::new(202, "config file could not be read");
+    pub const CONFIG_INVALID: Self = Self::new(203, "config file is invalid");
+    pub const CONFIG_INCOMPLETE: Self = Self::new(204, "config is missing a required value");
+    pub const VERSION_INVALID: Self =
+        Self::new(205, "package version is not a valid semantic version");
+    pub const CONFIG_WRITE: Self = Self::new(206, "config file could not be updated");
+    pub const CHANGELOG_SECTION: Self =
+        Self::new(207, "changelog has no section for the package version");
+    pub const OFFLINE: Self = Self::new(208, "network access is needed, but the run is offline");
+    pub const CONFIG_KEY: Self = Self::new(209, "config key is invalid or not set");
+    pub const SECRET_UNAVAILABLE: Self = Self::new(210, "secret could not be read");
+
+    pub const OUTPUT_DIR: Self = Self::new(301, "output directory could not be created");
+    pub const ARCHIVE_WRITE: Self = Self::new(302, "archive could not be written");
+    pub const CHECKSUM_WRITE: Self = Self::new(303, "checksum could not be written");
+    pub const FILES_SKIPPED: Self = Self::new(304, "files were skipped after errors");
+    pub const SIGNING_FAILED: Self = Self::new(305, "artifact could not be signed");
+    pub const PACKAGE_WRITE: Self = Self::new(306, "package could not be written");
+    pub const FEED_WRITE: Self = Self::new(307, "update feed could not be written");
+    pub const DELTA_BASE: Self = Self::new(308, "previous archive could not be read");
+    pub const FINALIZE: Self =
+        Self::new(309, "artifact could not be moved into the output directory");
+    pub const ARCHIVE_READ: Self = Self::new(310, "archive could not be read");
+    pub const ARCHIVE_TOO_LARGE: Self = Self::new(311, "archive is larger than `output.warn-size`");
+    pub const LOCKFILE: Self = Self::new(312, "lockfile could not be read or written");
+
+    pub const HOOK_FAILED: Self = Self::new(401, "build hook failed");
+    pub const PLUGIN_FAILED: Self = Self::new(402, "pipeline plugin failed");
+    pub const PLUGIN_WARNING: Self = Self::new(403, "pipeline plugin reported a warning");
+
+    pub const ARTIFACT_MISMATCH: Self =
+        Self::new(501, "artifact does not match its checksum or signature");
+    pub const NOT_REPRODUCIBLE: Self = Self::new(502, "artifact differs when it is made again");
+    pub const INSTALL_TEST_FAILED: Self = Self::new(503, "test install of the archive failed");
+    pub const SNAPSHOT_MISMATCH: Self = Self::new(504, "archive differs from its snapshot");
+    pub const LOCKFILE_MISMATCH: Self = Self::new(505, "packaged inputs differ from the lockfile");
+
+    pub const CANCELLED: Self = Self::new(601, "the run was cancelled");
+
+    pub const WARNINGS_DENIED: Self = Self::new(701, "warnings were reported with deny-warnings");
+    pub const LINTS_DENIED: Self = Self::new(702, "lint rules denied in `[lint]` were broken");
+
+    pub const PUBLISH_FAILED: Self = Self::new(801, "artifacts could not be published");
+
+    pub const GIT_FAILED: Self = Self::new(901, "git command failed");
+
+    pub const LINT_SCRIPT_MISSING: Self = Self::new(1001, "installer script does not exist");
+    pub const LINT_SCRIPT_NOT_EXECUTABLE: Self =
+        Self::new(1002, "installer script is not executable");
+    pub const LINT_SCRIPT_SHEBANG: Self = Self::new(1003, "script does not start with `#!`");
+    pub const LINT_EXCLUDE_UNUSED: Self = Self::new(1004, "exclude path does not exist");
+    pub const LINT_SOURCE_OVERLAP: Self =
+        Self::new(1005, "source directory is inside another source");
+
+    const fn new(number: u16, summary: &'static str) -> Self {
+        Self { number, summary }
+    }
+
+    /// The code on its own, e.g. `RK0102`
+    pub fn code(&self) -> String {
+        format!("RK{:04}", self.number)
+    }
+
+    pub fn summary(&self) -> &'static str {
+        self.summary
+    }
+
+    pub fn class(&self) -> FailureClass {
+        match self.number / 100 {
+            2 => FailureClass::Config,
+            4 => FailureClass::Hook,
+            5 => FailureClass::Verification,
+            6 => FailureClass::UserAbort,
+            7 | 10 => FailureClass::DeniedWarnings,
+            _ => FailureClass::Io,
+        }
+    }
+
+    /// Attach the path this error concerns
+    pub fn at(self, path: impl Into<PathBuf>) -> CodedPath {
+        CodedPath {
+            code: self,
+            path: path.into(),
+        }
+    }
+
+    /// Find the code attached anywhere in the chain of `err`
+    pub fn of(err: &anyhow::Error) -> Option<Self> {
+        err.downcast_ref::<CodedPath>()
+            .map(|coded| coded.code)
+            .or_else(|| err.downcast_ref::<Self>().copied())
+            .or_else(|| rumkinst_error_of(err).map(RumkinstError::code))
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code(), self.summary)
+    }
+}
+
+impl std::error::Error for ErrorCode {}
+
+/// An [`ErrorCode`] along with the path it relates to
+#[derive(Debug, Clone)]
+pub struct CodedPath {
+    code: ErrorCode,
+    path: PathBuf,
+}
+
+impl CodedPath {
+    /// Find the related path attached anywhere in the chain of `err`
+    pub fn path_of(err: &anyhow::Error) -> Option<&Path> {
+        err.downcast_ref::<Self>()
+            .map(|coded| coded.path.as_path())
+            .or_else(|| rumkinst_error_of(err).and_then(RumkinstError::path))
+    }
+}
+
+impl std::fmt::Display for CodedPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({:?})", self.code, self.path)
+    }
+}
+
+impl std::error::Error for CodedPath {}
+
+fn rumkinst_error_of(err: &anyhow::Error) -> Option<&RumkinstError> {
+    err.chain()
+        .find_map(|cause| cause.downcast_ref::<RumkinstError>())
+}
+
+/// A span of a source file that an error points at, rendered as an annotated snippet
+#[derive(Debug, Clone)]
+pub struct SourceSnippet {
+    name: String,
+    source: String,
+    span: Range<usize>,
+    label: String,
+    help: Option<String>,
+}
+
+impl SourceSnippet {
+    /// `span` is a byte range into `source`, and is clamped to its bounds
+    pub fn new(
+        name: impl Into<String>,
+        source: impl Into<String>,
+        span: Range<usize>,
+        label: impl Into<String>,
+    ) -> Self {
+        let source = source.into();
+        let start = floor_char_boundary(&source, span.start);
+        let end = floor_char_boundary(&source, span.end).max(start);
+        Self {
+            name: name.into(),
+            source,
+            span: start..end,
+            label: label.into(),
+            help: None,
+        }
+    }
+
+    pub fn with_help(mut self, help: Option<String>) -> Self {
+        self.help = help;
+        self
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn help(&self) -> Option<&str> {
+        self.help.as_deref()
+    }
+
+    /// One-based line and column of the start of the span
+    pub fn line_col(&self) -> (usize, usize) {
+        let before = &self.source[..self.span.start];
+        let line = before.matches('\n').count() + 1;
+        let column = before
+            .rfind('\n')
+            .map(|newline| &before[newline + 1..])
+            .unwrap_or(before)
+            .chars()
+            .count()
+            + 1;
+        (line, column)
+    }
+
+    /// The snippet with a line-number gutter, the span underlined, and any help text
+    pub fn render(&self) -> String {
+        let (line_number, column) = self.line_col();
+        let line_start = self.source[..self.span.start]
+            .rfind('\n')
+            .map(|newline| newline + 1)
+            .unwrap_or(0);
+        let line_end = self.source[self.span.start..]
+            .find('\n')
+            .map(|newline| self.span.start + newline)
+            .unwrap_or(self.source.len());
+        let line = self.source[line_start..line_end].trim_end_matches('\r');
+
+        let padding = " ".repeat(column - 1);
+        let underline = "^".repeat(
+            self.source[self.span.start..self.span.end.min(line_end)]
+                .chars()
+                .count()
+                .max(1),
+        );
+        let gutter = " ".repeat(line_number.to_string().len());
+
+        let mut rendered = format!(
+            "{gutter}--> {}:{line_number}:{column}\n\
+             {gutter} |\n\
+             {line_number} | {line}\n\
+             {gutter} | {padding}{underline} {}",
+            self.name, self.label
+        );
+        if let Some(help) = &self.help {
+            rendered.push_str(&format!("\n{gutter} |\n{gutter} = help: {help}"));
+        }
+        rendered
+    }
+}
+
+fn floor_char_boundary(source: &str, index: usize) -> usize {
+    let mut index = index.min(source.len());
+    while !source.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+impl std::fmt::Display for SourceSnippet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (line, column) = self.line_col();
+        write!(f, "{}:{line}:{column}: {}", self.name, self.label)
+    }
+}
 
-pub struct FatalError;
+impl std::error::Error for SourceSnippet {}
+
+impl SourceSnippet {
+    /// Find a snippet anywhere in the chain of `err`
+    fn of(err: &anyhow::Error) -> Option<&SourceSnippet> {
+        err.chain().find_map(|cause| cause.downcast_ref::<Self>())
+    }
+}
+
+pub trait WithCode<T> {
+    /// Attach `code` to the error chain, directly above the current error
+    fn code(self, code: ErrorCode) -> anyhow::Result<T>;
+    /// Attach `code` and the path it relates to, directly above the current error
+    fn code_at(self, code: ErrorCode, path: impl Into<PathBuf>) -> anyhow::Result<T>;
+}
+
+impl<T, E: Into<anyhow::Error>> WithCode<T> for Result<T, E> {
+    #[inline(always)]
+    fn code(self, code: ErrorCode) -> anyhow::Result<T> {
+        self.map_err(|err| err.into().context(code))
+    }
+    #[inline(always)]
+    fn code_at(self, code: ErrorCode, path: impl Into<PathBuf>) -> anyhow::Result<T> {
+        self.map_err(|err| err.into().context(code.at(path)))
+    }
+}
+
+/// The broad kind of failure that ended a run, used to pick the process exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureClass {
+    Config,
+    Io,
+    Hook,
+    Verification,
+    UserAbort,
+    /// Warnings were reported while they were configured to fail the run
+    DeniedWarnings,
+    /// The error chain carried no [`ErrorCode`]
+    Other,
+}
+
+pub struct FatalError {
+    class: FailureClass,
+}
+
+impl FatalError {
+    pub fn class(&self) -> FailureClass {
+        self.class
+    }
+}
 
 impl std::fmt::Debug for FatalError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Encountered a fatal error, cannot continue")
+        write!(
+            f,
+            "Encountered a fatal error, cannot continue ({:?})",
+            self.class
+        )
     }
 }
 
@@ -27,17 +411,273 @@ impl<T> Log for Result<T, anyhow::Error> {
     type FatalSuccess = T;
     #[inline(always)]
     fn warn(self) -> Self {
-        self.inspect_err(|err| warn!("{err:?}"))
+        self.inspect_err(|err| report(Severity::Warning, err))
     }
     #[inline(always)]
     fn error(self) -> Self {
-        self.inspect_err(|err| error!("{err:?}"))
+        self.inspect_err(|err| report(Severity::Error, err))
     }
     #[inline(always)]
     fn fatal(self) -> Result<Self::FatalSuccess, FatalError> {
         self.map_err(|err| {
-            error!(target: "fatal", "{err:?}");
-            FatalError
+            report(Severity::Fatal, &err);
+            FatalError {
+                class: ErrorCode::of(&err)
+                    .map(|code| code.class())
+                    .unwrap_or(FailureClass::Other),
+            }
         })
     }
 }
+
+/// An issue reported during the run, kept for the end-of-run summary
+#[derive(Debug, Clone)]
+pub struct CollectedDiagnostic {
+    pub code: Option<ErrorCode>,
+    pub message: String,
+}
+
+impl CollectedDiagnostic {
+    fn new(err: &anyhow::Error) -> Self {
+        Self {
+            code: ErrorCode::of(err),
+            message: err.to_string(),
+        }
+    }
+}
+
+/// What to do when a single file can't be read or packaged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FileErrorPolicy {
+    /// Stop the whole run at the first failure
+    #[default]
+    Abort,
+    /// Report and record the failure, skip the file, and carry on
+    KeepGoing,
+}
+
+/// The warnings and skipped errors of a build, kept for the end-of-run summary
+///
+/// Every build collects into the one in its options, so builds running side by side in the same
+/// process each count only their own.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    warnings: Mutex<Vec<CollectedDiagnostic>>,
+    errors: Mutex<Vec<CollectedDiagnostic>>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Report a non-fatal issue, and keep it for the end-of-run summary
+    pub fn warn(
+        &self,
+        cause: impl std::error::Error + Send + Sync + 'static,
+        message: impl Display,
+    ) {
+        let err = anyhow::Error::new(cause).context(message.to_string());
+        report(Severity::Warning, &err);
+        self.warnings
+            .lock()
+            .expect("collected warnings mutex is poisoned")
+            .push(CollectedDiagnostic::new(&err));
+    }
+
+    /// Report an error that was skipped past, and keep it for the end-of-run summary
+    fn skip(&self, err: RumkinstError) {
+        let err = anyhow::Error::new(err);
+        report(Severity::Error, &err);
+        self.errors
+            .lock()
+            .expect("collected errors mutex is poisoned")
+            .push(CollectedDiagnostic::new(&err));
+    }
+
+    /// All warnings collected so far, in the order they were reported
+    pub fn warnings(&self) -> Vec<CollectedDiagnostic> {
+        self.warnings
+            .lock()
+            .expect("collected warnings mutex is poisoned")
+            .clone()
+    }
+
+    /// All errors skipped past under [`FileErrorPolicy::KeepGoing`], in the order they were
+    /// reported
+    pub fn errors(&self) -> Vec<CollectedDiagnostic> {
+        self.errors
+            .lock()
+            .expect("collected errors mutex is poisoned")
+            .clone()
+    }
+}
+
+/// A [`FileErrorPolicy`] along with the [`Diagnostics`] of the build it applies to
+#[derive(Debug, Clone, Copy)]
+pub struct FilePolicy<'a> {
+    policy: FileErrorPolicy,
+    diagnostics: &'a Diagnostics,
+}
+
+impl<'a> FilePolicy<'a> {
+    pub fn new(policy: FileErrorPolicy, diagnostics: &'a Diagnostics) -> Self {
+        Self {
+            policy,
+            diagnostics,
+        }
+    }
+
+    /// Pass the error through when aborting, otherwise record it and return `None`
+    pub fn handle<T>(self, result: Result<T, RumkinstError>) -> Result<Option<T>, RumkinstError> {
+        match (self.policy, result) {
+            (_, Ok(value)) => Ok(Some(value)),
+            (_, Err(RumkinstError::Cancelled)) => Err(RumkinstError::Cancelled),
+            (FileErrorPolicy::Abort, Err(err)) => Err(err),
+            (FileErrorPolicy::KeepGoing, Err(err)) => {
+                self.diagnostics.skip(err);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Where warnings about the files are collected
+    pub fn diagnostics(self) -> &'a Diagnostics {
+        self.diagnostics
+    }
+}
+
+/// Fail if `count` errors were skipped past, so a keep-going run still exits unsuccessfully
+pub fn check_skipped_errors(count: usize) -> Result<(), RumkinstError> {
+    match count {
+        0 => Ok(()),
+        count => Err(RumkinstError::FilesSkipped { count }),
+    }
+}
+
+/// Fail if `count` warnings were reported, for runs where warnings are denied
+pub fn check_warnings(count: usize) -> Result<(), RumkinstError> {
+    match count {
+        0 => Ok(()),
+        count => Err(RumkinstError::WarningsDenied { count }),
+    }
+}
+
+/// Re-print every warning and skipped error in `diagnostics`, so they don't scroll away during a
+/// long run
+pub fn print_summary(diagnostics: &Diagnostics) {
+    if diagnostic_format() == DiagnosticFormat::Json {
+        return;
+    }
+
+    let warnings = diagnostics.warnings();
+    if !warnings.is_empty() {
+        warn!(
+            target: "summary",
+            "{}",
+            tr!("warnings-reported", count = warnings.len())
+        );
+        for warning in warnings {
+            warn!(target: "summary", "  {}", warning.summary_line());
+        }
+    }
+
+    let errors = diagnostics.errors();
+    if !errors.is_empty() {
+        error!(
+            target: "summary",
+            "{}",
+            tr!("errors-skipped", count = errors.len())
+        );
+        for skipped in errors {
+            error!(target: "summary", "  {}", skipped.summary_line());
+        }
+    }
+}
+
+impl CollectedDiagnostic {
+    fn summary_line(&self) -> String {
+        match self.code {
+            Some(code) => format!("[{}] {}", code.code(), self.message),
+            None => self.message.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Severity {
+    Warning,
+    Error,
+    Fatal,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonDiagnostic<'a> {
+    severity: Severity,
+    code: Option<String>,
+    summary: Option<&'static str>,
+    message: String,
+    causes: Vec<String>,
+    path: Option<&'a Path>,
+    snippet: Option<JsonSnippet<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonSnippet<'a> {
+    file: &'a str,
+    line: usize,
+    column: usize,
+    label: &'a str,
+    help: Option<&'a str>,
+}
+
+impl<'a> From<&'a SourceSnippet> for JsonSnippet<'a> {
+    fn from(snippet: &'a SourceSnippet) -> Self {
+        let (line, column) = snippet.line_col();
+        Self {
+            file: &snippet.name,
+            line,
+            column,
+            label: snippet.label(),
+            help: snippet.help(),
+        }
+    }
+}
+
+fn report(severity: Severity, err: &anyhow::Error) {
+    match diagnostic_format() {
+        DiagnosticFormat::Human => {
+            let prefix = code_prefix(err);
+            let snippet = SourceSnippet::of(err)
+                .map(|snippet| format!("\n\n{}\n", snippet.render()))
+                .unwrap_or_default();
+            match severity {
+                Severity::Warning => warn!("{prefix}{err:?}{snippet}"),
+                Severity::Error => error!("{prefix}{err:?}{snippet}"),
+                Severity::Fatal => error!(target: "fatal", "{prefix}{err:?}{snippet}"),
+            }
+        }
+        DiagnosticFormat::Json => {
+            let code = ErrorCode::of(err);
+            let diagnostic = JsonDiagnostic {
+                severity,
+                code: code.map(|code| code.code()),
+                summary: code.map(|code| code.summary()),
+                message: err.to_string(),
+                causes: err.chain().skip(1).map(|cause| cause.to_string()).collect(),
+                path: CodedPath::path_of(err),
+                snippet: SourceSnippet::of(err).map(JsonSnippet::from),
+            };
+            let line = serde_json::to_string(&diagnostic)
+                .expect("diagnostics should always serialize to json");
+            print_above_progress(&redact(&line));
+        }
+    }
+}
+
+fn code_prefix(err: &anyhow::Error) -> String {
+    ErrorCode::of(err)
+        .map(|code| format!("[{}] ", code.code()))
+        .unwrap_or_default()
+}
@@ -0,0 +1,135 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::Context;
+use nanoid::nanoid;
+use sha2::{Digest, Sha256};
+
+use super::{CollectedDiagnostic, Diagnostics};
+use crate::{progress_log::Phase, secrets::redact};
+
+/// File name of the report inside the output directory of a run
+const REPORT_FILE_NAME: &str = "error-report.txt";
+
+/// What is known about the current run, filled in as `make` progresses
+static REPORT_CONTEXT: Mutex<ReportContext> = Mutex::new(ReportContext {
+    config_path: None,
+    config_digest: None,
+    run_id: None,
+    out_dir: None,
+});
+
+struct ReportContext {
+    config_path: Option<PathBuf>,
+    config_digest: Option<String>,
+    run_id: Option<String>,
+    out_dir: Option<PathBuf>,
+}
+
+/// Record the config file used for this run, so its digest ends up in an error report
+pub fn record_config(path: &Path, contents: &[u8]) {
+    let mut context = REPORT_CONTEXT
+        .lock()
+        .expect("report context mutex is poisoned");
+    context.config_path = Some(path.to_path_buf());
+    context.config_digest = Some(format!("{:x}", Sha256::digest(contents)));
+}
+
+/// Record the run id and output directory, an error report is written there if it still exists
+pub fn record_run(run_id: &str, out_dir: &Path) {
+    let mut context = REPORT_CONTEXT
+        .lock()
+        .expect("report context mutex is poisoned");
+    context.run_id = Some(run_id.to_string());
+    context.out_dir = Some(std::path::absolute(out_dir).unwrap_or_else(|_| out_dir.to_path_buf()));
+}
+
+/// Write everything known about a failed run to a report file, along with the `phase` it stopped
+/// in and the warnings and skipped errors in `diagnostics`, returning where it was written
+///
+/// The report goes into the output directory of the run, or the temp directory if there is none.
+/// Failing to write it is only logged, as the original error is what matters.
+pub fn write_error_report(
+    err: &anyhow::Error,
+    phase: Option<Phase>,
+    diagnostics: &Diagnostics,
+) -> Option<PathBuf> {
+    let context = REPORT_CONTEXT
+        .lock()
+        .expect("report context mutex is poisoned");
+    let path = match &context.out_dir {
+        Some(out_dir) if out_dir.is_dir() => out_dir.join(REPORT_FILE_NAME),
+        _ => std::env::temp_dir().join(format!(
+            "rumkinst-error-report-{}.txt",
+            context.run_id.clone().unwrap_or_else(|| nanoid!())
+        )),
+    };
+
+    let report = render_report(&context, err, phase, diagnostics);
+    match fs::write(&path, redact(&report).as_bytes())
+        .with_context(|| format!("failed to write error report to {path:?}"))
+    {
+        Ok(()) => Some(path),
+        Err(err) => {
+            log::warn!("{err:?}");
+            None
+        }
+    }
+}
+
+fn render_report(
+    context: &ReportContext,
+    err: &anyhow::Error,
+    phase: Option<Phase>,
+    diagnostics: &Diagnostics,
+) -> String {
+    fn or_unknown<T: std::fmt::Debug>(value: Option<T>) -> String {
+        value.map_or_else(|| "unknown".to_string(), |value| format!("{value:?}"))
+    }
+
+    let mut report = format!(
+        "rumkinst error report\n\
+         =====================\n\
+         time:          {time}\n\
+         version:       {version}\n\
+         platform:      {os} ({arch})\n\
+         arguments:     {args:?}\n\
+         directory:     {directory}\n\
+         run id:        {run_id}\n\
+         config:        {config}\n\
+         config sha256: {digest}\n\
+         phase:         {phase}\n\
+         \n\
+         error:\n\
+         {err:?}\n",
+        time = jiff::Timestamp::now(),
+        version = env!("CARGO_PKG_VERSION"),
+        os = std::env::consts::OS,
+        arch = std::env::consts::ARCH,
+        args = std::env::args_os().collect::<Vec<_>>(),
+        directory = or_unknown(std::env::current_dir().ok()),
+        run_id = or_unknown(context.run_id.as_ref()),
+        config = or_unknown(context.config_path.as_ref()),
+        digest = context.config_digest.as_deref().unwrap_or("unknown"),
+        phase = phase.map_or("none", |phase| phase.title()),
+    );
+
+    write_diagnostics(&mut report, "skipped errors", &diagnostics.errors());
+    write_diagnostics(&mut report, "warnings", &diagnostics.warnings());
+
+    report
+}
+
+fn write_diagnostics(report: &mut String, heading: &str, diagnostics: &[CollectedDiagnostic]) {
+    if diagnostics.is_empty() {
+        return;
+    }
+
+    report.push_str(&format!("\n{heading}:\n"));
+    for diagnostic in diagnostics {
+        report.push_str(&format!("  {}\n", diagnostic.summary_line()));
+    }
+}
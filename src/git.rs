@@ -0,0 +1,30 @@
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use anyhow::{Context, Result};
+use rumkinst::error_log::{ErrorCode, WithCode};
+
+/// Run `git` in `dir`, returning what it printed
+pub fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let mut command = Command::new("git");
+    command.args(args).stdin(Stdio::null());
+    if !dir.as_os_str().is_empty() {
+        command.current_dir(dir);
+    }
+
+    let output = command
+        .output()
+        .code(ErrorCode::GIT_FAILED)
+        .context("failed to run `git`")?;
+    if !output.status.success() {
+        return Err(ErrorCode::GIT_FAILED).context(format!(
+            "`git {}` {}: {}",
+            args.join(" "),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,208 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::{Context, Result};
+use indicatif::HumanBytes;
+use jiff::Timestamp;
+use rumkinst::{BuildOutput, config::find_config_file_at};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cli::HistoryArgs,
+    suggest::{print_suggestions, suggest_excludes},
+};
+
+/// Name of the file in `out/` every run is recorded in
+const HISTORY_FILE_NAME: &str = "history.json";
+
+/// One run, as recorded in the history file
+#[derive(Clone, Serialize, Deserialize)]
+struct HistoryEntry {
+    run_id: String,
+    started_at: String,
+    version: Option<String>,
+    success: bool,
+    elapsed_seconds: f64,
+    /// `None` for runs that failed before the build finished
+    files: Option<usize>,
+    archive_bytes: Option<u64>,
+}
+
+/// A run being recorded, which is added to the history as failed when dropped unless it is
+/// marked as successful first
+pub struct RunHistory {
+    path: PathBuf,
+    started: Instant,
+    entry: HistoryEntry,
+}
+
+impl RunHistory {
+    /// Start recording the run `run_id` into the history in `out_root`
+    pub fn start(out_root: &Path, run_id: &str, version: Option<&str>) -> Self {
+        Self {
+            path: out_root.join(HISTORY_FILE_NAME),
+            started: Instant::now(),
+            entry: HistoryEntry {
+                run_id: run_id.to_string(),
+                started_at: Timestamp::now().to_string(),
+                version: version.map(String::from),
+                success: false,
+                elapsed_seconds: 0.0,
+                files: None,
+                archive_bytes: None,
+            },
+        }
+    }
+
+    /// Record the run as successful, having made `output`
+    pub fn succeeded(mut self, output: &BuildOutput) {
+        self.entry.success = true;
+        self.entry.files = Some(output.stats.files);
+        self.entry.archive_bytes = Some(output.stats.archive_bytes);
+    }
+}
+
+impl Drop for RunHistory {
+    fn drop(&mut self) {
+        self.entry.elapsed_seconds = self.started.elapsed().as_secs_f64();
+        if let Err(err) = append_entry(&self.path, &self.entry) {
+            log::warn!("failed to record the run in the build history: {err:#}");
+        }
+    }
+}
+
+fn read_history(path: &Path) -> Result<Vec<HistoryEntry>> {
+    match fs::read(path) {
+        Ok(bytes) => {
+            serde_json::from_slice(&bytes).with_context(|| format!("failed to parse {path:?}"))
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(err) => Err(err).with_context(|| format!("failed to read {path:?}")),
+    }
+}
+
+/// Add `entry` to the history at `path`, replacing it in one step so it is never left half
+/// written
+fn append_entry(path: &Path, entry: &HistoryEntry) -> Result<()> {
+    let mut history = read_history(path)?;
+    history.push(entry.clone());
+    let json = serde_json::to_vec_pretty(&history).context("failed to serialize history")?;
+    let temporary = path.with_extension("json.tmp");
+    fs::write(&temporary, json).with_context(|| format!("failed to write {temporary:?}"))?;
+    fs::rename(&temporary, path).with_context(|| format!("failed to write {path:?}"))
+}
+
+/// Print the latest runs recorded for the project given by `args`, with how their archive size
+/// and duration changed from the successful run before
+pub fn command_history(args: HistoryArgs) -> Result<()> {
+    let config_path =
+        find_config_file_at(args.path).context("could not find `rumkinst.toml` config file")?;
+    let base_dir = config_path
+        .parent()
+        .context("could not find the parent directory of rumkinst.toml")?;
+    let out_root = base_dir.join("out");
+    let history = read_history(&out_root.join(HISTORY_FILE_NAME))?;
+    let shown = &history[history.len().saturating_sub(args.limit)..];
+
+    if args.suggest_excludes {
+        // Older runs may have been removed by `[output] keep-runs`
+        let runs = shown
+            .iter()
+            .filter(|entry| entry.success)
+            .map(|entry| out_root.join(&entry.run_id))
+            .filter(|dir| dir.is_dir())
+            .collect::<Vec<_>>();
+        let suggestions = suggest_excludes(&runs, args.min_size)?;
+        if args.json {
+            let json = serde_json::to_string_pretty(&suggestions)
+                .context("failed to serialize suggestions")?;
+            println!("{json}");
+        } else {
+            print_suggestions(&suggestions, runs.len());
+        }
+        return Ok(());
+    }
+
+    if args.json {
+        let json = serde_json::to_string_pretty(shown).context("failed to serialize history")?;
+        println!("{json}");
+        return Ok(());
+    }
+    if shown.is_empty() {
+        println!("No runs recorded yet");
+        return Ok(());
+    }
+
+    // The successful run before the first one shown, so its changes can be shown too
+    let mut previous = history[..history.len() - shown.len()]
+        .iter()
+        .rfind(|entry| entry.success);
+    println!(
+        "{:<22}  {:<20}  {:<10}  {:<7}  {:>18}  {:>18}",
+        "Run", "Started", "Version", "Status", "Duration", "Archive"
+    );
+    for entry in shown {
+        let started = entry
+            .started_at
+            .parse::<Timestamp>()
+            .map(|time| time.strftime("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|_| entry.started_at.clone());
+        let status = match entry.success {
+            true => "ok",
+            false => "failed",
+        };
+        let mut duration = format!("{:.2}s", entry.elapsed_seconds);
+        let mut archive = entry
+            .archive_bytes
+            .map(|bytes| HumanBytes(bytes).to_string())
+            .unwrap_or_else(|| "-".to_string());
+        if entry.success
+            && let Some(previous) = previous
+        {
+            duration.push_str(&change(previous.elapsed_seconds, entry.elapsed_seconds));
+            if let (Some(before), Some(after)) = (previous.archive_bytes, entry.archive_bytes) {
+                archive.push_str(&change(before as f64, after as f64));
+            }
+        }
+        println!(
+            "{:<22}  {started:<20}  {:<10}  {status:<7}  {duration:>18}  {archive:>18}",
+            entry.run_id,
+            entry.version.as_deref().unwrap_or("-"),
+        );
+        if entry.success {
+            previous = Some(entry);
+        }
+    }
+
+    let successful = shown
+        .iter()
+        .filter(|entry| entry.success)
+        .collect::<Vec<_>>();
+    if let [first, .., last] = successful.as_slice() {
+        println!(
+            "\nOver {} successful runs: duration {:.2}s to {:.2}s{}, archive {} to {}{}",
+            successful.len(),
+            first.elapsed_seconds,
+            last.elapsed_seconds,
+            change(first.elapsed_seconds, last.elapsed_seconds),
+            HumanBytes(first.archive_bytes.unwrap_or_default()),
+            HumanBytes(last.archive_bytes.unwrap_or_default()),
+            change(
+                first.archive_bytes.unwrap_or_default() as f64,
+                last.archive_bytes.unwrap_or_default() as f64
+            ),
+        );
+    }
+    Ok(())
+}
+
+/// How `after` changed from `before`, as a percentage in parentheses
+fn change(before: f64, after: f64) -> String {
+    match before > 0.0 {
+        true => format!(" ({:+.0}%)", (after - before) / before * 100.0),
+        false => String::new(),
+    }
+}
@@ -0,0 +1,116 @@
+//! Translations of the messages shown to people running rumkinst, such as prompts, summaries and
+//! the failure of each command
+//!
+//! Messages are [Fluent](https://projectfluent.org) files in `src/i18n`, built into rumkinst. The
+//! locale comes from `RUMKINST_LANG`, then `LANGUAGE`, `LC_ALL`, `LC_MESSAGES` and `LANG`, and
+//! messages missing from it fall back to English. Debug and trace logs stay in English.
+
+use std::{env, sync::OnceLock};
+
+#[doc(hidden)]
+pub use fluent_bundle::FluentArgs;
+use fluent_bundle::{FluentResource, concurrent::FluentBundle};
+use unic_langid::LanguageIdentifier;
+
+/// Messages in English, which every other locale falls back to
+const ENGLISH: (&str, &str) = ("en", include_str!("i18n/en.ftl"));
+
+/// Every other bundled locale with its messages
+const TRANSLATIONS: &[(&str, &str)] = &[("de", include_str!("i18n/de.ftl"))];
+
+type Bundle = FluentBundle<FluentResource>;
+
+/// The bundle of the chosen locale, followed by English if that is a different one
+static BUNDLES: OnceLock<Vec<Bundle>> = OnceLock::new();
+
+/// Translate the message `id` into the locale of the environment, filling in its arguments
+///
+/// ```ignore
+/// let line = tr!("run-warnings", count = 2);
+/// ```
+#[macro_export]
+macro_rules! tr {
+    ($id:literal) => {
+        $crate::i18n::message($id, None)
+    };
+    ($id:literal, $($name:ident = $value:expr),+ $(,)?) => {{
+        let mut args = $crate::i18n::FluentArgs::new();
+        $(args.set(stringify!($name), $value);)+
+        $crate::i18n::message($id, Some(&args))
+    }};
+}
+
+/// The message `id` in the locale of the environment, see [`tr!`](crate::tr)
+///
+/// Falls back to English, and then to `id` itself if no bundled locale has it.
+pub fn message(id: &str, args: Option<&FluentArgs>) -> String {
+    let bundles = BUNDLES.get_or_init(|| load_bundles(&requested_locales()));
+    for bundle in bundles {
+        let Some(pattern) = bundle.get_message(id).and_then(|message| message.value()) else {
+            continue;
+        };
+        let mut errors = vec![];
+        let message = bundle.format_pattern(pattern, args, &mut errors);
+        if errors.is_empty() {
+            return message.into_owned();
+        }
+        log::debug!("failed to format message `{id}`: {errors:?}");
+    }
+    id.to_string()
+}
+
+/// Locales asked for by the environment, most preferred first
+fn requested_locales() -> Vec<LanguageIdentifier> {
+    let mut requested = vec![];
+    if let Ok(lang) = env::var("RUMKINST_LANG") {
+        requested.push(lang);
+    }
+    // `LANGUAGE` is a list, and only used when a locale is set at all
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()));
+    if let Some(locale) = locale.filter(|locale| !matches!(locale.as_str(), "C" | "POSIX")) {
+        if let Ok(languages) = env::var("LANGUAGE") {
+            requested.extend(languages.split(':').map(String::from));
+        }
+        requested.push(locale);
+    }
+
+    requested
+        .iter()
+        .filter_map(|locale| {
+            // POSIX locales look like `de_DE.UTF-8@euro`
+            let locale = locale.split(['.', '@']).next().unwrap_or_default();
+            locale.replace('_', "-").parse().ok()
+        })
+        .collect()
+}
+
+/// Bundles for the first of `requested` rumkinst has a translation for, then English
+fn load_bundles(requested: &[LanguageIdentifier]) -> Vec<Bundle> {
+    // English itself is asked for by finding no translation
+    let chosen = requested
+        .iter()
+        .map(|requested| requested.language.as_str())
+        .take_while(|language| *language != ENGLISH.0)
+        .find_map(|language| TRANSLATIONS.iter().find(|(locale, _)| *locale == language));
+
+    chosen
+        .into_iter()
+        .chain([&ENGLISH])
+        .map(|(locale, messages)| {
+            let langid = locale
+                .parse::<LanguageIdentifier>()
+                .expect("bundled locales should be valid language identifiers");
+            let resource = FluentResource::try_new(messages.to_string())
+                .expect("bundled messages should be valid Fluent");
+            let mut bundle = Bundle::new_concurrent(vec![langid]);
+            // Terminals don't need the isolation marks around arguments
+            bundle.set_use_isolating(false);
+            bundle
+                .add_resource(resource)
+                .expect("bundled messages should have unique ids");
+            bundle
+        })
+        .collect()
+}
@@ -0,0 +1,53 @@
+## Fragen, bevor etwas ersetzt oder entfernt wird
+
+# Wird an jede Frage angehängt
+prompt-choices = [j/N]
+# Durch Kommas getrennte Antworten, die fortfahren, neben `y` und `yes`
+prompt-yes-answers = j, ja
+prompt-no-terminal = { $question } Es wird fortgefahren, da es kein Terminal zum Nachfragen gibt
+confirm-replace-asset = „{ $name }“ in { $release } ersetzen?
+confirm-replace-assets = { $count } vorhandene Dateien in { $release } ersetzen?
+confirm-overwrite-run = Den früheren Lauf „{ $run }“ überschreiben?
+confirm-remove-runs =
+    { $count ->
+        [one] 1 alten Lauf entfernen, der { $size } belegt?
+       *[other] { $count } alte Läufe entfernen, die { $size } belegen?
+    }
+runs-kept = Alte Läufe werden behalten, obwohl sie die Grenzen in `[output]` überschreiten
+
+## Zusammenfassungen am Ende eines Laufs
+
+warnings-reported =
+    { $count ->
+        [one] 1 Warnung in diesem Lauf:
+       *[other] { $count } Warnungen in diesem Lauf:
+    }
+errors-skipped =
+    { $count ->
+        [one] 1 Fehler in diesem Lauf übersprungen:
+       *[other] { $count } Fehler in diesem Lauf übersprungen:
+    }
+run-finished = Lauf „{ $run }“ abgeschlossen
+run-no-artifacts = Es wurden keine Artefakte erstellt
+run-artifacts = Artefakte:
+run-plugin-artifact = { $kind } vom Plugin `{ $plugin }`
+run-files = Dateien:   { $packaged } gepackt, { $skipped } übersprungen
+run-warnings = Warnungen: { $count }
+run-time = Dauer:     { $seconds } s
+
+## Fehlschläge der einzelnen Befehle
+
+new-failed = das neue rumkinst-Verzeichnis konnte nicht erstellt werden
+version-failed = die Paketversion konnte nicht geändert werden
+stats-failed = das Archiv konnte nicht analysiert werden
+history-failed = der Build-Verlauf konnte nicht angezeigt werden
+test-failed = der Installer konnte nicht getestet werden
+lint-failed = das Projekt konnte nicht geprüft werden
+snapshot-failed = vom Archiv konnte kein Snapshot erstellt werden
+make-failed = die Installer-Artefakte konnten nicht mit rumkinst erstellt werden
+error-report-written = Ein vollständiger Fehlerbericht wurde nach { $path } geschrieben, bitte an Fehlermeldungen anhängen
+
+## Ein Projekt erstellen
+
+new-creating = Ein neues rumkinst-Verzeichnis wird erstellt...
+new-created = Neues rumkinst-Verzeichnis in { $path } erstellt
@@ -0,0 +1,53 @@
+## Questions asked before replacing or removing something
+
+# Appended to every question
+prompt-choices = [y/N]
+# Comma separated answers that go ahead, besides `y` and `yes`
+prompt-yes-answers = y, yes
+prompt-no-terminal = { $question } Going ahead, as there is no terminal to ask on
+confirm-replace-asset = Replace "{ $name }" on { $release }?
+confirm-replace-assets = Replace { $count } existing assets on { $release }?
+confirm-overwrite-run = Overwrite the earlier run "{ $run }"?
+confirm-remove-runs =
+    { $count ->
+        [one] Remove 1 old run taking up { $size }?
+       *[other] Remove { $count } old runs taking up { $size }?
+    }
+runs-kept = Keeping old runs, even though they are over the limits in `[output]`
+
+## Summaries at the end of a run
+
+warnings-reported =
+    { $count ->
+        [one] 1 warning reported during this run:
+       *[other] { $count } warnings reported during this run:
+    }
+errors-skipped =
+    { $count ->
+        [one] 1 error skipped during this run:
+       *[other] { $count } errors skipped during this run:
+    }
+run-finished = Finished run "{ $run }"
+run-no-artifacts = No artifacts were made
+run-artifacts = Artifacts:
+run-plugin-artifact = { $kind } from plugin `{ $plugin }`
+run-files = Files:    { $packaged } packaged, { $skipped } skipped
+run-warnings = Warnings: { $count }
+run-time = Time:     { $seconds }s
+
+## Failures of each command
+
+new-failed = failed to create new rumkinst directory
+version-failed = failed to change the package version
+stats-failed = failed to analyze archive
+history-failed = failed to show the build history
+test-failed = failed to test the installer
+lint-failed = failed to lint the project
+snapshot-failed = failed to snapshot the archive
+make-failed = failed to make installer artifacts with rumkinst
+error-report-written = A full error report was written to { $path }, attach it to bug reports
+
+## Creating a project
+
+new-creating = Creating a new rumkinst directory...
+new-created = Succesfully created new rumkinst directory at { $path }
@@ -0,0 +1,586 @@
+use std::{
+    env,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{Context, Result};
+use nanoid::nanoid;
+use rumkinst::{
+    config::{Config, find_config_file_at},
+    error_log::{ErrorCode, WithCode},
+    installer_gen::{
+        ArchiveEntry, Digester, Sha256Digester, extract_archive, read_listing, to_hex,
+    },
+    progress_log::NoProgress,
+};
+use serde::Serialize;
+
+use crate::{
+    cli::{ContainerEngine, TestArgs},
+    run_dir::LATEST_LINK_NAME,
+    stats::find_archive,
+};
+
+/// Most problems listed for a single check, the rest are only counted
+const MAX_DETAILS: usize = 20;
+
+/// Most lines of its output listed for a failed script
+const OUTPUT_TAIL: usize = 10;
+
+/// Where the test directory is mounted in containers
+const CONTAINER_DIR: &str = "/rumkinst";
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Pass,
+    Fail,
+    Skip,
+}
+
+/// The outcome of one step of a test install
+#[derive(Serialize)]
+struct Check {
+    name: &'static str,
+    status: Status,
+    /// Why the check failed or was skipped, or what it looked at
+    details: Vec<String>,
+}
+
+impl Check {
+    fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Pass,
+            details: vec![detail.into()],
+        }
+    }
+
+    fn skip(name: &'static str, reason: impl Into<String>) -> Self {
+        Self {
+            name,
+            status: Status::Skip,
+            details: vec![reason.into()],
+        }
+    }
+
+    /// Passes if `problems` is empty, otherwise fails listing them
+    fn from_problems(name: &'static str, mut problems: Vec<String>, passed: String) -> Self {
+        if problems.is_empty() {
+            return Self::pass(name, passed);
+        }
+        if problems.len() > MAX_DETAILS {
+            let more = problems.len() - MAX_DETAILS;
+            problems.truncate(MAX_DETAILS);
+            problems.push(format!("and {more} more"));
+        }
+        Self {
+            name,
+            status: Status::Fail,
+            details: problems,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct TestReport {
+    archive: PathBuf,
+    /// Image of the container the scripts ran in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    container: Option<String>,
+    prefix: PathBuf,
+    checks: Vec<Check>,
+    /// Everything the scripts and the container engine printed
+    log: String,
+}
+
+/// A running container of the image being tested in, removed once dropped
+struct Container {
+    engine: &'static str,
+    id: String,
+}
+
+impl Container {
+    /// Start a container of `image` that idles until it is removed, with `dir` mounted into it at
+    /// [`CONTAINER_DIR`]
+    fn start(engine: &'static str, image: &str, dir: &Path) -> Result<Self, String> {
+        log::info!("Starting a {engine} container of {image:?}");
+        let output = Command::new(engine)
+            .args([
+                "run",
+                "--detach",
+                "--rm",
+                "--entrypoint",
+                "tail",
+                "--volume",
+            ])
+            .arg(format!("{}:{CONTAINER_DIR}:z", dir.display()))
+            .args([image, "-f", "/dev/null"])
+            .output()
+            .map_err(|err| format!("failed to run `{engine}`: {err}"))?;
+        if !output.status.success() {
+            return Err(format!(
+                "`{engine} run` {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        Ok(Self {
+            engine,
+            id: String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        })
+    }
+}
+
+impl Drop for Container {
+    fn drop(&mut self) {
+        // What the scripts made may belong to root in the container, so it is made removable
+        // from outside of it first
+        let chmod = Command::new(self.engine)
+            .args(["exec", &self.id, "chmod", "-R", "a+rwX", CONTAINER_DIR])
+            .output();
+        let removed = Command::new(self.engine)
+            .args(["rm", "--force", &self.id])
+            .output();
+        if chmod.and(removed).is_err() {
+            log::warn!("failed to remove {} container {}", self.engine, self.id);
+        }
+    }
+}
+
+/// Runs the installer scripts of a test install, keeping what they print
+#[derive(Default)]
+struct ScriptRunner {
+    /// Runs the scripts in a container instead of on this machine
+    container: Option<Container>,
+    log: String,
+}
+
+/// Install the archive given by `args` into a throwaway prefix, check the installed files against
+/// its listing, uninstall it again and report every step
+pub fn command_test(args: TestArgs) -> Result<()> {
+    let config_path =
+        find_config_file_at(args.path).context("could not find `rumkinst.toml` config file")?;
+    let bytes = fs::read(&config_path)
+        .code_at(ErrorCode::CONFIG_UNREADABLE, &config_path)
+        .with_context(|| format!("failed to read {config_path:?}"))?;
+    let base_dir = config_path
+        .parent()
+        .context("could not find the parent directory of rumkinst.toml")?;
+    let config = Config::read_named(bytes.as_slice(), &config_path.to_string_lossy())
+        .with_context(|| format!("could not read rumkinst config at {config_path:?}"))?
+        .with_base_dir(base_dir);
+
+    let archive = args
+        .archive
+        .unwrap_or_else(|| base_dir.join("out").join(LATEST_LINK_NAME));
+    let archive = match archive.is_dir() {
+        true => find_archive(&archive)?,
+        false => archive,
+    };
+
+    let engine = match args.engine {
+        ContainerEngine::Docker => "docker",
+        ContainerEngine::Podman => "podman",
+    };
+    let images = match args.in_container.is_empty() {
+        true => vec![None],
+        false => args.in_container.iter().map(Some).collect(),
+    };
+
+    let mut reports = vec![];
+    for image in images {
+        let dir = env::temp_dir().join(format!("rumkinst-test-{}", nanoid!()));
+        let report = test_install(&config, &archive, &dir, image.map(|image| (engine, image)));
+        match args.keep {
+            true => log::info!("Kept the test install at {dir:?}"),
+            false => {
+                if let Err(err) = fs::remove_dir_all(&dir) {
+                    log::warn!("failed to remove the test install at {dir:?}: {err}");
+                }
+            }
+        }
+        reports.push(report?);
+    }
+
+    if args.json {
+        let json = match reports.as_slice() {
+            [report] if args.in_container.is_empty() => serde_json::to_string_pretty(report),
+            reports => serde_json::to_string_pretty(reports),
+        }
+        .context("failed to serialize report")?;
+        println!("{json}");
+    } else {
+        reports.iter().for_each(print_report);
+    }
+    let checks = reports.iter().flat_map(|report| &report.checks);
+    let failed = checks
+        .clone()
+        .filter(|check| check.status == Status::Fail)
+        .count();
+    if failed > 0 {
+        return Err(ErrorCode::INSTALL_TEST_FAILED.at(&archive))
+            .context(format!("{failed} of {} checks failed", checks.count()));
+    }
+    Ok(())
+}
+
+/// Extract `archive` into `dir`, install its `root` source into a prefix in it with the
+/// installer scripts run around that, then uninstall it again
+///
+/// With `container`, an engine and an image, the scripts run in a fresh container of that image.
+fn test_install(
+    config: &Config,
+    archive: &Path,
+    dir: &Path,
+    container: Option<(&'static str, &String)>,
+) -> Result<TestReport> {
+    let payload = dir.join("payload");
+    let prefix = dir.join("prefix");
+    fs::create_dir_all(&prefix).with_context(|| format!("failed to create {prefix:?}"))?;
+    let mut checks = vec![];
+    let mut scripts = ScriptRunner::default();
+    let report = |checks, scripts: ScriptRunner, prefix| TestReport {
+        archive: archive.to_path_buf(),
+        container: container.map(|(_, image)| image.clone()),
+        prefix,
+        checks,
+        log: scripts.log,
+    };
+
+    let entries = extract_archive(archive, &payload, &Sha256Digester)
+        .code_at(ErrorCode::ARCHIVE_READ, archive)
+        .with_context(|| format!("failed to extract archive {archive:?}"))?;
+    checks.push(Check::pass(
+        "extract archive",
+        format!("{} files", entries.len()),
+    ));
+
+    let listing_path = PathBuf::from(format!("{}.list", archive.display()));
+    let manifest = match read_listing(&listing_path) {
+        Ok(listing) => {
+            checks.push(Check::from_problems(
+                "archive matches its listing",
+                compare_entries(&entries, &listing),
+                format!("{} files listed", listing.len()),
+            ));
+            listing
+        }
+        Err(err) => {
+            checks.push(Check::skip(
+                "archive matches its listing",
+                format!("no listing at {listing_path:?}: {err}"),
+            ));
+            entries
+        }
+    };
+
+    if let Some((engine, image)) = container {
+        match Container::start(engine, image, dir) {
+            Ok(container) => {
+                checks.push(Check::pass("start container", container.id.clone()));
+                scripts.container = Some(container);
+            }
+            Err(err) => {
+                checks.push(Check {
+                    name: "start container",
+                    status: Status::Fail,
+                    details: vec![err],
+                });
+                return Ok(report(checks, scripts, prefix));
+            }
+        }
+    }
+
+    checks.push(scripts.run(
+        "preinstall script",
+        "installer.preinstall",
+        config.preinstall(),
+        &payload,
+        &prefix,
+    ));
+
+    let Some(root) = config.root_source() else {
+        checks.push(Check::skip(
+            "install files",
+            "the `root` source is disabled",
+        ));
+        return Ok(report(checks, scripts, prefix));
+    };
+    let root_name = name_of(root);
+    let installed = manifest
+        .iter()
+        .filter_map(|entry| {
+            let relative = entry.name.strip_prefix(&root_name)?.strip_prefix('/')?;
+            Some((entry, prefix.join(relative)))
+        })
+        .collect::<Vec<_>>();
+
+    let mut problems = vec![];
+    for (entry, target) in &installed {
+        let copied = target
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::copy(payload.join(&entry.name), target));
+        if let Err(err) = copied {
+            problems.push(format!("{}: {err}", entry.name));
+        }
+    }
+    checks.push(Check::from_problems(
+        "install files",
+        problems,
+        format!("{} files into {prefix:?}", installed.len()),
+    ));
+
+    checks.push(scripts.run(
+        "postinstall script",
+        "installer.postinstall",
+        config.postinstall(),
+        &payload,
+        &prefix,
+    ));
+
+    let problems = installed
+        .iter()
+        .filter_map(|(entry, target)| check_installed(entry, target).err())
+        .collect();
+    checks.push(Check::from_problems(
+        "installed files match the listing",
+        problems,
+        format!("{} files checked", installed.len()),
+    ));
+
+    checks.push(uninstall(&installed, &prefix));
+
+    Ok(report(checks, scripts, prefix))
+}
+
+/// Differences between the files read from an archive and the ones in its listing
+fn compare_entries(entries: &[ArchiveEntry], listing: &[ArchiveEntry]) -> Vec<String> {
+    let mut problems = vec![];
+    for listed in listing {
+        match entries.iter().find(|entry| entry.name == listed.name) {
+            None => problems.push(format!("{} is listed but not in the archive", listed.name)),
+            Some(entry) if entry.digest != listed.digest || entry.size != listed.size => {
+                problems.push(format!("{} differs from its listing", listed.name))
+            }
+            Some(entry) if entry.mode != listed.mode => problems.push(format!(
+                "{} has mode {:04o} instead of {:04o}",
+                listed.name, entry.mode, listed.mode
+            )),
+            Some(_) => {}
+        }
+    }
+    for entry in entries {
+        if !listing.iter().any(|listed| listed.name == entry.name) {
+            problems.push(format!("{} is in the archive but not listed", entry.name));
+        }
+    }
+    problems
+}
+
+impl ScriptRunner {
+    /// Run the installer script set as `key`, extracted into `payload`, with `RUMKINST_PREFIX`
+    /// set to the prefix it installs into
+    fn run(
+        &mut self,
+        name: &'static str,
+        key: &str,
+        script: Option<&Path>,
+        payload: &Path,
+        prefix: &Path,
+    ) -> Check {
+        let Some(script) = script else {
+            return Check::skip(name, format!("`{key}` is not set"));
+        };
+        let fail = |details: Vec<String>| Check {
+            name,
+            status: Status::Fail,
+            details,
+        };
+        if !payload.join(script).is_file() {
+            return fail(vec![format!("{script:?} is not in the archive")]);
+        }
+
+        log::info!("Running {name} {script:?}");
+        let mut command = match &self.container {
+            None => {
+                let mut command = Command::new("sh");
+                command
+                    .arg(payload.join(script))
+                    .current_dir(payload)
+                    .env("RUMKINST_PREFIX", prefix);
+                command
+            }
+            Some(container) => {
+                let mut command = Command::new(container.engine);
+                command
+                    .args(["exec", "--workdir"])
+                    .arg(format!("{CONTAINER_DIR}/payload"))
+                    .arg("--env")
+                    .arg(format!("RUMKINST_PREFIX={CONTAINER_DIR}/prefix"))
+                    .args([&container.id, "sh"])
+                    .arg(format!("{CONTAINER_DIR}/payload/{}", name_of(script)));
+                command
+            }
+        };
+        let output = match command.output() {
+            Ok(output) => output,
+            Err(err) => return fail(vec![format!("failed to run {script:?}: {err}")]),
+        };
+
+        let printed = format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        self.log.push_str(&printed);
+        if output.status.success() {
+            return Check::pass(name, format!("{script:?} succeeded"));
+        }
+        let lines = printed.lines().collect::<Vec<_>>();
+        let tail = &lines[lines.len().saturating_sub(OUTPUT_TAIL)..];
+        let mut details = vec![format!("{script:?} {}", output.status)];
+        details.extend(tail.iter().map(|line| format!("| {line}")));
+        fail(details)
+    }
+}
+
+/// Whether the file installed at `target` has the size, contents and permissions of `entry`
+fn check_installed(entry: &ArchiveEntry, target: &Path) -> Result<(), String> {
+    let problem = |detail: &str| format!("{}: {detail}", entry.name);
+    let metadata = fs::metadata(target).map_err(|err| problem(&err.to_string()))?;
+    if metadata.len() != entry.size {
+        return Err(problem(&format!(
+            "is {} bytes instead of {}",
+            metadata.len(),
+            entry.size
+        )));
+    }
+    let digest = File::open(target)
+        .and_then(|mut file| Sha256Digester.digest(&mut file, &NoProgress))
+        .map_err(|err| problem(&err.to_string()))?;
+    if to_hex(&digest) != entry.digest {
+        return Err(problem("contents differ from the archive"));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = metadata.permissions().mode() & 0o7777;
+        if mode != entry.mode {
+            return Err(problem(&format!(
+                "has mode {mode:04o} instead of {:04o}",
+                entry.mode
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Remove every installed file and the directories left empty by that, failing if anything is
+/// left behind in `prefix`
+fn uninstall(installed: &[(&ArchiveEntry, PathBuf)], prefix: &Path) -> Check {
+    let mut problems = vec![];
+    let mut dirs = vec![];
+    for (entry, target) in installed {
+        if let Err(err) = fs::remove_file(target) {
+            problems.push(format!("failed to remove {}: {err}", entry.name));
+        }
+        dirs.extend(
+            target
+                .ancestors()
+                .skip(1)
+                .take_while(|dir| *dir != prefix)
+                .map(Path::to_path_buf),
+        );
+    }
+    // Deepest first, and only once empty, so anything left behind is found below
+    dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+    dirs.dedup();
+    for dir in dirs {
+        fs::remove_dir(dir).ok();
+    }
+
+    let mut left = vec![];
+    leftovers(prefix, prefix, &mut left);
+    problems.extend(
+        left.into_iter()
+            .map(|path| format!("{path} was left behind")),
+    );
+    Check::from_problems(
+        "uninstall",
+        problems,
+        format!("{} files removed", installed.len()),
+    )
+}
+
+/// Everything under `dir`, relative to `prefix`
+fn leftovers(prefix: &Path, dir: &Path, left: &mut Vec<String>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let relative = path.strip_prefix(prefix).unwrap_or(&path);
+        match path.is_dir() && !path.is_symlink() {
+            true => {
+                let before = left.len();
+                leftovers(prefix, &path, left);
+                if left.len() == before {
+                    left.push(format!("{}/", relative.display()));
+                }
+            }
+            false => left.push(relative.display().to_string()),
+        }
+    }
+}
+
+/// `path` as the start of archive entry names
+fn name_of(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            std::path::Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn print_report(report: &TestReport) {
+    match &report.container {
+        Some(image) => println!("Test install of {:?} in {image}", report.archive),
+        None => println!("Test install of {:?}", report.archive),
+    }
+    for check in &report.checks {
+        let status = match check.status {
+            Status::Pass => "pass",
+            Status::Fail => "FAIL",
+            Status::Skip => "skip",
+        };
+        match check.details.as_slice() {
+            [detail] => println!("  {status}  {}: {detail}", check.name),
+            details => {
+                println!("  {status}  {}", check.name);
+                for detail in details {
+                    println!("          {detail}");
+                }
+            }
+        }
+    }
+    let count = |status| {
+        report
+            .checks
+            .iter()
+            .filter(|check| check.status == status)
+            .count()
+    };
+    println!(
+        "{} passed, {} failed, {} skipped",
+        count(Status::Pass),
+        count(Status::Fail),
+        count(Status::Skip)
+    );
+}
@@ -0,0 +1,255 @@
+use std::{
+    fs::Metadata,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use flate2::{Compression, GzBuilder, write::GzEncoder};
+use zip::{
+    CompressionMethod,
+    write::{SimpleFileOptions, StreamWriter},
+};
+
+use super::{
+    names::entry_path,
+    xattrs::{PaxRecord, pax_header},
+};
+use crate::config::ArchiveFormat;
+
+/// A destination for the packaged files of a build, in some archive format
+///
+/// Entry names are the paths files are stored under, relative to the archive root. Writers refuse
+/// names that could be extracted outside of it, see [`entry_path`].
+pub trait ArchiveWriter {
+    /// Add a file read from `contents`, `size` bytes long, with the unix permissions `mode` and
+    /// the rest of `metadata`
+    fn append_file(
+        &mut self,
+        name: &Path,
+        metadata: &Metadata,
+        mode: u32,
+        size: u64,
+        contents: &mut dyn Read,
+    ) -> io::Result<()>;
+    /// Add a file made during the build rather than read from disk, last modified `mtime`
+    /// seconds after the unix epoch
+    fn append_bytes(
+        &mut self,
+        name: &Path,
+        mode: u32,
+        mtime: u64,
+        contents: &[u8],
+    ) -> io::Result<()>;
+    fn append_dir(&mut self, name: &Path) -> io::Result<()>;
+    /// Attach the extended attributes in `xattrs` to the next entry added, for formats that can
+    /// store them
+    fn append_xattrs(&mut self, xattrs: &[PaxRecord]) -> io::Result<()> {
+        let _ = xattrs;
+        Ok(())
+    }
+    /// Write any trailing data, no entries can be added afterwards
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+/// Extensions of files that are marked executable when built on a platform without unix modes
+#[cfg(not(unix))]
+const EXECUTABLE_EXTENSIONS: &[&str] = &["sh", "bash", "zsh", "fish", "py", "pl", "rb"];
+
+/// Unix permissions to store for the file at `path`
+///
+/// Platforms without unix modes mark scripts executable by their extension, and every other
+/// file as `0o644`.
+pub(crate) fn unix_mode(metadata: &Metadata, path: &Path) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let _ = path;
+        metadata.permissions().mode() & 0o7777
+    }
+    #[cfg(not(unix))]
+    {
+        let script = path.extension().is_some_and(|extension| {
+            EXECUTABLE_EXTENSIONS
+                .iter()
+                .any(|known| extension.eq_ignore_ascii_case(known))
+        });
+        match metadata.is_dir() || script {
+            true => 0o755,
+            false => 0o644,
+        }
+    }
+}
+
+/// Create a writer for `format`, writing into `destination`
+///
+/// `name` is the file name of the archive, which some formats record in their headers.
+pub fn new_archive_writer<'a, W: Write + 'a>(
+    format: ArchiveFormat,
+    destination: W,
+    name: &str,
+) -> Box<dyn ArchiveWriter + 'a> {
+    match format {
+        ArchiveFormat::TarGz => Box::new(TarGzArchiveWriter::new(destination, name)),
+        ArchiveFormat::Zip => Box::new(ZipArchiveWriter::new(destination)),
+    }
+}
+
+/// A gzip compressed tarball
+pub struct TarGzArchiveWriter<W: Write> {
+    builder: tar::Builder<GzEncoder<W>>,
+}
+
+impl<W: Write> TarGzArchiveWriter<W> {
+    pub fn new(destination: W, name: &str) -> Self {
+        let encoder = GzBuilder::new()
+            .filename(name)
+            .write(destination, Compression::best());
+        Self {
+            builder: tar::Builder::new(encoder),
+        }
+    }
+}
+
+impl<W: Write> ArchiveWriter for TarGzArchiveWriter<W> {
+    fn append_file(
+        &mut self,
+        name: &Path,
+        metadata: &Metadata,
+        mode: u32,
+        size: u64,
+        contents: &mut dyn Read,
+    ) -> io::Result<()> {
+        let name = &entry_path(name)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(metadata);
+        header.set_mode(mode);
+        // Special files included as empty files are stored as plain files
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_size(size);
+        self.builder.append_data(&mut header, name, contents)
+    }
+
+    fn append_bytes(
+        &mut self,
+        name: &Path,
+        mode: u32,
+        mtime: u64,
+        contents: &[u8],
+    ) -> io::Result<()> {
+        let name = &entry_path(name)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_mode(mode);
+        header.set_mtime(mtime);
+        header.set_size(contents.len() as u64);
+        self.builder.append_data(&mut header, name, contents)
+    }
+
+    fn append_dir(&mut self, name: &Path) -> io::Result<()> {
+        let name = &entry_path(name)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_mode(0o755);
+        header.set_size(0);
+        self.builder.append_data(&mut header, name, io::empty())
+    }
+
+    fn append_xattrs(&mut self, xattrs: &[PaxRecord]) -> io::Result<()> {
+        if xattrs.is_empty() {
+            return Ok(());
+        }
+        let contents = pax_header(xattrs);
+        let mut header = tar::Header::new_ustar();
+        header.set_entry_type(tar::EntryType::XHeader);
+        header.set_mode(0o644);
+        header.set_size(contents.len() as u64);
+        self.builder
+            .append_data(&mut header, "PaxHeaders/xattrs", contents.as_slice())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.builder.finish()?;
+        self.builder.get_mut().try_finish()
+    }
+}
+
+/// A deflate compressed zip file, written as a stream so `destination` does not need to seek
+pub struct ZipArchiveWriter<W: Write> {
+    writer: Option<zip::ZipWriter<StreamWriter<W>>>,
+}
+
+impl<W: Write> ZipArchiveWriter<W> {
+    pub fn new(destination: W) -> Self {
+        Self {
+            writer: Some(zip::ZipWriter::new_stream(destination)),
+        }
+    }
+
+    fn writer(&mut self) -> io::Result<&mut zip::ZipWriter<StreamWriter<W>>> {
+        self.writer
+            .as_mut()
+            .ok_or_else(|| io::Error::other("zip archive was already finished"))
+    }
+
+    /// Start a file entry called `name`, its contents are written to the returned writer
+    fn start_file(
+        &mut self,
+        name: &Path,
+        mode: u32,
+    ) -> io::Result<&mut zip::ZipWriter<StreamWriter<W>>> {
+        let name = &entry_path(name)?;
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .unix_permissions(mode);
+
+        if name.to_str().is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "zip files can only store UTF-8 names, use the tar.gz archive format instead",
+            ));
+        }
+        let writer = self.writer()?;
+        writer.start_file_from_path(name, options)?;
+        Ok(writer)
+    }
+}
+
+impl<W: Write> ArchiveWriter for ZipArchiveWriter<W> {
+    fn append_file(
+        &mut self,
+        name: &Path,
+        _metadata: &Metadata,
+        mode: u32,
+        _size: u64,
+        contents: &mut dyn Read,
+    ) -> io::Result<()> {
+        io::copy(contents, self.start_file(name, mode)?)?;
+        Ok(())
+    }
+
+    /// Zip entries are stored with the default modification time, `mtime` is not recorded
+    fn append_bytes(
+        &mut self,
+        name: &Path,
+        mode: u32,
+        _mtime: u64,
+        contents: &[u8],
+    ) -> io::Result<()> {
+        self.start_file(name, mode)?.write_all(contents)
+    }
+
+    fn append_dir(&mut self, name: &Path) -> io::Result<()> {
+        let name = entry_path(name)?;
+        let options = SimpleFileOptions::default();
+        self.writer()?
+            .add_directory_from_path(name, options)
+            .map_err(io::Error::from)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(writer) = self.writer.take() {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+use std::{
+    env,
+    fmt::Write,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use jiff::Timestamp;
+
+use super::{GeneratedFile, RumkinstFiles, entry_name};
+use crate::{RumkinstError, VcsInfo, config::Config};
+
+impl RumkinstFiles {
+    /// Archive a file describing the build at `output.build-info`, after every other file
+    ///
+    /// The build time is read from `SOURCE_DATE_EPOCH` when it is set, so the file does not keep
+    /// the archive from being reproducible.
+    pub(crate) fn add_build_info(
+        &mut self,
+        config: &Config,
+        vcs: Option<&VcsInfo>,
+    ) -> Result<(), RumkinstError> {
+        let Some(entry) = config.build_info() else {
+            return Ok(());
+        };
+        let name = entry_name(entry);
+        self.check_generated_entry(&name, "`output.build-info`")?;
+
+        let built = build_time();
+        let mut contents = format!("name: {}\n", config.get_name());
+        if let Some(version) = config.version() {
+            writeln!(contents, "version: {version}").expect("writing to a String should succeed");
+        }
+        let time = Timestamp::from_second(built as i64).unwrap_or(Timestamp::UNIX_EPOCH);
+        writeln!(contents, "built: {time}").expect("writing to a String should succeed");
+        if let Some(vcs) = vcs {
+            let dirty = match vcs.dirty {
+                true => " (dirty)",
+                false => "",
+            };
+            writeln!(contents, "commit: {}{dirty}", vcs.commit)
+                .expect("writing to a String should succeed");
+        }
+        writeln!(contents, "rumkinst: {}", env!("CARGO_PKG_VERSION"))
+            .expect("writing to a String should succeed");
+
+        log::debug!("archiving the build info as {name:?}");
+        self.generated.push(GeneratedFile {
+            entry: entry.to_path_buf(),
+            mtime: built,
+            mode: 0o644,
+            contents: contents.into_bytes(),
+        });
+        Ok(())
+    }
+}
+
+/// Seconds since the unix epoch the build happened at, `SOURCE_DATE_EPOCH` if it is set
+pub(super) fn build_time() -> u64 {
+    env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.trim().parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_secs())
+                .unwrap_or_default()
+        })
+}
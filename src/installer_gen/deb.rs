@@ -0,0 +1,267 @@
+use std::{
+    fs::{self, File},
+    io::{self, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use flate2::{Compression, write::GzEncoder};
+
+use super::{
+    RumkinstFiles,
+    root_tree::{append_root_files, root_header},
+};
+use crate::{
+    CancelHandle, RumkinstError,
+    config::{Config, DebConfig},
+    error_log::FilePolicy,
+    progress_log::ProgressSink,
+};
+
+/// Name of the package in the control file, Debian only allows lowercase names without `_`
+pub(crate) fn package_name(config: &Config) -> String {
+    config.get_name().to_ascii_lowercase().replace('_', "-")
+}
+
+/// File name of the `.deb`, following Debian's `name_version_architecture.deb` convention
+pub(crate) fn deb_file_name(config: &Config, deb: &DebConfig) -> Result<String, RumkinstError> {
+    Ok(format!(
+        "{}_{}_{}.deb",
+        package_name(config),
+        version(config)?,
+        deb.architecture
+    ))
+}
+
+fn version(config: &Config) -> Result<&str, RumkinstError> {
+    config.version().ok_or(RumkinstError::ConfigMissing {
+        key: "package.version",
+        needed_by: "a .deb",
+    })
+}
+
+/// Write a Debian binary package into `destination`
+///
+/// The `root` source becomes the filesystem payload, and the `preinstall` and `postinstall`
+/// scripts become the `preinst` and `postinst` maintainer scripts. `deb_path` is only used for
+/// diagnostics and to place a temporary copy of the payload next to it.
+pub(crate) fn write_deb<W: Write>(
+    config: &Config,
+    files: &RumkinstFiles,
+    mut destination: W,
+    deb_path: &Path,
+    policy: FilePolicy<'_>,
+    progress: &dyn ProgressSink,
+    cancel: &CancelHandle,
+) -> Result<(), RumkinstError> {
+    let deb = config
+        .deb
+        .as_ref()
+        .expect("a .deb should only be written when [deb] is set");
+    let package_error = |source| RumkinstError::Package {
+        path: deb_path.to_path_buf(),
+        source,
+    };
+
+    // The size of every member comes before its contents, so the payload has to be written out
+    // in full before it can be added
+    let mut data_path = deb_path.as_os_str().to_os_string();
+    data_path.push(".data.partial");
+    let data_path = PathBuf::from(data_path);
+
+    let result = (|| {
+        let data = File::create_new(&data_path).map_err(package_error)?;
+        let (mut data, installed_size) =
+            write_data(config, files, data, deb_path, policy, progress, cancel)?;
+        let control = write_control(config, deb, installed_size, deb_path)?;
+
+        let mtime = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or_default();
+        let data_len = data.seek(SeekFrom::End(0)).map_err(package_error)?;
+        data.seek(SeekFrom::Start(0)).map_err(package_error)?;
+
+        destination
+            .write_all(b"!<arch>\n")
+            .and_then(|_| write_ar_member(&mut destination, "debian-binary", mtime, b"2.0\n"))
+            .and_then(|_| write_ar_member(&mut destination, "control.tar.gz", mtime, &control))
+            .and_then(|_| write_ar_header(&mut destination, "data.tar.gz", mtime, data_len))
+            .and_then(|_| io::copy(&mut cancel.reader(&mut data), &mut destination))
+            .and_then(|_| write_ar_padding(&mut destination, data_len))
+            .and_then(|_| destination.flush())
+            .map_err(|source| match cancel.is_cancelled() {
+                true => RumkinstError::Cancelled,
+                false => package_error(source),
+            })
+    })();
+
+    fs::remove_file(&data_path).ok();
+    result
+}
+
+/// Write the gzipped tarball of everything in the `root` source into `data`, returning it along
+/// with the installed size in KiB
+fn write_data(
+    config: &Config,
+    files: &RumkinstFiles,
+    data: File,
+    deb_path: &Path,
+    policy: FilePolicy<'_>,
+    progress: &dyn ProgressSink,
+    cancel: &CancelHandle,
+) -> Result<(File, u64), RumkinstError> {
+    let package_error = |source| RumkinstError::Package {
+        path: deb_path.to_path_buf(),
+        source,
+    };
+
+    let mut tar = tar::Builder::new(GzEncoder::new(data, Compression::best()));
+    let installed_bytes = append_root_files(
+        &mut tar,
+        files,
+        config.root.path(),
+        policy,
+        progress,
+        cancel,
+        &package_error,
+    )?;
+
+    let data = tar
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map_err(package_error)?;
+    Ok((data, installed_bytes.div_ceil(1024)))
+}
+
+/// The gzipped tarball holding the control file and maintainer scripts
+fn write_control(
+    config: &Config,
+    deb: &DebConfig,
+    installed_size: u64,
+    deb_path: &Path,
+) -> Result<Vec<u8>, RumkinstError> {
+    let control_error = |source| RumkinstError::Package {
+        path: deb_path.to_path_buf(),
+        source,
+    };
+
+    let mut tar = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::best()));
+
+    let control = control_file(config, deb, installed_size)?;
+    let mut header = root_header(tar::EntryType::Regular);
+    header.set_mode(0o644);
+    header.set_size(control.len() as u64);
+    tar.append_data(&mut header, "control", control.as_bytes())
+        .map_err(control_error)?;
+
+    let scripts = [
+        ("preinst", &config.installer.preinstall),
+        ("postinst", &config.installer.postinstall),
+    ];
+    for (name, script) in scripts {
+        let Some(script) = script else {
+            continue;
+        };
+        let resolved = config.resolve(script);
+        let contents = fs::read(&resolved).map_err(|source| RumkinstError::SourceRead {
+            path: resolved,
+            source,
+        })?;
+
+        let mut header = root_header(tar::EntryType::Regular);
+        header.set_mode(0o755);
+        header.set_size(contents.len() as u64);
+        tar.append_data(&mut header, name, contents.as_slice())
+            .map_err(control_error)?;
+    }
+
+    tar.into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map_err(control_error)
+}
+
+/// The `control` file, describing the package to dpkg
+fn control_file(
+    config: &Config,
+    deb: &DebConfig,
+    installed_size: u64,
+) -> Result<String, RumkinstError> {
+    let maintainer = deb
+        .maintainer
+        .as_ref()
+        .or(config.package.authors.first())
+        .ok_or(RumkinstError::ConfigMissing {
+            key: "deb.maintainer",
+            needed_by: "a .deb without any package.authors",
+        })?;
+
+    let mut control = format!(
+        "Package: {}\nVersion: {}\nArchitecture: {}\nMaintainer: {maintainer}\nInstalled-Size: {installed_size}\n",
+        package_name(config),
+        version(config)?,
+        deb.architecture,
+    );
+    if let Some(section) = &deb.section {
+        control.push_str(&format!("Section: {section}\n"));
+    }
+    if !deb.depends.is_empty() {
+        control.push_str(&format!("Depends: {}\n", deb.depends.join(", ")));
+    }
+
+    // The first line is the synopsis, every following line is indented and blank lines are `.`
+    let description = config
+        .package
+        .description
+        .as_deref()
+        .map(str::trim)
+        .filter(|description| !description.is_empty())
+        .unwrap_or(config.get_name());
+    let mut lines = description.lines();
+    control.push_str(&format!(
+        "Description: {}\n",
+        lines.next().unwrap_or_default()
+    ));
+    for line in lines {
+        match line.trim() {
+            "" => control.push_str(" .\n"),
+            line => control.push_str(&format!(" {line}\n")),
+        }
+    }
+
+    Ok(control)
+}
+
+/// Write a whole member of an `ar` archive
+fn write_ar_member(
+    destination: &mut impl Write,
+    name: &str,
+    mtime: u64,
+    contents: &[u8],
+) -> io::Result<()> {
+    write_ar_header(destination, name, mtime, contents.len() as u64)?;
+    destination.write_all(contents)?;
+    write_ar_padding(destination, contents.len() as u64)
+}
+
+/// Write the 60 byte header of an `ar` member, owned by root with mode `0644`
+fn write_ar_header(
+    destination: &mut impl Write,
+    name: &str,
+    mtime: u64,
+    size: u64,
+) -> io::Result<()> {
+    writeln!(
+        destination,
+        "{name:<16}{mtime:<12}{:<6}{:<6}{:<8o}{size:<10}`",
+        0, 0, 0o100644
+    )
+}
+
+/// Members of an `ar` archive are aligned to two bytes
+fn write_ar_padding(destination: &mut impl Write, size: u64) -> io::Result<()> {
+    if size % 2 == 1 {
+        destination.write_all(b"\n")?;
+    }
+    Ok(())
+}
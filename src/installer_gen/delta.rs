@@ -0,0 +1,186 @@
+//! File-level deltas between two builds
+//!
+//! A delta holds every added or changed file whole, there is no binary diffing of the files in
+//! it, and rumkinst ships nothing that applies it. Its manifest lists the digest of every file
+//! in both builds, so an updater can check an install against the base, extract the delta over
+//! it and delete the removed files itself.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    fs::{self, File},
+    io::{self, Read, Seek, Write},
+    path::Path,
+};
+
+use nanoid::nanoid;
+use serde_json::{Value, json};
+
+use super::{ArchiveWriter, Digester, RumkinstFiles, entry_name, read_archive_entries, to_hex};
+use crate::{CancelHandle, RumkinstError, error_log::FilePolicy, progress_log::NoProgress};
+
+/// Add every file that is new or differs from the archive at `base_path` to `delta`, then finish
+/// it. Returns the manifest describing the delta.
+///
+/// Each file is read once, into a temporary spool file as it is digested, and appended from the
+/// spool when it changed, so transforms aren't run twice.
+pub(crate) fn write_delta(
+    files: &RumkinstFiles,
+    base_path: &Path,
+    delta: &mut dyn ArchiveWriter,
+    digester: &dyn Digester,
+    policy: FilePolicy<'_>,
+    cancel: &CancelHandle,
+) -> Result<Value, RumkinstError> {
+    let base_error = |source| RumkinstError::DeltaBase {
+        path: base_path.to_path_buf(),
+        source,
+    };
+    let base_digest = File::open(base_path)
+        .and_then(|mut file| digester.digest(&mut file, &NoProgress))
+        .map_err(base_error)?;
+    let base = archive_digests(base_path, digester).map_err(base_error)?;
+
+    let spool_path = env::temp_dir().join(format!("rumkinst-delta-{}", nanoid!()));
+    let spool = File::options()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&spool_path)
+        .map_err(|source| RumkinstError::Package {
+            path: spool_path.clone(),
+            source,
+        });
+    let manifest =
+        spool.and_then(|spool| write_files(files, base, spool, delta, digester, policy, cancel));
+    fs::remove_file(&spool_path).ok();
+    let (added, changed, removed, unchanged) = manifest?;
+
+    log::info!(
+        "Delta has {} added, {} changed and {} removed files",
+        added.len(),
+        changed.len(),
+        removed.len()
+    );
+
+    Ok(json!({
+        "format": "files",
+        "algorithm": digester.algorithm(),
+        "base": {
+            "archive": base_path.file_name().map(|name| name.to_string_lossy()),
+            "digest": to_hex(&base_digest),
+        },
+        "added": added,
+        "changed": changed,
+        "removed": removed,
+        "unchanged": unchanged,
+    }))
+}
+
+/// The added, changed, removed and unchanged files of the manifest
+type DeltaFiles = (Vec<Value>, Vec<Value>, Vec<Value>, Vec<Value>);
+
+/// Add the files of `files` that aren't in `base` with the same digest to `delta`, spooling each
+/// through `spool`
+fn write_files(
+    files: &RumkinstFiles,
+    mut base: BTreeMap<String, String>,
+    mut spool: File,
+    delta: &mut dyn ArchiveWriter,
+    digester: &dyn Digester,
+    policy: FilePolicy<'_>,
+    cancel: &CancelHandle,
+) -> Result<DeltaFiles, RumkinstError> {
+    let (mut added, mut changed, mut unchanged) = (vec![], vec![], vec![]);
+    for path in files.all_files() {
+        cancel.check()?;
+
+        let read_error = |source| RumkinstError::SourceRead {
+            path: files.resolve(path),
+            source,
+        };
+        let spooled = policy.handle(
+            files
+                .open(path)
+                .and_then(|file| {
+                    spool.set_len(0)?;
+                    spool.rewind()?;
+                    let mut tee = Tee {
+                        inner: cancel.reader(file.contents),
+                        copy: &mut spool,
+                    };
+                    let digest = digester.digest(&mut tee, &NoProgress)?;
+                    let size = spool.stream_position()?;
+                    spool.rewind()?;
+                    Ok((file.metadata, file.mode, size, to_hex(&digest)))
+                })
+                .map_err(|source| match cancel.is_cancelled() {
+                    true => RumkinstError::Cancelled,
+                    false => read_error(source),
+                }),
+        )?;
+        let Some((metadata, mode, size, digest)) = spooled else {
+            continue;
+        };
+
+        let name = entry_name(files.entry(path));
+        let base_digest = base.remove(&name);
+        if base_digest.as_ref() == Some(&digest) {
+            unchanged.push(json!({ "path": name, "digest": digest }));
+            continue;
+        }
+
+        delta
+            .append_file(
+                files.entry(path),
+                &metadata,
+                mode,
+                size,
+                &mut cancel.reader(&mut spool),
+            )
+            .map_err(|source| match cancel.is_cancelled() {
+                true => RumkinstError::Cancelled,
+                false => RumkinstError::Archive {
+                    path: Some(path.clone()),
+                    source,
+                },
+            })?;
+        match base_digest {
+            Some(base_digest) => {
+                changed.push(json!({ "path": name, "digest": digest, "base_digest": base_digest }))
+            }
+            None => added.push(json!({ "path": name, "digest": digest })),
+        }
+    }
+    delta
+        .finish()
+        .map_err(|source| RumkinstError::Archive { path: None, source })?;
+
+    let removed = base
+        .into_iter()
+        .map(|(name, digest)| json!({ "path": name, "base_digest": digest }))
+        .collect();
+    Ok((added, changed, removed, unchanged))
+}
+
+/// Reader that writes everything read from `inner` to `copy` as well
+struct Tee<'a, R> {
+    inner: R,
+    copy: &'a mut File,
+}
+
+impl<R: Read> Read for Tee<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.copy.write_all(&buf[..read])?;
+        Ok(read)
+    }
+}
+
+/// Digest of every file in the tarball or zip at `path`, keyed by [`entry_name`]
+fn archive_digests(path: &Path, digester: &dyn Digester) -> io::Result<BTreeMap<String, String>> {
+    Ok(read_archive_entries(path, digester)?
+        .into_iter()
+        .map(|entry| (entry.name, entry.digest))
+        .collect())
+}
@@ -0,0 +1,52 @@
+use hashbrown::HashSet;
+
+use super::{
+    IncludedFiles, RumkinstFiles,
+    included_files::{ExclusionFilter, PathExplorer},
+};
+use crate::{
+    CancelHandle, RumkinstError, config::ExtraFile, error_log::FilePolicy,
+    progress_log::ProgressSink,
+};
+
+impl RumkinstFiles {
+    /// Archive every one of `extra` under its `dest`, after the files of every source
+    pub(super) fn add_extra_files(
+        &mut self,
+        extra: &[ExtraFile],
+        allow_outside_sources: bool,
+        policy: FilePolicy<'_>,
+        progress: &dyn ProgressSink,
+        cancel: &CancelHandle,
+    ) -> Result<(), RumkinstError> {
+        let mut added = IncludedFiles {
+            files: vec![],
+            empty_files: HashSet::new(),
+        };
+        for file in extra {
+            if self.base.join(&file.src).is_dir() {
+                return Err(RumkinstError::SourceUnsupported {
+                    path: self.base.join(&file.src),
+                });
+            }
+            let found = PathExplorer::new(&self.base, file.src.clone(), ExclusionFilter::default())
+                .with_allow_outside(allow_outside_sources)
+                .search(policy, progress, cancel)?;
+            if found.files.is_empty() {
+                continue;
+            }
+            log::debug!("archiving {:?} as {:?}", file.src, file.dest);
+            if !found.empty_files.is_empty() {
+                added.empty_files.insert(file.dest.clone());
+            }
+            self.located
+                .insert(file.dest.clone(), self.base.join(&file.src));
+            if let Some(mode) = file.mode {
+                self.modes.insert(file.dest.clone(), mode);
+            }
+            added.files.push(file.dest.clone());
+        }
+        self.extra_files = Some(added);
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use std::path::Component;
+
+use serde::Serialize;
+
+use crate::config::Config;
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Manifest<'a> {
+    app_id: &'a str,
+    runtime: &'a str,
+    runtime_version: &'a str,
+    sdk: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    command: Option<&'a str>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    finish_args: &'a [String],
+    modules: Vec<Module<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct Module<'a> {
+    name: &'a str,
+    buildsystem: &'static str,
+    build_commands: Vec<String>,
+    sources: Vec<ArchiveSource<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ArchiveSource<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<&'a str>,
+    sha256: &'a str,
+    /// The archive holds each source directory side by side, so none of them can be stripped
+    strip_components: u32,
+}
+
+/// A Flatpak manifest with a single module, installing the `root` source into `/app`
+///
+/// The module's source is the archive `archive_name`, with the SHA-256 digest `sha256`.
+pub(crate) fn flatpak_manifest(
+    config: &Config,
+    archive_name: &str,
+    sha256: &str,
+    root_is_file: bool,
+) -> String {
+    let flatpak = config
+        .flatpak
+        .as_ref()
+        .expect("a flatpak manifest should only be made when [flatpak] is set");
+
+    let mut build_commands = vec![];
+    if !config.root.disable {
+        // Entries are stored under their source path, without any leading `./`, and always
+        // with `/` separators
+        let root = config
+            .root
+            .path()
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(part) => Some(part.to_string_lossy()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+        let root = root.replace('\'', r"'\''");
+        build_commands.push(match root_is_file {
+            true => format!("cp -a '{root}' /app/"),
+            false => format!("cp -a '{root}/.' /app/"),
+        });
+    }
+
+    let manifest = Manifest {
+        app_id: &flatpak.app_id,
+        runtime: &flatpak.runtime,
+        runtime_version: &flatpak.runtime_version,
+        sdk: &flatpak.sdk,
+        command: flatpak.command.as_deref(),
+        finish_args: &flatpak.finish_args,
+        modules: vec![Module {
+            name: config.get_name(),
+            buildsystem: "simple",
+            build_commands,
+            sources: vec![ArchiveSource {
+                kind: "archive",
+                path: flatpak.archive_url.is_none().then_some(archive_name),
+                url: flatpak.archive_url.as_deref(),
+                sha256,
+                strip_components: 0,
+            }],
+        }],
+    };
+
+    let mut manifest = serde_json::to_string_pretty(&manifest)
+        .expect("flatpak manifest should always serialize to json");
+    manifest.push('\n');
+    manifest
+}
@@ -1,95 +1,273 @@
-use std::path::PathBuf;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
-use anyhow::{Context, Result};
 use hashbrown::HashSet;
 
-use crate::progress_log::set_progress_message;
+use super::names::{entry_name, unarchivable_name, unusual_name};
+use crate::{
+    CancelHandle, RumkinstError,
+    config::{SpecialFiles, UnusualNames},
+    error_log::{Diagnostics, ErrorCode, FilePolicy},
+    progress_log::ProgressSink,
+};
 
+#[derive(Default)]
 pub(crate) struct ExclusionFilter {
     filter: HashSet<PathBuf>,
+    matched: HashSet<PathBuf>,
 }
 
 impl From<&Vec<PathBuf>> for ExclusionFilter {
     fn from(value: &Vec<PathBuf>) -> Self {
         ExclusionFilter {
             filter: HashSet::from_iter(value.iter().cloned()),
+            matched: HashSet::new(),
         }
     }
 }
 
+impl ExclusionFilter {
+    fn excludes(&mut self, path: &PathBuf) -> bool {
+        let excluded = self.filter.contains(path);
+        if excluded {
+            self.matched.insert(path.clone());
+        }
+        excluded
+    }
+
+    fn warn_unmatched(&self, root: &PathBuf, diagnostics: &Diagnostics) {
+        for exclude in self.filter.difference(&self.matched) {
+            diagnostics.warn(
+                ErrorCode::EXCLUDE_UNUSED.at(exclude),
+                format!("exclude {exclude:?} did not match anything while searching {root:?}"),
+            );
+        }
+    }
+}
+
+#[derive(Clone)]
 pub(crate) struct IncludedFiles {
     pub(crate) files: Vec<PathBuf>,
+    /// Special files in `files`, which are packaged as empty regular files
+    pub(crate) empty_files: HashSet<PathBuf>,
 }
 
-pub(crate) struct PathExplorer {
+/// Searches a source directory for files to package
+///
+/// Found files, like `root` and the exclusion filter, are relative to `base`. Only filesystem
+/// access goes through `base`, so archive entries never include it.
+pub(crate) struct PathExplorer<'a> {
+    base: &'a Path,
     root: PathBuf,
     filter: ExclusionFilter,
+    allow_outside: bool,
+    unusual_names: UnusualNames,
+    special_files: SpecialFiles,
 }
 
-impl PathExplorer {
-    pub(crate) fn new(root: PathBuf, filter: ExclusionFilter) -> Self {
-        log::debug!("created new PathExplorer for {root:?}");
-        Self { root, filter }
+impl<'a> PathExplorer<'a> {
+    pub(crate) fn new(base: &'a Path, root: PathBuf, filter: ExclusionFilter) -> Self {
+        log::debug!("created new PathExplorer for {root:?} in {base:?}");
+        Self {
+            base,
+            root,
+            filter,
+            allow_outside: false,
+            unusual_names: UnusualNames::default(),
+            special_files: SpecialFiles::default(),
+        }
     }
 
-    pub(crate) fn search(self) -> Result<IncludedFiles> {
+    /// Whether the source may be outside `base`, and symlinks in it may point outside of it
+    pub(crate) fn with_allow_outside(mut self, allow_outside: bool) -> Self {
+        self.allow_outside = allow_outside;
+        self
+    }
+
+    pub(crate) fn with_unusual_names(mut self, unusual_names: UnusualNames) -> Self {
+        self.unusual_names = unusual_names;
+        self
+    }
+
+    pub(crate) fn with_special_files(mut self, special_files: SpecialFiles) -> Self {
+        self.special_files = special_files;
+        self
+    }
+
+    pub(crate) fn search(
+        mut self,
+        policy: FilePolicy<'_>,
+        progress: &dyn ProgressSink,
+        cancel: &CancelHandle,
+    ) -> Result<IncludedFiles, RumkinstError> {
         log::trace!("searching with PathExplorer");
-        if self.root.is_dir() {
+        let resolved = self.base.join(&self.root);
+        if !resolved.exists() {
+            return Err(RumkinstError::SourceMissing { path: resolved });
+        }
+        let within = match self.allow_outside {
+            true => None,
+            // Absolute sources are only searched once allowed, symlinks may still not leave them
+            false if self.root.is_absolute() => Some(canonical(&resolved)?),
+            false => Some(self.contained_root(&resolved)?),
+        };
+
+        if resolved.is_dir() {
             log::debug!("path is a directory, searching recursively");
-            Ok(IncludedFiles {
-                files: visit_dirs(self.root, &self.filter)?,
-            })
-        } else if self.root.is_file() {
+            let mut included = IncludedFiles {
+                files: Vec::new(),
+                empty_files: HashSet::new(),
+            };
+            self.recurse_into(
+                self.root.clone(),
+                within.as_deref(),
+                &mut included,
+                policy,
+                progress,
+                cancel,
+            )?;
+            self.filter.warn_unmatched(&self.root, policy.diagnostics());
+            Ok(included)
+        } else if resolved.is_file() {
             log::debug!("path is a single file, using single item buffer");
             Ok(IncludedFiles {
                 files: vec![self.root],
+                empty_files: HashSet::new(),
             })
-        } else if !self.root.exists() {
-            anyhow::bail!("failed to search {:?}, file path does not exist", self.root)
         } else {
-            anyhow::bail!(
-                "failed to search {:?}, encountered an unexpected error",
-                self.root
-            )
+            Err(RumkinstError::SourceUnsupported { path: resolved })
         }
     }
-}
 
-fn visit_dirs(path: PathBuf, filter: &ExclusionFilter) -> Result<Vec<PathBuf>> {
-    log::trace!("visiting directory recursively from root");
-    let mut buf = Vec::new();
-    recurse_into(path, filter, &mut buf).context("error while visiting dir")?;
-    Ok(buf)
-}
-
-fn recurse_into(path: PathBuf, filter: &ExclusionFilter, buf: &mut Vec<PathBuf>) -> Result<()> {
-    log::trace!("searching directory recursively");
-    log::debug!("searching items in {path:?}");
-    for entry in path
-        .read_dir()
-        .with_context(|| format!("failed to read directory {path:?}"))?
-    {
-        let entry =
-            entry.with_context(|| format!("failed to read entry inside of directory {path:?}"))?;
-        let path = entry.path();
-
-        if filter.filter.contains(&path) {
-            log::debug!("found path {path:?} which is excluded by the filter, continuing");
-            continue;
+    /// The canonical path of the source at `resolved`, failing if it is outside of `base`
+    fn contained_root(&self, resolved: &Path) -> Result<PathBuf, RumkinstError> {
+        let base = match self.base.as_os_str().is_empty() {
+            true => canonical(Path::new("."))?,
+            false => canonical(self.base)?,
+        };
+        let root = canonical(resolved)?;
+        if !root.starts_with(&base) {
+            return Err(RumkinstError::SourceEscape {
+                path: resolved.to_path_buf(),
+                allowed: base,
+            });
         }
+        Ok(root)
+    }
 
-        set_progress_message(format!("Reading {path:?}"));
+    /// Add every file under `path` to `buf`, skipping symlinks that resolve outside of `within`
+    fn recurse_into(
+        &mut self,
+        path: PathBuf,
+        within: Option<&Path>,
+        buf: &mut IncludedFiles,
+        policy: FilePolicy<'_>,
+        progress: &dyn ProgressSink,
+        cancel: &CancelHandle,
+    ) -> Result<(), RumkinstError> {
+        log::debug!("searching items in {path:?}");
+        let resolved = self.base.join(&path);
+        let entries = resolved
+            .read_dir()
+            .map_err(|source| RumkinstError::SourceRead {
+                path: resolved.clone(),
+                source,
+            })?;
+        for entry in entries {
+            cancel.check()?;
+            let Some(entry) = policy.handle(entry.map_err(|source| RumkinstError::SourceRead {
+                path: resolved.clone(),
+                source,
+            }))?
+            else {
+                continue;
+            };
+            let resolved = entry.path();
+            let path = path.join(entry.file_name());
 
-        if path.is_file() {
-            log::debug!("file at {path:?}, appending to file buffer");
-            buf.push(path);
-        } else if path.is_dir() {
-            log::debug!("directory at {path:?}, searching directory contents recursively");
-            recurse_into(path, filter, buf)?;
-        } else {
-            anyhow::bail!("failed to find file or directory to read at {path:?}");
+            if self.filter.excludes(&path) {
+                log::debug!("found path {path:?} which is excluded by the filter, continuing");
+                continue;
+            }
+
+            progress.set_message(&entry_name(&path));
+
+            if let Some(reason) = unarchivable_name(&entry.file_name()) {
+                policy.handle::<()>(Err(RumkinstError::UnusualName {
+                    path: resolved,
+                    reason,
+                }))?;
+                continue;
+            }
+            if let Some(reason) = unusual_name(&entry.file_name()) {
+                match self.unusual_names {
+                    UnusualNames::Allow => log::debug!("source path {path:?} {reason}"),
+                    UnusualNames::Warn => policy.diagnostics().warn(
+                        ErrorCode::SOURCE_UNUSUAL_NAME.at(&resolved),
+                        format!("source path {path:?} {reason}"),
+                    ),
+                    UnusualNames::Reject => {
+                        policy.handle::<()>(Err(RumkinstError::UnusualName {
+                            path: resolved,
+                            reason,
+                        }))?;
+                        continue;
+                    }
+                }
+            }
+
+            // Only symlinks can lead out of a canonical directory, and a broken one is reported
+            // below
+            if let Some(within) = within
+                && resolved.is_symlink()
+                && let Ok(target) = fs::canonicalize(&resolved)
+                && !target.starts_with(within)
+            {
+                policy.handle::<()>(Err(RumkinstError::SourceEscape {
+                    path: resolved,
+                    allowed: within.to_path_buf(),
+                }))?;
+                continue;
+            }
+
+            if resolved.is_file() {
+                log::debug!("file at {path:?}, appending to file buffer");
+                buf.files.push(path);
+            } else if resolved.is_dir() {
+                log::debug!("directory at {path:?}, searching directory contents recursively");
+                policy.handle(self.recurse_into(path, within, buf, policy, progress, cancel))?;
+            } else if resolved.is_symlink() {
+                policy.handle::<()>(Err(RumkinstError::SourceBrokenLink { path: resolved }))?;
+            } else {
+                match self.special_files {
+                    SpecialFiles::SkipWithWarning => policy.diagnostics().warn(
+                        ErrorCode::SOURCE_UNSUPPORTED.at(&resolved),
+                        format!("skipping {path:?}, it is not a regular file or directory"),
+                    ),
+                    SpecialFiles::Error => {
+                        policy.handle::<()>(Err(RumkinstError::SourceUnsupported {
+                            path: resolved,
+                        }))?;
+                    }
+                    SpecialFiles::IncludeAsEmpty => {
+                        log::debug!("special file at {path:?}, appending as an empty file");
+                        buf.empty_files.insert(path.clone());
+                        buf.files.push(path);
+                    }
+                }
+            }
         }
+
+        Ok(())
     }
+}
 
-    Ok(())
+/// The canonical form of `path`, which has to exist
+fn canonical(path: &Path) -> Result<PathBuf, RumkinstError> {
+    fs::canonicalize(path).map_err(|source| RumkinstError::SourceRead {
+        path: path.to_path_buf(),
+        source,
+    })
 }
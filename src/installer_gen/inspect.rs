@@ -0,0 +1,211 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use flate2::read::GzDecoder;
+
+use super::{
+    Digester, entry_name,
+    names::entry_path,
+    to_hex,
+    xattrs::{PaxRecord, restore_xattrs},
+};
+use crate::progress_log::NoProgress;
+
+/// A regular file read back from an archive
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct ArchiveEntry {
+    /// Path of the entry, escaped the same way as in progress messages and manifests
+    pub name: String,
+    /// Uncompressed size, in bytes
+    pub size: u64,
+    /// Unix permissions stored for the entry
+    pub mode: u32,
+    /// Lowercase hex digest of the contents
+    pub digest: String,
+}
+
+/// Every regular file in the tarball or zip at `path`, in the order they were archived
+///
+/// Entries whose paths could be extracted outside the directory they are extracted into are
+/// refused, with the same rules archives are written with. Tarballs are read to the end of the
+/// gzip stream so its checksum is checked, and errors name the entry they were found at.
+pub fn read_archive_entries(path: &Path, digester: &dyn Digester) -> io::Result<Vec<ArchiveEntry>> {
+    read_entries(path, digester, None)
+}
+
+/// Extract every regular file in the tarball or zip at `path` into `dest`, with the unix
+/// permissions stored for it, and return them in the order they were archived
+///
+/// Extended attributes and ACLs stored in a tarball are restored where the filesystem allows.
+///
+/// Entries are checked the same way as by [`read_archive_entries`], so nothing is written
+/// outside of `dest`.
+pub fn extract_archive(
+    path: &Path,
+    dest: &Path,
+    digester: &dyn Digester,
+) -> io::Result<Vec<ArchiveEntry>> {
+    read_entries(path, digester, Some(dest))
+}
+
+fn read_entries(
+    path: &Path,
+    digester: &dyn Digester,
+    dest: Option<&Path>,
+) -> io::Result<Vec<ArchiveEntry>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut magic = [0; 2];
+    file.read_exact(&mut magic)?;
+    file.rewind()?;
+
+    let mut entries = vec![];
+    match &magic {
+        b"PK" => {
+            let mut zip = zip::ZipArchive::new(file)?;
+            for index in 0..zip.len() {
+                let mut entry = zip.by_index(index)?;
+                if entry.is_file() {
+                    let relative = checked_entry_path(Path::new(entry.name()))?;
+                    let name = entry_name(&relative);
+                    let size = entry.size();
+                    let mode = entry.unix_mode().unwrap_or(0o644) & 0o7777;
+                    let digest = digest_entry(&mut entry, &relative, mode, dest, digester)
+                        .map_err(|err| at_entry(err, &name))?;
+                    entries.push(ArchiveEntry {
+                        name,
+                        size,
+                        mode,
+                        digest: to_hex(&digest),
+                    });
+                }
+            }
+        }
+        [0x1f, 0x8b] => {
+            let mut tar = tar::Archive::new(GzDecoder::new(file));
+            let mut last = None;
+            for entry in tar.entries()? {
+                let after_last = |err| match &last {
+                    Some(last) => io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("after entry `{last}`: {err}"),
+                    ),
+                    None => err,
+                };
+                let mut entry = entry.map_err(after_last)?;
+                let path = entry.path().map_err(after_last)?.into_owned();
+                let name = entry_name(&path);
+                if entry.header().entry_type().is_file() {
+                    let relative = checked_entry_path(&path)?;
+                    let size = entry.size();
+                    let mode = entry.header().mode().map_err(|err| at_entry(err, &name))?;
+                    let xattrs = pax_records(&mut entry).map_err(|err| at_entry(err, &name))?;
+                    let digest = digest_entry(&mut entry, &relative, mode, dest, digester)
+                        .map_err(|err| at_entry(err, &name))?;
+                    if let Some(dest) = dest {
+                        restore_xattrs(&dest.join(&relative), &xattrs);
+                    }
+                    entries.push(ArchiveEntry {
+                        name: name.clone(),
+                        size,
+                        mode,
+                        digest: to_hex(&digest),
+                    });
+                }
+                last = Some(name);
+            }
+            // The gzip trailer holds the checksum of the whole stream, only checked at its end
+            io::copy(&mut tar.into_inner(), &mut io::sink())?;
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a .tar.gz or .zip archive",
+            ));
+        }
+    }
+    Ok(entries)
+}
+
+/// Every PAX record stored for `entry`
+fn pax_records<R: Read>(entry: &mut tar::Entry<R>) -> io::Result<Vec<PaxRecord>> {
+    let Some(extensions) = entry.pax_extensions()? else {
+        return Ok(vec![]);
+    };
+    extensions
+        .map(|extension| {
+            let extension = extension?;
+            let key = extension
+                .key()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            Ok((key.to_string(), extension.value_bytes().to_vec()))
+        })
+        .collect()
+}
+
+/// Digest the contents of `entry`, also writing them to `relative` under `dest` when extracting
+fn digest_entry(
+    entry: &mut dyn Read,
+    relative: &Path,
+    mode: u32,
+    dest: Option<&Path>,
+    digester: &dyn Digester,
+) -> io::Result<Vec<u8>> {
+    let Some(dest) = dest else {
+        return digester.digest(entry, &NoProgress);
+    };
+    let target = dest.join(relative);
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut copy = File::create(&target)?;
+    let digest = digester.digest(
+        &mut TeeReader {
+            inner: entry,
+            copy: &mut copy,
+        },
+        &NoProgress,
+    )?;
+    set_mode(&copy, mode)?;
+    Ok(digest)
+}
+
+#[cfg(unix)]
+fn set_mode(file: &File, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    file.set_permissions(fs::Permissions::from_mode(mode))
+}
+
+/// Platforms without unix modes keep the permissions files are created with
+#[cfg(not(unix))]
+fn set_mode(_file: &File, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+/// Writes everything read through it into `copy`
+struct TeeReader<'a> {
+    inner: &'a mut dyn Read,
+    copy: &'a mut File,
+}
+
+impl Read for TeeReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.copy.write_all(&buf[..read])?;
+        Ok(read)
+    }
+}
+
+/// `err`, found while reading the entry called `name`
+fn at_entry(err: io::Error, name: &str) -> io::Error {
+    io::Error::new(err.kind(), format!("in entry `{name}`: {err}"))
+}
+
+/// `path` read from an archive, refused with the same rules archives are written with
+fn checked_entry_path(path: &Path) -> io::Result<PathBuf> {
+    entry_path(path).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
@@ -0,0 +1,94 @@
+use std::ffi::OsStr;
+
+use hashbrown::HashSet;
+
+use super::{
+    IncludedFiles, RumkinstFiles, entry_name,
+    included_files::{ExclusionFilter, PathExplorer},
+    normalized,
+};
+use crate::{
+    CancelHandle, RumkinstError, config::LicensesConfig, error_log::FilePolicy,
+    progress_log::ProgressSink,
+};
+
+/// What the names of license files start with, in uppercase
+const LICENSE_NAMES: &[&str] = &["LICENSE", "LICENCE", "NOTICE", "COPYING"];
+
+/// Whether `name` is the name of a license file, such as `LICENSE`, `COPYING.txt` or
+/// `LICENSE-MIT`
+fn is_license_name(name: &OsStr) -> bool {
+    let name = name.to_string_lossy().to_uppercase();
+    LICENSE_NAMES.iter().any(|license| {
+        name.strip_prefix(license)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '-', '_']))
+    })
+}
+
+impl RumkinstFiles {
+    /// Bundle copies of the license files found in the sources, archived under the directory of
+    /// `licenses` followed by their own entry path, and every file `licenses` includes
+    pub(super) fn collect_licenses(
+        &mut self,
+        licenses: &LicensesConfig,
+        allow_outside_sources: bool,
+        policy: FilePolicy<'_>,
+        progress: &dyn ProgressSink,
+        cancel: &CancelHandle,
+    ) -> Result<(), RumkinstError> {
+        let mut bundled = IncludedFiles {
+            files: vec![],
+            empty_files: HashSet::new(),
+        };
+        let mut located = vec![];
+        if licenses.scan {
+            let found = [&self.root_files, &self.env_files, &self.script_files]
+                .into_iter()
+                .flatten()
+                .flat_map(|files| files.files.iter());
+            for path in found {
+                if path.file_name().is_some_and(is_license_name) {
+                    let name = licenses.dir.join(self.entry(path));
+                    located.push((name.clone(), self.resolve(path)));
+                    bundled.files.push(name);
+                }
+            }
+        }
+
+        for include in &licenses.include {
+            let found = PathExplorer::new(&self.base, include.clone(), ExclusionFilter::default())
+                .with_allow_outside(allow_outside_sources)
+                .search(policy, progress, cancel)?;
+            for path in found.files {
+                // An included file keeps its name, while directories keep their layout
+                let name = match normalized(&path).strip_prefix(normalized(include)) {
+                    Ok(relative) if !relative.as_os_str().is_empty() => licenses.dir.join(relative),
+                    _ => licenses
+                        .dir
+                        .join(path.file_name().unwrap_or(path.as_os_str())),
+                };
+                if found.empty_files.contains(&path) {
+                    bundled.empty_files.insert(name.clone());
+                }
+                located.push((name.clone(), self.base.join(&path)));
+                bundled.files.push(name);
+            }
+        }
+
+        for (name, path) in located {
+            log::debug!("bundling license {path:?} as {name:?}");
+            self.located.insert(name, path);
+        }
+        self.license_files = Some(bundled);
+        Ok(())
+    }
+
+    /// Entry names of every bundled license file, empty unless `[licenses]` is set
+    pub(crate) fn license_entries(&self) -> Vec<String> {
+        self.license_files
+            .iter()
+            .flat_map(|files| files.files.iter())
+            .map(|path| entry_name(self.entry(path)))
+            .collect()
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    fs::{self, Metadata},
+    io::{self, Read},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    ArchiveEntry, DigestState, Digester, Signer, archive::ArchiveWriter, names::entry_name, to_hex,
+    xattrs::PaxRecord,
+};
+use crate::progress_log::NoProgress;
+
+/// Directory of the archive the signed manifest is embedded in, which is not listed itself
+pub(crate) const MANIFEST_DIR: &str = ".rumkinst";
+
+/// Entry name of the manifest embedded in archives, a copy of their listing
+pub(crate) const MANIFEST_NAME: &str = ".rumkinst/manifest";
+
+/// Passes entries on to another [`ArchiveWriter`], writing a listing of every one of them to
+/// `path` once it is finished
+pub(crate) struct ListingWriter<'a> {
+    inner: &'a mut dyn ArchiveWriter,
+    digester: &'a dyn Digester,
+    path: &'a Path,
+    /// Signs the listing, which is then embedded into the archive along with its signature
+    signer: Option<&'a dyn Signer>,
+    entries: Vec<ListedEntry>,
+}
+
+/// An entry of an archive, as written in its listing
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct ListedEntry {
+    pub(crate) name: String,
+    pub(crate) mode: u32,
+    pub(crate) size: u64,
+    /// Lowercase hex digest of the contents, empty for directories
+    pub(crate) digest: String,
+}
+
+impl<'a> ListingWriter<'a> {
+    pub(crate) fn new(
+        inner: &'a mut dyn ArchiveWriter,
+        digester: &'a dyn Digester,
+        path: &'a Path,
+    ) -> Self {
+        Self {
+            inner,
+            digester,
+            path,
+            signer: None,
+            entries: vec![],
+        }
+    }
+
+    pub(crate) fn with_signer(mut self, signer: Option<&'a dyn Signer>) -> Self {
+        self.signer = signer;
+        self
+    }
+
+    /// One line per entry sorted by name, with its unix mode, size in bytes, digest and name,
+    /// so the listings of two versions can be diffed
+    fn listing(&mut self) -> String {
+        self.entries.sort();
+        self.entries
+            .iter()
+            .map(|entry| listing_line(entry.mode, entry.size, &entry.digest, &entry.name))
+            .collect()
+    }
+}
+
+fn listing_line(mode: u32, size: u64, digest: &str, name: &str) -> String {
+    let digest = match digest.is_empty() {
+        true => "-",
+        false => digest,
+    };
+    format!("{mode:04o} {size:>12} {digest} {name}\n")
+}
+
+/// `entries` sorted by name in the format of listings, so they can be read back with
+/// [`read_listing`]
+pub fn format_listing(entries: &[ArchiveEntry]) -> String {
+    let mut entries = entries.iter().collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    entries
+        .into_iter()
+        .map(|entry| listing_line(entry.mode, entry.size, &entry.digest, &entry.name))
+        .collect()
+}
+
+/// The regular files in the listing at `path`, written next to an archive as `<archive>.list`
+pub fn read_listing(path: &Path) -> io::Result<Vec<ArchiveEntry>> {
+    let listing = fs::read_to_string(path)?;
+    let entries = parse_listing(&listing)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a rumkinst listing"))?;
+    Ok(entries
+        .into_iter()
+        .filter(|entry| !entry.digest.is_empty())
+        .map(|entry| ArchiveEntry {
+            name: entry.name,
+            size: entry.size,
+            mode: entry.mode,
+            digest: entry.digest,
+        })
+        .collect())
+}
+
+/// The entries of a listing written by [`ListingWriter`], `None` if a line of it is not in that
+/// format
+pub(crate) fn parse_listing(listing: &str) -> Option<Vec<ListedEntry>> {
+    listing
+        .lines()
+        .map(|line| {
+            let (mode, rest) = line.split_once(' ')?;
+            let (size, rest) = rest.trim_start().split_once(' ')?;
+            let (digest, name) = rest.split_once(' ')?;
+            Some(ListedEntry {
+                name: name.to_string(),
+                mode: u32::from_str_radix(mode, 8).ok()?,
+                size: size.parse().ok()?,
+                digest: match digest {
+                    "-" => String::new(),
+                    digest => digest.to_string(),
+                },
+            })
+        })
+        .collect()
+}
+
+/// Hashes everything read through it
+struct DigestReader<'a> {
+    inner: &'a mut dyn Read,
+    state: Box<dyn DigestState>,
+}
+
+impl Read for DigestReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.state.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+impl ArchiveWriter for ListingWriter<'_> {
+    fn append_file(
+        &mut self,
+        name: &Path,
+        metadata: &Metadata,
+        mode: u32,
+        size: u64,
+        contents: &mut dyn Read,
+    ) -> io::Result<()> {
+        let mut reader = DigestReader {
+            inner: contents,
+            state: self.digester.start(),
+        };
+        self.inner
+            .append_file(name, metadata, mode, size, &mut reader)?;
+        self.entries.push(ListedEntry {
+            name: entry_name(name),
+            mode,
+            size,
+            digest: to_hex(&reader.state.finish()),
+        });
+        Ok(())
+    }
+
+    fn append_bytes(
+        &mut self,
+        name: &Path,
+        mode: u32,
+        mtime: u64,
+        contents: &[u8],
+    ) -> io::Result<()> {
+        self.inner.append_bytes(name, mode, mtime, contents)?;
+        let digest = self.digester.digest(&mut &*contents, &NoProgress)?;
+        self.entries.push(ListedEntry {
+            name: entry_name(name),
+            mode,
+            size: contents.len() as u64,
+            digest: to_hex(&digest),
+        });
+        Ok(())
+    }
+
+    fn append_dir(&mut self, name: &Path) -> io::Result<()> {
+        self.inner.append_dir(name)?;
+        self.entries.push(ListedEntry {
+            name: entry_name(name) + "/",
+            mode: 0o755,
+            size: 0,
+            digest: String::new(),
+        });
+        Ok(())
+    }
+
+    fn append_xattrs(&mut self, xattrs: &[PaxRecord]) -> io::Result<()> {
+        self.inner.append_xattrs(xattrs)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let listing = self.listing();
+        fs::write(self.path, &listing)?;
+        if let Some(signer) = self.signer {
+            let digest = self.digester.digest(&mut listing.as_bytes(), &NoProgress)?;
+            let signature = signer
+                .sign(self.path, &digest)
+                .map_err(|err| io::Error::other(format!("failed to sign the manifest: {err}")))?;
+            let mtime = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_secs())
+                .unwrap_or_default();
+            let signature_name = format!("{MANIFEST_NAME}.{}", signer.extension());
+            self.inner
+                .append_bytes(Path::new(MANIFEST_NAME), 0o644, mtime, listing.as_bytes())?;
+            self.inner
+                .append_bytes(Path::new(&signature_name), 0o644, mtime, &signature)?;
+        }
+        self.inner.finish()
+    }
+}
@@ -1,50 +1,644 @@
+mod archive;
+mod build_info;
+mod deb;
+mod delta;
+mod extra_files;
+mod flatpak;
 mod included_files;
+mod inspect;
+mod licenses;
+mod listing;
+mod names;
+mod nsis;
+mod oci;
+mod root_tree;
+mod signing;
+mod targets;
+mod transform;
+mod wasm_hooks;
+mod xattrs;
 
-use std::io::Write;
+use std::{
+    fs::{self, File, Metadata},
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+};
+
+use hashbrown::{HashMap, HashSet, hash_map::Entry};
 
-use anyhow::{Context, Result};
+use archive::unix_mode;
+pub use archive::{ArchiveWriter, TarGzArchiveWriter, ZipArchiveWriter, new_archive_writer};
+pub(crate) use deb::{deb_file_name, write_deb};
+pub(crate) use delta::write_delta;
+pub(crate) use flatpak::flatpak_manifest;
 use included_files::{ExclusionFilter, IncludedFiles, PathExplorer};
+pub use inspect::{ArchiveEntry, extract_archive, read_archive_entries};
+pub(crate) use listing::{ListingWriter, MANIFEST_DIR, MANIFEST_NAME, parse_listing};
+pub use listing::{format_listing, read_listing};
+pub(crate) use names::entry_name;
+use names::windows_name_problem;
+pub(crate) use nsis::{compile_nsis_script, nsis_installer_name, nsis_script};
+pub(crate) use oci::{image_reference, load_into_docker, sanitize_tag, write_oci_layout};
+use root_tree::install_path;
+pub(crate) use signing::DigestWriter;
+pub use signing::{DigestState, Digester, Sha256Digester, Signer, to_hex};
+pub(crate) use wasm_hooks::{DEFAULT_WASM_FUEL, HookMetadata, check_wasm_hooks};
+use xattrs::{PaxRecord, XattrCapture, read_xattrs};
 
 use crate::{
-    config::{Config, SourceConfig},
-    progress_log::{increment_progress, set_progress_message},
+    CancelHandle, RumkinstError,
+    config::{
+        CaseCollisions, Config, DuplicateEntries, SourceConfig, Transform, TransformRule,
+        UnusualNames,
+    },
+    download::FetchedFile,
+    error_log::{Diagnostics, ErrorCode, FilePolicy},
+    progress_log::ProgressSink,
 };
 
+#[derive(Clone)]
 pub struct RumkinstFiles {
+    base: PathBuf,
     root_files: Option<IncludedFiles>,
     env_files: Option<IncludedFiles>,
     script_files: Option<IncludedFiles>,
+    /// Files named in `[package]`, such as the changelog
+    package_files: Option<IncludedFiles>,
+    /// Copies of license files bundled by `[licenses]`
+    license_files: Option<IncludedFiles>,
+    /// Files from `[[output.extra-files]]`, archived after every other file
+    extra_files: Option<IncludedFiles>,
+    /// Where files that are not under `base`, such as downloads, are on disk
+    located: HashMap<PathBuf, PathBuf>,
+    /// Entry paths of the files in sources with a `strip-prefix`, or renamed by transforms
+    entries: HashMap<PathBuf, PathBuf>,
+    /// Transforms of the files matched by `[[transforms]]`, in the order they apply
+    transforms: HashMap<PathBuf, Vec<Transform>>,
+    /// Targets of the fetched files only packaged for some of `[targets]`
+    target_only: HashMap<PathBuf, Vec<String>>,
+    /// Unix permissions of the files archived with other permissions than their own
+    modes: HashMap<PathBuf, u32>,
+    /// Files made during the build, archived after every other file
+    generated: Vec<GeneratedFile>,
+    /// Which extended attributes of files are archived with them
+    xattrs: XattrCapture,
+    /// Where the results of slow transforms are cached, `None` to transform files every time
+    transform_cache: Option<PathBuf>,
+}
+
+/// A file made during the build, which is archived from memory
+#[derive(Clone)]
+pub(crate) struct GeneratedFile {
+    entry: PathBuf,
+    /// Seconds since the unix epoch to archive the file as last modified at
+    mtime: u64,
+    /// Unix permissions to archive the file with
+    mode: u32,
+    contents: Vec<u8>,
+}
+
+/// A file opened to be archived
+pub(crate) struct OpenedFile {
+    pub(crate) metadata: Metadata,
+    /// Unix permissions to archive the file with
+    pub(crate) mode: u32,
+    /// Size of `contents`, which differs from the size in `metadata` once transformed
+    pub(crate) size: u64,
+    pub(crate) contents: Box<dyn Read>,
+    /// PAX records of the extended attributes to archive the file with
+    pub(crate) xattrs: Vec<PaxRecord>,
 }
 
 impl RumkinstFiles {
     fn new(
+        base: PathBuf,
         root_files: Option<IncludedFiles>,
         env_files: Option<IncludedFiles>,
         script_files: Option<IncludedFiles>,
     ) -> Self {
         Self {
+            base,
             root_files,
             env_files,
             script_files,
+            package_files: None,
+            license_files: None,
+            extra_files: None,
+            located: HashMap::new(),
+            entries: HashMap::new(),
+            transforms: HashMap::new(),
+            target_only: HashMap::new(),
+            modes: HashMap::new(),
+            generated: vec![],
+            xattrs: XattrCapture::default(),
+            transform_cache: None,
+        }
+    }
+
+    /// Package every one of `fetched` in the source its destination is inside of, searching
+    /// fetched directories the same way as a source
+    fn add_fetched(
+        &mut self,
+        config: &Config,
+        fetched: Vec<FetchedFile>,
+        policy: FilePolicy<'_>,
+        progress: &dyn ProgressSink,
+        cancel: &CancelHandle,
+    ) -> Result<(), RumkinstError> {
+        for file in fetched {
+            let dest = normalized(&file.dest);
+            let source = [
+                (&config.root, &mut self.root_files),
+                (&config.env, &mut self.env_files),
+                (&config.scripts, &mut self.script_files),
+            ]
+            .into_iter()
+            .find_map(|(source, files)| {
+                let relative = dest.strip_prefix(normalized(source.path())).ok()?;
+                Some((source, files, relative.to_path_buf()))
+            });
+            let Some((source, files, relative)) = source else {
+                return Err(RumkinstError::DownloadDest {
+                    path: file.dest,
+                    reason: "it is not inside the `root`, `env` or `scripts` source",
+                });
+            };
+            let is_dir = file.path.is_dir();
+            let Some(files) = files
+                .as_mut()
+                .filter(|_| is_dir || !relative.as_os_str().is_empty())
+            else {
+                return Err(RumkinstError::DownloadDest {
+                    path: file.dest,
+                    reason: "its source is disabled, or it is the source itself",
+                });
+            };
+            let dest = source.path().join(relative);
+
+            let found = match is_dir {
+                true => PathExplorer::new(&file.path, PathBuf::new(), ExclusionFilter::default())
+                    .with_unusual_names(config.build.unusual_names)
+                    .with_special_files(source.special_files)
+                    .search(policy, progress, cancel)?,
+                false => IncludedFiles {
+                    files: vec![PathBuf::new()],
+                    empty_files: HashSet::new(),
+                },
+            };
+            let existing = files.files.iter().cloned().collect::<HashSet<_>>();
+            for found_path in found.files {
+                // A fetched file is found as an empty path
+                let (path, located) = match found_path.as_os_str().is_empty() {
+                    true => (dest.clone(), file.path.clone()),
+                    false => (dest.join(&found_path), file.path.join(&found_path)),
+                };
+                if existing.contains(&path) {
+                    return Err(RumkinstError::DownloadDest {
+                        path: file.dest.join(&found_path),
+                        reason: "a file in its source is already there",
+                    });
+                }
+                log::debug!("packaging {located:?} as {path:?}");
+                if found.empty_files.contains(&found_path) {
+                    files.empty_files.insert(path.clone());
+                }
+                files.files.push(path.clone());
+                if !file.targets.is_empty() {
+                    self.target_only.insert(path.clone(), file.targets.clone());
+                }
+                self.located.insert(path, located);
+            }
+        }
+        Ok(())
+    }
+
+    /// Archive the files of every absolute source under its `archive-prefix`, and those of every
+    /// source with a `strip-prefix` without it
+    fn strip_prefixes(&mut self, config: &Config) -> Result<(), RumkinstError> {
+        for (source, files) in [
+            (&config.root, &self.root_files),
+            (&config.env, &self.env_files),
+            (&config.scripts, &self.script_files),
+        ] {
+            let Some(files) = files else {
+                continue;
+            };
+            // Files of an absolute source are archived under its `archive-prefix` before any
+            // prefix is stripped
+            if source.path().is_absolute() {
+                for path in &files.files {
+                    let entry = source
+                        .archive_prefix
+                        .join(install_path(source.path(), path));
+                    self.entries.insert(path.clone(), entry);
+                }
+            }
+            let Some(prefix) = &source.strip_prefix else {
+                continue;
+            };
+            let prefix = normalized(prefix);
+            for path in &files.files {
+                let Ok(stripped) = normalized(self.entry(path))
+                    .strip_prefix(&prefix)
+                    .map(Path::to_path_buf)
+                else {
+                    return Err(RumkinstError::StripPrefix {
+                        path: path.clone(),
+                        prefix,
+                    });
+                };
+                // A source that is a single file keeps its name when its whole path is stripped
+                let entry = match stripped.as_os_str().is_empty() {
+                    true => PathBuf::from(path.file_name().unwrap_or(path.as_os_str())),
+                    false => stripped,
+                };
+                self.entries.insert(path.clone(), entry);
+            }
+        }
+        Ok(())
+    }
+
+    /// Every file found in the `root` source
+    pub(crate) fn root_files(&self) -> &[PathBuf] {
+        self.root_files
+            .as_ref()
+            .map(|files| files.files.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Every file found in the `scripts` source
+    pub(crate) fn script_files(&self) -> &[PathBuf] {
+        self.script_files
+            .as_ref()
+            .map(|files| files.files.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Every file found in any source, in the order they are archived
+    pub(crate) fn all_files(&self) -> impl Iterator<Item = &PathBuf> {
+        self.sources().flat_map(|files| files.files.iter())
+    }
+
+    /// The `count` largest files, by entry name and size on disk before any transforms
+    pub(crate) fn largest_files(&self, count: usize) -> Vec<(String, u64)> {
+        let mut sizes = self
+            .all_files()
+            .filter_map(|path| {
+                let size = fs::metadata(self.resolve(path)).ok()?.len();
+                Some((entry_name(self.entry(path)), size))
+            })
+            .chain(
+                self.generated
+                    .iter()
+                    .map(|file| (entry_name(&file.entry), file.contents.len() as u64)),
+            )
+            .collect::<Vec<_>>();
+        sizes.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        sizes.truncate(count);
+        sizes
+    }
+
+    /// Fail if a source file or another generated file is already archived as `name`, the entry
+    /// name of a file generated by `origin`
+    fn check_generated_entry(&self, name: &str, origin: &'static str) -> Result<(), RumkinstError> {
+        let duplicate = match self
+            .all_files()
+            .find(|path| entry_name(self.entry(path)) == name)
+        {
+            Some(path) => Some(normalized(&self.resolve(path))),
+            None => self
+                .generated
+                .iter()
+                .find(|file| entry_name(&file.entry) == name)
+                .map(|file| file.entry.clone()),
+        };
+        match duplicate {
+            Some(path) => Err(RumkinstError::GeneratedDuplicate {
+                path,
+                origin,
+                entry: name.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Where `path`, as found while searching, is on disk
+    pub(crate) fn resolve(&self, path: &Path) -> PathBuf {
+        match self.located.get(path) {
+            Some(located) => located.clone(),
+            None => self.base.join(path),
+        }
+    }
+
+    /// The path `path`, as found while searching, is archived under
+    pub(crate) fn entry<'a>(&'a self, path: &'a Path) -> &'a Path {
+        self.entries.get(path).map_or(path, PathBuf::as_path)
+    }
+
+    /// Keep the results of slow transforms in `dir`, reusing them while the files are unchanged
+    pub(crate) fn cache_transforms_in(&mut self, dir: PathBuf) {
+        self.transform_cache = Some(dir);
+    }
+
+    /// Open `path`, as found while searching, along with its metadata, after its transforms
+    ///
+    /// Special files included as empty files are never opened, as reading a fifo or device could
+    /// block forever, and read as empty instead.
+    pub(crate) fn open(&self, path: &Path) -> io::Result<OpenedFile> {
+        let resolved = self.resolve(path);
+        let mode = |metadata: &Metadata| match self.modes.get(path) {
+            Some(mode) => *mode,
+            None => unix_mode(metadata, self.entry(path)),
+        };
+        let xattrs = read_xattrs(&resolved, self.xattrs)?;
+        let empty = self.sources().any(|files| files.empty_files.contains(path));
+        if empty {
+            let metadata = fs::metadata(&resolved)?;
+            return Ok(OpenedFile {
+                mode: mode(&metadata),
+                metadata,
+                size: 0,
+                contents: Box::new(io::empty()),
+                xattrs,
+            });
+        }
+        let mut file = File::open(&resolved)?;
+        let metadata = file.metadata()?;
+        let Some(transforms) = self.transforms.get(path) else {
+            return Ok(OpenedFile {
+                mode: mode(&metadata),
+                size: metadata.len(),
+                metadata,
+                contents: Box::new(file),
+                xattrs,
+            });
+        };
+
+        let mut contents = vec![];
+        file.read_to_end(&mut contents)?;
+        let contents = transform::apply_all(
+            transforms,
+            contents,
+            &self.base,
+            path,
+            self.transform_cache.as_deref(),
+        )?;
+        Ok(OpenedFile {
+            mode: mode(&metadata),
+            metadata,
+            size: contents.len() as u64,
+            contents: Box::new(io::Cursor::new(contents)),
+            xattrs,
+        })
+    }
+
+    /// Find the transforms of every file, and add `.gz` to the entries of gzipped files
+    fn match_transforms(&mut self, rules: &[TransformRule]) {
+        let paths = self.all_files().cloned().collect::<Vec<_>>();
+        for path in paths {
+            let transforms = rules
+                .iter()
+                .filter(|rule| rule.files.iter().any(|pattern| pattern.matches(&path)))
+                .map(|rule| rule.transform.clone())
+                .collect::<Vec<_>>();
+            if transforms.is_empty() {
+                continue;
+            }
+            let entry = transformed_name(&transforms, self.entry(&path));
+            log::debug!("transforming {path:?} with {transforms:?}, archived as {entry:?}");
+            self.entries.insert(path.clone(), entry);
+            self.transforms.insert(path, transforms);
+        }
+    }
+
+    /// Where `path`, found in the `root` source at `root`, is installed on the target system,
+    /// named after its transforms
+    pub(crate) fn install_path(&self, root: &Path, path: &Path) -> PathBuf {
+        let installed = install_path(root, path);
+        match self.transforms.get(path) {
+            Some(transforms) => transformed_name(transforms, &installed),
+            None => installed,
         }
     }
 
+    /// Report files whose entry names only differ by case from an earlier file, as set by
+    /// `mode`. Rejected files that are skipped past are dropped.
+    fn check_case_collisions(
+        &mut self,
+        mode: CaseCollisions,
+        policy: FilePolicy<'_>,
+    ) -> Result<(), RumkinstError> {
+        if mode == CaseCollisions::Allow {
+            return Ok(());
+        }
+
+        let mut seen = HashMap::new();
+        let mut dropped = HashSet::new();
+        for path in self.all_files() {
+            let name = entry_name(self.entry(path));
+            let other = match seen.entry(name.to_lowercase()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    entry.insert((name, path.clone()));
+                    continue;
+                }
+            };
+            // Exactly equal names are the same file, left after removing duplicates
+            if other.0 == name {
+                continue;
+            }
+
+            let collision = RumkinstError::CaseCollision {
+                path: path.clone(),
+                other: other.1.clone(),
+            };
+            match mode {
+                CaseCollisions::Allow => {}
+                CaseCollisions::Warn => policy.diagnostics().warn(
+                    ErrorCode::SOURCE_CASE_COLLISION.at(path),
+                    collision.to_string(),
+                ),
+                CaseCollisions::Reject => {
+                    if policy.handle::<()>(Err(collision))?.is_none() {
+                        dropped.insert(path.clone());
+                    }
+                }
+            }
+        }
+
+        for files in [
+            &mut self.root_files,
+            &mut self.env_files,
+            &mut self.script_files,
+            &mut self.package_files,
+            &mut self.license_files,
+            &mut self.extra_files,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            files.files.retain(|path| !dropped.contains(path));
+        }
+        Ok(())
+    }
+
+    /// Keep a single one of the files archived under each entry path, the one picked by `mode`
+    ///
+    /// A file found by two overlapping sources counts as a duplicate too, as it would otherwise
+    /// be archived twice.
+    fn remove_duplicates(
+        &mut self,
+        mode: DuplicateEntries,
+        policy: FilePolicy<'_>,
+    ) -> Result<(), RumkinstError> {
+        let sources = [
+            ("the `root` source", &self.root_files),
+            ("the `env` source", &self.env_files),
+            ("the `scripts` source", &self.script_files),
+            ("`[package]`", &self.package_files),
+            ("`[licenses]`", &self.license_files),
+            ("`[[output.extra-files]]`", &self.extra_files),
+        ];
+        let mut seen = HashMap::<String, (usize, &PathBuf)>::new();
+        let mut dropped = HashSet::new();
+        for (index, (origin, files)) in sources.iter().enumerate() {
+            let Some(files) = files else {
+                continue;
+            };
+            for path in &files.files {
+                let name = entry_name(self.entry(path));
+                let (other_index, other) = match seen.entry(name.clone()) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        entry.insert((index, path));
+                        continue;
+                    }
+                };
+
+                let keep_first = match mode {
+                    DuplicateEntries::First => true,
+                    DuplicateEntries::Last => false,
+                    DuplicateEntries::Reject => {
+                        let duplicate = RumkinstError::DuplicateEntry {
+                            path: normalized(&self.resolve(path)),
+                            origin,
+                            other: normalized(&self.resolve(other)),
+                            other_origin: sources[*other_index].0,
+                            entry: name,
+                        };
+                        policy.handle::<()>(Err(duplicate))?;
+                        true
+                    }
+                };
+                log::debug!(
+                    "{path:?} from {origin} and {other:?} from {} are both archived as {:?}, \
+                     keeping the {} one",
+                    sources[*other_index].0,
+                    entry_name(self.entry(path)),
+                    if keep_first { "first" } else { "last" },
+                );
+                if keep_first {
+                    dropped.insert((index, path.clone()));
+                } else {
+                    dropped.insert((*other_index, other.to_path_buf()));
+                    (*other_index, *other) = (index, path);
+                }
+            }
+        }
+        drop(seen);
+
+        for (index, files) in [
+            &mut self.root_files,
+            &mut self.env_files,
+            &mut self.script_files,
+            &mut self.package_files,
+            &mut self.license_files,
+            &mut self.extra_files,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            if let Some(files) = files {
+                files
+                    .files
+                    .retain(|path| !dropped.contains(&(index, path.clone())));
+            }
+        }
+        Ok(())
+    }
+
+    /// Warn about every file that can't be extracted on Windows under its entry name
+    fn check_windows_names(&self, diagnostics: &Diagnostics) {
+        for path in self.all_files() {
+            if let Some(problem) = windows_name_problem(self.entry(path)) {
+                diagnostics.warn(
+                    ErrorCode::SOURCE_WINDOWS_NAME.at(path),
+                    format!(
+                        "{path:?} can't be extracted on Windows, {problem}. Rename it, or \
+                         exclude it from its source"
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Every searched source, then the package files and licenses
+    fn sources(&self) -> impl Iterator<Item = &IncludedFiles> {
+        [
+            &self.root_files,
+            &self.env_files,
+            &self.script_files,
+            &self.package_files,
+            &self.license_files,
+            &self.extra_files,
+        ]
+        .into_iter()
+        .flatten()
+    }
+
     pub fn total_files(&self) -> usize {
         get_files_len(&self.root_files)
             + get_files_len(&self.env_files)
             + get_files_len(&self.script_files)
+            + get_files_len(&self.package_files)
+            + get_files_len(&self.license_files)
+            + get_files_len(&self.extra_files)
+            + self.generated.len()
     }
 
-    pub fn write_archive<W: Write>(&self, destination: W) -> Result<()> {
-        let mut archive = tar::Builder::new(destination);
-
-        write_archive(&self.root_files, &mut archive)?;
-        write_archive(&self.env_files, &mut archive)?;
-        write_archive(&self.script_files, &mut archive)?;
-
-        archive.finish().context("failed to finish archive")?;
+    /// Append every included file to `archive`, then finish it
+    pub fn write_archive(
+        &self,
+        archive: &mut dyn ArchiveWriter,
+        policy: FilePolicy<'_>,
+        progress: &dyn ProgressSink,
+        cancel: &CancelHandle,
+    ) -> Result<(), RumkinstError> {
+        for files in [
+            &self.root_files,
+            &self.env_files,
+            &self.script_files,
+            &self.package_files,
+            &self.license_files,
+            &self.extra_files,
+        ] {
+            write_archive(self, files, archive, policy, progress, cancel)?;
+        }
+        for file in &self.generated {
+            cancel.check()?;
+            progress.set_message(&entry_name(&file.entry));
+            archive
+                .append_bytes(&file.entry, file.mode, file.mtime, &file.contents)
+                .map_err(|source| RumkinstError::Archive {
+                    path: Some(file.entry.clone()),
+                    source,
+                })?;
+            progress.increment(1);
+        }
 
-        Ok(())
+        archive
+            .finish()
+            .map_err(|source| RumkinstError::Archive { path: None, source })
     }
 }
 
@@ -53,33 +647,167 @@ fn get_files_len(opt: &Option<IncludedFiles>) -> usize {
     opt.as_ref().map(|files| files.files.len()).unwrap_or(0)
 }
 
-fn write_archive<W: Write>(
+fn write_archive(
+    all_files: &RumkinstFiles,
     opt: &Option<IncludedFiles>,
-    archive: &mut tar::Builder<W>,
-) -> Result<()> {
+    archive: &mut dyn ArchiveWriter,
+    policy: FilePolicy<'_>,
+    progress: &dyn ProgressSink,
+    cancel: &CancelHandle,
+) -> Result<(), RumkinstError> {
     if let Some(files) = opt {
         for path in files.files.iter() {
-            set_progress_message(format!("Writing {path:?} to archive"));
-            archive
-                .append_path(path)
-                .with_context(|| format!("failed to append {path:?} to archive"))?;
-            increment_progress(1);
+            cancel.check()?;
+            progress.set_message(&entry_name(path));
+
+            // Only opening the file can be skipped safely, once appending starts the archive
+            // may already hold part of the entry
+            let file = policy.handle(all_files.open(path).map_err(|source| {
+                RumkinstError::SourceRead {
+                    path: all_files.resolve(path),
+                    source,
+                }
+            }))?;
+            if let Some(file) = file {
+                archive
+                    .append_xattrs(&file.xattrs)
+                    .and_then(|()| {
+                        archive.append_file(
+                            all_files.entry(path),
+                            &file.metadata,
+                            file.mode,
+                            file.size,
+                            &mut cancel.reader(file.contents),
+                        )
+                    })
+                    .map_err(|source| match cancel.is_cancelled() {
+                        true => RumkinstError::Cancelled,
+                        false => RumkinstError::Archive {
+                            path: Some(path.clone()),
+                            source,
+                        },
+                    })?;
+            }
+
+            progress.increment(1);
         }
     }
 
     Ok(())
 }
 
-pub fn find_all_files(config: &Config) -> Result<RumkinstFiles> {
+/// Search every enabled source of `config` for files to package
+///
+/// Sources have to be inside the base directory, and symlinks inside a source have to point
+/// back into it, unless `allow_outside_sources` is set. Files in `[[sources.download]]` are left
+/// out, as only a build downloads them.
+pub fn find_all_files(
+    config: &Config,
+    policy: FilePolicy<'_>,
+    allow_outside_sources: bool,
+    progress: &dyn ProgressSink,
+    cancel: &CancelHandle,
+) -> Result<RumkinstFiles, RumkinstError> {
+    find_files(
+        config,
+        vec![],
+        policy,
+        allow_outside_sources,
+        progress,
+        cancel,
+    )
+}
+
+/// Search like [`find_all_files`], also packaging the `fetched` downloads
+pub(crate) fn find_files(
+    config: &Config,
+    fetched: Vec<FetchedFile>,
+    policy: FilePolicy<'_>,
+    allow_outside_sources: bool,
+    progress: &dyn ProgressSink,
+    cancel: &CancelHandle,
+) -> Result<RumkinstFiles, RumkinstError> {
     log::trace!("finding files for packaging");
-    let root = search_source(&config.root).inspect(|_| increment_progress(1))?;
-    let env = search_source(&config.env).inspect(|_| increment_progress(1))?;
-    let script = search_source(&config.scripts).inspect(|_| increment_progress(1))?;
+    let base = config.base_dir();
+    let search = |source| {
+        search_source(
+            base,
+            source,
+            policy,
+            allow_outside_sources,
+            config.build.unusual_names,
+            progress,
+            cancel,
+        )
+        .inspect(|_| progress.increment(1))
+    };
+    let root = search(&config.root)?;
+    let env = search(&config.env)?;
+    let script = search(&config.scripts)?;
 
-    Ok(RumkinstFiles::new(root, env, script))
+    let mut files = RumkinstFiles::new(base.to_path_buf(), root, env, script);
+    files.xattrs = XattrCapture {
+        xattrs: config.build.xattrs,
+        acls: config.build.acls,
+    };
+    files.add_fetched(config, fetched, policy, progress, cancel)?;
+    files.strip_prefixes(config)?;
+    if let Some(changelog) = config.changelog() {
+        if config.resolve(changelog).is_dir() {
+            return Err(RumkinstError::SourceUnsupported {
+                path: config.resolve(changelog),
+            });
+        }
+        let explorer = PathExplorer::new(base, changelog.to_path_buf(), ExclusionFilter::default())
+            .with_allow_outside(allow_outside_sources);
+        files.package_files = Some(explorer.search(policy, progress, cancel)?);
+    }
+    if let Some(licenses) = &config.licenses {
+        files.collect_licenses(licenses, allow_outside_sources, policy, progress, cancel)?;
+    }
+    files.match_transforms(&config.transforms);
+    files.add_extra_files(
+        &config.output.extra_files,
+        allow_outside_sources,
+        policy,
+        progress,
+        cancel,
+    )?;
+    files.remove_duplicates(config.build.duplicate_entries, policy)?;
+    files.check_case_collisions(config.build.case_collisions, policy)?;
+    if config.targets_windows() {
+        files.check_windows_names(policy.diagnostics());
+    }
+    Ok(files)
+}
+
+/// `name` with the extensions `transforms` add to it
+fn transformed_name(transforms: &[Transform], name: &Path) -> PathBuf {
+    let mut name = name.as_os_str().to_owned();
+    for transform in transforms {
+        if *transform == Transform::Gzip {
+            name.push(".gz");
+        }
+    }
+    PathBuf::from(name)
 }
 
-fn search_source(source: &SourceConfig) -> Result<Option<IncludedFiles>> {
+/// `path` without any `.` components, so paths written differently can be compared
+fn normalized(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+fn search_source(
+    base: &Path,
+    source: &SourceConfig,
+    policy: FilePolicy<'_>,
+    allow_outside_sources: bool,
+    unusual_names: UnusualNames,
+    progress: &dyn ProgressSink,
+    cancel: &CancelHandle,
+) -> Result<Option<IncludedFiles>, RumkinstError> {
     log::trace!("searching a source");
 
     if source.disable {
@@ -87,7 +815,26 @@ fn search_source(source: &SourceConfig) -> Result<Option<IncludedFiles>> {
         return Ok(None);
     }
 
-    let filter = ExclusionFilter::from(source.exclude());
-    let explorer = PathExplorer::new(source.path().to_path_buf(), filter);
-    explorer.search().map(Some)
+    // Nothing outside of the base directory is searched unless it was asked for, an absolute
+    // path is never a mistyped relative one
+    let filter = match source.path().is_absolute() {
+        true if !source.allow_absolute => {
+            return Err(RumkinstError::AbsoluteSource {
+                path: source.path().to_path_buf(),
+            });
+        }
+        true => ExclusionFilter::from(
+            &source
+                .exclude()
+                .iter()
+                .map(|exclude| source.path().join(exclude))
+                .collect(),
+        ),
+        false => ExclusionFilter::from(source.exclude()),
+    };
+    let explorer = PathExplorer::new(base, source.path().to_path_buf(), filter)
+        .with_allow_outside(allow_outside_sources)
+        .with_unusual_names(unusual_names)
+        .with_special_files(source.special_files);
+    explorer.search(policy, progress, cancel).map(Some)
 }
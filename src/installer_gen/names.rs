@@ -0,0 +1,259 @@
+use std::{
+    borrow::Cow,
+    ffi::OsStr,
+    fmt::Write,
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+/// Why `name` is unusual, `None` if it is valid UTF-8 without any control characters
+pub(crate) fn unusual_name(name: &OsStr) -> Option<&'static str> {
+    match name.to_str() {
+        None => Some("is not valid UTF-8"),
+        Some(name) if name.chars().any(char::is_control) => {
+            Some("contains a newline or other control character")
+        }
+        Some(_) => None,
+    }
+}
+
+/// Why `name` can't be packaged at all whatever `unusual-names` is set to, `None` if it can
+///
+/// A backslash is read as a separator when the archive is extracted on Windows, so
+/// [`entry_path`] refuses any name holding one.
+pub(crate) fn unarchivable_name(name: &OsStr) -> Option<&'static str> {
+    name_bytes(name)
+        .contains(&b'\\')
+        .then_some("contains a backslash, which archives can't hold inside a name")
+}
+
+/// Names Windows reserves for devices, which no file can have with or without an extension
+const RESERVED_WINDOWS_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Characters that can't appear anywhere in a Windows file name
+const INVALID_WINDOWS_CHARS: &[char] = &['<', '>', ':', '"', '|', '?', '*', '\\'];
+
+/// Longest path Windows tools can extract without long path support, counted in UTF-16 units
+const WINDOWS_MAX_PATH: usize = 260;
+
+/// Why the file archived from `path` can't be extracted on Windows, `None` if it can
+///
+/// The length is checked against the entry name alone, so a path close to the limit can still
+/// be too long once the directory it is extracted into is added.
+pub(crate) fn windows_name_problem(path: &Path) -> Option<String> {
+    let parts = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    for part in &parts {
+        let stem = part.split('.').next().unwrap_or_default().trim_end();
+        if let Some(reserved) = RESERVED_WINDOWS_NAMES
+            .iter()
+            .find(|reserved| stem.eq_ignore_ascii_case(reserved))
+        {
+            return Some(format!(
+                "`{part}` uses `{reserved}`, which Windows reserves for a device"
+            ));
+        }
+        if part.ends_with(['.', ' ']) {
+            return Some(format!(
+                "`{part}` ends with a dot or space, which Windows drops"
+            ));
+        }
+        if let Some(invalid) = part
+            .chars()
+            .find(|char| INVALID_WINDOWS_CHARS.contains(char) || char.is_control())
+        {
+            return Some(format!(
+                "`{part}` contains {invalid:?}, which Windows does not allow"
+            ));
+        }
+    }
+
+    let length = parts.join("/").encode_utf16().count();
+    if length > WINDOWS_MAX_PATH {
+        return Some(format!(
+            "it is {length} characters long, over the {WINDOWS_MAX_PATH} Windows allows without \
+             long path support"
+        ));
+    }
+    None
+}
+
+/// `path` normalized into a safe archive entry path
+///
+/// `.` components are dropped, and paths that are absolute, have `..` components, have
+/// backslashes inside a component, or end up empty are refused, so no entry can be extracted
+/// outside the directory it is extracted into. The same rules apply when reading archives back.
+pub(crate) fn entry_path(path: &Path) -> io::Result<PathBuf> {
+    let unsafe_path = |reason: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unsafe archive entry {path:?}, {reason}"),
+        )
+    };
+
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::Normal(part) => {
+                if name_bytes(part).contains(&b'\\') {
+                    return Err(unsafe_path("it has a backslash inside a path component"));
+                }
+                normalized.push(part);
+            }
+            Component::ParentDir => return Err(unsafe_path("it has a `..` component")),
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(unsafe_path("it is absolute"));
+            }
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        return Err(unsafe_path("it has no name"));
+    }
+    Ok(normalized)
+}
+
+/// `path` as it is named inside an archive, with `/` separators and without any `.` components,
+/// escaped with [`escape_name`]
+pub(crate) fn entry_name(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(escape_name(part)),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// `name` as printable UTF-8, for messages and manifests
+///
+/// Backslashes are doubled, control characters are written as `\n`, `\t` or `\u{..}`, and bytes
+/// that aren't valid UTF-8 as `\xNN`, so the original name can always be recovered.
+fn escape_name(name: &OsStr) -> String {
+    // Writing into a `String` never fails
+    let mut escaped = String::new();
+    for chunk in name_bytes(name).utf8_chunks() {
+        for char in chunk.valid().chars() {
+            match char {
+                '\\' => escaped.push_str("\\\\"),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                '\t' => escaped.push_str("\\t"),
+                char if char.is_control() => {
+                    write!(escaped, "\\u{{{:x}}}", char as u32).ok();
+                }
+                char => escaped.push(char),
+            }
+        }
+        for byte in chunk.invalid() {
+            write!(escaped, "\\x{byte:02x}").ok();
+        }
+    }
+    escaped
+}
+
+/// The raw bytes of `name` where the platform has them, otherwise its lossy UTF-8 form
+#[cfg(unix)]
+fn name_bytes(name: &OsStr) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Cow::Borrowed(name.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn name_bytes(name: &OsStr) -> Cow<'_, [u8]> {
+    Cow::Owned(name.to_string_lossy().into_owned().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entry_path_drops_current_dir() {
+        assert_eq!(
+            entry_path(Path::new("./root/./etc/b.conf")).unwrap(),
+            Path::new("root/etc/b.conf")
+        );
+    }
+
+    #[test]
+    fn entry_path_refuses_unsafe_paths() {
+        for path in [
+            "../etc/passwd",
+            "root/../../x",
+            "/etc/passwd",
+            "",
+            ".",
+            "./.",
+        ] {
+            let err = entry_path(Path::new(path)).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{path:?}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn entry_path_refuses_backslashes() {
+        assert!(entry_path(Path::new("root/..\\..\\x")).is_err());
+        assert!(unarchivable_name(OsStr::new("a\\b")).is_some());
+        assert!(unarchivable_name(OsStr::new("a b")).is_none());
+    }
+
+    #[test]
+    fn escape_name_escapes_control_characters_and_backslashes() {
+        assert_eq!(escape_name(OsStr::new("plain.txt")), "plain.txt");
+        assert_eq!(escape_name(OsStr::new("a\nb\tc\rd")), "a\\nb\\tc\\rd");
+        assert_eq!(escape_name(OsStr::new("a\\n")), "a\\\\n");
+        assert_eq!(escape_name(OsStr::new("bell\u{7}")), "bell\\u{7}");
+        assert_eq!(escape_name(OsStr::new("café")), "café");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn escape_name_escapes_invalid_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let name = OsStr::from_bytes(b"a\xffb");
+        assert_eq!(escape_name(name), "a\\xffb");
+        assert_eq!(unusual_name(name), Some("is not valid UTF-8"));
+    }
+
+    #[test]
+    fn entry_name_joins_escaped_components() {
+        assert_eq!(entry_name(Path::new("./root/new\nline")), "root/new\\nline");
+    }
+
+    #[test]
+    fn windows_name_problem_finds_reserved_and_invalid_names() {
+        for path in [
+            "root/CON",
+            "root/nul.txt",
+            "root/com1 .log",
+            "root/dir./a",
+            "root/a ",
+            "root/what?",
+            "root/a:b",
+        ] {
+            assert!(windows_name_problem(Path::new(path)).is_some(), "{path:?}");
+        }
+        for path in ["root/console", "root/a.b.c", "root/COM10", "root/.hidden"] {
+            assert_eq!(windows_name_problem(Path::new(path)), None, "{path:?}");
+        }
+    }
+
+    #[test]
+    fn windows_name_problem_finds_long_paths() {
+        let long = format!("root/{}", "a".repeat(WINDOWS_MAX_PATH));
+        assert!(windows_name_problem(Path::new(&long)).is_some());
+        let short = format!("root/{}", "a".repeat(WINDOWS_MAX_PATH - "root/".len()));
+        assert_eq!(windows_name_problem(Path::new(&short)), None);
+    }
+}
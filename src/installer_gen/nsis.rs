@@ -0,0 +1,244 @@
+use std::{
+    fmt::Write,
+    io,
+    path::{self, Component, Path},
+    process::{Command, Stdio},
+};
+
+use hashbrown::HashSet;
+
+use super::{RumkinstFiles, entry_name};
+use crate::{RumkinstError, config::Config, progress_log::ProgressSink};
+
+/// File name of the installer `makensis` writes, following `name-version-setup.exe`
+pub(crate) fn nsis_installer_name(config: &Config) -> String {
+    match config.version() {
+        Some(version) => format!("{}-{version}-setup.exe", config.get_name()),
+        None => format!("{}-setup.exe", config.get_name()),
+    }
+}
+
+/// An NSIS script installing the `root` source into the install directory, along with an
+/// uninstaller and an entry in "Apps & features"
+///
+/// With `allow-user-install`, the installer runs per-machine for administrators and per-user for
+/// everyone else, otherwise it always asks for administrator rights. Source files are referred to
+/// by absolute path, so the script has to be compiled on the machine that generated it.
+pub(crate) fn nsis_script(
+    config: &Config,
+    files: &RumkinstFiles,
+    progress: &dyn ProgressSink,
+) -> Result<String, RumkinstError> {
+    let nsis = config
+        .nsis
+        .as_ref()
+        .expect("an NSIS script should only be made when [nsis] is set");
+    let name = config.get_name();
+    let install_dir = escape(nsis.install_dir.as_deref().unwrap_or(name));
+
+    let mut script = String::new();
+    let mut line = |text: &str| {
+        script.push_str(text);
+        script.push('\n');
+    };
+
+    line(&format!(
+        "; Generated by rumkinst {}",
+        env!("CARGO_PKG_VERSION")
+    ));
+    line("Unicode true");
+    line("SetCompressor /SOLID lzma");
+    line(&format!("Name \"{}\"", escape(name)));
+    line(&format!(
+        "OutFile \"{}\"",
+        escape(&nsis_installer_name(config))
+    ));
+    line(&format!(
+        "!define UNINSTALL_KEY \"Software\\Microsoft\\Windows\\CurrentVersion\\Uninstall\\{}\"",
+        escape(name)
+    ));
+    line("");
+
+    if config.installer.allow_user_install {
+        line("!define MULTIUSER_EXECUTIONLEVEL Highest");
+        line("!define MULTIUSER_INSTALLMODE_COMMANDLINE");
+        line(&format!(
+            "!define MULTIUSER_INSTALLMODE_INSTDIR \"{install_dir}\""
+        ));
+        line("!define MULTIUSER_USE_PROGRAMFILES64");
+        line("!include MultiUser.nsh");
+        line("");
+        for (function, init) in [(".onInit", "INIT"), ("un.onInit", "UNINIT")] {
+            line(&format!("Function {function}"));
+            line(&format!("  !insertmacro MULTIUSER_{init}"));
+            line("FunctionEnd");
+        }
+    } else {
+        line("RequestExecutionLevel admin");
+        line(&format!("InstallDir \"$PROGRAMFILES64\\{install_dir}\""));
+        line("");
+        for function in [".onInit", "un.onInit"] {
+            line(&format!("Function {function}"));
+            line("  SetShellVarContext all");
+            line("FunctionEnd");
+        }
+    }
+    line("");
+
+    for page in [
+        "Page directory",
+        "Page instfiles",
+        "UninstPage uninstConfirm",
+        "UninstPage instfiles",
+    ] {
+        line(page);
+    }
+    line("");
+
+    let mut installed = vec![];
+    let mut dirs = vec![];
+    let mut seen_dirs = HashSet::new();
+    let mut out_path = None;
+
+    line("Section \"Install\"");
+    for path in files.root_files() {
+        progress.set_message(&entry_name(path));
+
+        let relative = files.install_path(config.root.path(), path);
+        let parent = relative.parent().unwrap_or(Path::new(""));
+        for dir in parent.ancestors() {
+            if !dir.as_os_str().is_empty() && seen_dirs.insert(dir.to_path_buf()) {
+                dirs.push(dir.to_path_buf());
+            }
+        }
+        if out_path.as_deref() != Some(parent) {
+            line(&format!("  SetOutPath \"{}\"", installed_path(parent)));
+            out_path = Some(parent.to_path_buf());
+        }
+
+        let resolved = files.resolve(path);
+        let source = path::absolute(&resolved).map_err(|source| RumkinstError::SourceRead {
+            path: resolved,
+            source,
+        })?;
+        let file_name = relative
+            .file_name()
+            .expect("installed files should always have a file name");
+        line(&format!(
+            "  File \"/oname={}\" \"{}\"",
+            escape(&file_name.to_string_lossy()),
+            escape(&source.to_string_lossy())
+        ));
+        installed.push(relative);
+
+        progress.increment(1);
+    }
+
+    let mut registry = vec![
+        ("DisplayName", escape(name)),
+        ("InstallLocation", "$INSTDIR".to_string()),
+        (
+            "UninstallString",
+            "$\\\"$INSTDIR\\uninstall.exe$\\\"".to_string(),
+        ),
+    ];
+    if let Some(version) = config.version() {
+        registry.push(("DisplayVersion", escape(version)));
+    }
+    if let Some(publisher) = nsis.publisher.as_ref().or(config.package.authors.first()) {
+        registry.push(("Publisher", escape(publisher)));
+    }
+
+    line("  SetOutPath \"$INSTDIR\"");
+    line("  WriteUninstaller \"$INSTDIR\\uninstall.exe\"");
+    for (key, value) in registry {
+        line(&format!(
+            "  WriteRegStr SHCTX \"${{UNINSTALL_KEY}}\" \"{key}\" \"{value}\""
+        ));
+    }
+    for key in ["NoModify", "NoRepair"] {
+        line(&format!(
+            "  WriteRegDWORD SHCTX \"${{UNINSTALL_KEY}}\" \"{key}\" 1"
+        ));
+    }
+    line("SectionEnd");
+    line("");
+
+    line("Section \"Uninstall\"");
+    for relative in &installed {
+        line(&format!("  Delete \"{}\"", installed_path(relative)));
+    }
+    line("  Delete \"$INSTDIR\\uninstall.exe\"");
+    // Deepest first, and without `/r` so anything the user added is left alone
+    dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+    for dir in &dirs {
+        line(&format!("  RMDir \"{}\"", installed_path(dir)));
+    }
+    line("  RMDir \"$INSTDIR\"");
+    line("  DeleteRegKey SHCTX \"${UNINSTALL_KEY}\"");
+    line("SectionEnd");
+
+    Ok(script)
+}
+
+/// Where `relative` ends up under the install directory, as an escaped NSIS path
+fn installed_path(relative: &Path) -> String {
+    let mut installed = "$INSTDIR".to_string();
+    for component in relative.components() {
+        if let Component::Normal(part) = component {
+            // Writing into a `String` never fails
+            write!(installed, "\\{}", escape(&part.to_string_lossy())).ok();
+        }
+    }
+    installed
+}
+
+/// Escape `text` for use inside a double quoted NSIS string
+fn escape(text: &str) -> String {
+    text.replace('$', "$$")
+        .replace('"', "$\\\"")
+        .replace('\n', "$\\n")
+        .replace('\r', "$\\r")
+        .replace('\t', "$\\t")
+}
+
+/// Compile the NSIS script at `script_path` with `makensis`, which writes the installer next to it
+pub(crate) fn compile_nsis_script(script_path: &Path) -> Result<(), RumkinstError> {
+    let compile_error = |source| RumkinstError::Package {
+        path: script_path.to_path_buf(),
+        source,
+    };
+    let script_dir = script_path
+        .parent()
+        .expect("the NSIS script should always be inside the output directory");
+
+    let output = Command::new("makensis")
+        .arg("-V2")
+        .arg("-INPUTCHARSET")
+        .arg("UTF8")
+        .arg(script_path.file_name().expect("script has a file name"))
+        .current_dir(script_dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|err| {
+            compile_error(io::Error::new(
+                err.kind(),
+                format!("failed to run `makensis`: {err}"),
+            ))
+        })?;
+    if !output.status.success() {
+        // makensis reports script errors on stdout
+        let report = match output.stderr.trim_ascii().is_empty() {
+            true => output.stdout,
+            false => output.stderr,
+        };
+        return Err(compile_error(io::Error::other(format!(
+            "`makensis` {}: {}",
+            output.status,
+            String::from_utf8_lossy(&report).trim()
+        ))));
+    }
+    Ok(())
+}
@@ -0,0 +1,324 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use flate2::{Compression, write::GzEncoder};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+
+use super::{DigestWriter, RumkinstFiles, Sha256Digester, root_tree::append_root_files, to_hex};
+use crate::{
+    CancelHandle, RumkinstError,
+    config::{Config, OciConfig},
+    error_log::FilePolicy,
+    progress_log::ProgressSink,
+};
+
+const INDEX_MEDIA_TYPE: &str = "application/vnd.oci.image.index.v1+json";
+const MANIFEST_MEDIA_TYPE: &str = "application/vnd.oci.image.manifest.v1+json";
+const CONFIG_MEDIA_TYPE: &str = "application/vnd.oci.image.config.v1+json";
+const LAYER_MEDIA_TYPE: &str = "application/vnd.oci.image.layer.v1.tar+gzip";
+
+/// Name and tag the image is stored under, e.g. `demo:1.2.3`
+pub(crate) fn image_reference(config: &Config) -> String {
+    let oci = config
+        .oci
+        .as_ref()
+        .expect("an OCI image should only be made when [oci] is set");
+    let tag = oci.tag.as_deref().or(config.version()).unwrap_or("latest");
+    format!(
+        "{}:{}",
+        config.get_name().to_ascii_lowercase(),
+        sanitize_tag(tag)
+    )
+}
+
+/// Replace every character not allowed in an OCI tag, such as the `+` of a semver version
+pub(crate) fn sanitize_tag(tag: &str) -> String {
+    tag.chars()
+        .map(
+            |ch| match ch.is_ascii_alphanumeric() || "_.-".contains(ch) {
+                true => ch,
+                false => '-',
+            },
+        )
+        .collect()
+}
+
+/// Write an OCI image layout into `layout_dir`, with the `root` source as a single layer on top
+/// of the configured base image
+pub(crate) fn write_oci_layout(
+    config: &Config,
+    files: &RumkinstFiles,
+    layout_dir: &Path,
+    policy: FilePolicy<'_>,
+    progress: &dyn ProgressSink,
+    cancel: &CancelHandle,
+) -> Result<(), RumkinstError> {
+    let oci = config
+        .oci
+        .as_ref()
+        .expect("an OCI image should only be made when [oci] is set");
+    let layout_error = |source| RumkinstError::Package {
+        path: layout_dir.to_path_buf(),
+        source,
+    };
+
+    let blobs = layout_dir.join("blobs").join("sha256");
+    fs::create_dir_all(&blobs).map_err(layout_error)?;
+
+    let base = oci
+        .base
+        .as_ref()
+        .map(|base| read_base(&config.resolve(base), &blobs))
+        .transpose()?;
+
+    let (layer, diff_id) = write_layer(
+        config,
+        files,
+        &blobs,
+        policy,
+        progress,
+        cancel,
+        &layout_error,
+    )?;
+    cancel.check()?;
+
+    let (base_config, mut layers) = base
+        .map(|base| (Some(base.config), base.layers))
+        .unwrap_or_default();
+    layers.push(layer);
+
+    let image_config = image_config(oci, base_config, &diff_id);
+    let config_descriptor =
+        write_blob(&blobs, CONFIG_MEDIA_TYPE, &image_config).map_err(layout_error)?;
+
+    let manifest = json!({
+        "schemaVersion": 2,
+        "mediaType": MANIFEST_MEDIA_TYPE,
+        "config": config_descriptor,
+        "layers": layers,
+    });
+    let mut manifest_descriptor =
+        write_blob(&blobs, MANIFEST_MEDIA_TYPE, &manifest).map_err(layout_error)?;
+
+    let reference = image_reference(config);
+    let (_, tag) = reference
+        .rsplit_once(':')
+        .expect("image reference should always have a tag");
+    manifest_descriptor["annotations"] = json!({
+        "io.containerd.image.name": reference,
+        "org.opencontainers.image.ref.name": tag,
+    });
+
+    let index = json!({
+        "schemaVersion": 2,
+        "mediaType": INDEX_MEDIA_TYPE,
+        "manifests": [manifest_descriptor],
+    });
+    fs::write(layout_dir.join("index.json"), index.to_string()).map_err(layout_error)?;
+    fs::write(
+        layout_dir.join("oci-layout"),
+        json!({ "imageLayoutVersion": "1.0.0" }).to_string(),
+    )
+    .map_err(layout_error)
+}
+
+/// The base image, as read from an OCI image layout
+struct BaseImage {
+    config: Value,
+    layers: Vec<Value>,
+}
+
+/// Read the first image in the layout at `base_dir`, copying its layers into `blobs`
+fn read_base(base_dir: &Path, blobs: &Path) -> Result<BaseImage, RumkinstError> {
+    let invalid = |reason: &str| RumkinstError::SourceRead {
+        path: base_dir.to_path_buf(),
+        source: io::Error::new(io::ErrorKind::InvalidData, reason.to_string()),
+    };
+
+    let index = read_json(&base_dir.join("index.json"))?;
+    let descriptor = index["manifests"]
+        .get(0)
+        .ok_or_else(|| invalid("base image layout does not hold any images"))?;
+    if descriptor["mediaType"] == INDEX_MEDIA_TYPE {
+        return Err(invalid(
+            "base image must be a single platform image, not an index",
+        ));
+    }
+
+    let manifest = read_json(
+        &blob_path(base_dir, &descriptor["digest"])
+            .ok_or_else(|| invalid("base image manifest has an invalid digest"))?,
+    )?;
+    let config = read_json(
+        &blob_path(base_dir, &manifest["config"]["digest"])
+            .ok_or_else(|| invalid("base image config has an invalid digest"))?,
+    )?;
+
+    let layers = manifest["layers"].as_array().cloned().unwrap_or_default();
+    for layer in &layers {
+        let source = blob_path(base_dir, &layer["digest"])
+            .ok_or_else(|| invalid("base image layer has an invalid digest"))?;
+        let destination = blobs.join(source.file_name().expect("blob paths have a file name"));
+        if !destination.exists() {
+            fs::copy(&source, &destination).map_err(|source_err| RumkinstError::SourceRead {
+                path: source,
+                source: source_err,
+            })?;
+        }
+    }
+
+    Ok(BaseImage { config, layers })
+}
+
+fn read_json(path: &Path) -> Result<Value, RumkinstError> {
+    fs::read(path)
+        .and_then(|bytes| Ok(serde_json::from_slice(&bytes)?))
+        .map_err(|source| RumkinstError::SourceRead {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+/// Where the blob with `digest` is kept in the layout at `layout_dir`, `None` if `digest` is not
+/// a valid SHA-256 digest
+fn blob_path(layout_dir: &Path, digest: &Value) -> Option<PathBuf> {
+    let hex = digest.as_str()?.strip_prefix("sha256:")?;
+    if hex.len() != 64 || !hex.chars().all(|ch| ch.is_ascii_hexdigit()) {
+        return None;
+    }
+    Some(layout_dir.join("blobs").join("sha256").join(hex))
+}
+
+/// Write the layer holding the `root` source into `blobs`, returning its descriptor and the
+/// digest of its uncompressed contents
+fn write_layer(
+    config: &Config,
+    files: &RumkinstFiles,
+    blobs: &Path,
+    policy: FilePolicy<'_>,
+    progress: &dyn ProgressSink,
+    cancel: &CancelHandle,
+    write_error: &dyn Fn(io::Error) -> RumkinstError,
+) -> Result<(Value, String), RumkinstError> {
+    // The digest is the name of the blob, so it can only be moved into place once written
+    let partial_path = blobs.join("layer.partial");
+    let file = File::create(&partial_path).map_err(write_error)?;
+
+    let compressed = DigestWriter::new(file, &Sha256Digester);
+    let uncompressed = DigestWriter::new(
+        GzEncoder::new(compressed, Compression::best()),
+        &Sha256Digester,
+    );
+    let mut tar = tar::Builder::new(uncompressed);
+    append_root_files(
+        &mut tar,
+        files,
+        config.root.path(),
+        policy,
+        progress,
+        cancel,
+        write_error,
+    )?;
+
+    let (encoder, diff_id, _) = tar.into_inner().map_err(write_error)?.into_parts();
+    let (mut file, digest, size) = encoder.finish().map_err(write_error)?.into_parts();
+    file.flush().map_err(write_error)?;
+
+    let digest = to_hex(&digest);
+    fs::rename(&partial_path, blobs.join(&digest)).map_err(write_error)?;
+
+    let descriptor = json!({
+        "mediaType": LAYER_MEDIA_TYPE,
+        "digest": format!("sha256:{digest}"),
+        "size": size,
+    });
+    Ok((descriptor, format!("sha256:{}", to_hex(&diff_id))))
+}
+
+/// The image config, extending `base` if there is one
+fn image_config(oci: &OciConfig, base: Option<Value>, diff_id: &str) -> Value {
+    let created = jiff::Timestamp::now().to_string();
+    let mut image = base.unwrap_or_else(|| {
+        json!({
+            "architecture": oci.architecture,
+            "os": "linux",
+            "config": {},
+            "rootfs": { "type": "layers", "diff_ids": [] },
+        })
+    });
+
+    image["created"] = json!(created);
+    if let Some(entrypoint) = &oci.entrypoint {
+        image["config"]["Entrypoint"] = json!(entrypoint);
+    }
+    if let Some(cmd) = &oci.cmd {
+        image["config"]["Cmd"] = json!(cmd);
+    }
+
+    let history = json!({
+        "created": created,
+        "created_by": format!("rumkinst {}", env!("CARGO_PKG_VERSION")),
+    });
+    match image["history"].as_array_mut() {
+        Some(entries) => entries.push(history),
+        None => image["history"] = json!([history]),
+    }
+    match image["rootfs"]["diff_ids"].as_array_mut() {
+        Some(diff_ids) => diff_ids.push(json!(diff_id)),
+        None => image["rootfs"] = json!({ "type": "layers", "diff_ids": [diff_id] }),
+    }
+
+    image
+}
+
+/// Write `value` as a blob, returning its descriptor
+fn write_blob(blobs: &Path, media_type: &str, value: &Value) -> io::Result<Value> {
+    let bytes = serde_json::to_vec(value)?;
+    let digest = to_hex(&Sha256::digest(&bytes));
+    fs::write(blobs.join(&digest), &bytes)?;
+
+    Ok(json!({
+        "mediaType": media_type,
+        "digest": format!("sha256:{digest}"),
+        "size": bytes.len(),
+    }))
+}
+
+/// Load the image in the layout at `layout_dir` into the local docker daemon
+pub(crate) fn load_into_docker(layout_dir: &Path) -> Result<(), RumkinstError> {
+    let load_error = |source| RumkinstError::Package {
+        path: layout_dir.to_path_buf(),
+        source,
+    };
+
+    let mut child = Command::new("docker")
+        .arg("load")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(load_error)?;
+
+    let stdin = child.stdin.take().expect("stdin of `docker load` is piped");
+    let mut tar = tar::Builder::new(stdin);
+    // If docker stopped reading early, its exit status says why
+    let written = tar
+        .append_dir_all(".", layout_dir)
+        .and_then(|_| tar.into_inner());
+    drop(written);
+
+    let output = child.wait_with_output().map_err(load_error)?;
+    if !output.status.success() {
+        return Err(load_error(io::Error::other(format!(
+            "`docker load` {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))));
+    }
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use hashbrown::HashSet;
+
+use super::{RumkinstFiles, archive::unix_mode, entry_name, names::entry_path};
+use crate::{CancelHandle, RumkinstError, error_log::FilePolicy, progress_log::ProgressSink};
+
+/// Append every file in the `root` source to `tar`, installed relative to `/` and owned by
+/// `root:root`, as system packages and images expect
+///
+/// Parent directories get entries of their own. Failures to write `tar` are turned into errors
+/// by `write_error`. Returns the total size of the appended files in bytes.
+pub(crate) fn append_root_files<W: Write>(
+    tar: &mut tar::Builder<W>,
+    files: &RumkinstFiles,
+    root: &Path,
+    policy: FilePolicy<'_>,
+    progress: &dyn ProgressSink,
+    cancel: &CancelHandle,
+    write_error: &dyn Fn(io::Error) -> RumkinstError,
+) -> Result<u64, RumkinstError> {
+    let mut dirs = HashSet::new();
+    let mut installed_bytes = 0;
+
+    for path in files.root_files() {
+        cancel.check()?;
+        progress.set_message(&entry_name(path));
+
+        let name = entry_path(&files.install_path(root, path)).map_err(write_error)?;
+        let parents: Vec<_> = name
+            .ancestors()
+            .skip(1)
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .collect();
+        for parent in parents.into_iter().rev() {
+            if dirs.insert(parent.to_path_buf()) {
+                let mut header = root_header(tar::EntryType::Directory);
+                header.set_mode(0o755);
+                header.set_size(0);
+                tar.append_data(&mut header, parent, io::empty())
+                    .map_err(write_error)?;
+            }
+        }
+
+        let file = policy.handle(
+            files
+                .open(path)
+                .map_err(|source| RumkinstError::SourceRead {
+                    path: files.resolve(path),
+                    source,
+                }),
+        )?;
+        if let Some(file) = file {
+            let mut header = root_header(tar::EntryType::Regular);
+            header.set_metadata(&file.metadata);
+            header.set_mode(unix_mode(&file.metadata, &name));
+            // Special files included as empty files are stored as plain files
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_size(file.size);
+            header.set_uid(0);
+            header.set_gid(0);
+            tar.append_data(&mut header, &name, cancel.reader(file.contents))
+                .map_err(|source| match cancel.is_cancelled() {
+                    true => RumkinstError::Cancelled,
+                    false => write_error(source),
+                })?;
+            installed_bytes += file.size;
+        }
+
+        progress.increment(1);
+    }
+
+    Ok(installed_bytes)
+}
+
+/// Where `path`, found in the `root` source at `root`, is installed on the target system
+pub(crate) fn install_path(root: &Path, path: &Path) -> PathBuf {
+    match path.strip_prefix(root) {
+        Ok(relative) if !relative.as_os_str().is_empty() => relative.to_path_buf(),
+        // `root` is a single file rather than a directory
+        _ => PathBuf::from(path.file_name().unwrap_or(path.as_os_str())),
+    }
+}
+
+/// A header for an entry owned by `root:root`
+pub(crate) fn root_header(entry_type: tar::EntryType) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(entry_type);
+    header.set_uid(0);
+    header.set_gid(0);
+    // Only fails for names longer than the header allows, which these are not
+    header.set_username("root").ok();
+    header.set_groupname("root").ok();
+    header.set_mtime(
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or_default(),
+    );
+    header
+}
@@ -0,0 +1,135 @@
+use std::{
+    error::Error,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::progress_log::ProgressSink;
+
+/// Size of each chunk read from an artifact while hashing it
+const DIGEST_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Computes the checksums written next to each artifact
+pub trait Digester: Send + Sync {
+    /// Name of the algorithm, used as the extension of checksum files, e.g. `sha256`
+    fn algorithm(&self) -> &str;
+    /// Start hashing a new artifact
+    fn start(&self) -> Box<dyn DigestState>;
+
+    /// Hash everything in `reader`, reporting each byte read to `progress`
+    fn digest(&self, reader: &mut dyn Read, progress: &dyn ProgressSink) -> io::Result<Vec<u8>> {
+        let mut state = self.start();
+        let mut buf = vec![0; DIGEST_CHUNK_SIZE];
+        loop {
+            let read = reader.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            state.update(&buf[..read]);
+            progress.increment(read as u64);
+        }
+        Ok(state.finish())
+    }
+}
+
+/// A [`Digester`] part way through hashing an artifact
+pub trait DigestState: Send {
+    fn update(&mut self, bytes: &[u8]);
+    fn finish(self: Box<Self>) -> Vec<u8>;
+}
+
+/// Signs artifacts once their checksums are known
+///
+/// Only the digest is handed over, so implementations backed by an HSM or a remote signing
+/// service never need the artifact itself.
+pub trait Signer: Send + Sync {
+    /// Extension of the signature file written next to each artifact, e.g. `sig`
+    fn extension(&self) -> &str;
+    /// Sign `digest`, the checksum of the artifact at `path`
+    fn sign(&self, path: &Path, digest: &[u8]) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>>;
+    /// Check that `signature` is valid for `digest`, the checksum of the artifact at `path`,
+    /// failing if it is not
+    ///
+    /// Returns `Ok(false)` when this signer can't check signatures, which is the default.
+    fn verify(
+        &self,
+        path: &Path,
+        digest: &[u8],
+        signature: &[u8],
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let _ = (path, digest, signature);
+        Ok(false)
+    }
+}
+
+/// The default [`Digester`], hashing locally with SHA-256
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha256Digester;
+
+impl Digester for Sha256Digester {
+    fn algorithm(&self) -> &str {
+        "sha256"
+    }
+
+    fn start(&self) -> Box<dyn DigestState> {
+        Box::new(Sha256::new())
+    }
+}
+
+impl DigestState for Sha256 {
+    fn update(&mut self, bytes: &[u8]) {
+        Digest::update(self, bytes);
+    }
+
+    fn finish(self: Box<Self>) -> Vec<u8> {
+        self.finalize().to_vec()
+    }
+}
+
+/// Passes everything written through to `inner`, hashing it on the way
+pub(crate) struct DigestWriter<W> {
+    inner: W,
+    state: Box<dyn DigestState>,
+    written: u64,
+}
+
+impl<W: Write> DigestWriter<W> {
+    pub(crate) fn new(inner: W, digester: &dyn Digester) -> Self {
+        Self {
+            inner,
+            state: digester.start(),
+            written: 0,
+        }
+    }
+
+    /// The digest of, and number of bytes in, everything written so far
+    pub(crate) fn finish(self) -> (Vec<u8>, u64) {
+        let (_, digest, written) = self.into_parts();
+        (digest, written)
+    }
+
+    /// Like [`DigestWriter::finish`], but also hands back the inner writer
+    pub(crate) fn into_parts(self) -> (W, Vec<u8>, u64) {
+        (self.inner, self.state.finish(), self.written)
+    }
+}
+
+impl<W: Write> Write for DigestWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.state.update(&buf[..written]);
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Lowercase hex encoding of `bytes`, as written into checksum files
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
@@ -0,0 +1,34 @@
+use super::RumkinstFiles;
+use crate::config::TargetConfig;
+
+impl RumkinstFiles {
+    /// The files packaged for `target`, without the ones it excludes and the fetched files only
+    /// packaged for other targets
+    pub(crate) fn for_target(&self, target: &TargetConfig) -> Self {
+        let mut files = self.clone();
+        for included in [
+            &mut files.root_files,
+            &mut files.env_files,
+            &mut files.script_files,
+            &mut files.package_files,
+            &mut files.license_files,
+            &mut files.extra_files,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            included.files.retain(|path| {
+                let other_target = self
+                    .target_only
+                    .get(path)
+                    .is_some_and(|targets| !targets.contains(&target.name));
+                let excluded = target.exclude.iter().any(|pattern| pattern.matches(path));
+                if other_target || excluded {
+                    log::debug!("leaving {path:?} out of target {}", target.name);
+                }
+                !(other_target || excluded)
+            });
+        }
+        files
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+};
+
+use flate2::{Compression, GzBuilder};
+use nanoid::nanoid;
+
+use super::{Digester, Sha256Digester, to_hex};
+use crate::{config::Transform, progress_log::NoProgress};
+
+/// `contents` of the file found at `path` after every one of `transforms`, with commands run
+/// from `base`
+///
+/// With a `cache` directory, the result of transforms that run a program is kept in it by the
+/// transforms, `path` and `contents`, and reused as long as none of them change.
+pub(crate) fn apply_all(
+    transforms: &[Transform],
+    mut contents: Vec<u8>,
+    base: &Path,
+    path: &Path,
+    cache: Option<&Path>,
+) -> io::Result<Vec<u8>> {
+    let slow = transforms.iter().any(|transform| {
+        matches!(
+            transform,
+            Transform::Strip | Transform::StripDebug | Transform::Run(_)
+        )
+    });
+    let cached = match cache {
+        Some(cache) if slow => cache.join(cache_key(transforms, &contents, path)?),
+        _ => {
+            for transform in transforms {
+                contents = apply(transform, contents, base, path)?;
+            }
+            return Ok(contents);
+        }
+    };
+    match fs::read(&cached) {
+        Ok(output) => {
+            log::debug!("using cached transforms of {path:?} at {cached:?}");
+            return Ok(output);
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => log::debug!("failed to read cached transforms {cached:?}: {err}"),
+    }
+
+    for transform in transforms {
+        contents = apply(transform, contents, base, path)?;
+    }
+    // Written under another name first, so a build reading it never sees part of it
+    let partial = cached.with_extension(format!("{}.part", nanoid!()));
+    let stored = fs::create_dir_all(cached.parent().expect("cached files are in a directory"))
+        .and_then(|()| fs::write(&partial, &contents))
+        .and_then(|()| fs::rename(&partial, &cached));
+    if let Err(err) = stored {
+        log::debug!("failed to cache transforms of {path:?}: {err}");
+        fs::remove_file(&partial).ok();
+    }
+    Ok(contents)
+}
+
+/// Name of the cached result of `transforms` for the file at `path` holding `contents`
+fn cache_key(transforms: &[Transform], contents: &[u8], path: &Path) -> io::Result<String> {
+    let mut key = format!(
+        "{transforms:?}\0{}\0{:?}\0",
+        path.to_string_lossy(),
+        env::var_os("STRIP")
+    )
+    .into_bytes();
+    key.extend_from_slice(contents);
+    let digest = Sha256Digester.digest(&mut key.as_slice(), &NoProgress)?;
+    Ok(to_hex(&digest)[..32].to_string())
+}
+
+/// `contents` of the file found at `path` after `transform`, with commands run from `base`
+///
+/// Files are transformed in memory, so nothing is copied to disk along the way.
+pub(crate) fn apply(
+    transform: &Transform,
+    contents: Vec<u8>,
+    base: &Path,
+    path: &Path,
+) -> io::Result<Vec<u8>> {
+    match transform {
+        Transform::Gzip => {
+            // Without a name or modification time, so the output only depends on the contents
+            let mut encoder = GzBuilder::new().write(vec![], Compression::best());
+            encoder.write_all(&contents)?;
+            encoder.finish()
+        }
+        Transform::TrimWhitespace => Ok(map_lines(&contents, |line, ending| {
+            let end = line
+                .iter()
+                .rposition(|byte| !matches!(byte, b' ' | b'\t'))
+                .map_or(0, |last| last + 1);
+            [&line[..end], ending].concat()
+        })),
+        Transform::Lf => Ok(map_lines(&contents, |line, ending| match ending {
+            b"\r\n" => [line, b"\n"].concat(),
+            _ => [line, ending].concat(),
+        })),
+        Transform::Crlf => Ok(map_lines(&contents, |line, ending| match ending {
+            b"\n" => [line, b"\r\n"].concat(),
+            _ => [line, ending].concat(),
+        })),
+        Transform::Strip => strip(contents, &[]),
+        Transform::StripDebug => strip(contents, &["-S"]),
+        Transform::Run(run) => run_command(run, contents, base, path),
+    }
+}
+
+/// Magic numbers of ELF files, then of 32 and 64 bit Mach-O files in either byte order, then of
+/// universal Mach-O files
+const OBJECT_MAGIC: &[&[u8]] = &[
+    b"\x7fELF",
+    &[0xfe, 0xed, 0xfa, 0xce],
+    &[0xfe, 0xed, 0xfa, 0xcf],
+    &[0xce, 0xfa, 0xed, 0xfe],
+    &[0xcf, 0xfa, 0xed, 0xfe],
+    &[0xca, 0xfe, 0xba, 0xbe],
+];
+
+/// `contents` run through `strip` with `flags`, or as they are if they are not an ELF or Mach-O
+/// file
+///
+/// `strip` can't read stdin, so `contents` are written to a temporary file first.
+fn strip(contents: Vec<u8>, flags: &[&str]) -> io::Result<Vec<u8>> {
+    if !OBJECT_MAGIC.iter().any(|magic| contents.starts_with(magic)) {
+        return Ok(contents);
+    }
+
+    let program = env::var_os("STRIP").unwrap_or("strip".into());
+    let input = env::temp_dir().join(format!("rumkinst-strip-{}", nanoid!()));
+    let output = input.with_extension("out");
+    let result = fs::write(&input, &contents).and_then(|()| {
+        let status = Command::new(&program)
+            .args(flags)
+            .arg("-o")
+            .arg(&output)
+            .arg(&input)
+            .status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "{} failed, {status}",
+                program.to_string_lossy()
+            )));
+        }
+        fs::read(&output)
+    });
+    fs::remove_file(&input).ok();
+    fs::remove_file(&output).ok();
+    result
+}
+
+/// `contents` with every line replaced by `map`, which is given the line and its line ending
+fn map_lines(contents: &[u8], map: impl Fn(&[u8], &[u8]) -> Vec<u8>) -> Vec<u8> {
+    let mut mapped = Vec::with_capacity(contents.len());
+    for line in contents.split_inclusive(|byte| *byte == b'\n') {
+        let ending = match line {
+            [.., b'\r', b'\n'] => 2,
+            [.., b'\n'] => 1,
+            _ => 0,
+        };
+        let (line, ending) = line.split_at(line.len() - ending);
+        mapped.extend(map(line, ending));
+    }
+    mapped
+}
+
+/// The output of `run`, given `contents` on stdin
+fn run_command(run: &[String], contents: Vec<u8>, base: &Path, path: &Path) -> io::Result<Vec<u8>> {
+    let (program, args) = run
+        .split_first()
+        .expect("transform commands should always name a program");
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .env("RUMKINST_TRANSFORM_PATH", path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped());
+    if !base.as_os_str().is_empty() {
+        command.current_dir(base);
+    }
+    let mut child = command.spawn()?;
+
+    let mut stdin = child
+        .stdin
+        .take()
+        .expect("stdin of the command should be piped");
+    let mut stdout = child
+        .stdout
+        .take()
+        .expect("stdout of the command should be piped");
+    // Writing on another thread, as the command may fill stdout before reading all of stdin
+    let output = thread::scope(|scope| {
+        let writer = scope.spawn(move || match stdin.write_all(&contents) {
+            // The command does not need all of the file
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            result => result,
+        });
+        let mut output = vec![];
+        stdout.read_to_end(&mut output)?;
+        writer
+            .join()
+            .expect("writing to a transform command should never panic")?;
+        Ok::<_, io::Error>(output)
+    });
+    let status = child.wait()?;
+    let output = output?;
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "transform `{}` failed, {status}",
+            run.join(" ")
+        )));
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_lines_splits_off_each_line_ending() {
+        let lines = std::cell::RefCell::new(vec![]);
+        let mapped = map_lines(b"a\r\nb\nc", |line, ending| {
+            lines.borrow_mut().push((line.to_vec(), ending.to_vec()));
+            [line, ending].concat()
+        });
+        assert_eq!(mapped, b"a\r\nb\nc");
+        assert_eq!(
+            lines.into_inner(),
+            [
+                (b"a".to_vec(), b"\r\n".to_vec()),
+                (b"b".to_vec(), b"\n".to_vec()),
+                (b"c".to_vec(), b"".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn map_lines_keeps_empty_lines_and_lone_carriage_returns() {
+        let upper = |line: &[u8], ending: &[u8]| [&line.to_ascii_uppercase(), ending].concat();
+        assert_eq!(map_lines(b"", upper), b"");
+        assert_eq!(map_lines(b"\n\na\rb\n", upper), b"\n\nA\rB\n");
+    }
+}
@@ -0,0 +1,317 @@
+//! WebAssembly hooks, sandboxed build steps set in `[[wasm-hooks]]`
+//!
+//! A hook is a core WebAssembly module that exports its `memory` and a `run` function taking
+//! nothing and returning an `i32`, anything but `0` failing the build. It is run once the sources
+//! are found, without WASI, so it can only reach the build through these imports from the
+//! `rumkinst` module, where strings are UTF-8 passed as a pointer and length into its memory:
+//!
+//! - `source_count() -> i32`: number of source files
+//! - `source_name(index: i32, ptr: i32, len: i32) -> i32`: writes as much of the entry name of a
+//!   source file as fits, and returns its full length
+//! - `source_read(index: i32, offset: i64, ptr: i32, len: i32) -> i32`: reads a source file as it
+//!   is on disk, before any transforms, from `offset`, and returns how many bytes were read, `0`
+//!   at its end
+//! - `options(ptr: i32, len: i32) -> i32`: writes as much of the `options` of the hook as JSON as
+//!   fits, and returns its full length
+//! - `emit_file(name_ptr: i32, name_len: i32, data_ptr: i32, data_len: i32, mode: i32)`: archives
+//!   a file after every other file, with the unix permissions `mode`
+//! - `append_metadata(key_ptr: i32, key_len: i32, value_ptr: i32, value_len: i32)`: adds a value
+//!   to the hook's section of `hooks` in `metadata.json`
+//! - `log(level: i32, ptr: i32, len: i32)`: logs a message, at `0` error to `4` trace
+//!
+//! Calls with invalid arguments, such as an index past the last source file, stop the hook.
+
+use std::collections::BTreeMap;
+
+use super::RumkinstFiles;
+use crate::{RumkinstError, config::Config};
+
+/// Fuel a hook may use up when `fuel` is not set, enough for a few seconds of work
+pub(crate) const DEFAULT_WASM_FUEL: u64 = 10_000_000_000;
+
+/// Metadata appended by the hooks, by hook name
+pub(crate) type HookMetadata = BTreeMap<String, BTreeMap<String, String>>;
+
+/// Fail if `config` sets `[[wasm-hooks]]` but rumkinst was built without the `wasm` feature
+pub(crate) fn check_wasm_hooks(config: &Config) -> Result<(), RumkinstError> {
+    if cfg!(not(feature = "wasm")) && !config.wasm_hooks.is_empty() {
+        return Err(RumkinstError::FeatureDisabled {
+            feature: "wasm",
+            needed_by: "`[[wasm-hooks]]`",
+        });
+    }
+    Ok(())
+}
+
+impl RumkinstFiles {
+    /// Run every hook in `[[wasm-hooks]]` in order, archiving the files they emit after every
+    /// other file, and return the metadata they appended
+    #[cfg(not(feature = "wasm"))]
+    pub(crate) fn run_wasm_hooks(
+        &mut self,
+        config: &Config,
+    ) -> Result<HookMetadata, RumkinstError> {
+        check_wasm_hooks(config)?;
+        Ok(HookMetadata::new())
+    }
+
+    /// Run every hook in `[[wasm-hooks]]` in order, archiving the files they emit after every
+    /// other file, and return the metadata they appended
+    #[cfg(feature = "wasm")]
+    pub(crate) fn run_wasm_hooks(
+        &mut self,
+        config: &Config,
+    ) -> Result<HookMetadata, RumkinstError> {
+        let mut metadata = HookMetadata::new();
+        if config.wasm_hooks.is_empty() {
+            return Ok(metadata);
+        }
+
+        let sources = self
+            .all_files()
+            .map(|path| (super::entry_name(self.entry(path)), self.resolve(path)))
+            .collect::<Vec<_>>();
+        for hook in &config.wasm_hooks {
+            let name = hook.module.file_stem().map_or_else(
+                || "hook".to_string(),
+                |stem| stem.to_string_lossy().into_owned(),
+            );
+            log::info!("Running wasm hook `{name}`");
+            let state = host::run(config, hook, &name, sources.clone()).map_err(|source| {
+                RumkinstError::WasmHook {
+                    hook: name.clone(),
+                    source: source.into(),
+                }
+            })?;
+
+            for file in state.emitted {
+                self.check_generated_entry(&file.name, "`[[wasm-hooks]]`")?;
+                log::debug!("wasm hook `{name}` archived {:?}", file.name);
+                self.generated.push(super::GeneratedFile {
+                    entry: file.name.split('/').collect(),
+                    mtime: super::build_info::build_time(),
+                    mode: file.mode,
+                    contents: file.contents,
+                });
+            }
+            metadata.entry(name).or_default().extend(state.metadata);
+        }
+        Ok(metadata)
+    }
+}
+
+#[cfg(feature = "wasm")]
+mod host {
+    use std::{
+        collections::BTreeMap,
+        fs::File,
+        io::{Read, Seek, SeekFrom},
+        path::PathBuf,
+    };
+
+    use wasmtime::{
+        Caller, Engine, Error, Extern, Linker, Memory, Module, Result, Store, StoreLimits,
+        StoreLimitsBuilder,
+    };
+
+    use crate::config::{Config, WasmHookConfig};
+
+    /// Most memory a hook may grow to
+    const MAX_MEMORY: usize = 1 << 30;
+
+    /// A file emitted by a hook
+    pub(super) struct EmittedFile {
+        /// Entry name, with `/` between its parts
+        pub(super) name: String,
+        pub(super) mode: u32,
+        pub(super) contents: Vec<u8>,
+    }
+
+    /// What a hook can see and what it did, kept in its store
+    pub(super) struct HostState {
+        hook: String,
+        /// Entry names and paths on disk of the source files
+        sources: Vec<(String, PathBuf)>,
+        options: Vec<u8>,
+        pub(super) emitted: Vec<EmittedFile>,
+        pub(super) metadata: BTreeMap<String, String>,
+        limits: StoreLimits,
+    }
+
+    /// Compile and run the hook configured by `hook`, which is called `name`
+    pub(super) fn run(
+        config: &Config,
+        hook: &WasmHookConfig,
+        name: &str,
+        sources: Vec<(String, PathBuf)>,
+    ) -> Result<HostState> {
+        let mut engine_config = wasmtime::Config::new();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config)?;
+        let module = Module::from_file(&engine, config.resolve(&hook.module))?;
+
+        let state = HostState {
+            hook: name.to_string(),
+            sources,
+            options: serde_json::to_vec(&hook.options)?,
+            emitted: vec![],
+            metadata: BTreeMap::new(),
+            limits: StoreLimitsBuilder::new().memory_size(MAX_MEMORY).build(),
+        };
+        let mut store = Store::new(&engine, state);
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(hook.fuel)?;
+
+        let linker = linker(&engine)?;
+        let instance = linker.instantiate(&mut store, &module)?;
+        let run = instance.get_typed_func::<(), i32>(&mut store, "run")?;
+        let status = run.call(&mut store, ())?;
+        if status != 0 {
+            return Err(Error::msg(format!("`run` returned {status}")));
+        }
+        Ok(store.into_data())
+    }
+
+    /// The `rumkinst` imports a hook may use
+    fn linker(engine: &Engine) -> Result<Linker<HostState>> {
+        let mut linker = Linker::new(engine);
+        linker.func_wrap(
+            "rumkinst",
+            "source_count",
+            |caller: Caller<'_, HostState>| -> Result<i32> {
+                Ok(i32::try_from(caller.data().sources.len())?)
+            },
+        )?;
+        linker.func_wrap(
+            "rumkinst",
+            "source_name",
+            |mut caller: Caller<'_, HostState>, index: i32, ptr: i32, len: i32| -> Result<i32> {
+                let name = source(&caller, index)?.0.clone();
+                write_bytes(&mut caller, ptr, len, name.as_bytes())
+            },
+        )?;
+        linker.func_wrap(
+            "rumkinst",
+            "source_read",
+            |mut caller: Caller<'_, HostState>,
+             index: i32,
+             offset: i64,
+             ptr: i32,
+             len: i32|
+             -> Result<i32> {
+                let path = source(&caller, index)?.1.clone();
+                let mut file = File::open(&path)?;
+                file.seek(SeekFrom::Start(u64::try_from(offset)?))?;
+                let mut contents = vec![];
+                file.take(u64::try_from(len)?).read_to_end(&mut contents)?;
+                write_bytes(&mut caller, ptr, len, &contents)
+            },
+        )?;
+        linker.func_wrap(
+            "rumkinst",
+            "options",
+            |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<i32> {
+                let options = caller.data().options.clone();
+                write_bytes(&mut caller, ptr, len, &options)
+            },
+        )?;
+        linker.func_wrap(
+            "rumkinst",
+            "emit_file",
+            |mut caller: Caller<'_, HostState>,
+             name_ptr: i32,
+             name_len: i32,
+             data_ptr: i32,
+             data_len: i32,
+             mode: i32|
+             -> Result<()> {
+                let name = read_string(&mut caller, name_ptr, name_len)?;
+                let relative = name
+                    .split('/')
+                    .all(|part| !matches!(part, "" | "." | "..") && !part.contains('\\'));
+                if !relative {
+                    return Err(Error::msg(format!(
+                        "emitted file {name:?} is not a relative path"
+                    )));
+                }
+                let contents = read_bytes(&mut caller, data_ptr, data_len)?;
+                caller.data_mut().emitted.push(EmittedFile {
+                    name,
+                    mode: mode as u32 & 0o7777,
+                    contents,
+                });
+                Ok(())
+            },
+        )?;
+        linker.func_wrap(
+            "rumkinst",
+            "append_metadata",
+            |mut caller: Caller<'_, HostState>,
+             key_ptr: i32,
+             key_len: i32,
+             value_ptr: i32,
+             value_len: i32|
+             -> Result<()> {
+                let key = read_string(&mut caller, key_ptr, key_len)?;
+                let value = read_string(&mut caller, value_ptr, value_len)?;
+                caller.data_mut().metadata.insert(key, value);
+                Ok(())
+            },
+        )?;
+        linker.func_wrap(
+            "rumkinst",
+            "log",
+            |mut caller: Caller<'_, HostState>, level: i32, ptr: i32, len: i32| -> Result<()> {
+                let message = read_string(&mut caller, ptr, len)?;
+                let level = match level {
+                    0 => log::Level::Error,
+                    1 => log::Level::Warn,
+                    3 => log::Level::Debug,
+                    4 => log::Level::Trace,
+                    _ => log::Level::Info,
+                };
+                log::log!(level, "wasm hook `{}`: {message}", caller.data().hook);
+                Ok(())
+            },
+        )?;
+        Ok(linker)
+    }
+
+    /// The source file at `index`
+    fn source<'a>(caller: &'a Caller<'_, HostState>, index: i32) -> Result<&'a (String, PathBuf)> {
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| caller.data().sources.get(index))
+            .ok_or_else(|| Error::msg(format!("there is no source file {index}")))
+    }
+
+    fn memory(caller: &mut Caller<'_, HostState>) -> Result<Memory> {
+        caller
+            .get_export("memory")
+            .and_then(Extern::into_memory)
+            .ok_or_else(|| Error::msg("the module does not export its `memory`"))
+    }
+
+    fn read_bytes(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32) -> Result<Vec<u8>> {
+        let mut bytes = vec![0; usize::try_from(len)?];
+        // Pointers into a 32 bit memory are unsigned
+        memory(caller)?.read(&*caller, ptr as u32 as usize, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    fn read_string(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32) -> Result<String> {
+        Ok(String::from_utf8(read_bytes(caller, ptr, len)?)?)
+    }
+
+    /// Write as much of `bytes` as fits in `len` bytes at `ptr`, and return the length of `bytes`
+    fn write_bytes(
+        caller: &mut Caller<'_, HostState>,
+        ptr: i32,
+        len: i32,
+        bytes: &[u8],
+    ) -> Result<i32> {
+        let fits = bytes.len().min(usize::try_from(len)?);
+        memory(caller)?.write(&mut *caller, ptr as u32 as usize, &bytes[..fits])?;
+        Ok(i32::try_from(bytes.len())?)
+    }
+}
@@ -0,0 +1,193 @@
+//! Extended attributes and POSIX ACLs of source files, archived as PAX records
+//!
+//! Attributes are stored the way GNU tar and bsdtar store them, as `SCHILY.xattr.<name>` records
+//! holding the raw value, and ACLs as a `SCHILY.acl.access` record holding their text form, so
+//! `tar --xattrs --acls` restores them too.
+
+use std::{io, path::Path};
+
+/// Prefix of the PAX record keys holding an extended attribute
+const XATTR_PREFIX: &str = "SCHILY.xattr.";
+
+/// PAX record key holding the access ACL in its text form
+const ACL_KEY: &str = "SCHILY.acl.access";
+
+/// Extended attribute the Linux kernel keeps the access ACL of a file in
+#[cfg(unix)]
+const ACL_XATTR: &str = "system.posix_acl_access";
+
+/// Which extended attributes of source files are archived, set in `[build]`
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct XattrCapture {
+    pub(crate) xattrs: bool,
+    pub(crate) acls: bool,
+}
+
+/// A PAX record key and its value
+pub(crate) type PaxRecord = (String, Vec<u8>);
+
+/// The PAX records for the extended attributes of the file at `path` that `capture` asks for
+///
+/// Filesystems without extended attributes, and platforms other than unix, have none.
+pub(crate) fn read_xattrs(path: &Path, capture: XattrCapture) -> io::Result<Vec<PaxRecord>> {
+    if !capture.xattrs && !capture.acls {
+        return Ok(vec![]);
+    }
+    #[cfg(unix)]
+    {
+        let names = match xattr::list(path) {
+            Ok(names) => names,
+            Err(err) if err.kind() == io::ErrorKind::Unsupported => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+        let mut records = vec![];
+        for name in names {
+            let Some(name) = name.to_str().map(str::to_string) else {
+                log::debug!("leaving out extended attribute {name:?} of {path:?}, not UTF-8");
+                continue;
+            };
+            let Some(value) = xattr::get(path, &name)? else {
+                continue;
+            };
+            if name == ACL_XATTR {
+                if capture.acls {
+                    let text = acl_to_text(&value).ok_or_else(|| {
+                        io::Error::new(io::ErrorKind::InvalidData, "ACL could not be read")
+                    })?;
+                    records.push((ACL_KEY.to_string(), text.into_bytes()));
+                }
+            } else if capture.xattrs && !name.starts_with("system.") {
+                records.push((format!("{XATTR_PREFIX}{name}"), value));
+            }
+        }
+        records.sort();
+        Ok(records)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Ok(vec![])
+    }
+}
+
+/// `records` as the contents of a PAX extended header
+pub(crate) fn pax_header(records: &[PaxRecord]) -> Vec<u8> {
+    let mut header = vec![];
+    for (key, value) in records {
+        // Each record starts with its own length in decimal, which counts its own digits too
+        let rest = key.len() + value.len() + 3;
+        let mut length = rest + 1;
+        while length != rest + length.to_string().len() {
+            length = rest + length.to_string().len();
+        }
+        header.extend_from_slice(format!("{length} {key}=").as_bytes());
+        header.extend_from_slice(value);
+        header.push(b'\n');
+    }
+    header
+}
+
+/// Restore the extended attributes and ACL in `records` onto the file at `path`, as far as the
+/// platform and filesystem allow
+///
+/// Attributes that can't be set, such as `security.capability` without root, are only logged.
+pub(crate) fn restore_xattrs(path: &Path, records: &[PaxRecord]) {
+    #[cfg(unix)]
+    for (key, value) in records {
+        let (name, value) = match key.as_str() {
+            ACL_KEY => match std::str::from_utf8(value).ok().and_then(acl_from_text) {
+                Some(acl) => (ACL_XATTR, acl),
+                None => {
+                    log::debug!("leaving out the ACL of {path:?}, it could not be read");
+                    continue;
+                }
+            },
+            key => match key.strip_prefix(XATTR_PREFIX) {
+                Some(name) => (name, value.clone()),
+                None => continue,
+            },
+        };
+        if let Err(err) = xattr::set(path, name, &value) {
+            log::debug!("could not restore extended attribute `{name}` of {path:?}: {err}");
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = (path, records);
+}
+
+/// Tags of the entries of a Linux ACL, and their names in its text form
+#[cfg(unix)]
+const ACL_TAGS: [(u16, &str); 6] = [
+    (0x01, "user"),
+    (0x02, "user"),
+    (0x04, "group"),
+    (0x08, "group"),
+    (0x10, "mask"),
+    (0x20, "other"),
+];
+
+/// Version of the Linux ACL xattr format
+#[cfg(unix)]
+const ACL_VERSION: u32 = 2;
+
+/// The Linux ACL xattr `acl` in the text form of `setfacl`, with numeric ids such as
+/// `user::rw-,user:1000:r--,group::r--,mask::r--,other::r--`
+#[cfg(unix)]
+fn acl_to_text(acl: &[u8]) -> Option<String> {
+    let (version, entries) = acl.split_first_chunk::<4>()?;
+    if u32::from_le_bytes(*version) != ACL_VERSION || entries.len() % 8 != 0 {
+        return None;
+    }
+    let entries = entries
+        .chunks_exact(8)
+        .map(|entry| {
+            let tag = u16::from_le_bytes([entry[0], entry[1]]);
+            let perm = u16::from_le_bytes([entry[2], entry[3]]);
+            let id = u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]);
+            let (_, name) = ACL_TAGS.iter().find(|(known, _)| *known == tag)?;
+            // Only the named user and group entries have an id
+            let id = match tag {
+                0x02 | 0x08 => id.to_string(),
+                _ => String::new(),
+            };
+            let perms = [(4, 'r'), (2, 'w'), (1, 'x')]
+                .map(|(bit, ch)| if perm & bit != 0 { ch } else { '-' })
+                .iter()
+                .collect::<String>();
+            Some(format!("{name}:{id}:{perms}"))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(entries.join(","))
+}
+
+/// The Linux ACL xattr for the text form written by [`acl_to_text`]
+#[cfg(unix)]
+fn acl_from_text(text: &str) -> Option<Vec<u8>> {
+    let mut acl = ACL_VERSION.to_le_bytes().to_vec();
+    for entry in text.split([',', '\n']).filter(|entry| !entry.is_empty()) {
+        let mut parts = entry.split(':');
+        let (name, id, perms) = (parts.next()?, parts.next()?, parts.next()?);
+        let named = !id.is_empty();
+        let (tag, _) = ACL_TAGS
+            .iter()
+            .find(|(tag, known)| *known == name && matches!(tag, 0x02 | 0x08) == named)?;
+        let id = match named {
+            true => id.parse::<u32>().ok()?,
+            false => u32::MAX,
+        };
+        let perm = perms
+            .chars()
+            .map(|ch| match ch {
+                'r' => Some(4),
+                'w' => Some(2),
+                'x' => Some(1),
+                '-' => Some(0),
+                _ => None,
+            })
+            .sum::<Option<u16>>()?;
+        acl.extend_from_slice(&tag.to_le_bytes());
+        acl.extend_from_slice(&perm.to_le_bytes());
+        acl.extend_from_slice(&id.to_le_bytes());
+    }
+    Some(acl)
+}
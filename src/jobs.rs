@@ -0,0 +1,53 @@
+use std::{
+    collections::VecDeque,
+    num::NonZeroUsize,
+    sync::Mutex,
+    thread::{self, available_parallelism},
+};
+
+/// Work that can run on any thread, borrowing from the caller for `'a`
+pub(crate) type Task<'a, T> = Box<dyn FnOnce() -> T + Send + 'a>;
+
+/// Threads used when neither the options nor the config set `jobs`
+pub(crate) fn default_jobs() -> NonZeroUsize {
+    available_parallelism().unwrap_or(NonZeroUsize::MIN)
+}
+
+/// Run every task on at most `jobs` threads, returning their results in the order of `tasks`
+///
+/// With a single job or a single task everything runs on the calling thread.
+pub(crate) fn run_tasks<'a, T: Send>(jobs: NonZeroUsize, tasks: Vec<Task<'a, T>>) -> Vec<T> {
+    let workers = jobs.get().min(tasks.len());
+    if workers <= 1 {
+        return tasks.into_iter().map(|task| task()).collect();
+    }
+
+    let queue = Mutex::new(tasks.into_iter().enumerate().collect::<VecDeque<_>>());
+    let results = Mutex::new(Vec::new());
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                loop {
+                    let next = queue
+                        .lock()
+                        .expect("task queue lock should never be poisoned")
+                        .pop_front();
+                    let Some((index, task)) = next else {
+                        break;
+                    };
+                    let result = task();
+                    results
+                        .lock()
+                        .expect("task results lock should never be poisoned")
+                        .push((index, result));
+                }
+            });
+        }
+    });
+
+    let mut results = results
+        .into_inner()
+        .expect("task results lock should never be poisoned");
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
@@ -1,4 +1,37 @@
+mod build;
+pub mod cache;
+mod cancel;
+mod changelog;
+mod command_source;
 pub mod config;
+mod confirm;
+mod download;
+mod environment;
+mod error;
 pub mod error_log;
+pub mod i18n;
 pub mod installer_gen;
+mod jobs;
+mod lint;
+mod lockfile;
+mod plugin;
 pub mod progress_log;
+mod publish;
+mod secrets;
+mod vcs;
+
+pub use build::{
+    ArchiveArtifact, BuildOptions, BuildOutput, BuildStats, PhaseTiming, StreamedArchive,
+    TargetArchive, build, publish, write_archive,
+};
+#[cfg(feature = "tokio")]
+pub use build::{build_async, write_archive_async};
+pub use cancel::CancelHandle;
+pub use confirm::{AssumeYes, Confirm};
+pub use environment::{BuildEnvironment, capture_environment};
+pub use error::RumkinstError;
+pub use lint::{LintFinding, lint};
+pub use lockfile::{LOCKFILE_NAME, LockfileMode};
+pub use plugin::PluginArtifact;
+pub use secrets::redact;
+pub use vcs::VcsInfo;
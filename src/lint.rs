@@ -0,0 +1,287 @@
+use std::{
+    collections::HashSet,
+    fs,
+    io::Read,
+    path::{Component, Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{
+    config::{Config, LintLevel, LintRule, SourceConfig},
+    error_log::{Diagnostics, ErrorCode},
+    installer_gen::{RumkinstFiles, entry_name},
+};
+
+/// Scripts with these extensions are run by an interpreter chosen from the extension, so they
+/// need no `#!` line
+const NO_SHEBANG_EXTENSIONS: [&str; 3] = ["ps1", "bat", "cmd"];
+
+/// A place where the config or its source directories break a [`LintRule`]
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct LintFinding {
+    pub rule: LintRule,
+    /// Level of the rule in `[lint]`, never [`LintLevel::Allow`]
+    pub level: LintLevel,
+    pub message: String,
+    /// The file or directory the finding is about, relative to the base directory
+    pub path: PathBuf,
+}
+
+/// Check `config` and its source directories against every rule not allowed in `[lint]`
+///
+/// Only what is on disk is looked at, so nothing is downloaded, run or built.
+pub fn lint(config: &Config) -> Vec<LintFinding> {
+    let mut findings = vec![];
+    let mut find = |rule: LintRule, path: &Path, message: String| {
+        let level = config.lint.level(rule);
+        if level != LintLevel::Allow {
+            findings.push(LintFinding {
+                rule,
+                level,
+                message,
+                path: path.to_path_buf(),
+            });
+        }
+    };
+
+    let installer_scripts = [
+        ("installer.preinstall", &config.installer.preinstall),
+        ("installer.postinstall", &config.installer.postinstall),
+    ];
+    for (key, script) in installer_scripts {
+        let Some(script) = script else {
+            continue;
+        };
+        let resolved = config.resolve(script);
+        if !resolved.is_file() {
+            find(
+                LintRule::ScriptMissing,
+                script,
+                format!("`{key}` is set to {script:?}, which does not exist"),
+            );
+            continue;
+        }
+        if !is_executable(&resolved) {
+            find(
+                LintRule::ScriptNotExecutable,
+                script,
+                format!("`{key}` script {script:?} is not executable"),
+            );
+        }
+        if !has_shebang(&resolved) {
+            find(
+                LintRule::ScriptMissingShebang,
+                script,
+                format!("`{key}` script {script:?} does not start with a `#!` line"),
+            );
+        }
+    }
+
+    // Build hooks are optional, so only the ones that exist are checked
+    let hooks = [
+        ("build.prebuild", &config.build.prebuild),
+        ("build.postbuild", &config.build.postbuild),
+    ];
+    for (key, hook) in hooks {
+        let resolved = config.resolve(hook);
+        if resolved.is_file() && !has_shebang(&resolved) {
+            find(
+                LintRule::ScriptMissingShebang,
+                hook,
+                format!("`{key}` hook {hook:?} does not start with a `#!` line"),
+            );
+        }
+    }
+
+    let sources = [
+        ("root", &config.root),
+        ("env", &config.env),
+        ("scripts", &config.scripts),
+    ]
+    .into_iter()
+    .filter(|(_, source)| !source.disable)
+    .collect::<Vec<_>>();
+    for (name, source) in &sources {
+        for exclude in source.exclude() {
+            if fs::symlink_metadata(config.resolve(exclude)).is_err() {
+                find(
+                    LintRule::ExcludeUnused,
+                    exclude,
+                    format!(
+                        "exclude {exclude:?} of `[{name}]` does not exist, so it excludes nothing"
+                    ),
+                );
+            }
+        }
+    }
+    for (i, (name, source)) in sources.iter().enumerate() {
+        for (other_name, other) in &sources[..i] {
+            let (inner, outer) = match (overlaps(source, other), overlaps(other, source)) {
+                (true, _) => ((name, source), (other_name, other)),
+                (_, true) => ((other_name, other), (name, source)),
+                _ => continue,
+            };
+            find(
+                LintRule::SourceOverlap,
+                inner.1.path(),
+                format!(
+                    "`[{}]` at {:?} is inside `[{}]` at {:?}, so its files are packaged twice",
+                    inner.0,
+                    inner.1.path(),
+                    outer.0,
+                    outer.1.path()
+                ),
+            );
+        }
+    }
+
+    findings
+}
+
+/// Warn about every build hook that would fail to run, like [`preflight_scripts`], before any of
+/// them runs
+pub(crate) fn preflight_hooks(config: &Config, diagnostics: &Diagnostics) {
+    let hooks = [
+        ("build.prebuild", &config.build.prebuild),
+        ("build.postbuild", &config.build.postbuild),
+    ];
+    for (key, hook) in hooks {
+        check_on_disk(config, hook, &format!("`{key}` hook {hook:?}"), diagnostics);
+    }
+}
+
+/// Warn about every packaged script that would fail to run because it is not executable, has no
+/// `#!` line or has CRLF line endings, which breaks the `#!` line and every command in it
+///
+/// Files in the `scripts` source are checked as they are archived, after their transforms and
+/// with their archived permissions. Installer scripts outside of it are checked on disk.
+pub(crate) fn preflight_scripts(config: &Config, files: &RumkinstFiles, diagnostics: &Diagnostics) {
+    let mut packaged = HashSet::new();
+    for path in files.script_files() {
+        packaged.insert(normalized(&files.resolve(path)));
+        if !needs_shebang(path) {
+            continue;
+        }
+        // Files that can't be read fail the build when they are archived instead
+        let Ok(mut file) = files.open(path) else {
+            continue;
+        };
+        let mut contents = vec![];
+        if file.contents.read_to_end(&mut contents).is_err() {
+            continue;
+        }
+        let name = entry_name(files.entry(path));
+        check_script(
+            path,
+            &format!("packaged script `{name}`"),
+            file.mode & 0o111 != 0,
+            &contents,
+            diagnostics,
+        );
+    }
+
+    let installer_scripts = [
+        ("installer.preinstall", &config.installer.preinstall),
+        ("installer.postinstall", &config.installer.postinstall),
+    ];
+    for (key, script) in installer_scripts {
+        if let Some(script) = script
+            && !packaged.contains(&normalized(&config.resolve(script)))
+        {
+            check_on_disk(
+                config,
+                script,
+                &format!("`{key}` script {script:?}"),
+                diagnostics,
+            );
+        }
+    }
+}
+
+/// Warn about the problems of the script at `path` relative to the base directory, if it exists
+fn check_on_disk(config: &Config, path: &Path, name: &str, diagnostics: &Diagnostics) {
+    let resolved = config.resolve(path);
+    if !needs_shebang(&resolved) {
+        return;
+    }
+    if let Ok(contents) = fs::read(&resolved) {
+        check_script(path, name, is_executable(&resolved), &contents, diagnostics);
+    }
+}
+
+/// Warn about the problems of the script at `path`, called `name` in the warnings
+fn check_script(
+    path: &Path,
+    name: &str,
+    executable: bool,
+    contents: &[u8],
+    diagnostics: &Diagnostics,
+) {
+    if !executable {
+        diagnostics.warn(
+            ErrorCode::SCRIPT_NOT_EXECUTABLE.at(path),
+            format!("{name} is not executable, run `chmod +x` on {path:?}"),
+        );
+    }
+    if !contents.starts_with(b"#!") {
+        diagnostics.warn(
+            ErrorCode::SCRIPT_SHEBANG.at(path),
+            format!("{name} does not start with a `#!` line naming its interpreter"),
+        );
+    }
+    if contents.windows(2).any(|pair| pair == b"\r\n") {
+        diagnostics.warn(
+            ErrorCode::SCRIPT_CRLF.at(path),
+            format!(
+                "{name} has CRLF line endings, which break its `#!` line and commands when it \
+                 runs. Convert it to LF, or add an `lf` transform for it"
+            ),
+        );
+    }
+}
+
+/// Whether `path` is not run by an interpreter chosen from its extension, so it needs a `#!`
+/// line to be run
+fn needs_shebang(path: &Path) -> bool {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+    !extension.is_some_and(|extension| NO_SHEBANG_EXTENSIONS.contains(&extension.as_str()))
+}
+
+/// Whether `source` is inside `other`, or both are the same directory
+fn overlaps(source: &SourceConfig, other: &SourceConfig) -> bool {
+    normalized(source.path()).starts_with(normalized(other.path()))
+}
+
+fn normalized(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| *component != Component::CurDir)
+        .collect()
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path).is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+}
+
+/// Windows has no executable permission, anything that exists can be run
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> bool {
+    true
+}
+
+/// Whether the script at `path` starts with `#!`, or is run by an interpreter without one
+fn has_shebang(path: &Path) -> bool {
+    if !needs_shebang(path) {
+        return true;
+    }
+    let mut start = [0; 2];
+    fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut start))
+        .is_ok_and(|()| start == *b"#!")
+}
@@ -0,0 +1,59 @@
+use std::fs;
+
+use anyhow::{Context, Result};
+use rumkinst::{
+    RumkinstError,
+    config::{Config, LintLevel, find_config_file_at},
+    error_log::{ErrorCode, Log, WithCode},
+    lint,
+};
+
+use crate::cli::LintArgs;
+
+/// Report every lint finding in the project, failing if any of them are denied
+pub fn command_lint(args: LintArgs) -> Result<()> {
+    let config_path =
+        find_config_file_at(args.path).context("could not find `rumkinst.toml` config file")?;
+    let bytes = fs::read(&config_path)
+        .code_at(ErrorCode::CONFIG_UNREADABLE, &config_path)
+        .with_context(|| format!("failed to read {config_path:?}"))?;
+    let base_dir = config_path
+        .parent()
+        .context("could not find the parent directory of rumkinst.toml")?;
+    let config = Config::read_named(bytes.as_slice(), &config_path.to_string_lossy())
+        .with_context(|| format!("could not read rumkinst config at {config_path:?}"))?
+        .with_base_dir(base_dir);
+
+    let findings = lint(&config);
+    let mut denied = 0;
+    for finding in &findings {
+        let finding_err = Err::<(), _>(
+            anyhow::Error::new(finding.rule.code().at(&finding.path)).context(format!(
+                "{} ({})",
+                finding.message,
+                finding.rule.name()
+            )),
+        );
+        match finding.level {
+            LintLevel::Deny => {
+                denied += 1;
+                finding_err.error().ok();
+            }
+            _ => {
+                finding_err.warn().ok();
+            }
+        }
+    }
+
+    match findings.len() {
+        0 => log::info!("No lint findings"),
+        count => log::info!(
+            "{count} lint finding{}, {denied} denied",
+            if count == 1 { "" } else { "s" }
+        ),
+    }
+    if denied > 0 {
+        return Err(RumkinstError::LintsDenied { count: denied }.into());
+    }
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use std::{
+    fs::{self, File, TryLockError},
+    io::{Read, Seek, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use jiff::Timestamp;
+use rumkinst::{
+    CancelHandle, RumkinstError,
+    error_log::{ErrorCode, WithCode},
+};
+use serde::{Deserialize, Serialize};
+
+/// Name of the lock file kept in `out/` while a run is making artifacts
+const LOCK_FILE_NAME: &str = ".lock";
+
+/// How often a locked project is checked again while waiting for it
+const WAIT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Written into the lock file, so a run that can't take the lock can say who holds it
+#[derive(Serialize, Deserialize)]
+struct LockHolder {
+    pid: u32,
+    /// `None` until the run has picked its run directory
+    run_id: Option<String>,
+    started_at: String,
+}
+
+/// Exclusive hold on a project's `out/` directory, released when dropped
+pub struct ProjectLock {
+    file: File,
+    path: PathBuf,
+    holder: LockHolder,
+}
+
+impl ProjectLock {
+    /// Record the id of the run holding the lock, for runs waiting on it to show
+    pub fn record_run(&mut self, run_id: &str) -> Result<()> {
+        self.holder.run_id = Some(run_id.to_string());
+        write_holder(&mut self.file, &self.holder)
+            .with_context(|| format!("failed to write {:?}", self.path))
+    }
+}
+
+/// Take the lock on `out_root`, so simultaneous runs in one project can't interleave their hooks
+/// and output
+///
+/// If another run holds it, this fails straight away unless `wait` is set, in which case it waits
+/// for that run to finish or for `cancel` to be cancelled.
+pub fn lock_project(out_root: &Path, wait: bool, cancel: &CancelHandle) -> Result<ProjectLock> {
+    fs::create_dir_all(out_root)
+        .code_at(ErrorCode::OUTPUT_DIR, out_root)
+        .with_context(|| format!("failed to create output directory {out_root:?}"))?;
+    let path = out_root.join(LOCK_FILE_NAME);
+    let mut file = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .with_context(|| format!("failed to open lock file {path:?}"))?;
+
+    let mut waiting = false;
+    loop {
+        match file.try_lock() {
+            Ok(()) => break,
+            Err(TryLockError::WouldBlock) => {}
+            Err(TryLockError::Error(err)) => {
+                return Err(err).with_context(|| format!("failed to lock {path:?}"));
+            }
+        }
+
+        let holder = describe_holder(&mut file);
+        if !wait {
+            return Err(ErrorCode::PROJECT_LOCKED.at(&path)).context(format!(
+                "another run ({holder}) is making artifacts in this project, pass --wait to wait \
+                 for it to finish"
+            ));
+        }
+        if !waiting {
+            log::info!("Waiting for another run ({holder}) to finish");
+            waiting = true;
+        }
+        if cancel.is_cancelled() {
+            return Err(RumkinstError::Cancelled.into());
+        }
+        thread::sleep(WAIT_INTERVAL);
+    }
+
+    let holder = LockHolder {
+        pid: std::process::id(),
+        run_id: None,
+        started_at: Timestamp::now().to_string(),
+    };
+    write_holder(&mut file, &holder).with_context(|| format!("failed to write {path:?}"))?;
+    log::debug!("locked {path:?}");
+    Ok(ProjectLock { file, path, holder })
+}
+
+/// Who holds the lock, as recorded in `file` by the run that took it
+fn describe_holder(file: &mut File) -> String {
+    let mut contents = String::new();
+    let holder = file
+        .rewind()
+        .and_then(|()| file.read_to_string(&mut contents))
+        .ok()
+        .and_then(|_| serde_json::from_str::<LockHolder>(&contents).ok());
+    match holder {
+        Some(LockHolder {
+            pid,
+            run_id: Some(run_id),
+            started_at,
+        }) => format!("run \"{run_id}\", process {pid}, started at {started_at}"),
+        Some(LockHolder {
+            pid, started_at, ..
+        }) => format!("process {pid}, started at {started_at}"),
+        None => "unknown holder".to_string(),
+    }
+}
+
+fn write_holder(file: &mut File, holder: &LockHolder) -> std::io::Result<()> {
+    file.set_len(0)?;
+    file.rewind()?;
+    serde_json::to_writer(&mut *file, holder)?;
+    file.write_all(b"\n")?;
+    file.flush()
+}
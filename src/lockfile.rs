@@ -0,0 +1,302 @@
+//! `rumkinst.lock`, the exact inputs a build packaged, so later builds can be held to them
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    CancelHandle, RumkinstError,
+    config::Config,
+    download::FetchedFile,
+    installer_gen::{Digester, RumkinstFiles, entry_name, to_hex},
+    jobs::{Task, run_tasks},
+    progress_log::NoProgress,
+};
+
+/// Name of the lockfile, next to rumkinst.toml
+pub const LOCKFILE_NAME: &str = "rumkinst.lock";
+
+/// Version of the lockfile format, raised whenever it changes
+const LOCKFILE_VERSION: u32 = 1;
+
+/// Written at the top of the lockfile
+const LOCKFILE_HEADER: &str = "# Written by `rumkinst make`, do not edit it by hand\n\n";
+
+/// Most differences named in the error for a lockfile that does not match
+const SHOWN_DIFFERENCES: usize = 10;
+
+/// What a [`build`](crate::build) does with the `rumkinst.lock` of the project
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LockfileMode {
+    /// Neither read nor write it
+    #[default]
+    Ignore,
+    /// Record what was packaged in it once the build succeeds
+    Update,
+    /// Fail before making any artifact if anything else would be packaged than it records,
+    /// leaving it as it is
+    Locked,
+}
+
+/// Every packaged file with the digest of its contents, and where remote sources were fetched
+/// from
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Lockfile {
+    version: u32,
+    /// Algorithm of every digest of `files`
+    algorithm: String,
+    #[serde(default, rename = "download", skip_serializing_if = "Vec::is_empty")]
+    downloads: Vec<LockedDownload>,
+    #[serde(default, rename = "git", skip_serializing_if = "Vec::is_empty")]
+    git: Vec<LockedGit>,
+    #[serde(default, rename = "file", skip_serializing_if = "Vec::is_empty")]
+    files: Vec<LockedFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+struct LockedDownload {
+    dest: PathBuf,
+    url: String,
+    sha256: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+struct LockedGit {
+    dest: PathBuf,
+    git: String,
+    rev: String,
+    /// The full hash of the commit `rev` resolved to
+    commit: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+struct LockedFile {
+    /// Entry path of the file in archives
+    path: String,
+    digest: String,
+}
+
+impl Lockfile {
+    /// A lockfile pinning the remote sources of `config`, as they were fetched into `fetched`,
+    /// without any files yet
+    pub(crate) fn new(config: &Config, fetched: &[FetchedFile], algorithm: &str) -> Self {
+        let downloads = config
+            .sources
+            .download
+            .iter()
+            .map(|download| LockedDownload {
+                dest: download.dest.clone(),
+                url: download.url.clone(),
+                sha256: download.sha256.clone(),
+            })
+            .collect();
+        let commits = fetched
+            .iter()
+            .filter_map(|file| Some((&file.dest, file.commit.as_ref()?)))
+            .collect::<BTreeMap<_, _>>();
+        let git = config
+            .sources
+            .git
+            .iter()
+            .filter_map(|source| {
+                Some(LockedGit {
+                    dest: source.dest.clone(),
+                    git: source.git.clone(),
+                    rev: source.rev.clone(),
+                    commit: commits.get(&source.dest)?.to_string(),
+                })
+            })
+            .collect();
+        Self {
+            version: LOCKFILE_VERSION,
+            algorithm: algorithm.to_string(),
+            downloads,
+            git,
+            files: vec![],
+        }
+    }
+
+    /// Add every file of `files`, with the digest of its contents as they are archived
+    ///
+    /// Files are digested on at most `jobs` threads, each taking an even share of them.
+    pub(crate) fn add_files(
+        &mut self,
+        files: &RumkinstFiles,
+        digester: &dyn Digester,
+        jobs: NonZeroUsize,
+        cancel: &CancelHandle,
+    ) -> Result<(), RumkinstError> {
+        let paths = files.all_files().collect::<Vec<_>>();
+        let share = paths.len().div_ceil(jobs.get()).max(1);
+        let tasks = paths
+            .chunks(share)
+            .map(|paths| {
+                Box::new(move || {
+                    paths
+                        .iter()
+                        .map(|path| {
+                            cancel.check()?;
+                            let read_error = |source| RumkinstError::SourceRead {
+                                path: files.resolve(path),
+                                source,
+                            };
+                            let mut file = files.open(path).map_err(read_error)?;
+                            let digest = digester
+                                .digest(&mut file.contents, &NoProgress)
+                                .map_err(read_error)?;
+                            Ok(LockedFile {
+                                path: entry_name(files.entry(path)),
+                                digest: to_hex(&digest),
+                            })
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                }) as Task<'_, Result<Vec<LockedFile>, RumkinstError>>
+            })
+            .collect();
+        for locked in run_tasks(jobs, tasks) {
+            self.files.extend(locked?);
+        }
+        self.files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(())
+    }
+
+    /// Fail with every difference if the lockfile at `path` records anything else than this one
+    pub(crate) fn check(&self, path: &Path) -> Result<(), RumkinstError> {
+        let read_error =
+            |source: Box<dyn std::error::Error + Send + Sync>| RumkinstError::LockfileRead {
+                path: path.to_path_buf(),
+                source,
+            };
+        let locked = fs::read_to_string(path).map_err(|err| read_error(err.into()))?;
+        let locked = toml::from_str::<Lockfile>(&locked).map_err(|err| read_error(err.into()))?;
+        if locked.version != LOCKFILE_VERSION {
+            return Err(read_error(
+                format!(
+                    "it is version {} of the format, only version {LOCKFILE_VERSION} is read",
+                    locked.version
+                )
+                .into(),
+            ));
+        }
+
+        let differences = self.differences(&locked);
+        if differences.is_empty() {
+            log::debug!("packaged inputs match {path:?}");
+            return Ok(());
+        }
+        Err(RumkinstError::LockfileMismatch {
+            path: path.to_path_buf(),
+            differences,
+        })
+    }
+
+    /// What changed from `locked` to this lockfile, one line per source or file
+    fn differences(&self, locked: &Lockfile) -> Vec<String> {
+        let mut differences = vec![];
+        if self.algorithm != locked.algorithm {
+            differences.push(format!(
+                "digests are {} instead of {}",
+                self.algorithm, locked.algorithm
+            ));
+        }
+
+        let by_dest = |downloads: &[LockedDownload]| {
+            downloads
+                .iter()
+                .map(|download| {
+                    (
+                        download.dest.clone(),
+                        (download.url.clone(), download.sha256.clone()),
+                    )
+                })
+                .collect::<BTreeMap<_, _>>()
+        };
+        differences.extend(changes(
+            by_dest(&locked.downloads),
+            by_dest(&self.downloads),
+            |dest| format!("download to {dest:?}"),
+        ));
+        let by_dest = |git: &[LockedGit]| {
+            git.iter()
+                .map(|source| {
+                    (
+                        source.dest.clone(),
+                        (source.git.clone(), source.commit.clone()),
+                    )
+                })
+                .collect::<BTreeMap<_, _>>()
+        };
+        differences.extend(changes(by_dest(&locked.git), by_dest(&self.git), |dest| {
+            format!("git checkout at {dest:?}")
+        }));
+        let by_path = |files: &[LockedFile]| {
+            files
+                .iter()
+                .map(|file| (file.path.clone(), file.digest.clone()))
+                .collect::<BTreeMap<_, _>>()
+        };
+        differences.extend(changes(
+            by_path(&locked.files),
+            by_path(&self.files),
+            |path| format!("`{path}`"),
+        ));
+
+        if differences.len() > SHOWN_DIFFERENCES {
+            let hidden = differences.len() - SHOWN_DIFFERENCES;
+            differences.truncate(SHOWN_DIFFERENCES);
+            differences.push(format!("{hidden} more"));
+        }
+        differences
+    }
+
+    /// Write the lockfile to `path`, leaving it untouched if it already records the same
+    pub(crate) fn write(&self, path: &Path) -> Result<(), RumkinstError> {
+        let write_error = |source| RumkinstError::LockfileWrite {
+            path: path.to_path_buf(),
+            source,
+        };
+        let contents = toml::to_string_pretty(self).expect("lockfiles should always serialize");
+        let contents = format!("{LOCKFILE_HEADER}{contents}");
+        if fs::read_to_string(path).is_ok_and(|existing| existing == contents) {
+            return Ok(());
+        }
+        let temporary = path.with_extension("lock.tmp");
+        fs::write(&temporary, contents).map_err(write_error)?;
+        fs::rename(&temporary, path).map_err(write_error)?;
+        log::info!("Recorded {} packaged files in {path:?}", self.files.len());
+        Ok(())
+    }
+}
+
+/// What was added, removed and changed from `locked` to `current`, each named by `describe`
+fn changes<K: Ord, V: PartialEq>(
+    mut locked: BTreeMap<K, V>,
+    current: BTreeMap<K, V>,
+    describe: impl Fn(&K) -> String,
+) -> Vec<String> {
+    let mut changes = vec![];
+    for (key, value) in current {
+        match locked.remove(&key) {
+            None => changes.push(format!("{} was added", describe(&key))),
+            Some(locked) if locked != value => {
+                changes.push(format!("{} changed", describe(&key)));
+            }
+            Some(_) => {}
+        }
+    }
+    changes.extend(
+        locked
+            .keys()
+            .map(|key| format!("{} was removed", describe(key))),
+    );
+    changes
+}
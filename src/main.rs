@@ -1,30 +1,93 @@
+mod bump;
+mod cache_command;
 mod cli;
+mod config_command;
+mod git;
+mod history;
+mod install_test;
+mod lint_command;
+mod lock;
+mod metadata;
+mod notify;
+mod plugin_command;
+mod prompt;
+mod retention;
+mod run_dir;
+mod snapshot;
+mod stats;
+mod suggest;
+mod summary;
+mod template;
 
 use std::{
     fs::{self, File},
-    io::{Seek, Write},
+    io::{self, BufWriter, IsTerminal, Write},
     path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+    time::Instant,
 };
 
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
+use bump::bump_version;
+use cache_command::command_cache;
 use clap::{Parser, ValueEnum};
-use cli::{Command, Rumkinst};
-use flate2::{Compression, GzBuilder};
-use nanoid::nanoid;
+use cli::{Command, LogTarget, MakeArgs, OutputFormat, Rumkinst};
+use config_command::command_config;
+use history::{RunHistory, command_history};
+use indicatif::{HumanBytes, MultiProgress};
+use install_test::command_test;
+use lint_command::command_lint;
+use lock::lock_project;
+use metadata::{RunDetails, write_metadata};
+use notify::{Notifier, Outcome, notify};
+use plugin_command::run_plugin_subcommand;
+use prompt::TerminalConfirm;
+use retention::prune_runs;
 use rumkinst::{
+    BuildOptions, CancelHandle, LockfileMode, PhaseTiming, build,
     config::{Config, find_config_file_at, identifier::Identifier},
-    error_log::Log,
-    installer_gen::{RumkinstFiles, find_all_files},
-    progress_log::{progress_wrapper, setup_log_wrapper},
+    error_log::{
+        DiagnosticFormat, Diagnostics, ErrorCode, FailureClass, FatalError, FileErrorPolicy, Log,
+        WithCode, check_skipped_errors, check_warnings, print_summary,
+        report::{record_config, record_run, write_error_report},
+        set_diagnostic_format,
+    },
+    progress_log::{
+        DEFAULT_LOG_FILE_KEEP, DEFAULT_LOG_FILE_SIZE, OverallProgress, Phase, PhaseWeights,
+        ProgressSink, SystemLog, close_run_log, open_log_file, open_run_log, open_system_log,
+        setup_log_wrapper,
+    },
+    publish, tr, write_archive,
 };
-use sha2::{Digest, Sha256};
+use run_dir::{choose_run_id, claim_run_dir, link_latest};
+use snapshot::command_snapshot;
+use stats::command_stats;
+use summary::print_run_summary;
+use template::new_from_template;
+
+fn setup_diagnostics(config: &Rumkinst) {
+    set_diagnostic_format(match config.format {
+        OutputFormat::Human => DiagnosticFormat::Human,
+        OutputFormat::Json => DiagnosticFormat::Json,
+    });
+}
+
+/// A log record under `--format json`, shaped like the diagnostics
+#[derive(serde::Serialize)]
+struct JsonRecord<'a> {
+    severity: &'static str,
+    target: &'a str,
+    message: String,
+}
 
-fn setup_logging(config: &Rumkinst) {
-    let logger = env_logger::Builder::from_env(
+/// Set up the terminal logger, returning the container progress bars are drawn in
+fn setup_logging(config: &Rumkinst) -> MultiProgress {
+    let mut builder = env_logger::Builder::from_env(
         env_logger::Env::default()
             .default_filter_or(
                 config
-                    .log_level
+                    .log_level()
                     .to_possible_value()
                     .expect("log level possible value should never be None")
                     .get_name(),
@@ -36,45 +99,173 @@ fn setup_logging(config: &Rumkinst) {
                     .expect("color display possible value should never be None")
                     .get_name(),
             ),
-    )
-    .build();
+    );
+    // Diagnostics are already written as JSON, every other record follows the same shape
+    if let OutputFormat::Json = config.format {
+        builder.format(|buf, record| {
+            let severity = match record.level() {
+                log::Level::Error => "error",
+                log::Level::Warn => "warning",
+                log::Level::Info => "info",
+                log::Level::Debug => "debug",
+                log::Level::Trace => "trace",
+            };
+            let line = serde_json::to_string(&JsonRecord {
+                severity,
+                target: record.target(),
+                message: record.args().to_string(),
+            })
+            .expect("log records should always serialize to json");
+            writeln!(buf, "{line}")
+        });
+    }
+    let logger = builder.build();
     let filter = logger.filter();
 
-    setup_log_wrapper(logger, filter);
+    setup_log_wrapper(logger, filter)
 }
 
-fn move_to_config_parent(path: &Path) -> Result<()> {
-    log::trace!("moving working directory");
-    std::env::set_current_dir(path.parent().context("could not find parent directory")?)
-        .context("failed to change working directory")?;
-    Ok(())
+fn setup_system_log(config: &Rumkinst) {
+    let system_log = match config.log_target {
+        LogTarget::Terminal => return,
+        LogTarget::Syslog => SystemLog::Syslog,
+        LogTarget::Journald => SystemLog::Journald,
+    };
+    if let Err(err) = open_system_log(system_log) {
+        log::warn!("failed to connect to {system_log:?}, only logging to the terminal: {err}");
+    }
+}
+
+/// Process exit codes, one per [`FailureClass`]. Usage errors exit with `2`, from clap.
+///
+/// | Code  | Meaning                                      |
+/// |-------|----------------------------------------------|
+/// | `0`   | success                                      |
+/// | `1`   | unclassified failure                         |
+/// | `3`   | invalid or missing configuration             |
+/// | `4`   | reading sources or writing artifacts failed  |
+/// | `5`   | a build hook failed                          |
+/// | `6`   | artifact verification failed                 |
+/// | `7`   | warnings were reported with deny-warnings    |
+/// | `130` | aborted by the user                          |
+fn exit_code(class: FailureClass) -> ExitCode {
+    ExitCode::from(match class {
+        FailureClass::Other => 1,
+        FailureClass::Config => 3,
+        FailureClass::Io => 4,
+        FailureClass::Hook => 5,
+        FailureClass::Verification => 6,
+        FailureClass::DeniedWarnings => 7,
+        FailureClass::UserAbort => 130,
+    })
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() -> ExitCode {
     let rumkinst = Rumkinst::parse();
 
-    setup_logging(&rumkinst);
+    let bars = setup_logging(&rumkinst);
+    setup_diagnostics(&rumkinst);
+    setup_system_log(&rumkinst);
+    if let Some(path) = &rumkinst.log_file
+        && let Err(err) = open_log_file(path, DEFAULT_LOG_FILE_SIZE, DEFAULT_LOG_FILE_KEEP)
+    {
+        log::warn!("failed to open log file {path:?}: {err}");
+    }
+    if let Command::Plugin(args) = &rumkinst.subcommand {
+        return run_plugin_subcommand(args);
+    }
 
-    match rumkinst.subcommand {
-        Command::New { name, dir_name } => {
-            command_new(name, PathBuf::from(format!("./{}", dir_name.as_str())))
-                .context("failed to create new rumkinst directory")
-                .fatal()?
-        }
-        Command::Make { path } => command_make(path)
-            .context("failed to make installer artifacts with rumkinst")
-            .fatal()?,
+    match run(rumkinst, bars) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(fatal) => exit_code(fatal.class()),
     }
+}
 
-    Ok(())
+fn run(rumkinst: Rumkinst, bars: MultiProgress) -> Result<(), FatalError> {
+    let diagnostics = Arc::new(Diagnostics::new());
+    // The phase `make` stopped in, for the error report
+    let mut phase = None;
+    let result = match rumkinst.subcommand {
+        Command::New {
+            name,
+            dir_name,
+            template_git,
+        } => {
+            let dir_path = PathBuf::from(format!("./{}", dir_name.as_str()));
+            match template_git {
+                Some(url) => new_from_template(name, dir_path, &url, rumkinst.offline),
+                None => command_new(name, dir_path),
+            }
+            .context(tr!("new-failed"))
+        }
+        Command::Version(args) => bump_version(args).context(tr!("version-failed")),
+        Command::Stats(args) => command_stats(args).context(tr!("stats-failed")),
+        Command::Config(args) => command_config(args),
+        Command::History(args) => command_history(args).context(tr!("history-failed")),
+        Command::Test(args) => command_test(args).context(tr!("test-failed")),
+        Command::Lint(args) => command_lint(args).context(tr!("lint-failed")),
+        Command::Snapshot(args) => command_snapshot(args).context(tr!("snapshot-failed")),
+        Command::Cache(args) => command_cache(args),
+        Command::Plugin(_) => unreachable!("plugin subcommands are run before any other"),
+        Command::Make(args) => {
+            let notifier = args.notify.clone().map(|command| match command {
+                Some(command) => Notifier::Command(command),
+                None => Notifier::Desktop,
+            });
+            let started = Instant::now();
+            let progress = Arc::new(OverallProgress::new(PhaseWeights::default(), bars));
+            let result = command_make(
+                args,
+                rumkinst.offline,
+                rumkinst.log_file,
+                diagnostics.clone(),
+                progress.clone(),
+            );
+            phase = progress.current_phase();
+            print_summary(&diagnostics);
+            if let Some(notifier) = notifier {
+                let outcome = Outcome {
+                    success: result.is_ok(),
+                    elapsed: started.elapsed(),
+                    out_dir: result.as_ref().ok().and_then(Option::as_deref),
+                    error: result.as_ref().err().and_then(|err| {
+                        err.root_cause()
+                            .to_string()
+                            .lines()
+                            .next()
+                            .map(String::from)
+                    }),
+                };
+                notify(&notifier, &outcome);
+            }
+            result.map(|_| ()).context(tr!("make-failed"))
+        }
+    };
+
+    // A cancelled run is not a bug, so there is nothing worth reporting
+    let report = result
+        .as_ref()
+        .err()
+        .filter(|err| ErrorCode::of(err) != Some(ErrorCode::CANCELLED))
+        .and_then(|err| write_error_report(err, phase, &diagnostics));
+    result.fatal().inspect_err(|_| {
+        if let Some(report) = report {
+            log::error!(
+                "{}",
+                tr!("error-report-written", path = format!("{report:?}"))
+            );
+        }
+    })
 }
 
 fn command_new(name: Identifier, dir_path: PathBuf) -> Result<()> {
     log::trace!("running command logic for `new`");
-    log::info!("Creating a new rumkinst directory...");
+    log::info!("{}", tr!("new-creating"));
 
     if dir_path.exists() {
-        anyhow::bail!("cannot create directory at {dir_path:?}, one already exists");
+        return Err(ErrorCode::PROJECT_EXISTS.at(&dir_path)).with_context(|| {
+            format!("cannot create directory at {dir_path:?}, one already exists")
+        });
     }
 
     create_dir_with_context(dir_path.clone())?;
@@ -84,93 +275,317 @@ fn command_new(name: Identifier, dir_path: PathBuf) -> Result<()> {
     create_dir_with_context(dir_path.join("scripts"))?;
 
     let config_file = File::create_new(dir_path.join("rumkinst.toml"))
+        .code(ErrorCode::PROJECT_CREATE)
         .with_context(|| format!("failed to create `rumkinst.toml` inside {dir_path:?}"))?;
 
     Config::write_default(config_file, name)
         .context("failed to write default config to `rumkinst.toml`")?;
 
-    log::info!("Succesfully created new rumkinst directory at {dir_path:?}");
+    log::info!("{}", tr!("new-created", path = format!("{dir_path:?}")));
     Ok(())
 }
 
 fn create_dir_with_context(dir_path: PathBuf) -> Result<()> {
-    fs::create_dir(&dir_path).with_context(|| format!("failed to create directory at {dir_path:?}"))
+    fs::create_dir(&dir_path)
+        .code_at(ErrorCode::PROJECT_CREATE, &dir_path)
+        .with_context(|| format!("failed to create directory at {dir_path:?}"))
 }
 
-fn command_make(path: Option<PathBuf>) -> Result<()> {
+/// Make the installer artifacts of a project, returning the output directory of the run, or
+/// `None` when only the archive was written to stdout
+fn command_make(
+    args: MakeArgs,
+    offline: bool,
+    log_file: Option<PathBuf>,
+    diagnostics: Arc<Diagnostics>,
+    progress: Arc<OverallProgress>,
+) -> Result<Option<PathBuf>> {
     log::trace!("running command logic for `make`");
-    let config_path =
-        find_config_file_at(path).context("could not find `rumkinst.toml` config file")?;
-
-    let config_file =
-        File::open(&config_path).with_context(|| format!("failed to open {config_path:?}"))?;
-
-    let config = Config::read(config_file)
-        .with_context(|| format!("could not read rumkinst config at {config_path:?}"))?;
-
-    move_to_config_parent(&config_path)
-        .context("could not move to the parent directory of rumkinst.toml")?;
+    let started_at = jiff::Timestamp::now();
+    let config_path = find_config_file_at(args.path.clone())
+        .context("could not find `rumkinst.toml` config file")?;
+
+    let config_bytes = fs::read(&config_path)
+        .code_at(ErrorCode::CONFIG_UNREADABLE, &config_path)
+        .with_context(|| format!("failed to read {config_path:?}"))?;
+    record_config(&config_path, &config_bytes);
+
+    let base_dir = config_path
+        .parent()
+        .context("could not find the parent directory of rumkinst.toml")?;
+    let name = config_path.to_string_lossy();
+    let config = match args.allow_absolute {
+        true => Config::read_named_allowing_absolute(config_bytes.as_slice(), &name),
+        false => Config::read_named(config_bytes.as_slice(), &name),
+    }
+    .with_context(|| format!("could not read rumkinst config at {config_path:?}"))?
+    .with_base_dir(base_dir);
+    // Reopened with the rotation limits of the config, even when given on the command line
+    if let Some(path) = log_file.or_else(|| config.log_file().map(|file| base_dir.join(file))) {
+        let (max_size, keep) = config.log_rotation();
+        open_log_file(&path, max_size, keep)
+            .with_context(|| format!("failed to open log file {path:?}"))?;
+    }
 
-    let run_id = nanoid!();
-    let out_dir = PathBuf::from(format!("./out/{run_id}"));
-    fs::create_dir_all(&out_dir)
-        .with_context(|| format!("failed to create output directory {out_dir:?}"))?;
+    let cancel = CancelHandle::new();
+    cancel_on_interrupt(cancel.clone());
+
+    let out_root = base_dir.join("out");
+    if args.stdout {
+        stream_archive(
+            &config,
+            &args,
+            &out_root,
+            offline,
+            cancel,
+            diagnostics,
+            progress,
+        )?;
+        return Ok(None);
+    }
+    let run_id = choose_run_id(&config, base_dir).context("failed to choose a run id")?;
+    // Held until this function returns, so run directories are only claimed, linked and pruned
+    // under the lock
+    let mut lock = lock_project(&out_root, args.wait, &cancel)?;
+    let confirm = Arc::new(TerminalConfirm::new(args.yes));
+    let (run_id, out_dir) = claim_run_dir(
+        &out_root,
+        run_id,
+        config.run_id_collision(),
+        confirm.as_ref(),
+    )?;
+    lock.record_run(&run_id)?;
+    // Dropped before the lock, so the history is only written under it
+    let history = RunHistory::start(&out_root, &run_id, config.version());
+    record_run(&run_id, &out_dir);
+    open_run_log(&out_dir.join("rumkinst.log")).context("failed to open run log")?;
+    log::debug!("started run \"{run_id}\" for {config_path:?}");
+
+    let policy = if args.keep_going {
+        FileErrorPolicy::KeepGoing
+    } else {
+        FileErrorPolicy::Abort
+    };
+    let options = BuildOptions::new(&out_dir)
+        .with_file_error_policy(policy)
+        .with_diagnostics(diagnostics)
+        .with_progress(progress.clone())
+        .with_cancel(cancel)
+        .with_confirm(confirm.clone())
+        .with_vcs(!args.no_vcs)
+        .with_allow_outside_sources(args.allow_outside_sources)
+        .with_offline(offline)
+        .with_refresh(args.refresh)
+        .with_reproducibility_check(args.verify_reproducible)
+        .with_lockfile(match args.locked {
+            true => LockfileMode::Locked,
+            false => LockfileMode::Update,
+        });
+    let options = match args.delta_from {
+        Some(base) => options.with_delta_base(base),
+        None => options,
+    };
+    let options = match args.jobs {
+        Some(jobs) => options.with_jobs(jobs),
+        None => options,
+    };
+    let mut output = match build(&config, options.clone()) {
+        Ok(output) => output,
+        Err(err) => {
+            discard_partial_output(&out_dir, args.keep_partial);
+            return Err(err.into());
+        }
+    };
+    // The artifacts are kept even if publishing fails, so the run can be inspected and published
+    // again
+    let published = match args.no_publish {
+        true => {
+            progress.phase_skipped(Phase::Publishing);
+            Ok(())
+        }
+        false => publish(&config, &mut output, &options),
+    };
+    progress.finish();
+
+    let run = RunDetails {
+        run_id: &run_id,
+        config_path: &config_path,
+        config_bytes: &config_bytes,
+        started_at,
+    };
+    write_metadata(&config, &run, &output).context("failed to write run metadata")?;
+
+    print_run_summary(config.theme(), &run_id, &output);
+    if let Some(reference) = &output.registry_reference {
+        log::info!("Pushed artifacts to \"{reference}\"");
+    }
+    for url in &output.release_urls {
+        log::info!("Published release at {url}");
+    }
+    for url in &output.uploaded_urls {
+        log::info!("Uploaded {url}");
+    }
+    if args.timings {
+        print_timings(&output.stats.phases);
+    }
 
-    log::info!("Reading source directories");
-    let all_files = progress_wrapper(3, || find_all_files(&config))
-        .context("could not find all files for packaging")?;
+    published.with_context(|| {
+        format!("failed to publish, the artifacts made by this run were kept in {out_dir:?}")
+    })?;
+    check_skipped_errors(output.stats.skipped)?;
+    if args.deny_warnings || config.deny_warnings() {
+        check_warnings(output.stats.warnings)?;
+    }
+    // Only a run that succeeded becomes the latest one
+    link_latest(&out_root, &run_id);
+    history.succeeded(&output);
+
+    prune_runs(
+        &out_root,
+        &out_dir,
+        config.keep_runs(),
+        config.max_output_size(),
+        confirm.as_ref(),
+    )
+    .context("failed to remove old runs")
+    .warn()
+    .ok();
 
-    log::info!("Making rumkinst artifacts...");
+    Ok(Some(out_dir))
+}
 
-    if all_files.total_files() > 0 {
-        progress_wrapper(all_files.total_files() as u64, || {
-            make_archive(&config, &out_dir, &all_files)
-        })
-        .context("failed to make archive file")?;
+/// Write the archive of `config` to stdout, without claiming a run directory, and log its
+/// checksum
+fn stream_archive(
+    config: &Config,
+    args: &MakeArgs,
+    out_root: &Path,
+    offline: bool,
+    cancel: CancelHandle,
+    diagnostics: Arc<Diagnostics>,
+    progress: Arc<OverallProgress>,
+) -> Result<()> {
+    if io::stdout().is_terminal() {
+        bail!("refusing to write the archive to a terminal, pipe or redirect stdout instead");
+    }
+    // Held until the archive is written, so the lockfile and hooks don't race another run
+    let _lock = lock_project(out_root, args.wait, &cancel)?;
+    let policy = if args.keep_going {
+        FileErrorPolicy::KeepGoing
     } else {
-        log::warn!("no source files included, skipping making archive file");
+        FileErrorPolicy::Abort
+    };
+    let options = BuildOptions::new(out_root)
+        .with_file_error_policy(policy)
+        .with_diagnostics(diagnostics)
+        .with_progress(progress.clone())
+        .with_cancel(cancel)
+        .with_vcs(!args.no_vcs)
+        .with_allow_outside_sources(args.allow_outside_sources)
+        .with_offline(offline)
+        .with_refresh(args.refresh)
+        .with_lockfile(match args.locked {
+            true => LockfileMode::Locked,
+            false => LockfileMode::Update,
+        });
+    let options = match args.jobs {
+        Some(jobs) => options.with_jobs(jobs),
+        None => options,
+    };
+
+    let mut stdout = BufWriter::new(io::stdout().lock());
+    let streamed = write_archive(config, &mut stdout, &options)?;
+    stdout
+        .flush()
+        .context("failed to write the archive to stdout")?;
+    progress.finish();
+
+    let name = format!(
+        "{}.{}",
+        config.get_name(),
+        config.archive_format().extension()
+    );
+    log::info!(
+        "Wrote {} files to stdout as {name}, {}, sha256 {}",
+        streamed.files,
+        HumanBytes(streamed.bytes),
+        streamed.digest
+    );
+    if let Some(path) = &args.checksum_file {
+        fs::write(path, format!("{}  {name}\n", streamed.digest))
+            .with_context(|| format!("failed to write checksum to {path:?}"))?;
     }
 
-    log::info!("Finished: artifacts available in output directory \"{run_id}\"");
-
+    check_skipped_errors(streamed.skipped)?;
+    if args.deny_warnings || config.deny_warnings() {
+        check_warnings(streamed.warnings)?;
+    }
     Ok(())
 }
 
-fn make_archive(config: &Config, out_dir: &Path, all_files: &RumkinstFiles) -> Result<()> {
-    let archive_name = format!("{}.tar.gz", config.get_name());
-    let checksum_name = format!("{archive_name}.sha256");
-
-    let archive_path = out_dir.join(&archive_name);
-    let checksum_path = out_dir.join(&checksum_name);
-
-    log::info!("Making archive \"{archive_name}\"");
-
-    let archive_file = File::create_new(&archive_path)
-        .with_context(|| format!("failed to create new archive file at {archive_path:?}"))?;
-    let mut checksum_file = File::create_new(&checksum_path)
-        .with_context(|| format!("failed to create new checksum file at {checksum_path:?}"))?;
-    let mut encoder = GzBuilder::new()
-        .filename(archive_name.as_str())
-        .write(archive_file, Compression::best());
-    all_files
-        .write_archive(&mut encoder)
-        .with_context(|| format!("failed to write archive to {archive_path:?}"))?;
-    let mut finished_file = encoder
-        .finish()
-        .context("failed to finish gzip encoding of archive")?;
-
-    finished_file
-        .seek(std::io::SeekFrom::Start(0))
-        .context("failed to seek archive to start for checksum generation")?;
-
-    let mut sha256 = Sha256::new();
-    std::io::copy(&mut finished_file, &mut sha256)
-        .context("failed to copy archive file into hasher")?;
-    let digest = sha256.finalize();
-
-    checksum_file
-        .write_fmt(format_args!("{digest:x}  {archive_name}"))
-        .with_context(|| format!("failed to write checksum to {checksum_path:?}"))?;
+/// Log a table of how long each phase took, with its share of their total
+fn print_timings(phases: &[PhaseTiming]) {
+    let total = phases
+        .iter()
+        .map(|phase| phase.elapsed.as_secs_f64())
+        .sum::<f64>();
+    let width = phases
+        .iter()
+        .map(|phase| phase.title.len())
+        .max()
+        .unwrap_or_default()
+        .max("Total".len());
+    log::info!("Phase timings:");
+    for phase in phases {
+        let seconds = phase.elapsed.as_secs_f64();
+        log::info!(
+            "  {:<width$}  {seconds:>8.3}s  {:>5.1}%",
+            phase.title,
+            match total > 0.0 {
+                true => seconds / total * 100.0,
+                false => 0.0,
+            }
+        );
+    }
+    log::info!("  {:<width$}  {total:>8.3}s", "Total");
+}
 
-    Ok(())
+/// Cancel the build on the first Ctrl-C, and exit straight away on the second
+fn cancel_on_interrupt(cancel: CancelHandle) {
+    ctrlc::set_handler(move || {
+        if cancel.is_cancelled() {
+            std::process::exit(130);
+        }
+        log::warn!("Cancelling, press Ctrl-C again to exit immediately");
+        cancel.cancel();
+    })
+    .context("failed to set Ctrl-C handler")
+    .warn()
+    .ok();
+}
+
+/// Name of the marker left in the output directory of a failed run kept with `--keep-partial`
+const PARTIAL_MARKER: &str = "INCOMPLETE";
+
+/// Remove the output directory of a failed run, so nothing left behind looks like a finished build
+fn discard_partial_output(out_dir: &Path, keep_partial: bool) {
+    if keep_partial {
+        log::warn!("Keeping partial output in {out_dir:?}");
+        fs::write(
+            out_dir.join(PARTIAL_MARKER),
+            "this run failed, the artifacts in this directory are incomplete\n",
+        )
+        .with_context(|| format!("failed to mark {out_dir:?} as incomplete"))
+        .warn()
+        .ok();
+        return;
+    }
+
+    log::debug!("removing partial output in {out_dir:?}");
+    close_run_log();
+    fs::remove_dir_all(out_dir)
+        .code_at(ErrorCode::OUTPUT_DIR, out_dir)
+        .with_context(|| format!("failed to remove partial output in {out_dir:?}"))
+        .warn()
+        .ok();
 }
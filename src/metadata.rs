@@ -0,0 +1,244 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use jiff::Timestamp;
+use rumkinst::{
+    ArchiveArtifact, BuildEnvironment, BuildOutput, capture_environment, config::Config,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Name of the metadata file written into the output directory of every successful run
+pub const METADATA_FILE_NAME: &str = "metadata.json";
+
+/// Everything about a finished run that release automation needs, without having to parse logs
+#[derive(Serialize)]
+struct RunMetadata<'a> {
+    rumkinst_version: &'static str,
+    run_id: &'a str,
+    package: PackageMetadata<'a>,
+    config: ConfigMetadata,
+    started_at: String,
+    finished_at: String,
+    elapsed_seconds: f64,
+    /// Time taken by each phase and hook of the build, in the order they started
+    phases: Vec<PhaseMetadata<'a>>,
+    files: FileCounts,
+    /// Entry names of the license files bundled into the archive
+    licenses: &'a [String],
+    /// Metadata appended by each wasm hook, by hook name
+    hooks: &'a BTreeMap<String, BTreeMap<String, String>>,
+    artifacts: Vec<ArtifactMetadata>,
+    published: PublishedMetadata<'a>,
+    vcs: Option<VcsMetadata<'a>>,
+    /// The machine the run was made on, left out when `[output.environment]` turns it off
+    #[serde(skip_serializing_if = "Option::is_none")]
+    environment: Option<BuildEnvironment>,
+}
+
+#[derive(Serialize)]
+struct PackageMetadata<'a> {
+    name: &'a str,
+    version: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct ConfigMetadata {
+    path: PathBuf,
+    sha256: String,
+}
+
+#[derive(Serialize)]
+struct PhaseMetadata<'a> {
+    title: &'a str,
+    elapsed_seconds: f64,
+}
+
+#[derive(Serialize)]
+struct FileCounts {
+    total: usize,
+    skipped: usize,
+    warnings: usize,
+    archive_bytes: u64,
+}
+
+/// A file in the output directory, named relative to it
+#[derive(Serialize)]
+struct ArtifactMetadata {
+    kind: String,
+    name: String,
+    /// Name of the plugin in `[[plugins]]` that made the artifact
+    #[serde(skip_serializing_if = "Option::is_none")]
+    plugin: Option<String>,
+    /// Name of the target in `[targets]` an archive was made for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    digest: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    algorithm: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    checksum: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    listing: Option<String>,
+}
+
+impl ArtifactMetadata {
+    fn file(kind: &str, path: &Path) -> Self {
+        Self {
+            kind: kind.to_string(),
+            name: file_name(path),
+            plugin: None,
+            target: None,
+            digest: None,
+            algorithm: None,
+            checksum: None,
+            signature: None,
+            listing: None,
+        }
+    }
+
+    fn archive(kind: &'static str, artifact: &ArchiveArtifact) -> Self {
+        Self {
+            digest: Some(artifact.digest.clone()),
+            algorithm: Some(artifact.algorithm.clone()),
+            checksum: Some(file_name(&artifact.checksum_path)),
+            signature: artifact.signature_path.as_deref().map(file_name),
+            listing: artifact.listing_path.as_deref().map(file_name),
+            ..Self::file(kind, &artifact.path)
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct VcsMetadata<'a> {
+    commit: &'a str,
+    tag: Option<&'a str>,
+    branch: Option<&'a str>,
+    dirty: bool,
+}
+
+#[derive(Serialize)]
+struct PublishedMetadata<'a> {
+    registry_reference: Option<&'a str>,
+    release_urls: &'a [String],
+    uploaded_urls: &'a [String],
+}
+
+/// Details of the run that produced `output`, as `metadata.json` in its output directory
+pub struct RunDetails<'a> {
+    pub run_id: &'a str,
+    pub config_path: &'a Path,
+    pub config_bytes: &'a [u8],
+    pub started_at: Timestamp,
+}
+
+/// Write `metadata.json` into the output directory of a successful run
+pub fn write_metadata(config: &Config, run: &RunDetails, output: &BuildOutput) -> Result<()> {
+    let mut artifacts = vec![];
+    let archives = [
+        ("archive", &output.archive),
+        ("delta", &output.delta),
+        ("deb", &output.deb),
+        ("windows-installer", &output.windows_installer),
+    ];
+    for (kind, artifact) in archives {
+        if let Some(artifact) = artifact {
+            artifacts.push(ArtifactMetadata::archive(kind, artifact));
+        }
+    }
+    for target in &output.target_archives {
+        artifacts.push(ArtifactMetadata {
+            target: Some(target.target.clone()),
+            ..ArtifactMetadata::archive("archive", &target.archive)
+        });
+    }
+    let files = [
+        ("delta-manifest", &output.delta_manifest),
+        ("flatpak-manifest", &output.flatpak_manifest),
+        ("oci-layout", &output.oci_layout),
+        ("nsis-script", &output.nsis_script),
+        ("release-notes", &output.release_notes),
+    ];
+    for (kind, path) in files {
+        if let Some(path) = path {
+            artifacts.push(ArtifactMetadata::file(kind, path));
+        }
+    }
+    for artifact in &output.plugin_artifacts {
+        // Plugins may make artifacts in subdirectories of the output directory
+        let name = artifact
+            .path
+            .strip_prefix(&output.out_dir)
+            .unwrap_or(&artifact.path);
+        artifacts.push(ArtifactMetadata {
+            name: name.to_string_lossy().into_owned(),
+            plugin: Some(artifact.plugin.clone()),
+            ..ArtifactMetadata::file(&artifact.kind, &artifact.path)
+        });
+    }
+
+    let metadata = RunMetadata {
+        rumkinst_version: env!("CARGO_PKG_VERSION"),
+        run_id: run.run_id,
+        package: PackageMetadata {
+            name: config.get_name(),
+            version: config.version(),
+        },
+        config: ConfigMetadata {
+            path: std::path::absolute(run.config_path)
+                .unwrap_or_else(|_| run.config_path.to_path_buf()),
+            sha256: format!("{:x}", Sha256::digest(run.config_bytes)),
+        },
+        started_at: run.started_at.to_string(),
+        finished_at: Timestamp::now().to_string(),
+        elapsed_seconds: output.stats.elapsed.as_secs_f64(),
+        phases: output
+            .stats
+            .phases
+            .iter()
+            .map(|phase| PhaseMetadata {
+                title: &phase.title,
+                elapsed_seconds: phase.elapsed.as_secs_f64(),
+            })
+            .collect(),
+        files: FileCounts {
+            total: output.stats.files,
+            skipped: output.stats.skipped,
+            warnings: output.stats.warnings,
+            archive_bytes: output.stats.archive_bytes,
+        },
+        licenses: &output.licenses,
+        hooks: &output.hook_metadata,
+        artifacts,
+        published: PublishedMetadata {
+            registry_reference: output.registry_reference.as_deref(),
+            release_urls: &output.release_urls,
+            uploaded_urls: &output.uploaded_urls,
+        },
+        vcs: output.vcs.as_ref().map(|vcs| VcsMetadata {
+            commit: &vcs.commit,
+            tag: vcs.tag.as_deref(),
+            branch: vcs.branch.as_deref(),
+            dirty: vcs.dirty,
+        }),
+        environment: capture_environment(config),
+    };
+
+    let path = output.out_dir.join(METADATA_FILE_NAME);
+    let json = serde_json::to_string_pretty(&metadata).context("failed to serialize metadata")?;
+    fs::write(&path, json + "\n").with_context(|| format!("failed to write {path:?}"))
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .expect("artifacts should always have a file name")
+        .to_string_lossy()
+        .into_owned()
+}
@@ -0,0 +1,130 @@
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use anyhow::{Context, Result, bail};
+use indicatif::HumanDuration;
+
+/// How a run is announced once it is over
+pub enum Notifier {
+    /// A notification on the desktop, through the notification tool of the platform
+    Desktop,
+    /// A shell command, told about the run through `RUMKINST_NOTIFY_*` environment variables
+    Command(String),
+}
+
+/// The end of a `make` run
+pub struct Outcome<'a> {
+    pub success: bool,
+    pub elapsed: Duration,
+    /// Output directory of the run, `None` if it failed before picking one
+    pub out_dir: Option<&'a Path>,
+    /// The error that stopped the run, first line only
+    pub error: Option<String>,
+}
+
+impl Outcome<'_> {
+    fn title(&self) -> &'static str {
+        match self.success {
+            true => "rumkinst make finished",
+            false => "rumkinst make failed",
+        }
+    }
+
+    fn body(&self) -> String {
+        let mut body = match self.success {
+            true => format!("Finished in {}", HumanDuration(self.elapsed)),
+            false => format!("Failed after {}", HumanDuration(self.elapsed)),
+        };
+        if let Some(error) = &self.error {
+            body.push_str(&format!(": {error}"));
+        }
+        if let Some(out_dir) = self.out_dir {
+            body.push_str(&format!("\nArtifacts in {out_dir:?}"));
+        }
+        body
+    }
+}
+
+/// Tell `notifier` about `outcome`, a failed notification is only logged
+pub fn notify(notifier: &Notifier, outcome: &Outcome) {
+    let result = match notifier {
+        Notifier::Desktop => notify_desktop(outcome),
+        Notifier::Command(command) => notify_command(command, outcome),
+    };
+    if let Err(err) = result {
+        log::warn!("failed to send a notification: {err:#}");
+    }
+}
+
+fn notify_desktop(outcome: &Outcome) -> Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        // AppleScript strings only need their quotes and backslashes escaped
+        let quoted =
+            |text: &str| format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(format!(
+            "display notification {} with title {}",
+            quoted(&outcome.body()),
+            quoted(outcome.title())
+        ));
+        command
+    } else if cfg!(unix) {
+        let mut command = Command::new("notify-send");
+        command
+            .arg("--app-name=rumkinst")
+            .arg(outcome.title())
+            .arg(outcome.body());
+        command
+    } else {
+        bail!("desktop notifications are not supported on this platform, use --notify=COMMAND");
+    };
+    run(&mut command)
+}
+
+fn notify_command(line: &str, outcome: &Outcome) -> Result<()> {
+    let mut command = match cfg!(windows) {
+        true => {
+            let mut command = Command::new("cmd");
+            command.arg("/C").arg(line);
+            command
+        }
+        false => {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(line);
+            command
+        }
+    };
+    command
+        .env("RUMKINST_NOTIFY_TITLE", outcome.title())
+        .env("RUMKINST_NOTIFY_BODY", outcome.body())
+        .env(
+            "RUMKINST_NOTIFY_STATUS",
+            match outcome.success {
+                true => "success",
+                false => "failure",
+            },
+        )
+        .env(
+            "RUMKINST_NOTIFY_ELAPSED_SECONDS",
+            outcome.elapsed.as_secs().to_string(),
+        );
+    if let Some(out_dir) = outcome.out_dir {
+        command.env("RUMKINST_OUT_DIR", out_dir);
+    }
+    run(&mut command)
+}
+
+fn run(command: &mut Command) -> Result<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command
+        .stdin(Stdio::null())
+        .status()
+        .with_context(|| format!("failed to run `{program}`"))?;
+    if !status.success() {
+        bail!("`{program}` {status}");
+    }
+    Ok(())
+}
@@ -0,0 +1,276 @@
+//! Pipeline plugins, external programs called at stages of a build as set in `[[plugins]]`
+//!
+//! A plugin is run once per stage it is listed for, from the base directory. It is sent a single
+//! JSON request on stdin and must answer with a single JSON response on stdout, anything it
+//! writes to stderr is shown as is. A plugin that exits unsuccessfully fails the build.
+//!
+//! The request holds:
+//!
+//! - `protocol`: version of this contract, currently `1`
+//! - `stage`: `artifacts` or `publish`
+//! - `plugin`: name of the plugin in the config, and `options`: its `options` table
+//! - `package`: `name` and `version` of the package
+//! - `base_dir` and `out_dir`: absolute paths of the project and of the run's output directory
+//! - `artifacts`: every artifact made so far, as `kind`, `path` and, for archives and packages,
+//!   `digest` and `algorithm`
+//! - `vcs`: `commit`, `tag`, `branch` and `dirty` of the git repository, or `null`
+//! - `offline`: whether the build may use the network
+//!
+//! Every field of the response is optional:
+//!
+//! - `artifacts`: files the plugin made in the output directory, as `kind` and `path` relative
+//!   to it without any `..`, only accepted at the `artifacts` stage. At that stage the output
+//!   directory is still the staging directory, which is moved into place once every plugin ran
+//! - `urls`: where the plugin published the artifacts, only accepted at the `publish` stage
+//! - `warnings`: messages reported as warnings of the build
+
+use std::{
+    error::Error,
+    fs::File,
+    io::Write,
+    path::{self, Component, Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::{
+    BuildOutput, RumkinstError,
+    config::{Config, PluginConfig, PluginStage},
+    error_log::{Diagnostics, ErrorCode},
+    installer_gen::{Digester, to_hex},
+    progress_log::NoProgress,
+};
+
+/// Version of the JSON contract between rumkinst and its plugins
+const PROTOCOL_VERSION: u32 = 1;
+
+/// A file made by a plugin at the `artifacts` stage
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct PluginArtifact {
+    /// Name of the plugin in `[[plugins]]`
+    pub plugin: String,
+    /// Kind of the artifact, as the plugin named it
+    pub kind: String,
+    pub path: PathBuf,
+    /// Lowercase hex digest of the file once the plugin made it, checked again before it is
+    /// published
+    pub digest: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct PluginResponse {
+    artifacts: Vec<ResponseArtifact>,
+    urls: Vec<String>,
+    warnings: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ResponseArtifact {
+    kind: String,
+    path: PathBuf,
+}
+
+/// Whether any plugin in `config` is called at `stage`
+pub(crate) fn has_plugins(config: &Config, stage: PluginStage) -> bool {
+    plugins_at(config, stage).next().is_some()
+}
+
+fn plugins_at(config: &Config, stage: PluginStage) -> impl Iterator<Item = &PluginConfig> {
+    config
+        .plugins
+        .iter()
+        .filter(move |plugin| plugin.stages.contains(&stage))
+}
+
+/// Call every plugin set for `stage` in order, adding what they made or published to `output`
+pub(crate) fn run_plugins(
+    config: &Config,
+    stage: PluginStage,
+    output: &mut BuildOutput,
+    digester: &dyn Digester,
+    diagnostics: &Diagnostics,
+    offline: bool,
+) -> Result<(), RumkinstError> {
+    for plugin in plugins_at(config, stage) {
+        let plugin_error = |source: Box<dyn Error + Send + Sync>| RumkinstError::Plugin {
+            plugin: plugin.name.clone(),
+            stage: stage.name(),
+            source,
+        };
+
+        log::info!(
+            "Running plugin `{}` at the {} stage",
+            plugin.name,
+            stage.name()
+        );
+        let request = request(config, plugin, stage, output, offline)?;
+        let response = call(config, plugin, &request).map_err(plugin_error)?;
+
+        for warning in response.warnings {
+            diagnostics.warn(
+                ErrorCode::PLUGIN_WARNING,
+                format!("plugin `{}`: {warning}", plugin.name),
+            );
+        }
+        match stage {
+            PluginStage::Artifacts => {
+                for artifact in response.artifacts {
+                    let path = output.out_dir.join(&artifact.path);
+                    let inside = artifact.path.components().next().is_some()
+                        && artifact
+                            .path
+                            .components()
+                            .all(|component| matches!(component, Component::Normal(_)));
+                    if !inside || !path.is_file() {
+                        return Err(plugin_error(
+                            format!(
+                                "artifact {:?} is not a file in the output directory",
+                                artifact.path
+                            )
+                            .into(),
+                        ));
+                    }
+                    let digest = File::open(&path)
+                        .and_then(|mut file| digester.digest(&mut file, &NoProgress))
+                        .map_err(|err| plugin_error(err.into()))?;
+                    log::debug!("plugin `{}` made {path:?}", plugin.name);
+                    output.plugin_artifacts.push(PluginArtifact {
+                        plugin: plugin.name.clone(),
+                        kind: artifact.kind,
+                        path,
+                        digest: to_hex(&digest),
+                    });
+                }
+            }
+            PluginStage::Publish => output.uploaded_urls.extend(response.urls),
+        }
+    }
+    Ok(())
+}
+
+/// The JSON request sent to `plugin` at `stage`
+fn request(
+    config: &Config,
+    plugin: &PluginConfig,
+    stage: PluginStage,
+    output: &BuildOutput,
+    offline: bool,
+) -> Result<Value, RumkinstError> {
+    let absolute = |path: &Path| {
+        path::absolute(path).map_err(|source| RumkinstError::Plugin {
+            plugin: plugin.name.clone(),
+            stage: stage.name(),
+            source: source.into(),
+        })
+    };
+
+    let archives = [
+        ("archive", &output.archive),
+        ("delta", &output.delta),
+        ("deb", &output.deb),
+        ("windows-installer", &output.windows_installer),
+    ];
+    let mut artifacts = vec![];
+    for (kind, artifact) in archives
+        .into_iter()
+        .filter_map(|(kind, artifact)| Some((kind, artifact.as_ref()?)))
+        .chain(
+            output
+                .target_archives
+                .iter()
+                .map(|target| ("archive", &target.archive)),
+        )
+    {
+        artifacts.push(json!({
+            "kind": kind,
+            "path": absolute(&artifact.path)?,
+            "digest": artifact.digest,
+            "algorithm": artifact.algorithm,
+        }));
+    }
+    let files = [
+        ("delta-manifest", &output.delta_manifest),
+        ("flatpak-manifest", &output.flatpak_manifest),
+        ("oci-layout", &output.oci_layout),
+        ("nsis-script", &output.nsis_script),
+        ("release-notes", &output.release_notes),
+    ];
+    for (kind, path) in files {
+        if let Some(path) = path {
+            artifacts.push(json!({ "kind": kind, "path": absolute(path)? }));
+        }
+    }
+    for artifact in &output.plugin_artifacts {
+        artifacts.push(json!({ "kind": artifact.kind, "path": absolute(&artifact.path)? }));
+    }
+
+    Ok(json!({
+        "protocol": PROTOCOL_VERSION,
+        "stage": stage.name(),
+        "plugin": plugin.name,
+        "options": plugin.options,
+        "package": {
+            "name": config.get_name(),
+            "version": config.version(),
+        },
+        "base_dir": absolute(match config.base_dir().as_os_str().is_empty() {
+            true => Path::new("."),
+            false => config.base_dir(),
+        })?,
+        "out_dir": absolute(&output.out_dir)?,
+        "artifacts": artifacts,
+        "vcs": output.vcs.as_ref().map(|vcs| json!({
+            "commit": vcs.commit,
+            "tag": vcs.tag,
+            "branch": vcs.branch,
+            "dirty": vcs.dirty,
+        })),
+        "offline": offline,
+    }))
+}
+
+/// Run `plugin` with `request` on its stdin, and parse the response on its stdout
+fn call(
+    config: &Config,
+    plugin: &PluginConfig,
+    request: &Value,
+) -> Result<PluginResponse, Box<dyn Error + Send + Sync>> {
+    // Commands with a path in them are relative to the project, like hooks
+    let program = match Path::new(&plugin.command).components().count() > 1 {
+        true => path::absolute(config.resolve(Path::new(&plugin.command)))?,
+        false => PathBuf::from(&plugin.command),
+    };
+    let mut command = Command::new(&program);
+    if !config.base_dir().as_os_str().is_empty() {
+        command.current_dir(config.base_dir());
+    }
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .map_err(|err| format!("failed to run {program:?}: {err}"))?;
+    let mut stdin = child
+        .stdin
+        .take()
+        .expect("stdin is piped for plugin requests");
+    // A plugin that stops reading early is judged by its exit status instead
+    serde_json::to_writer(&mut stdin, request).ok();
+    stdin.write_all(b"\n").ok();
+    drop(stdin);
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(format!("{program:?} {}", output.status).into());
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout.trim().is_empty() {
+        true => Ok(PluginResponse::default()),
+        false => serde_json::from_str(&stdout)
+            .map_err(|err| format!("{program:?} answered with an invalid response: {err}").into()),
+    }
+}
@@ -0,0 +1,37 @@
+use std::{env, ffi::OsString, io, process::Command, process::ExitCode};
+
+/// Run the plugin subcommand `rumkinst-<name>` from `PATH`, where `args` is the name followed by
+/// the arguments to pass on, and exit with its exit code
+///
+/// The plugin is given the path of this executable as `RUMKINST`, so it can call back into it.
+pub fn run_plugin_subcommand(args: &[OsString]) -> ExitCode {
+    let (name, args) = args
+        .split_first()
+        .expect("external subcommands should always have a name");
+    let mut program = OsString::from("rumkinst-");
+    program.push(name);
+
+    let mut command = Command::new(&program);
+    command.args(args);
+    if let Ok(exe) = env::current_exe() {
+        command.env("RUMKINST", exe);
+    }
+    log::debug!("running plugin subcommand {program:?}");
+    match command.status() {
+        Ok(status) => match status.code() {
+            Some(code) => ExitCode::from(u8::try_from(code).unwrap_or(1)),
+            // Killed by a signal
+            None => ExitCode::FAILURE,
+        },
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            log::error!(
+                "unrecognized subcommand {name:?}, and no {program:?} plugin was found on PATH"
+            );
+            ExitCode::from(2)
+        }
+        Err(err) => {
+            log::error!("failed to run plugin {program:?}: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
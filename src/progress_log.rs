@@ -1,98 +1,671 @@
 use std::{
     borrow::Cow,
-    sync::{LazyLock, OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    fmt::Display,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, LazyLock, Mutex, OnceLock,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    time::Instant,
 };
 
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+mod system_log;
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
 use indicatif_log_bridge::LogWrapper;
-use log::{LevelFilter, Log};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+pub use system_log::SystemLog;
+use system_log::SystemLogger;
+
+use crate::secrets::redact;
 
 static PROGRESS_STYLE: LazyLock<ProgressStyle> = LazyLock::new(|| {
-    ProgressStyle::with_template("[{elapsed_precise}] {wide_bar:.cyan/blue} {pos:>3}/{len:3} {msg}")
-        .expect("should be able to unwrap main ProgressStyle")
+    ProgressStyle::with_template(
+        "{prefix:>20.bold} [{elapsed_precise}] {wide_bar:.cyan/blue} {pos:>3}/{len:3} {msg}",
+    )
+    .expect("should be able to unwrap main ProgressStyle")
 });
 
+static OVERALL_STYLE: LazyLock<ProgressStyle> = LazyLock::new(|| {
+    ProgressStyle::with_template(
+        "{prefix:>20.bold} [{elapsed_precise}] {wide_bar:.green/blue} {percent:>3}% {msg:.bold}",
+    )
+    .expect("should be able to unwrap overall ProgressStyle")
+});
+
+/// Number of overall bar ticks for each unit of phase weight
+const TICKS_PER_WEIGHT: u64 = 1000;
+
+/// The bars the log wrapper prints above, so other terminal output can suspend them too
 static CENTRAL_PROGRESS_WRAPPER: OnceLock<CentralProgressWrapper> = OnceLock::new();
 
-struct CentralProgressWrapper {
-    multi: MultiProgress,
-    current: RwLock<Option<ProgressBar>>,
-}
+/// Plain-text activity log for the current run, written independently of terminal rendering
+static RUN_LOG: Mutex<Option<File>> = Mutex::new(None);
 
-impl CentralProgressWrapper {
-    fn get_current(&self) -> RwLockReadGuard<Option<ProgressBar>> {
-        self.current
-            .read()
-            .expect("current progressbar rwlock is poisoned")
-    }
+/// System logging service warnings and errors are forwarded to, see [`open_system_log`]
+static SYSTEM_LOG: OnceLock<SystemLogger> = OnceLock::new();
 
-    fn get_current_mut(&self) -> RwLockWriteGuard<Option<ProgressBar>> {
-        self.current
-            .write()
-            .expect("current progressbar rwlock is poisoned")
-    }
+/// Log file kept across runs, see [`open_log_file`]
+static LOG_FILE: Mutex<Option<RotatingLog>> = Mutex::new(None);
+
+/// Size in bytes a log file is rotated at, unless `[log] max-size` says otherwise
+pub const DEFAULT_LOG_FILE_SIZE: u64 = 10 << 20;
+
+/// Number of rotated log files kept, unless `[log] keep` says otherwise
+pub const DEFAULT_LOG_FILE_KEEP: usize = 3;
+
+/// Most verbose level recorded into the run log
+const RUN_LOG_LEVEL: LevelFilter = LevelFilter::Debug;
+
+struct CentralProgressWrapper {
+    multi: MultiProgress,
 }
 
-fn get_wrapper() -> &'static CentralProgressWrapper {
-    CENTRAL_PROGRESS_WRAPPER
-        .get()
-        .expect("log wrapper not initialized, make sure to call setup_log_wrapper first")
+/// A container drawing nothing, for progress nobody is watching
+pub fn hidden_bars() -> MultiProgress {
+    MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
 }
 
-pub fn setup_log_wrapper(logger: impl Log + 'static, filter: LevelFilter) {
+/// Install `logger` behind a log wrapper, returning the container log lines are printed above
+///
+/// Bars are only kept clear of log lines when they are added to the returned container.
+pub fn setup_log_wrapper(logger: impl Log + 'static, filter: LevelFilter) -> MultiProgress {
     let multi = MultiProgress::new();
 
-    LogWrapper::new(multi.clone(), logger)
+    LogWrapper::new(multi.clone(), TeeLogger { terminal: logger })
         .try_init()
         .expect("should have successfully initialized log wrapper");
-    log::set_max_level(filter);
+    log::set_max_level(filter.max(RUN_LOG_LEVEL));
 
     if CENTRAL_PROGRESS_WRAPPER
         .set(CentralProgressWrapper {
-            multi,
-            current: RwLock::new(None),
+            multi: multi.clone(),
         })
         .is_err()
     {
         panic!("setup_log_wrapper should only be called once");
     }
+    multi
+}
+
+/// Forwards records to the terminal logger, and copies them into the run log once one is open
+///
+/// Secrets read for publishing are redacted from every record first.
+struct TeeLogger<L> {
+    terminal: L,
+}
+
+impl<L: Log> TeeLogger<L> {
+    fn forward(&self, record: &Record) {
+        if self.terminal.enabled(record.metadata()) {
+            self.terminal.log(record);
+        }
+
+        if record.level() <= RUN_LOG_LEVEL {
+            write_run_log(record.level(), record.target(), record.args());
+        }
+
+        if record.level() <= Level::Warn
+            && let Some(system_log) = SYSTEM_LOG.get()
+        {
+            system_log.send(record);
+        }
+    }
+}
+
+impl<L: Log> Log for TeeLogger<L> {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.terminal.enabled(metadata) || metadata.level() <= RUN_LOG_LEVEL
+    }
+
+    fn log(&self, record: &Record) {
+        let message = record.args().to_string();
+        match redact(&message) {
+            Cow::Borrowed(_) => self.forward(record),
+            Cow::Owned(redacted) => {
+                self.forward(
+                    &Record::builder()
+                        .metadata(record.metadata().clone())
+                        .module_path(record.module_path())
+                        .file(record.file())
+                        .line(record.line())
+                        .args(format_args!("{redacted}"))
+                        .build(),
+                );
+            }
+        }
+    }
+
+    fn flush(&self) {
+        self.terminal.flush();
+    }
+}
+
+/// Start copying log records, phase timings and progress messages into a file at `path`
+pub fn open_run_log(path: &Path) -> io::Result<()> {
+    let file = File::create_new(path)?;
+
+    RUN_LOG
+        .lock()
+        .expect("run log mutex is poisoned")
+        .replace(file);
+
+    Ok(())
+}
+
+/// Stop copying into the run log and close its file, so the directory holding it can be removed
+pub fn close_run_log() {
+    RUN_LOG.lock().expect("run log mutex is poisoned").take();
+}
+
+/// Forward every warning and error logged from now on to `system_log`, as well as the terminal
+///
+/// Can only be opened once, later calls fail.
+pub fn open_system_log(system_log: SystemLog) -> io::Result<()> {
+    SYSTEM_LOG
+        .set(SystemLogger::connect(system_log)?)
+        .map_err(|_| io::Error::other("a system log is already open"))
+}
+
+/// Copy everything written to the run log into the file at `path` as well, appending to it
+///
+/// Unlike the run log the file is kept across runs. Once it grows past `max_size` bytes it is
+/// renamed to `{path}.1`, moving earlier ones up to `{path}.{keep}`, and a new one is started.
+/// Replaces any log file opened before.
+pub fn open_log_file(path: &Path, max_size: u64, keep: usize) -> io::Result<()> {
+    let log = RotatingLog::open(path.to_path_buf(), max_size, keep)?;
+
+    LOG_FILE
+        .lock()
+        .expect("log file mutex is poisoned")
+        .replace(log);
+
+    Ok(())
+}
+
+fn write_run_log(level: Level, target: &str, message: impl Display) {
+    let mut run_log = RUN_LOG.lock().expect("run log mutex is poisoned");
+    let mut log_file = LOG_FILE.lock().expect("log file mutex is poisoned");
+    if run_log.is_none() && log_file.is_none() {
+        return;
+    }
+
+    let line = format!(
+        "{} {level:<5} {target}: {message}\n",
+        jiff::Timestamp::now()
+    );
+    // Both logs are best effort, a failed write should never interrupt the build
+    if let Some(file) = run_log.as_mut() {
+        let _ = file.write_all(line.as_bytes());
+    }
+    if let Some(log) = log_file.as_mut() {
+        log.write(line.as_bytes());
+    }
 }
 
-pub fn progress_wrapper<F, R>(length: u64, logic: F) -> R
+/// A log file that is moved aside once it grows too large
+struct RotatingLog {
+    path: PathBuf,
+    file: File,
+    /// Bytes written to `file` so far, including what it held when opened
+    size: u64,
+    max_size: u64,
+    keep: usize,
+}
+
+impl RotatingLog {
+    fn open(path: PathBuf, max_size: u64, keep: usize) -> io::Result<Self> {
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_size,
+            keep,
+        })
+    }
+
+    fn write(&mut self, line: &[u8]) {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_size {
+            // Keep appending to the full file rather than losing records
+            let _ = self.rotate();
+        }
+        if self.file.write_all(line).is_ok() {
+            self.size += line.len() as u64;
+        }
+    }
+
+    /// Move the current file to `{path}.1` and every rotated one up by one, dropping the oldest
+    fn rotate(&mut self) -> io::Result<()> {
+        let rotated = |index: usize| {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{index}"));
+            PathBuf::from(name)
+        };
+        if self.keep > 0 {
+            for index in (1..self.keep).rev() {
+                match fs::rename(rotated(index), rotated(index + 1)) {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                    _ => {}
+                }
+            }
+            fs::rename(&self.path, rotated(1))?;
+        }
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// Write `line` to stderr without tearing any progress bars currently being drawn
+pub fn print_above_progress(line: &str) {
+    match CENTRAL_PROGRESS_WRAPPER.get() {
+        Some(wrapper) => wrapper.multi.suspend(|| eprintln!("{line}")),
+        None => eprintln!("{line}"),
+    }
+    write_run_log(Level::Info, "diagnostic", line);
+}
+
+/// Run `logic` with every progress bar hidden, such as to ask something on the terminal
+pub fn suspend_progress<R>(logic: impl FnOnce() -> R) -> R {
+    match CENTRAL_PROGRESS_WRAPPER.get() {
+        Some(wrapper) => wrapper.multi.suspend(logic),
+        None => logic(),
+    }
+}
+
+/// A cloneable handle to a single progress bar.
+///
+/// Every clone reports to the same bar, so a handle can be moved into worker
+/// threads without going through any global state.
+#[derive(Debug, Clone)]
+pub struct ProgressHandle {
+    bar: ProgressBar,
+    overall: Option<Arc<OverallLink>>,
+}
+
+/// The slice of the overall bar that a phase bar is responsible for
+#[derive(Debug)]
+struct OverallLink {
+    bar: ProgressBar,
+    /// Where the slice starts, moved when the phase runs again
+    base: AtomicU64,
+    span: u64,
+}
+
+impl ProgressHandle {
+    /// A handle that accepts updates but never draws anything
+    pub fn hidden() -> Self {
+        Self {
+            bar: ProgressBar::hidden(),
+            overall: None,
+        }
+    }
+
+    pub fn increment(&self, amount: u64) {
+        self.bar.inc(amount);
+
+        if let Some(link) = &self.overall {
+            let length = self.bar.length().unwrap_or(0).max(1);
+            let position = self.bar.position().min(length);
+            let base = link.base.load(Ordering::Relaxed);
+            link.bar.set_position(base + link.span * position / length);
+        }
+    }
+
+    pub fn set_message<S: Into<Cow<'static, str>>>(&self, msg: S) {
+        let msg = msg.into();
+        write_run_log(Level::Debug, &self.bar.prefix(), &msg);
+        self.bar.set_message(msg);
+    }
+}
+
+/// Records the start and end of a phase in the run log
+struct PhaseTimer {
+    title: &'static str,
+    start: Instant,
+}
+
+impl PhaseTimer {
+    fn start(title: &'static str) -> Self {
+        write_run_log(Level::Info, "phase", format_args!("started \"{title}\""));
+        Self {
+            title,
+            start: Instant::now(),
+        }
+    }
+
+    fn finish(self) {
+        write_run_log(
+            Level::Info,
+            "phase",
+            format_args!(
+                "finished \"{}\" in {:.3}s",
+                self.title,
+                self.start.elapsed().as_secs_f64()
+            ),
+        );
+    }
+}
+
+fn add_phase_bar(bars: &MultiProgress, title: &'static str, length: u64) -> ProgressBar {
+    bars.add(ProgressBar::new(length))
+        .with_style(PROGRESS_STYLE.clone())
+        .with_prefix(title)
+}
+
+/// Run `logic` with a progress bar of `length` steps in `bars`, labelled with `title`
+pub fn progress_wrapper<F, R>(bars: &MultiProgress, title: &'static str, length: u64, logic: F) -> R
 where
-    F: Fn() -> R,
+    F: FnOnce(&ProgressHandle) -> R,
 {
-    let wrapper = get_wrapper();
-    let mut current_pb = wrapper.get_current_mut();
+    let timer = PhaseTimer::start(title);
+    let handle = ProgressHandle {
+        bar: add_phase_bar(bars, title, length),
+        overall: None,
+    };
+
+    let result = logic(&handle);
 
-    if current_pb.is_some() {
-        panic!("progress bar already in use, cannot initialize another");
+    handle.bar.finish();
+    timer.finish();
+
+    result
+}
+
+/// The stages of a `make` run that contribute to the overall progress bar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Discovery,
+    Archiving,
+    Checksum,
+    Signing,
+    /// Building system packages, such as a `.deb`
+    Packaging,
+    /// Generating the Windows installer set by `[nsis]`
+    InstallerGeneration,
+    /// Sending finished artifacts to the targets under `[publish]`
+    Publishing,
+}
+
+impl Phase {
+    /// How many phases there are, `Publishing` being the last
+    const COUNT: usize = Phase::Publishing as usize + 1;
+
+    /// Position of this phase in the order a run goes through them, below [`Phase::COUNT`]
+    fn index(self) -> usize {
+        self as usize
     }
 
-    let pb = wrapper
-        .multi
-        .add(ProgressBar::new(length))
-        .with_style(PROGRESS_STYLE.clone());
+    pub fn title(&self) -> &'static str {
+        match self {
+            Phase::Discovery => "Scanning sources",
+            Phase::Archiving => "Writing archive",
+            Phase::Checksum => "Hashing",
+            Phase::Signing => "Signing",
+            Phase::Packaging => "Building packages",
+            Phase::InstallerGeneration => "Generating installer",
+            Phase::Publishing => "Publishing",
+        }
+    }
+}
 
-    current_pb.replace(pb.clone());
-    drop(current_pb);
+/// Relative share of the overall progress bar given to each [`Phase`]
+#[derive(Debug, Clone)]
+pub struct PhaseWeights {
+    discovery: u64,
+    archiving: u64,
+    checksum: u64,
+    signing: u64,
+    packaging: u64,
+    installer_generation: u64,
+    publishing: u64,
+}
 
-    let result = logic();
+impl Default for PhaseWeights {
+    fn default() -> Self {
+        Self {
+            discovery: 1,
+            archiving: 6,
+            checksum: 1,
+            signing: 1,
+            packaging: 3,
+            installer_generation: 1,
+            publishing: 2,
+        }
+    }
+}
 
-    let _ = wrapper.get_current_mut().take();
-    pb.finish();
+impl PhaseWeights {
+    pub fn with_weight(mut self, phase: Phase, weight: u64) -> Self {
+        *self.weight_mut(phase) = weight;
+        self
+    }
 
-    result
+    pub fn weight(&self, phase: Phase) -> u64 {
+        match phase {
+            Phase::Discovery => self.discovery,
+            Phase::Archiving => self.archiving,
+            Phase::Checksum => self.checksum,
+            Phase::Signing => self.signing,
+            Phase::Packaging => self.packaging,
+            Phase::InstallerGeneration => self.installer_generation,
+            Phase::Publishing => self.publishing,
+        }
+    }
+
+    fn weight_mut(&mut self, phase: Phase) -> &mut u64 {
+        match phase {
+            Phase::Discovery => &mut self.discovery,
+            Phase::Archiving => &mut self.archiving,
+            Phase::Checksum => &mut self.checksum,
+            Phase::Signing => &mut self.signing,
+            Phase::Packaging => &mut self.packaging,
+            Phase::InstallerGeneration => &mut self.installer_generation,
+            Phase::Publishing => &mut self.publishing,
+        }
+    }
+
+    fn total(&self) -> u64 {
+        self.discovery
+            + self.archiving
+            + self.checksum
+            + self.signing
+            + self.packaging
+            + self.installer_generation
+            + self.publishing
+    }
 }
 
-pub fn increment_progress(amount: u64) {
-    if let Some(pb) = &*get_wrapper().get_current() {
-        pb.inc(amount);
+/// Receives progress updates from a build, so library callers can display them however they like
+///
+/// A sink is shared with every thread taking part in a build, so implementations must handle
+/// their own synchronisation.
+pub trait ProgressSink: Send + Sync {
+    /// `phase` has started, and will take `length` steps
+    fn phase_started(&self, _phase: Phase, _length: u64) {}
+    /// `phase` has stopped, whether it succeeded or not
+    fn phase_finished(&self, _phase: Phase) {}
+    /// `phase` has nothing to do in this build, and will not start
+    fn phase_skipped(&self, _phase: Phase) {}
+    /// `amount` steps of the current phase are done
+    fn increment(&self, amount: u64);
+    /// What the current phase is working on right now
+    fn set_message(&self, message: &str);
+}
+
+/// A [`ProgressSink`] that ignores every update
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl ProgressSink for NoProgress {
+    fn increment(&self, _amount: u64) {}
+    fn set_message(&self, _message: &str) {}
+}
+
+impl ProgressSink for ProgressHandle {
+    fn increment(&self, amount: u64) {
+        ProgressHandle::increment(self, amount);
+    }
+
+    fn set_message(&self, message: &str) {
+        ProgressHandle::set_message(self, message.to_string());
+    }
+}
+
+/// Stored in [`OverallProgress`] while no phase is running
+const NO_PHASE: usize = usize::MAX;
+
+/// An umbrella bar aggregating every phase of a run, each phase getting its own sub-bar
+///
+/// Updates go straight to the sub-bar of the running phase without taking a lock, only starting
+/// and finishing a phase does.
+pub struct OverallProgress {
+    /// Container of the overall bar and every phase bar
+    bars: MultiProgress,
+    bar: ProgressBar,
+    weights: PhaseWeights,
+    completed: AtomicU64,
+    /// The sub-bar of each phase once it has started, by [`Phase::index`]
+    phases: [OnceLock<ProgressHandle>; Phase::COUNT],
+    /// Index of the running phase, [`NO_PHASE`] between phases
+    current: AtomicUsize,
+    /// The phase most recently started, cleared once every phase has finished
+    last_started: Mutex<Option<Phase>>,
+    /// Timer and end of the running phase on the overall bar
+    transition: Mutex<Option<(PhaseTimer, u64)>>,
+}
+
+impl OverallProgress {
+    /// Progress drawing its bars in `bars`, such as the container of [`setup_log_wrapper`] or
+    /// [`hidden_bars`]
+    pub fn new(weights: PhaseWeights, bars: MultiProgress) -> Self {
+        let bar = bars
+            .add(ProgressBar::new(weights.total() * TICKS_PER_WEIGHT))
+            .with_style(OVERALL_STYLE.clone())
+            .with_prefix("Overall");
+
+        Self {
+            bars,
+            bar,
+            weights,
+            completed: AtomicU64::new(0),
+            phases: std::array::from_fn(|_| OnceLock::new()),
+            current: AtomicUsize::new(NO_PHASE),
+            last_started: Mutex::new(None),
+            transition: Mutex::new(None),
+        }
+    }
+
+    fn transition(&self) -> std::sync::MutexGuard<'_, Option<(PhaseTimer, u64)>> {
+        self.transition
+            .lock()
+            .expect("phase transition mutex is poisoned")
+    }
+
+    /// The sub-bar of the running phase, if there is one
+    fn current(&self) -> Option<&ProgressHandle> {
+        let index = self.current.load(Ordering::Acquire);
+        self.phases.get(index).and_then(OnceLock::get)
+    }
+
+    fn last_started(&self) -> std::sync::MutexGuard<'_, Option<Phase>> {
+        self.last_started
+            .lock()
+            .expect("last started phase mutex is poisoned")
+    }
+
+    /// The phase this run is in, or was in when it stopped, if it has not finished
+    pub fn current_phase(&self) -> Option<Phase> {
+        *self.last_started()
+    }
+
+    pub fn finish(&self) {
+        self.bar.finish_with_message("done");
+        self.last_started().take();
     }
 }
 
-pub fn set_progress_message<S: Into<Cow<'static, str>>>(msg: S) {
-    if let Some(pb) = &*get_wrapper().get_current() {
-        pb.set_message(msg);
+impl ProgressSink for OverallProgress {
+    fn phase_started(&self, phase: Phase, length: u64) {
+        let span = self.weights.weight(phase) * TICKS_PER_WEIGHT;
+        let base = self.completed.fetch_add(span, Ordering::SeqCst);
+
+        self.last_started().replace(phase);
+        // Only changes once per phase, unlike the messages of the phase bar
+        self.bar.set_message(phase.title());
+
+        let mut transition = self.transition();
+        let slot = &self.phases[phase.index()];
+        match slot.get() {
+            // A phase that runs again starts its bar over, in its new slice of the overall bar
+            Some(handle) => {
+                handle.bar.reset();
+                handle.bar.set_length(length);
+                if let Some(link) = &handle.overall {
+                    link.base.store(base, Ordering::Relaxed);
+                }
+            }
+            None => {
+                let handle = ProgressHandle {
+                    bar: add_phase_bar(&self.bars, phase.title(), length),
+                    overall: Some(Arc::new(OverallLink {
+                        bar: self.bar.clone(),
+                        base: AtomicU64::new(base),
+                        span,
+                    })),
+                };
+                slot.set(handle)
+                    .expect("phase bars should only be set while holding the transition lock");
+            }
+        }
+        self.current.store(phase.index(), Ordering::Release);
+        transition.replace((PhaseTimer::start(phase.title()), base + span));
+    }
+
+    fn phase_finished(&self, _phase: Phase) {
+        let mut transition = self.transition();
+        let handle = self.current();
+        self.current.store(NO_PHASE, Ordering::Release);
+        if let Some((timer, end)) = transition.take() {
+            if let Some(handle) = handle {
+                handle.bar.finish();
+            }
+            self.bar.set_position(end);
+            self.bar.set_message("");
+            timer.finish();
+        }
+    }
+
+    fn phase_skipped(&self, phase: Phase) {
+        write_run_log(
+            Level::Info,
+            "phase",
+            format_args!("skipped \"{}\"", phase.title()),
+        );
+        let span = self.weights.weight(phase) * TICKS_PER_WEIGHT;
+        let position = self.completed.fetch_add(span, Ordering::SeqCst) + span;
+        self.bar.set_position(position);
+    }
+
+    fn increment(&self, amount: u64) {
+        if let Some(handle) = self.current() {
+            handle.increment(amount);
+        }
+    }
+
+    fn set_message(&self, message: &str) {
+        if let Some(handle) = self.current() {
+            handle.set_message(message.to_string());
+        }
     }
 }
@@ -0,0 +1,108 @@
+use std::io;
+
+use log::{Level, Record};
+
+/// A system logging service warnings and errors can be forwarded to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemLog {
+    /// The local syslog daemon, through `/dev/log`
+    Syslog,
+    /// The systemd journal, through its native protocol
+    Journald,
+}
+
+/// Name records are logged under
+const IDENTIFIER: &str = "rumkinst";
+
+/// A connection to a [`SystemLog`], records are sent as single datagrams
+pub(super) struct SystemLogger {
+    kind: SystemLog,
+    #[cfg(unix)]
+    socket: std::os::unix::net::UnixDatagram,
+}
+
+impl SystemLogger {
+    #[cfg(unix)]
+    pub(super) fn connect(kind: SystemLog) -> io::Result<Self> {
+        let path = match kind {
+            SystemLog::Syslog => "/dev/log",
+            SystemLog::Journald => "/run/systemd/journal/socket",
+        };
+        let socket = std::os::unix::net::UnixDatagram::unbound()?;
+        socket.connect(path)?;
+        Ok(Self { kind, socket })
+    }
+
+    #[cfg(not(unix))]
+    pub(super) fn connect(kind: SystemLog) -> io::Result<Self> {
+        let _ = kind;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "syslog and journald are only available on unix",
+        ))
+    }
+
+    /// Send `record`, failures are ignored as the terminal still shows it
+    pub(super) fn send(&self, record: &Record) {
+        let message = match self.kind {
+            SystemLog::Syslog => syslog_message(record),
+            SystemLog::Journald => journal_message(record),
+        };
+        #[cfg(unix)]
+        let _ = self.socket.send(&message);
+        #[cfg(not(unix))]
+        let _ = message;
+    }
+}
+
+/// Syslog severity of `level`
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// `record` in the format local syslog daemons accept, logged by the user facility
+fn syslog_message(record: &Record) -> Vec<u8> {
+    const USER_FACILITY: u8 = 1;
+    format!(
+        "<{}>{IDENTIFIER}[{}]: {}: {}",
+        USER_FACILITY * 8 + severity(record.level()),
+        std::process::id(),
+        record.target(),
+        record.args()
+    )
+    .into_bytes()
+}
+
+/// `record` in the native journal protocol, one field per line
+fn journal_message(record: &Record) -> Vec<u8> {
+    let mut message = vec![];
+    let mut field = |name: &str, value: &str| {
+        // Values spanning lines are written as their length followed by the raw bytes
+        match value.contains('\n') {
+            true => {
+                message.extend_from_slice(name.as_bytes());
+                message.push(b'\n');
+                message.extend_from_slice(&(value.len() as u64).to_le_bytes());
+                message.extend_from_slice(value.as_bytes());
+            }
+            false => message.extend_from_slice(format!("{name}={value}").as_bytes()),
+        }
+        message.push(b'\n');
+    };
+    field("MESSAGE", &record.args().to_string());
+    field("PRIORITY", &severity(record.level()).to_string());
+    field("SYSLOG_IDENTIFIER", IDENTIFIER);
+    field("RUMKINST_TARGET", record.target());
+    if let Some(file) = record.file() {
+        field("CODE_FILE", file);
+    }
+    if let Some(line) = record.line() {
+        field("CODE_LINE", &line.to_string());
+    }
+    message
+}
@@ -0,0 +1,43 @@
+use std::io::{self, IsTerminal, Write};
+
+use rumkinst::{Confirm, progress_log::suspend_progress, tr};
+
+/// Asks on the terminal before anything is replaced or removed, unless `--yes` was given
+///
+/// Without a terminal to ask on, such as in CI, everything goes ahead as if `--yes` was given.
+pub struct TerminalConfirm {
+    assume_yes: bool,
+}
+
+impl TerminalConfirm {
+    pub fn new(assume_yes: bool) -> Self {
+        Self { assume_yes }
+    }
+}
+
+impl Confirm for TerminalConfirm {
+    fn confirm(&self, question: &str) -> bool {
+        if self.assume_yes {
+            return true;
+        }
+        if !io::stdin().is_terminal() || !io::stderr().is_terminal() {
+            log::info!("{}", tr!("prompt-no-terminal", question = question));
+            return true;
+        }
+
+        suspend_progress(|| {
+            eprint!("{question} {} ", tr!("prompt-choices"));
+            io::stderr().flush().ok();
+            let mut answer = String::new();
+            if io::stdin().read_line(&mut answer).is_err() {
+                return false;
+            }
+            let answer = answer.trim().to_lowercase();
+            let yes = tr!("prompt-yes-answers");
+            ["y", "yes"]
+                .into_iter()
+                .chain(yes.split(',').map(str::trim))
+                .any(|yes| yes == answer)
+        })
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use jiff::{Timestamp, fmt::rfc2822::DateTimePrinter};
+use serde_json::{Value, json};
+
+use super::{artifacts, encode_url_segment, expand_template};
+use crate::{
+    BuildOutput, RumkinstError,
+    config::{Config, FeedConfig, FeedFormat},
+};
+
+const SPARKLE_NAMESPACE: &str = "http://www.andymatuschak.org/xml-namespaces/sparkle";
+const RUMKINST_NAMESPACE: &str = "https://github.com/GlitchlessCode/rumkinst";
+
+/// An artifact as listed in the update feed
+struct FeedArtifact {
+    kind: &'static str,
+    name: String,
+    url: String,
+    size: u64,
+    media_type: &'static str,
+    algorithm: String,
+    digest: String,
+}
+
+/// The release being added to the update feed
+struct FeedRelease<'a> {
+    version: &'a str,
+    channel: Option<&'a str>,
+    published_at: Timestamp,
+    artifacts: Vec<FeedArtifact>,
+}
+
+/// Add the artifacts of `output` to the update feed configured by `[feed]`, replacing any
+/// earlier entry for the same version and channel. Returns the path of the feed, or `None` if
+/// there was nothing to add.
+///
+/// Every other entry in the feed is left as it was, so the feed keeps growing with each release.
+pub(crate) fn update_feed(
+    config: &Config,
+    output: &BuildOutput,
+) -> Result<Option<PathBuf>, RumkinstError> {
+    let feed = config
+        .feed
+        .as_ref()
+        .expect("an update feed should only be updated when [feed] is set");
+    let version = config.version().ok_or(RumkinstError::ConfigMissing {
+        key: "package.version",
+        needed_by: "an update feed",
+    })?;
+    let path = config.resolve(&feed.path);
+    let feed_error = |source| RumkinstError::Feed {
+        path: path.clone(),
+        source,
+    };
+
+    let mut listed = vec![];
+    for (kind, artifact, media_type) in artifacts(config, output) {
+        let name = artifact
+            .path
+            .file_name()
+            .expect("artifacts should always have a file name")
+            .to_string_lossy()
+            .into_owned();
+        let size = fs::metadata(&artifact.path).map_err(feed_error)?.len();
+        listed.push(FeedArtifact {
+            kind,
+            url: artifact_url(config, feed, output, &name)?,
+            name,
+            size,
+            media_type,
+            algorithm: artifact.algorithm.clone(),
+            digest: artifact.digest.clone(),
+        });
+    }
+    if listed.is_empty() {
+        log::debug!("no artifacts to add to the update feed");
+        return Ok(None);
+    }
+
+    let existing = match fs::read_to_string(&path) {
+        Ok(existing) => Some(existing),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => return Err(feed_error(err)),
+    };
+    let release = FeedRelease {
+        version,
+        channel: feed.channel.as_deref(),
+        published_at: Timestamp::now(),
+        artifacts: listed,
+    };
+    let updated = match feed.format {
+        FeedFormat::Json => update_json(config, &release, existing.as_deref()),
+        FeedFormat::Appcast => update_appcast(config, &release, existing.as_deref()),
+    }
+    .map_err(feed_error)?;
+
+    log::info!("Adding {version} to the update feed at {path:?}");
+    write_replacing(&path, &updated).map_err(feed_error)?;
+    Ok(Some(path))
+}
+
+/// Where the artifact called `name` can be downloaded from
+fn artifact_url(
+    config: &Config,
+    feed: &FeedConfig,
+    output: &BuildOutput,
+    name: &str,
+) -> Result<String, RumkinstError> {
+    let encoded = encode_url_segment(name);
+    if let Some(url) = &feed.url {
+        return expand_template(url, config, &encoded, "a feed.url template using {version}");
+    }
+    output
+        .uploaded_urls
+        .iter()
+        .find(|url| url.rsplit('/').next() == Some(encoded.as_str()))
+        .cloned()
+        .ok_or(RumkinstError::ConfigMissing {
+            key: "feed.url",
+            needed_by: "an update feed of artifacts not uploaded to S3 or over HTTP",
+        })
+}
+
+/// Write `contents` to a temporary file next to `path` and move it over `path`, so readers never
+/// see a half written feed
+fn write_replacing(path: &Path, contents: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    fs::write(&temporary, contents)?;
+    fs::rename(&temporary, path)
+}
+
+/// The JSON feed in `existing`, or a new one, with `release` first in its `releases`
+fn update_json(
+    config: &Config,
+    release: &FeedRelease,
+    existing: Option<&str>,
+) -> io::Result<String> {
+    let mut document = match existing {
+        Some(existing) => serde_json::from_str(existing)?,
+        None => json!({ "name": config.get_name(), "releases": [] }),
+    };
+    let releases = document
+        .get_mut("releases")
+        .and_then(Value::as_array_mut)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the feed has no `releases` array",
+            )
+        })?;
+    releases.retain(|entry| {
+        entry["version"] != release.version || entry["channel"].as_str() != release.channel
+    });
+
+    let artifacts = release
+        .artifacts
+        .iter()
+        .map(|artifact| {
+            json!({
+                "kind": artifact.kind,
+                "name": artifact.name,
+                "url": artifact.url,
+                "size": artifact.size,
+                "media_type": artifact.media_type,
+                "algorithm": artifact.algorithm,
+                "digest": artifact.digest,
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut entry = json!({
+        "version": release.version,
+        "published_at": release.published_at.to_string(),
+        "artifacts": artifacts,
+    });
+    if let Some(channel) = release.channel {
+        entry["channel"] = json!(channel);
+    }
+    releases.insert(0, entry);
+
+    Ok(serde_json::to_string_pretty(&document)? + "\n")
+}
+
+/// The appcast in `existing`, or a new one, with an item for each artifact of `release` first
+///
+/// Items already in the appcast are kept exactly as they were, only those for the same version
+/// and channel as `release` are dropped.
+fn update_appcast(
+    config: &Config,
+    release: &FeedRelease,
+    existing: Option<&str>,
+) -> io::Result<String> {
+    let version = escape(release.version);
+    let channel = release.channel.map(escape);
+    let kept = existing
+        .map(items)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|item| {
+            element(item, "sparkle:version") != Some(version.as_str())
+                || element(item, "sparkle:channel") != channel.as_deref()
+        });
+    let published_at = DateTimePrinter::new()
+        .timestamp_to_string(&release.published_at)
+        .map_err(io::Error::other)?;
+
+    let mut appcast = String::new();
+    let mut line = |text: &str| {
+        appcast.push_str(text);
+        appcast.push('\n');
+    };
+
+    line("<?xml version=\"1.0\" encoding=\"utf-8\"?>");
+    line(&format!(
+        "<rss version=\"2.0\" xmlns:sparkle=\"{SPARKLE_NAMESPACE}\" xmlns:rumkinst=\"{RUMKINST_NAMESPACE}\">"
+    ));
+    line("  <channel>");
+    line(&format!("    <title>{}</title>", escape(config.get_name())));
+    for artifact in &release.artifacts {
+        line("    <item>");
+        line(&format!(
+            "      <title>{} {version}</title>",
+            escape(config.get_name())
+        ));
+        line(&format!("      <pubDate>{published_at}</pubDate>"));
+        line(&format!(
+            "      <sparkle:version>{version}</sparkle:version>"
+        ));
+        if let Some(channel) = &channel {
+            line(&format!(
+                "      <sparkle:channel>{channel}</sparkle:channel>"
+            ));
+        }
+        let os = match artifact.kind {
+            "windows-installer" => " sparkle:os=\"windows\"",
+            _ => "",
+        };
+        line(&format!(
+            "      <enclosure url=\"{}\" length=\"{}\" type=\"{}\"{os} />",
+            escape(&artifact.url),
+            artifact.size,
+            artifact.media_type
+        ));
+        line(&format!(
+            "      <rumkinst:digest algorithm=\"{}\">{}</rumkinst:digest>",
+            escape(&artifact.algorithm),
+            artifact.digest
+        ));
+        line("    </item>");
+    }
+    for item in kept {
+        line(&format!("    {item}"));
+    }
+    line("  </channel>");
+    line("</rss>");
+
+    Ok(appcast)
+}
+
+/// Every `<item>` in `appcast`, from its opening tag to its closing tag
+fn items(appcast: &str) -> Vec<&str> {
+    let mut items = vec![];
+    let mut rest = appcast;
+    while let Some(start) = rest.find("<item>") {
+        let Some(length) = rest[start..].find("</item>") else {
+            break;
+        };
+        let end = start + length + "</item>".len();
+        items.push(&rest[start..end]);
+        rest = &rest[end..];
+    }
+    items
+}
+
+/// Text inside the first `<name>` element of `item`, still escaped
+fn element<'a>(item: &'a str, name: &str) -> Option<&'a str> {
+    let open = format!("<{name}>");
+    let start = item.find(&open)? + open.len();
+    let length = item[start..].find(&format!("</{name}>"))?;
+    Some(item[start..start + length].trim())
+}
+
+/// Escape `text` for use in XML text and attribute values
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
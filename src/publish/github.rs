@@ -0,0 +1,110 @@
+use std::{fs, path::Path, process::Command};
+
+use super::{PublishedFile, confirm_replace, file_name, release_tag, run_tool};
+use crate::{Confirm, RumkinstError, config::Config};
+
+/// Upload `files` to the configured GitHub release through the `gh` CLI, creating the release
+/// with `notes` first if it does not exist yet, and return the URL of the release
+///
+/// Credentials are whatever `gh auth login` stored, or `GH_TOKEN`. Files already on the release
+/// with the same size are left alone, and other files of the same name are replaced once
+/// `confirm` agrees, so a failed publish can be re-run as is.
+pub(crate) fn publish_github_release(
+    config: &Config,
+    out_dir: &Path,
+    files: &[PublishedFile],
+    notes: &str,
+    confirm: &dyn Confirm,
+) -> Result<String, RumkinstError> {
+    let github = config
+        .publish
+        .github
+        .as_ref()
+        .expect("a release should only be published when [publish.github] is set");
+    let tag = release_tag(
+        config,
+        &github.tag,
+        "a GitHub release without publish.github.tag",
+    )?;
+    let publish_error = |source| RumkinstError::Publish {
+        target: format!("GitHub release {tag} of {}", github.repository),
+        source,
+    };
+    let gh = |subcommand: &str| {
+        let mut command = Command::new("gh");
+        command
+            .arg("release")
+            .arg(subcommand)
+            .arg(&tag)
+            .arg("--repo")
+            .arg(&github.repository);
+        command
+    };
+
+    let mut view = gh("view");
+    view.arg("--json")
+        .arg("assets")
+        .arg("--jq")
+        .arg(r#".assets[] | "\(.size)\t\(.name)""#);
+    let assets = match run_tool(&mut view, out_dir) {
+        Ok(assets) => Some(assets),
+        Err(err) if err.stderr.contains("release not found") => None,
+        Err(err) => return Err(publish_error(err.into())),
+    };
+    let mut files = files.iter().collect::<Vec<_>>();
+    if let Some(assets) = assets {
+        let assets = assets
+            .lines()
+            .filter_map(|asset| asset.split_once('\t'))
+            .collect::<Vec<_>>();
+        files.retain(|file| {
+            let name = file_name(file);
+            let uploaded = assets.iter().any(|(size, asset)| {
+                *asset == name
+                    && fs::metadata(out_dir.join(&name))
+                        .is_ok_and(|metadata| metadata.len().to_string() == *size)
+            });
+            if uploaded {
+                log::info!("\"{name}\" is already on GitHub release \"{tag}\", skipping it");
+            }
+            !uploaded
+        });
+        confirm_replace(
+            confirm,
+            &format!("GitHub release \"{tag}\""),
+            files.iter().copied(),
+            assets.iter().map(|(_, name)| *name),
+        )?;
+    } else {
+        log::info!("Creating GitHub release \"{tag}\" in {}", github.repository);
+        let title = match config.version() {
+            Some(version) => format!("{} {version}", config.get_name()),
+            None => tag.clone(),
+        };
+        let mut create = gh("create");
+        create.arg("--title").arg(title).arg("--notes").arg(notes);
+        if github.draft {
+            create.arg("--draft");
+        }
+        if github.prerelease {
+            create.arg("--prerelease");
+        }
+        run_tool(&mut create, out_dir).map_err(|err| publish_error(err.into()))?;
+    }
+
+    if !files.is_empty() {
+        log::info!(
+            "Uploading {} files to GitHub release \"{tag}\"",
+            files.len()
+        );
+        let mut upload = gh("upload");
+        upload
+            .arg("--clobber")
+            .args(files.iter().copied().map(file_name));
+        run_tool(&mut upload, out_dir).map_err(|err| publish_error(err.into()))?;
+    }
+
+    let mut view = gh("view");
+    view.arg("--json").arg("url").arg("--jq").arg(".url");
+    run_tool(&mut view, out_dir).map_err(|err| publish_error(err.into()))
+}
@@ -0,0 +1,240 @@
+use std::{env, path::Path, process::Command};
+
+use serde_json::{Value, json};
+
+use super::confirm_replace;
+use super::{
+    PublishedFile, ToolError, curl_quote, encode_url_segment, file_name, release_tag,
+    run_tool_with_input, run_tool_with_retries,
+};
+use crate::{
+    CancelHandle, Confirm, RumkinstError,
+    config::{Config, GitlabPublishConfig},
+    secrets::{read_secret, register_secret},
+};
+
+/// Upload `files` to the project's generic package registry, then link each of them from the
+/// release for the configured tag, creating it with `notes` if it does not exist yet. Returns the
+/// URL of the release.
+///
+/// Links already on the release are pointed at the new uploads once `confirm` agrees, so a failed
+/// publish can be re-run as is.
+pub(crate) fn publish_gitlab_release(
+    config: &Config,
+    out_dir: &Path,
+    files: &[PublishedFile],
+    notes: &str,
+    confirm: &dyn Confirm,
+    cancel: &CancelHandle,
+) -> Result<String, RumkinstError> {
+    let gitlab = config
+        .publish
+        .gitlab
+        .as_ref()
+        .expect("a release should only be published when [publish.gitlab] is set");
+    let version = config.version().ok_or(RumkinstError::ConfigMissing {
+        key: "package.version",
+        needed_by: "a GitLab generic package",
+    })?;
+    let tag = release_tag(
+        config,
+        &gitlab.tag,
+        "a GitLab release without publish.gitlab.tag",
+    )?;
+    let api = GitlabApi::new(config, gitlab, out_dir)?;
+    let publish_error = |err: ToolError| match cancel.is_cancelled() {
+        true => RumkinstError::Cancelled,
+        false => RumkinstError::Publish {
+            target: format!("GitLab release {tag} of {}", gitlab.project),
+            source: err.into(),
+        },
+    };
+
+    let package = gitlab.package.as_deref().unwrap_or(config.get_name());
+    let package_url = format!(
+        "{}/packages/generic/{}/{}",
+        api.project_url,
+        encode_url_segment(package),
+        encode_url_segment(version)
+    );
+
+    // Looked up before uploading, so nothing is replaced unless that is confirmed
+    let release_path = format!("releases/{}", encode_url_segment(&tag));
+    let (status, release) = api
+        .request("GET", &release_path, None)
+        .map_err(publish_error)?;
+    let existing = release["assets"]["links"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    if status == 200 {
+        confirm_replace(
+            confirm,
+            &format!("GitLab release \"{tag}\""),
+            files,
+            existing.iter().filter_map(|link| link["name"].as_str()),
+        )?;
+    }
+
+    let mut links = vec![];
+    for file in files {
+        cancel.check()?;
+
+        let name = file_name(file);
+        let url = format!("{package_url}/{}", encode_url_segment(&name));
+        log::info!("Uploading \"{name}\" to GitLab package \"{package}\" {version}");
+        let command = || {
+            let mut command = Command::new("curl");
+            command
+                .arg("--fail-with-body")
+                .arg("--silent")
+                .arg("--show-error")
+                .arg("--config")
+                .arg("-")
+                .arg("--upload-file")
+                .arg(&name)
+                .arg(&url);
+            command
+        };
+        run_tool_with_retries(command, out_dir, Some(&api.auth), cancel).map_err(publish_error)?;
+        links.push(json!({ "name": name, "url": url, "link_type": "package" }));
+    }
+    cancel.check()?;
+
+    let release = match status {
+        200 => {
+            log::info!(
+                "Linking {} files from GitLab release \"{tag}\"",
+                links.len()
+            );
+            for link in links {
+                let current = existing
+                    .iter()
+                    .find(|current| current["name"] == link["name"]);
+                let (method, path) = match current.and_then(|current| current["id"].as_u64()) {
+                    Some(id) => ("PUT", format!("{release_path}/assets/links/{id}")),
+                    None => ("POST", format!("{release_path}/assets/links")),
+                };
+                api.expect(method, &path, link).map_err(publish_error)?;
+            }
+            release
+        }
+        404 => {
+            log::info!("Creating GitLab release \"{tag}\" in {}", gitlab.project);
+            let name = format!("{} {version}", config.get_name());
+            let mut body = json!({
+                "tag_name": tag,
+                "name": name,
+                "description": notes,
+                "assets": { "links": links },
+            });
+            if let Some(git_ref) = &gitlab.git_ref {
+                body["ref"] = json!(git_ref);
+            }
+            api.expect("POST", "releases", body)
+                .map_err(publish_error)?
+        }
+        status => {
+            return Err(publish_error(ToolError::unexpected_status(
+                status, &release,
+            )));
+        }
+    };
+
+    Ok(release["_links"]["self"]
+        .as_str()
+        .map(str::to_string)
+        .unwrap_or_else(|| {
+            format!(
+                "{}/{}/-/releases/{}",
+                gitlab.url,
+                gitlab.project,
+                encode_url_segment(&tag)
+            )
+        }))
+}
+
+/// Requests against the REST API of a single GitLab project, made through `curl`
+struct GitlabApi<'a> {
+    project_url: String,
+    /// Curl config lines authenticating every request, passed on stdin to keep them private
+    auth: String,
+    out_dir: &'a Path,
+}
+
+impl<'a> GitlabApi<'a> {
+    fn new(
+        config: &Config,
+        gitlab: &GitlabPublishConfig,
+        out_dir: &'a Path,
+    ) -> Result<Self, RumkinstError> {
+        let header = match read_secret(&gitlab.token, config) {
+            Ok(token) => format!("PRIVATE-TOKEN: {token}"),
+            Err(err) => match env::var("CI_JOB_TOKEN") {
+                Ok(token) => {
+                    register_secret(&token);
+                    format!("JOB-TOKEN: {token}")
+                }
+                // Not running in GitLab CI, so the token is needed
+                Err(_) => return Err(err),
+            },
+        };
+
+        Ok(Self {
+            project_url: format!(
+                "{}/api/v4/projects/{}",
+                gitlab.url,
+                encode_url_segment(&gitlab.project)
+            ),
+            auth: format!("header = {}\n", curl_quote(&header)),
+            out_dir,
+        })
+    }
+
+    /// Send a request to `path` under the project, returning the status and the JSON response
+    fn request(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<(u16, Value), ToolError> {
+        let mut config = self.auth.clone();
+        if let Some(body) = body {
+            config.push_str("header = \"Content-Type: application/json\"\n");
+            config.push_str(&format!(
+                "data-binary = {}\n",
+                curl_quote(&body.to_string())
+            ));
+        }
+
+        let output = run_tool_with_input(
+            Command::new("curl")
+                .arg("--silent")
+                .arg("--show-error")
+                .arg("--config")
+                .arg("-")
+                .arg("--request")
+                .arg(method)
+                .arg("--write-out")
+                .arg("\n%{http_code}")
+                .arg(format!("{}/{path}", self.project_url)),
+            self.out_dir,
+            Some(&config),
+        )?;
+
+        let (response, status) = output.rsplit_once('\n').unwrap_or(("", &output));
+        let status = status.trim().parse().unwrap_or_default();
+        Ok((
+            status,
+            serde_json::from_str(response).unwrap_or(Value::Null),
+        ))
+    }
+
+    /// Send a request like [`GitlabApi::request`], failing unless it succeeds
+    fn expect(&self, method: &str, path: &str, body: Value) -> Result<Value, ToolError> {
+        match self.request(method, path, Some(body))? {
+            (200..=299, response) => Ok(response),
+            (status, response) => Err(ToolError::unexpected_status(status, &response)),
+        }
+    }
+}
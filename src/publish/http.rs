@@ -0,0 +1,119 @@
+use std::{path::Path, process::Command};
+
+use super::{
+    PublishedFile, curl_quote, encode_url_segment, expand_template, file_name,
+    run_tool_with_retries,
+};
+use crate::{
+    CancelHandle, RumkinstError,
+    config::{Config, HttpPublishConfig},
+    secrets::read_secret,
+};
+
+/// Upload every one of `files` with its own request through `curl`, returning the URL each one
+/// was sent to
+///
+/// Credentials are handed to curl on stdin, so they never show up in the process list.
+pub(crate) fn upload_over_http(
+    config: &Config,
+    out_dir: &Path,
+    files: &[PublishedFile],
+    cancel: &CancelHandle,
+) -> Result<Vec<String>, RumkinstError> {
+    let http = config
+        .publish
+        .http
+        .as_ref()
+        .expect("artifacts should only be uploaded when [publish.http] is set");
+    let credentials = credentials(config, http)?;
+
+    let mut urls = vec![];
+    for file in files {
+        cancel.check()?;
+
+        let name = file_name(file);
+        let url = expand(&http.url, config, &encode_url_segment(&name))?;
+        let mut headers = http
+            .headers
+            .iter()
+            .map(|(header, value)| Ok(format!("{header}: {}", expand(value, config, &name)?)))
+            .collect::<Result<Vec<_>, RumkinstError>>()?;
+        if !http
+            .headers
+            .keys()
+            .any(|header| header.eq_ignore_ascii_case("content-type"))
+        {
+            headers.push(format!("Content-Type: {}", file.media_type));
+        }
+        log::info!("Uploading \"{name}\" to {url}");
+
+        let command = || {
+            let mut command = Command::new("curl");
+            command
+                .arg("--fail-with-body")
+                .arg("--silent")
+                .arg("--show-error")
+                .arg("--request")
+                .arg(&http.method)
+                .arg("--upload-file")
+                .arg(&name);
+            for header in &headers {
+                command.arg("--header").arg(header);
+            }
+            if credentials.is_some() {
+                command.arg("--config").arg("-");
+            }
+            command.arg(&url);
+            command
+        };
+        run_tool_with_retries(command, out_dir, credentials.as_deref(), cancel).map_err(|err| {
+            match cancel.is_cancelled() {
+                true => RumkinstError::Cancelled,
+                false => RumkinstError::Publish {
+                    target: url.clone(),
+                    source: err.into(),
+                },
+            }
+        })?;
+
+        urls.push(url);
+    }
+    Ok(urls)
+}
+
+/// Replace the placeholders in a `[publish.http]` template
+fn expand(template: &str, config: &Config, file: &str) -> Result<String, RumkinstError> {
+    expand_template(
+        template,
+        config,
+        file,
+        "a publish.http template using {version}",
+    )
+}
+
+/// The curl config lines holding the credentials named by `http`, if there are any
+fn credentials(config: &Config, http: &HttpPublishConfig) -> Result<Option<String>, RumkinstError> {
+    if let Some(token) = &http.token {
+        let header = format!("Authorization: Bearer {}", read_secret(token, config)?);
+        return Ok(Some(format!("header = {}\n", curl_quote(&header))));
+    }
+    match (&http.username, &http.password) {
+        (Some(username), Some(password)) => {
+            let user = format!(
+                "{}:{}",
+                read_secret(username, config)?,
+                read_secret(password, config)?
+            );
+            Ok(Some(format!("user = {}\n", curl_quote(&user))))
+        }
+        (None, None) => Ok(None),
+        (Some(_), None) => Err(RumkinstError::ConfigMissing {
+            key: "publish.http.password",
+            needed_by: "a publish.http upload with a username",
+        }),
+        (None, Some(_)) => Err(RumkinstError::ConfigMissing {
+            key: "publish.http.username",
+            needed_by: "a publish.http upload with a password",
+        }),
+    }
+}
@@ -0,0 +1,349 @@
+mod feed;
+mod github;
+mod gitlab;
+mod http;
+mod oras;
+mod s3;
+mod verify;
+
+use std::{
+    fmt,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus, Stdio},
+    thread,
+    time::Duration,
+};
+
+use serde_json::Value;
+
+use crate::{
+    ArchiveArtifact, BuildOutput, CancelHandle, Confirm, RumkinstError, config::Config, tr,
+};
+
+pub(crate) use feed::update_feed;
+pub(crate) use github::publish_github_release;
+pub(crate) use gitlab::publish_gitlab_release;
+pub(crate) use http::upload_over_http;
+pub(crate) use oras::push_to_registry;
+pub(crate) use s3::upload_to_s3;
+pub(crate) use verify::verify_before_publish;
+
+/// How many times an upload is attempted before giving up on it
+const UPLOAD_ATTEMPTS: u32 = 3;
+
+/// A finished artifact to publish, along with the media type it is served as
+pub(crate) struct PublishedFile {
+    pub(crate) path: PathBuf,
+    pub(crate) media_type: &'static str,
+}
+
+impl PublishedFile {
+    fn new(path: &Path, media_type: &'static str) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            media_type,
+        }
+    }
+}
+
+/// Every archive and package in `output`, along with its kind and the media type it is served as
+fn artifacts<'a>(
+    config: &Config,
+    output: &'a BuildOutput,
+) -> Vec<(&'static str, &'a ArchiveArtifact, &'static str)> {
+    let artifacts = [
+        (
+            "archive",
+            &output.archive,
+            config.archive_format().media_type(),
+        ),
+        ("delta", &output.delta, config.archive_format().media_type()),
+        ("deb", &output.deb, "application/vnd.debian.binary-package"),
+        (
+            "windows-installer",
+            &output.windows_installer,
+            "application/vnd.microsoft.portable-executable",
+        ),
+    ];
+    let targeted = output.target_archives.iter().map(|target| {
+        (
+            "archive",
+            &target.archive,
+            config.archive_format().media_type(),
+        )
+    });
+    artifacts
+        .into_iter()
+        .filter_map(|(kind, artifact, media_type)| {
+            artifact
+                .as_ref()
+                .map(|artifact| (kind, artifact, media_type))
+        })
+        .chain(targeted)
+        .collect()
+}
+
+/// Every artifact of `output` worth publishing, each followed by its checksum, signature and
+/// listing, and the delta manifest last
+pub(crate) fn published_files(config: &Config, output: &BuildOutput) -> Vec<PublishedFile> {
+    let mut files = vec![];
+    for (_, artifact, media_type) in artifacts(config, output) {
+        let ArchiveArtifact {
+            path,
+            checksum_path,
+            signature_path,
+            listing_path,
+            ..
+        } = artifact;
+
+        files.push(PublishedFile::new(path, media_type));
+        files.push(PublishedFile::new(checksum_path, "text/plain"));
+        if let Some(signature_path) = signature_path {
+            files.push(PublishedFile::new(
+                signature_path,
+                "application/octet-stream",
+            ));
+        }
+        if let Some(listing_path) = listing_path {
+            files.push(PublishedFile::new(listing_path, "text/plain"));
+        }
+    }
+    if let Some(manifest) = &output.delta_manifest {
+        files.push(PublishedFile::new(manifest, "application/json"));
+    }
+    for artifact in &output.plugin_artifacts {
+        files.push(PublishedFile::new(
+            &artifact.path,
+            "application/octet-stream",
+        ));
+    }
+    files
+}
+
+/// Ask `confirm` whether to replace the files of `files` already among `existing` on `release`,
+/// failing with [`RumkinstError::Declined`] if it does not agree
+fn confirm_replace<'a>(
+    confirm: &dyn Confirm,
+    release: &str,
+    files: impl IntoIterator<Item = &'a PublishedFile>,
+    existing: impl Iterator<Item = &'a str>,
+) -> Result<(), RumkinstError> {
+    let existing = existing.collect::<Vec<_>>();
+    let replaced = files
+        .into_iter()
+        .map(file_name)
+        .filter(|name| existing.contains(&name.as_str()))
+        .collect::<Vec<_>>();
+    if replaced.is_empty() {
+        return Ok(());
+    }
+    let question = match replaced.as_slice() {
+        [name] => tr!(
+            "confirm-replace-asset",
+            name = name.as_str(),
+            release = release
+        ),
+        replaced => tr!(
+            "confirm-replace-assets",
+            count = replaced.len(),
+            release = release
+        ),
+    };
+    let replaced = match replaced.as_slice() {
+        [name] => format!("\"{name}\""),
+        replaced => format!("{} existing assets", replaced.len()),
+    };
+    match confirm.confirm(&question) {
+        true => Ok(()),
+        false => Err(RumkinstError::Declined {
+            action: format!("replace {replaced} on {release}"),
+        }),
+    }
+}
+
+/// File name of `file`, which publishing tools are given instead of its full path
+fn file_name(file: &PublishedFile) -> String {
+    file.path
+        .file_name()
+        .expect("published files should always have a file name")
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Tag of a release, `v` followed by the package version unless `tag` is configured
+fn release_tag(
+    config: &Config,
+    tag: &Option<String>,
+    needed_by: &'static str,
+) -> Result<String, RumkinstError> {
+    if let Some(tag) = tag {
+        return Ok(tag.clone());
+    }
+    config
+        .version()
+        .map(|version| format!("v{version}"))
+        .ok_or(RumkinstError::ConfigMissing {
+            key: "package.version",
+            needed_by,
+        })
+}
+
+/// Replace `{name}`, `{version}` and `{file}` in `template`, `file` is inserted as is
+fn expand_template(
+    template: &str,
+    config: &Config,
+    file: &str,
+    needed_by: &'static str,
+) -> Result<String, RumkinstError> {
+    let mut expanded = template
+        .replace("{name}", config.get_name())
+        .replace("{file}", file);
+    if expanded.contains("{version}") {
+        let version = config.version().ok_or(RumkinstError::ConfigMissing {
+            key: "package.version",
+            needed_by,
+        })?;
+        expanded = expanded.replace("{version}", version);
+    }
+    Ok(expanded)
+}
+
+/// Quote `value` for a curl config file
+fn curl_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Percent-encode everything in `segment` but the characters URLs leave unreserved
+fn encode_url_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(
+            |byte| match byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+                true => (byte as char).to_string(),
+                false => format!("%{byte:02X}"),
+            },
+        )
+        .collect()
+}
+
+/// A publishing tool that could not be run, or exited unsuccessfully
+#[derive(Debug)]
+pub(crate) struct ToolError {
+    message: String,
+    stderr: String,
+    /// `None` if the tool could not be run at all, or its response was rejected
+    status: Option<ExitStatus>,
+}
+
+impl ToolError {
+    /// The tool ran, but the service answered a request with an unexpected HTTP `status`
+    fn unexpected_status(status: u16, response: &Value) -> Self {
+        let reason = response["message"]
+            .as_str()
+            .or(response["error"].as_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| response.to_string());
+        Self {
+            message: format!("unexpected response {status}: {reason}"),
+            stderr: String::new(),
+            status: None,
+        }
+    }
+}
+
+impl fmt::Display for ToolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ToolError {}
+
+/// Run `command` from `out_dir`, returning what it printed to stdout
+///
+/// Tools record the paths they are given as the names of what they upload, so every published
+/// file is passed by file name and the tool runs from the directory holding them.
+fn run_tool(command: &mut Command, out_dir: &Path) -> Result<String, ToolError> {
+    run_tool_with_input(command, out_dir, None)
+}
+
+/// Run `command` like [`run_tool`], writing `input` to its stdin
+///
+/// Secrets are passed this way rather than as arguments, which other users on the machine can
+/// see.
+fn run_tool_with_input(
+    command: &mut Command,
+    out_dir: &Path,
+    input: Option<&str>,
+) -> Result<String, ToolError> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let spawn_error = |err: io::Error| ToolError {
+        message: format!("failed to run `{program}`: {err}"),
+        stderr: String::new(),
+        status: None,
+    };
+
+    let mut child = command
+        .current_dir(out_dir)
+        .stdin(match input {
+            Some(_) => Stdio::piped(),
+            None => Stdio::null(),
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(spawn_error)?;
+    if let Some(input) = input {
+        let mut stdin = child
+            .stdin
+            .take()
+            .expect("stdin is piped when there is input");
+        // If the tool stopped reading early, its exit status says why
+        stdin.write_all(input.as_bytes()).ok();
+    }
+    let output = child.wait_with_output().map_err(spawn_error)?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        let subcommand = command
+            .get_args()
+            .next()
+            .map(|arg| arg.to_string_lossy())
+            .filter(|arg| !arg.starts_with('-'))
+            .map(|arg| format!(" {arg}"))
+            .unwrap_or_default();
+        return Err(ToolError {
+            message: format!("`{program}{subcommand}` {}: {stderr}", output.status),
+            stderr,
+            status: Some(output.status),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Run the command made by `command` like [`run_tool_with_input`], running it again after a
+/// growing delay if it fails, for uploads and downloads that may hit transient network or
+/// service errors
+pub(crate) fn run_tool_with_retries(
+    command: impl Fn() -> Command,
+    out_dir: &Path,
+    input: Option<&str>,
+    cancel: &CancelHandle,
+) -> Result<String, ToolError> {
+    let mut attempt = 1;
+    loop {
+        match run_tool_with_input(&mut command(), out_dir, input) {
+            Err(err) if err.status.is_some() && attempt < UPLOAD_ATTEMPTS => {
+                let delay = Duration::from_secs(2u64.pow(attempt));
+                log::warn!("{err}, retrying in {}s", delay.as_secs());
+                thread::sleep(delay);
+                if cancel.is_cancelled() {
+                    return Err(err);
+                }
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::{path::Path, process::Command};
+
+use super::{PublishedFile, file_name, run_tool};
+use crate::{
+    RumkinstError, VcsInfo,
+    config::{Config, OciPublishConfig},
+    installer_gen::sanitize_tag,
+};
+
+/// Push `files` to the configured registry as a single OCI artifact, through the `oras` CLI
+///
+/// Registry credentials are whatever `oras login` or `docker login` stored. Every file must be
+/// inside `out_dir`, and is named after its file name in the registry.
+pub(crate) fn push_to_registry(
+    config: &Config,
+    out_dir: &Path,
+    files: &[PublishedFile],
+    vcs: Option<&VcsInfo>,
+) -> Result<String, RumkinstError> {
+    let registry = config
+        .publish
+        .oci
+        .as_ref()
+        .expect("artifacts should only be pushed when [publish.oci] is set");
+    let reference = artifact_reference(config, registry);
+    let publish_error = |source| RumkinstError::Publish {
+        target: reference.clone(),
+        source,
+    };
+
+    log::info!("Pushing {} files to \"{reference}\"", files.len());
+    let mut command = Command::new("oras");
+    command
+        .arg("push")
+        .arg("--artifact-type")
+        .arg(&registry.artifact_type);
+    if registry.plain_http {
+        command.arg("--plain-http");
+    }
+    if let Some(version) = config.version() {
+        command
+            .arg("--annotation")
+            .arg(format!("org.opencontainers.image.version={version}"));
+    }
+    if let Some(vcs) = vcs {
+        command
+            .arg("--annotation")
+            .arg(format!("org.opencontainers.image.revision={}", vcs.commit));
+    }
+    command.arg(&reference);
+    for file in files {
+        command.arg(format!("{}:{}", file_name(file), file.media_type));
+    }
+
+    run_tool(&mut command, out_dir).map_err(|err| publish_error(err.into()))?;
+    Ok(reference)
+}
+
+/// `reference` from the config, tagged with the package version if it has no tag or digest
+fn artifact_reference(config: &Config, registry: &OciPublishConfig) -> String {
+    let reference = registry.reference.as_str();
+    let repository = reference.rsplit('/').next().unwrap_or(reference);
+    if repository.contains([':', '@']) {
+        return reference.to_string();
+    }
+
+    format!(
+        "{reference}:{}",
+        sanitize_tag(config.version().unwrap_or("latest"))
+    )
+}
@@ -0,0 +1,91 @@
+use std::{env, path::Path, process::Command};
+
+use super::{PublishedFile, encode_url_segment, file_name, run_tool_with_retries};
+use crate::{
+    CancelHandle, RumkinstError,
+    config::{Config, S3PublishConfig},
+};
+
+/// Upload every one of `files` to the configured bucket through the `aws` CLI, returning the URL
+/// of each uploaded object
+///
+/// Each upload is retried a few times before giving up, since storage services regularly fail
+/// single requests under load.
+pub(crate) fn upload_to_s3(
+    config: &Config,
+    out_dir: &Path,
+    files: &[PublishedFile],
+    cancel: &CancelHandle,
+) -> Result<Vec<String>, RumkinstError> {
+    let s3 = config
+        .publish
+        .s3
+        .as_ref()
+        .expect("artifacts should only be uploaded when [publish.s3] is set");
+    let region = s3
+        .region
+        .clone()
+        .or_else(|| env::var("AWS_REGION").ok())
+        .or_else(|| env::var("AWS_DEFAULT_REGION").ok());
+
+    let mut urls = vec![];
+    for file in files {
+        cancel.check()?;
+
+        let name = file_name(file);
+        let key = match s3.prefix.is_empty() {
+            true => name.clone(),
+            false => format!("{}/{name}", s3.prefix),
+        };
+        let destination = format!("s3://{}/{key}", s3.bucket);
+        log::info!("Uploading \"{name}\" to {destination}");
+
+        let command = || {
+            let mut command = Command::new("aws");
+            command
+                .arg("s3")
+                .arg("cp")
+                .arg(&name)
+                .arg(&destination)
+                .arg("--content-type")
+                .arg(file.media_type)
+                .arg("--only-show-errors");
+            if s3.public {
+                command.arg("--acl").arg("public-read");
+            }
+            if let Some(endpoint) = &s3.endpoint {
+                command.arg("--endpoint-url").arg(endpoint);
+            }
+            if let Some(region) = &region {
+                command.arg("--region").arg(region);
+            }
+            command
+        };
+        run_tool_with_retries(command, out_dir, None, cancel).map_err(|err| {
+            match cancel.is_cancelled() {
+                true => RumkinstError::Cancelled,
+                false => RumkinstError::Publish {
+                    target: destination.clone(),
+                    source: err.into(),
+                },
+            }
+        })?;
+
+        urls.push(object_url(s3, region.as_deref(), &key));
+    }
+    Ok(urls)
+}
+
+/// URL of the object at `key`, path-style for custom endpoints and virtual-hosted for AWS
+fn object_url(s3: &S3PublishConfig, region: Option<&str>, key: &str) -> String {
+    let key = key
+        .split('/')
+        .map(encode_url_segment)
+        .collect::<Vec<_>>()
+        .join("/");
+    match (&s3.endpoint, region) {
+        (Some(endpoint), _) => format!("{endpoint}/{}/{key}", s3.bucket),
+        (None, Some(region)) => format!("https://{}.s3.{region}.amazonaws.com/{key}", s3.bucket),
+        (None, None) => format!("https://{}.s3.amazonaws.com/{key}", s3.bucket),
+    }
+}
@@ -0,0 +1,165 @@
+use std::{fs, path::Path};
+
+use super::artifacts;
+use crate::{
+    BuildOutput, RumkinstError,
+    config::Config,
+    installer_gen::{
+        Digester, MANIFEST_DIR, MANIFEST_NAME, Signer, parse_listing, read_archive_entries, to_hex,
+    },
+    progress_log::NoProgress,
+};
+
+/// Check every artifact of `output` against the digest recorded when it was made, its checksum
+/// file and its signature, so nothing tampered with or truncated on disk gets published
+///
+/// Archives with a listing are also read back entry by entry, so a corrupted archive is
+/// reported by the first entry that does not match its listing. Files made by plugins only have
+/// their digest checked.
+pub(crate) fn verify_before_publish(
+    config: &Config,
+    output: &BuildOutput,
+    digester: &dyn Digester,
+    signer: Option<&dyn Signer>,
+) -> Result<(), RumkinstError> {
+    for (_, artifact, _) in artifacts(config, output) {
+        let path = &artifact.path;
+        let mismatch = |reason: String| RumkinstError::Verification {
+            path: path.clone(),
+            reason,
+        };
+        log::debug!("verifying {path:?} before publishing");
+
+        let digest = fs::File::open(path)
+            .and_then(|mut file| digester.digest(&mut file, &NoProgress))
+            .map_err(|err| mismatch(format!("it could not be read: {err}")))?;
+        if to_hex(&digest) != artifact.digest {
+            let reason = match &artifact.listing_path {
+                Some(listing_path) => verify_entries(path, listing_path, digester).err(),
+                None => None,
+            };
+            return Err(mismatch(reason.unwrap_or_else(|| {
+                format!(
+                    "its {} digest is now {}, but was {} when it was made",
+                    artifact.algorithm,
+                    to_hex(&digest),
+                    artifact.digest
+                )
+            })));
+        }
+        if let Some(listing_path) = &artifact.listing_path {
+            verify_entries(path, listing_path, digester).map_err(mismatch)?;
+        }
+
+        let checksum = fs::read_to_string(&artifact.checksum_path)
+            .map_err(|err| mismatch(format!("its checksum file could not be read: {err}")))?;
+        let expected_name = file_name(path);
+        match checksum.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [listed, name] if *listed == artifact.digest && *name == expected_name => {}
+            _ => {
+                return Err(mismatch(format!(
+                    "it does not match its checksum file {:?}",
+                    file_name(&artifact.checksum_path)
+                )));
+            }
+        }
+
+        let Some(signature_path) = &artifact.signature_path else {
+            continue;
+        };
+        let signature = fs::read(signature_path)
+            .map_err(|err| mismatch(format!("its signature could not be read: {err}")))?;
+        if signature.is_empty() {
+            return Err(mismatch("its signature file is empty".to_string()));
+        }
+        let Some(signer) = signer else {
+            continue;
+        };
+        match signer.verify(path, &digest, &signature) {
+            Ok(true) => {}
+            Ok(false) => log::debug!("the signer can't check signatures, skipping {path:?}"),
+            Err(err) => return Err(mismatch(format!("its signature is not valid: {err}"))),
+        }
+    }
+
+    for artifact in &output.plugin_artifacts {
+        let path = &artifact.path;
+        log::debug!("verifying {path:?} before publishing");
+        let digest = fs::File::open(path)
+            .and_then(|mut file| digester.digest(&mut file, &NoProgress))
+            .map_err(|err| RumkinstError::Verification {
+                path: path.clone(),
+                reason: format!("it could not be read: {err}"),
+            })?;
+        if to_hex(&digest) != artifact.digest {
+            return Err(RumkinstError::Verification {
+                path: path.clone(),
+                reason: format!(
+                    "its {} digest is now {}, but was {} when plugin `{}` made it",
+                    digester.algorithm(),
+                    to_hex(&digest),
+                    artifact.digest,
+                    artifact.plugin
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Read every entry of the archive at `path` back, checking the gzip stream and tar headers, and
+/// compare them to the listing at `listing_path`, describing the first entry that differs
+fn verify_entries(path: &Path, listing_path: &Path, digester: &dyn Digester) -> Result<(), String> {
+    let listing = fs::read_to_string(listing_path)
+        .map_err(|err| format!("its listing could not be read: {err}"))?;
+    let listed = parse_listing(&listing).ok_or_else(|| {
+        format!(
+            "its listing {:?} is not in the format rumkinst writes",
+            file_name(listing_path)
+        )
+    })?;
+    let mut entries =
+        read_archive_entries(path, digester).map_err(|err| format!("it is corrupted: {err}"))?;
+    if let Some(manifest) = entries.iter().find(|entry| entry.name == MANIFEST_NAME) {
+        let digest = digester
+            .digest(&mut listing.as_bytes(), &NoProgress)
+            .map_err(|err| format!("its listing could not be read: {err}"))?;
+        if manifest.digest != to_hex(&digest) {
+            return Err("its embedded manifest does not match its listing".to_string());
+        }
+    }
+    // The embedded manifest and its signature are the only entries not listed
+    entries.retain(|entry| !entry.name.starts_with(&format!("{MANIFEST_DIR}/")));
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut entries = entries.into_iter().peekable();
+    for listed in listed.iter().filter(|listed| !listed.name.ends_with('/')) {
+        let entry = match entries.next_if(|entry| entry.name <= listed.name) {
+            Some(entry) if entry.name == listed.name => entry,
+            Some(entry) => return Err(format!("entry `{}` is not in its listing", entry.name)),
+            None => return Err(format!("entry `{}` of its listing is missing", listed.name)),
+        };
+        let differs = [
+            (entry.size != listed.size, "size"),
+            (entry.mode != listed.mode, "mode"),
+            (entry.digest != listed.digest, "contents"),
+        ];
+        if let Some((_, what)) = differs.into_iter().find(|(differs, _)| *differs) {
+            return Err(format!(
+                "the {what} of entry `{}` does not match its listing",
+                entry.name
+            ));
+        }
+    }
+    match entries.next() {
+        Some(entry) => Err(format!("entry `{}` is not in its listing", entry.name)),
+        None => Ok(()),
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .expect("artifacts should always have a file name")
+        .to_string_lossy()
+        .into_owned()
+}
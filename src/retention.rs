@@ -0,0 +1,218 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{Context, Result};
+use indicatif::HumanBytes;
+use rumkinst::{Confirm, error_log::Log, tr};
+
+/// A finished or failed run directory under `out/`
+struct Run {
+    path: PathBuf,
+    modified: SystemTime,
+    bytes: u64,
+}
+
+/// Remove the oldest run directories in `out_root` until at most `keep_runs` are left and they
+/// take at most `max_size` bytes together. `current` is never removed.
+///
+/// The runs are only removed once `confirm` agrees, and kept otherwise.
+pub fn prune_runs(
+    out_root: &Path,
+    current: &Path,
+    keep_runs: Option<usize>,
+    max_size: Option<u64>,
+    confirm: &dyn Confirm,
+) -> Result<()> {
+    if keep_runs.is_none() && max_size.is_none() {
+        return Ok(());
+    }
+
+    let mut runs = vec![];
+    for entry in fs::read_dir(out_root).with_context(|| format!("failed to read {out_root:?}"))? {
+        let entry = entry.with_context(|| format!("failed to read {out_root:?}"))?;
+        let path = entry.path();
+        // Anything but a plain directory, such as a link to the latest run, is not a run
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if !file_type.is_dir() || path == current {
+            continue;
+        }
+
+        let modified = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        runs.push(Run {
+            bytes: dir_size(&path),
+            path,
+            modified,
+        });
+    }
+    runs.sort_by_key(|run| std::cmp::Reverse(run.modified));
+
+    let mut kept = 1;
+    let mut kept_bytes = dir_size(current);
+    let mut outdated = vec![];
+    for run in runs {
+        let within_count = keep_runs.is_none_or(|keep_runs| kept < keep_runs);
+        let within_size = max_size.is_none_or(|max_size| kept_bytes + run.bytes <= max_size);
+        if within_count && within_size {
+            kept += 1;
+            kept_bytes += run.bytes;
+        } else {
+            outdated.push(run);
+        }
+    }
+    if outdated.is_empty() {
+        return Ok(());
+    }
+    let question = tr!(
+        "confirm-remove-runs",
+        count = outdated.len(),
+        size = HumanBytes(outdated.iter().map(|run| run.bytes).sum()).to_string()
+    );
+    if !confirm.confirm(&question) {
+        log::info!("{}", tr!("runs-kept"));
+        return Ok(());
+    }
+
+    let mut removed = 0;
+    for run in outdated {
+        log::debug!("removing old run {:?}", run.path);
+        let result = fs::remove_dir_all(&run.path)
+            .with_context(|| format!("failed to remove old run {:?}", run.path))
+            .warn();
+        if result.is_ok() {
+            removed += 1;
+        }
+    }
+
+    if removed > 0 {
+        log::info!(
+            "Removed {removed} old run{}",
+            if removed == 1 { "" } else { "s" }
+        );
+    }
+    Ok(())
+}
+
+/// Total size of every file under `path`, counting anything unreadable as empty
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(path) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => dir_size(&entry.path()),
+            Ok(_) => entry.metadata().map(|metadata| metadata.len()).unwrap_or(0),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use rumkinst::AssumeYes;
+
+    use super::*;
+
+    /// An `out/` directory in the temp directory, removed again once dropped
+    struct OutRoot(PathBuf);
+
+    impl OutRoot {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir()
+                .join(format!("rumkinst-retention-{}-{name}", std::process::id()));
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        /// Add a run holding `bytes` bytes, newer than every run added before
+        fn add_run(&self, name: &str, bytes: usize) -> PathBuf {
+            // Runs are ordered by modification time, so they need to be told apart
+            thread::sleep(Duration::from_millis(20));
+            let run = self.0.join(name);
+            fs::create_dir(&run).unwrap();
+            fs::write(run.join("archive"), vec![0; bytes]).unwrap();
+            run
+        }
+
+        /// Names of the runs left, sorted
+        fn runs(&self) -> Vec<String> {
+            let mut runs = fs::read_dir(&self.0)
+                .unwrap()
+                .map(|entry| entry.unwrap())
+                .filter(|entry| entry.file_type().unwrap().is_dir())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect::<Vec<_>>();
+            runs.sort();
+            runs
+        }
+    }
+
+    impl Drop for OutRoot {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    #[test]
+    fn prune_runs_keeps_the_newest_runs() {
+        let out = OutRoot::new("keep-runs");
+        for name in ["a", "b", "c"] {
+            out.add_run(name, 10);
+        }
+        let current = out.add_run("d", 10);
+
+        prune_runs(&out.0, &current, Some(2), None, &AssumeYes).unwrap();
+        assert_eq!(out.runs(), ["c", "d"]);
+    }
+
+    #[test]
+    fn prune_runs_keeps_runs_within_max_size() {
+        let out = OutRoot::new("max-size");
+        for name in ["a", "b", "c"] {
+            out.add_run(name, 100);
+        }
+        let current = out.add_run("d", 100);
+
+        prune_runs(&out.0, &current, None, Some(250), &AssumeYes).unwrap();
+        assert_eq!(out.runs(), ["c", "d"]);
+    }
+
+    #[test]
+    fn prune_runs_never_removes_the_current_run() {
+        let out = OutRoot::new("current");
+        let current = out.add_run("a", 1000);
+        out.add_run("b", 10);
+
+        prune_runs(&out.0, &current, Some(1), Some(10), &AssumeYes).unwrap();
+        assert_eq!(out.runs(), ["a"]);
+    }
+
+    // Linking directories needs extra privileges on Windows
+    #[cfg(unix)]
+    #[test]
+    fn prune_runs_skips_the_latest_link() {
+        use crate::run_dir::{LATEST_LINK_NAME, link_latest};
+
+        let out = OutRoot::new("latest");
+        out.add_run("a", 10);
+        let current = out.add_run("b", 10);
+        link_latest(&out.0, "a");
+
+        prune_runs(&out.0, &current, Some(1), None, &AssumeYes).unwrap();
+        assert_eq!(out.runs(), ["b"]);
+        assert!(
+            fs::symlink_metadata(out.0.join(LATEST_LINK_NAME)).is_ok(),
+            "the latest link should be left alone"
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, bail};
+use jiff::Timestamp;
+use nanoid::nanoid;
+use rumkinst::{
+    Confirm, VcsInfo,
+    config::{Config, RunIdCollision, RunIdScheme},
+    error_log::{ErrorCode, Log, WithCode},
+    tr,
+};
+
+/// Name of the link in `out/` pointing at the most recent successful run
+pub const LATEST_LINK_NAME: &str = "latest";
+
+/// Length of the commit hash used by [`RunIdScheme::Git`]
+const SHORT_HASH_LENGTH: usize = 12;
+
+/// Pick the id of a new run with the scheme set in `[output.run-id]`
+///
+/// The id may already be taken by an earlier run, see [`claim_run_dir`].
+pub fn choose_run_id(config: &Config, base_dir: &Path) -> Result<String> {
+    let id = match config.run_id_scheme() {
+        RunIdScheme::Nanoid => nanoid!(),
+        RunIdScheme::Timestamp => Timestamp::now().strftime("%Y%m%dT%H%M%SZ").to_string(),
+        RunIdScheme::Version => match config.version() {
+            Some(version) => sanitize(version),
+            None => bail!("the `version` run id scheme needs `package.version` to be set"),
+        },
+        RunIdScheme::Git => match VcsInfo::detect(base_dir) {
+            Some(vcs) => {
+                let hash = &vcs.commit[..vcs.commit.len().min(SHORT_HASH_LENGTH)];
+                match vcs.dirty {
+                    true => format!("{hash}-dirty"),
+                    false => hash.to_string(),
+                }
+            }
+            None => bail!("the `git` run id scheme needs the project to be in a git repository"),
+        },
+    };
+    if id.is_empty() || id.starts_with('.') || id == LATEST_LINK_NAME {
+        bail!("\"{id}\" can't be used as a run id");
+    }
+    Ok(id)
+}
+
+/// `id` with anything but ASCII letters, digits, `.`, `-`, `_` and `+` replaced by `_`
+fn sanitize(id: &str) -> String {
+    id.chars()
+        .map(|char| match char {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' | '+' => char,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// Create the directory for the run `id` in `out_root`, resolving a collision with an earlier run
+/// as set in `[output.run-id]`. Returns the final run id and its directory.
+///
+/// Must be called under the project lock, so no other run can claim the same directory.
+pub fn claim_run_dir(
+    out_root: &Path,
+    id: String,
+    collision: RunIdCollision,
+    confirm: &dyn Confirm,
+) -> Result<(String, PathBuf)> {
+    let mut run_id = id.clone();
+    let mut attempt = 1;
+    loop {
+        let out_dir = out_root.join(&run_id);
+        let err = match fs::create_dir(&out_dir) {
+            Ok(()) => return Ok((run_id, out_dir)),
+            Err(err) => err,
+        };
+        if err.kind() != io::ErrorKind::AlreadyExists {
+            return Err(err)
+                .code_at(ErrorCode::OUTPUT_DIR, &out_dir)
+                .with_context(|| format!("failed to create output directory {out_dir:?}"));
+        }
+
+        match collision {
+            RunIdCollision::Suffix => {
+                attempt += 1;
+                run_id = format!("{id}-{attempt}");
+            }
+            RunIdCollision::Overwrite => {
+                if !confirm.confirm(&tr!("confirm-overwrite-run", run = run_id.as_str())) {
+                    return Err(ErrorCode::CANCELLED.at(&out_dir)).context(format!(
+                        "declined to overwrite the earlier run \"{run_id}\""
+                    ));
+                }
+                log::warn!("Overwriting the earlier run \"{run_id}\"");
+                fs::remove_dir_all(&out_dir)
+                    .code_at(ErrorCode::OUTPUT_DIR, &out_dir)
+                    .with_context(|| format!("failed to remove earlier run {out_dir:?}"))?;
+            }
+            RunIdCollision::Error => {
+                return Err(ErrorCode::OUTPUT_DIR.at(&out_dir)).context(format!(
+                    "output directory for run \"{run_id}\" already exists, remove it or set \
+                     `output.run-id.on-collision`"
+                ));
+            }
+        }
+    }
+}
+
+/// Point `out/latest` at the run `run_id`, replacing the link in one step so scripts never see
+/// it missing
+///
+/// Failing to link is only a warning, the run itself already succeeded.
+pub fn link_latest(out_root: &Path, run_id: &str) {
+    let link = out_root.join(LATEST_LINK_NAME);
+    let staged = out_root.join(format!(".{LATEST_LINK_NAME}.tmp"));
+    let result = remove_link(&staged)
+        .and_then(|()| symlink_dir(Path::new(run_id), &staged))
+        .and_then(|()| replace_link(&staged, &link));
+    if result.is_err() {
+        remove_link(&staged).ok();
+    }
+    result
+        .with_context(|| format!("failed to link {link:?} to the latest run"))
+        .warn()
+        .ok();
+}
+
+fn remove_link(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(_) => remove_symlink_dir(path),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(unix)]
+fn symlink_dir(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink_dir(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_dir(target, link)
+}
+
+#[cfg(unix)]
+fn remove_symlink_dir(path: &Path) -> io::Result<()> {
+    fs::remove_file(path)
+}
+
+#[cfg(windows)]
+fn remove_symlink_dir(path: &Path) -> io::Result<()> {
+    fs::remove_dir(path)
+}
+
+#[cfg(unix)]
+fn replace_link(staged: &Path, link: &Path) -> io::Result<()> {
+    fs::rename(staged, link)
+}
+
+/// Windows can't rename over an existing directory link, so the old one is removed first
+#[cfg(windows)]
+fn replace_link(staged: &Path, link: &Path) -> io::Result<()> {
+    remove_link(link)?;
+    fs::rename(staged, link)
+}
@@ -0,0 +1,78 @@
+//! Reading secrets set as a [`SecretSource`], and keeping them out of logs and error reports
+
+use std::{borrow::Cow, env, fs, sync::RwLock};
+
+use crate::{
+    RumkinstError,
+    config::{Config, SecretSource},
+};
+
+/// Replaces every secret in redacted text
+const REDACTED: &str = "[redacted]";
+
+/// Every secret read during this run
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Read the secret from `source`, which is redacted from everything logged from then on
+pub(crate) fn read_secret(source: &SecretSource, config: &Config) -> Result<String, RumkinstError> {
+    let failed = |source_error: Box<dyn std::error::Error + Send + Sync>| RumkinstError::Secret {
+        secret: source.to_string(),
+        source: source_error,
+    };
+
+    let secret = match source {
+        SecretSource::Env(name) => env::var(name).map_err(|err| failed(err.into()))?,
+        SecretSource::File(path) => {
+            let contents =
+                fs::read_to_string(config.resolve(path)).map_err(|err| failed(err.into()))?;
+            contents.trim_end_matches(['\r', '\n']).to_string()
+        }
+        SecretSource::Keyring { service, user } => read_keyring(service, user).map_err(failed)?,
+    };
+    if secret.is_empty() {
+        return Err(failed("it is empty".into()));
+    }
+    register_secret(&secret);
+    Ok(secret)
+}
+
+/// Redact `secret` from everything logged from now on, for secrets found without a
+/// [`SecretSource`]
+pub(crate) fn register_secret(secret: &str) {
+    let mut secrets = SECRETS.write().expect("secrets lock is poisoned");
+    if !secret.is_empty() && !secrets.iter().any(|known| known == secret) {
+        secrets.push(secret.to_string());
+    }
+}
+
+/// `text` with every secret read so far replaced by `[redacted]`
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let secrets = SECRETS.read().expect("secrets lock is poisoned");
+    let mut text = Cow::Borrowed(text);
+    for secret in secrets.iter() {
+        if text.contains(secret.as_str()) {
+            text = Cow::Owned(text.replace(secret.as_str(), REDACTED));
+        }
+    }
+    text
+}
+
+#[cfg(feature = "keyring")]
+fn read_keyring(
+    service: &str,
+    user: &str,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(keyring::Entry::new(service, user)?.get_password()?)
+}
+
+#[cfg(not(feature = "keyring"))]
+fn read_keyring(
+    _service: &str,
+    _user: &str,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    Err(RumkinstError::FeatureDisabled {
+        feature: "keyring",
+        needed_by: "a secret read from the OS keyring",
+    }
+    .into())
+}
@@ -0,0 +1,104 @@
+use std::{collections::BTreeMap, fs};
+
+use anyhow::{Context, Result};
+use rumkinst::{
+    config::find_config_file_at,
+    error_log::{ErrorCode, WithCode},
+    installer_gen::{
+        ArchiveEntry, Sha256Digester, format_listing, read_archive_entries, read_listing,
+    },
+};
+
+use crate::{cli::SnapshotArgs, run_dir::LATEST_LINK_NAME, stats::find_archive};
+
+/// Name of the snapshot file next to rumkinst.toml
+const SNAPSHOT_FILE_NAME: &str = "rumkinst.snap";
+
+/// Record the files of the archive given by `args` in its snapshot, or check them against it
+///
+/// The snapshot is in the format of listings, one line per file sorted by name, so changes to it
+/// read well in a diff.
+pub fn command_snapshot(args: SnapshotArgs) -> Result<()> {
+    let config_path =
+        find_config_file_at(args.path).context("could not find `rumkinst.toml` config file")?;
+    let base_dir = config_path
+        .parent()
+        .context("could not find the parent directory of rumkinst.toml")?;
+    let snapshot_path = args
+        .file
+        .unwrap_or_else(|| base_dir.join(SNAPSHOT_FILE_NAME));
+
+    let archive = args
+        .archive
+        .unwrap_or_else(|| base_dir.join("out").join(LATEST_LINK_NAME));
+    let archive = match archive.is_dir() {
+        true => find_archive(&archive)?,
+        false => archive,
+    };
+    let entries = read_archive_entries(&archive, &Sha256Digester)
+        .code_at(ErrorCode::ARCHIVE_READ, &archive)
+        .with_context(|| format!("failed to read archive {archive:?}"))?;
+
+    if !args.check {
+        fs::write(&snapshot_path, format_listing(&entries))
+            .with_context(|| format!("failed to write snapshot {snapshot_path:?}"))?;
+        log::info!("Recorded {} files in {snapshot_path:?}", entries.len());
+        return Ok(());
+    }
+
+    let snapshot = read_listing(&snapshot_path)
+        .with_context(|| format!("failed to read snapshot {snapshot_path:?}"))?;
+    let changes = compare(&snapshot, &entries);
+    if changes.is_empty() {
+        log::info!("{archive:?} matches {snapshot_path:?}");
+        return Ok(());
+    }
+    println!("{archive:?} differs from {snapshot_path:?}:");
+    for change in &changes {
+        println!("  {change}");
+    }
+    Err(ErrorCode::SNAPSHOT_MISMATCH.at(&archive)).context(format!(
+        "{} files changed, run `rumkinst snapshot` to record them if that was intended",
+        changes.len()
+    ))
+}
+
+/// What changed from `snapshot` to `entries`, one line per file sorted by name
+fn compare(snapshot: &[ArchiveEntry], entries: &[ArchiveEntry]) -> Vec<String> {
+    let by_name = |entries: &[ArchiveEntry]| {
+        entries
+            .iter()
+            .map(|entry| (entry.name.clone(), entry.clone()))
+            .collect::<BTreeMap<_, _>>()
+    };
+    let mut snapshot = by_name(snapshot);
+    let mut changes = vec![];
+    for (name, entry) in by_name(entries) {
+        let Some(recorded) = snapshot.remove(&name) else {
+            changes.push((name.clone(), format!("+ {name}")));
+            continue;
+        };
+        let mut differences = vec![];
+        if entry.mode != recorded.mode {
+            differences.push(format!("mode {:04o} -> {:04o}", recorded.mode, entry.mode));
+        }
+        if entry.size != recorded.size {
+            differences.push(format!("size {} -> {}", recorded.size, entry.size));
+        } else if entry.digest != recorded.digest {
+            differences.push("contents".to_string());
+        }
+        if !differences.is_empty() {
+            changes.push((
+                name.clone(),
+                format!("~ {name} ({})", differences.join(", ")),
+            ));
+        }
+    }
+    changes.extend(
+        snapshot
+            .into_keys()
+            .map(|name| (name.clone(), format!("- {name}"))),
+    );
+    changes.sort();
+    changes.into_iter().map(|(_, change)| change).collect()
+}
@@ -0,0 +1,284 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use indicatif::HumanBytes;
+use rumkinst::{
+    config::find_config_file_at,
+    error_log::{ErrorCode, WithCode},
+    installer_gen::{ArchiveEntry, Sha256Digester, read_archive_entries},
+};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{cli::StatsArgs, metadata::METADATA_FILE_NAME, run_dir::LATEST_LINK_NAME};
+
+/// Where space in an archive goes
+#[derive(Serialize)]
+struct ArchiveStats {
+    archive: PathBuf,
+    files: usize,
+    /// Size of every file once extracted
+    bytes: u64,
+    /// Size of the archive itself
+    compressed_bytes: u64,
+    directories: Vec<Group>,
+    extensions: Vec<Group>,
+    largest: Vec<Largest>,
+    duplicates: Vec<Duplicates>,
+}
+
+/// Files grouped by a leading directory or an extension
+#[derive(Serialize, Default)]
+struct Group {
+    name: String,
+    files: usize,
+    bytes: u64,
+}
+
+#[derive(Serialize)]
+struct Largest {
+    name: String,
+    bytes: u64,
+}
+
+/// Files with the same contents
+#[derive(Serialize)]
+struct Duplicates {
+    names: Vec<String>,
+    bytes: u64,
+    /// Space taken by every copy but one
+    wasted_bytes: u64,
+}
+
+/// Print a breakdown of the archive given by `args`
+pub fn command_stats(args: StatsArgs) -> Result<()> {
+    let archive = match args.archive {
+        Some(path) => path,
+        None => {
+            let config_path = find_config_file_at(args.path)
+                .context("could not find `rumkinst.toml` config file")?;
+            let base_dir = config_path
+                .parent()
+                .context("could not find the parent directory of rumkinst.toml")?;
+            base_dir.join("out").join(LATEST_LINK_NAME)
+        }
+    };
+    let archive = match archive.is_dir() {
+        true => find_archive(&archive)?,
+        false => archive,
+    };
+
+    let entries = read_archive_entries(&archive, &Sha256Digester)
+        .code_at(ErrorCode::ARCHIVE_READ, &archive)
+        .with_context(|| format!("failed to read archive {archive:?}"))?;
+    let compressed_bytes = fs::metadata(&archive)
+        .code_at(ErrorCode::ARCHIVE_READ, &archive)
+        .with_context(|| format!("failed to read archive {archive:?}"))?
+        .len();
+    let stats = analyze(archive, compressed_bytes, &entries, args.top, args.depth);
+
+    if args.json {
+        let json = serde_json::to_string_pretty(&stats).context("failed to serialize stats")?;
+        println!("{json}");
+    } else {
+        print_stats(&stats);
+    }
+    Ok(())
+}
+
+/// The archive of the package in the run directory `dir`, as listed in its metadata or else the
+/// only archive in it that is not a delta
+pub fn find_archive(dir: &Path) -> Result<PathBuf> {
+    let listed = fs::read_to_string(dir.join(METADATA_FILE_NAME))
+        .ok()
+        .and_then(|metadata| serde_json::from_str::<Value>(&metadata).ok())
+        .and_then(|metadata| {
+            metadata["artifacts"]
+                .as_array()?
+                .iter()
+                .find(|artifact| artifact["kind"] == "archive")?["name"]
+                .as_str()
+                .map(|name| dir.join(name))
+        });
+    if let Some(archive) = listed {
+        return Ok(archive);
+    }
+
+    let mut archives = vec![];
+    for entry in fs::read_dir(dir)
+        .code_at(ErrorCode::ARCHIVE_READ, dir)
+        .with_context(|| format!("failed to read {dir:?}"))?
+    {
+        let path = entry
+            .code_at(ErrorCode::ARCHIVE_READ, dir)
+            .with_context(|| format!("failed to read {dir:?}"))?
+            .path();
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let is_archive = name.ends_with(".tar.gz") || name.ends_with(".zip");
+        if is_archive && !name.contains(".delta.") {
+            archives.push(path);
+        }
+    }
+    match <[PathBuf; 1]>::try_from(archives) {
+        Ok([archive]) => Ok(archive),
+        Err(archives) if archives.is_empty() => {
+            Err(ErrorCode::ARCHIVE_READ.at(dir)).context(format!("{dir:?} holds no archive"))
+        }
+        Err(_) => Err(ErrorCode::ARCHIVE_READ.at(dir)).context(format!(
+            "{dir:?} holds more than one archive, give the one to use instead"
+        )),
+    }
+}
+
+fn analyze(
+    archive: PathBuf,
+    compressed_bytes: u64,
+    entries: &[ArchiveEntry],
+    top: usize,
+    depth: usize,
+) -> ArchiveStats {
+    let mut directories = BTreeMap::<String, Group>::new();
+    let mut extensions = BTreeMap::<String, Group>::new();
+    let mut contents = HashMap::<&str, Vec<&ArchiveEntry>>::new();
+    for entry in entries {
+        let parts = entry.name.split('/').collect::<Vec<_>>();
+        let (file, dirs) = parts
+            .split_last()
+            .expect("entry names should never be empty");
+        let directory = match dirs.is_empty() {
+            true => ".".to_string(),
+            false => dirs[..dirs.len().min(depth.max(1))].join("/"),
+        };
+        let extension = match file.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => format!(".{}", extension.to_lowercase()),
+            _ => "(none)".to_string(),
+        };
+        for group in [
+            directories.entry(directory).or_default(),
+            extensions.entry(extension).or_default(),
+        ] {
+            group.files += 1;
+            group.bytes += entry.size;
+        }
+        if entry.size > 0 {
+            contents.entry(&entry.digest).or_default().push(entry);
+        }
+    }
+
+    let by_size = |groups: BTreeMap<String, Group>| {
+        let mut groups = groups
+            .into_iter()
+            .map(|(name, group)| Group { name, ..group })
+            .collect::<Vec<_>>();
+        groups.sort_by_key(|group| std::cmp::Reverse(group.bytes));
+        groups
+    };
+
+    let mut largest = entries
+        .iter()
+        .map(|entry| Largest {
+            name: entry.name.clone(),
+            bytes: entry.size,
+        })
+        .collect::<Vec<_>>();
+    largest.sort_by_key(|entry| std::cmp::Reverse(entry.bytes));
+    largest.truncate(top);
+
+    let mut duplicates = contents
+        .into_values()
+        .filter(|copies| copies.len() > 1)
+        .map(|copies| Duplicates {
+            names: copies.iter().map(|entry| entry.name.clone()).collect(),
+            bytes: copies[0].size,
+            wasted_bytes: copies[0].size * (copies.len() as u64 - 1),
+        })
+        .collect::<Vec<_>>();
+    duplicates.sort_by(|a, b| {
+        b.wasted_bytes
+            .cmp(&a.wasted_bytes)
+            .then(a.names.cmp(&b.names))
+    });
+    duplicates.truncate(top);
+
+    ArchiveStats {
+        archive,
+        files: entries.len(),
+        bytes: entries.iter().map(|entry| entry.size).sum(),
+        compressed_bytes,
+        directories: by_size(directories),
+        extensions: by_size(extensions),
+        largest,
+        duplicates,
+    }
+}
+
+fn print_stats(stats: &ArchiveStats) {
+    println!(
+        "{:?}: {} files, {} extracted, {} compressed ({})",
+        stats.archive,
+        stats.files,
+        HumanBytes(stats.bytes),
+        HumanBytes(stats.compressed_bytes),
+        percent(stats.compressed_bytes, stats.bytes)
+    );
+
+    for (title, groups) in [
+        ("By directory", &stats.directories),
+        ("By extension", &stats.extensions),
+    ] {
+        println!("\n{title}:");
+        for group in groups {
+            println!(
+                "  {:>10}  {:>6}  {:>6} files  {}",
+                HumanBytes(group.bytes).to_string(),
+                percent(group.bytes, stats.bytes),
+                group.files,
+                group.name
+            );
+        }
+    }
+
+    println!("\nLargest files:");
+    for entry in &stats.largest {
+        println!(
+            "  {:>10}  {}",
+            HumanBytes(entry.bytes).to_string(),
+            entry.name
+        );
+    }
+
+    if stats.duplicates.is_empty() {
+        println!("\nNo duplicate files");
+        return;
+    }
+    let wasted = stats
+        .duplicates
+        .iter()
+        .map(|duplicates| duplicates.wasted_bytes)
+        .sum();
+    println!(
+        "\nDuplicate files, {} could be saved by the largest groups:",
+        HumanBytes(wasted)
+    );
+    for duplicates in &stats.duplicates {
+        println!(
+            "  {:>10}  {} copies of {}: {}",
+            HumanBytes(duplicates.wasted_bytes).to_string(),
+            duplicates.names.len(),
+            HumanBytes(duplicates.bytes),
+            duplicates.names.join(", ")
+        );
+    }
+}
+
+/// `part` as a percentage of `whole`
+fn percent(part: u64, whole: u64) -> String {
+    match whole {
+        0 => "-".to_string(),
+        whole => format!("{:.1}%", part as f64 * 100.0 / whole as f64),
+    }
+}
@@ -0,0 +1,216 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use indicatif::HumanBytes;
+use rumkinst::{
+    error_log::{ErrorCode, WithCode},
+    installer_gen::{ArchiveEntry, Sha256Digester, read_archive_entries, read_listing},
+};
+use serde::Serialize;
+
+use crate::stats::find_archive;
+
+/// Directories whose contents are made by tools rather than written by hand
+const DERIVED_DIRECTORIES: [&str; 9] = [
+    "__pycache__",
+    ".cache",
+    ".pytest_cache",
+    ".mypy_cache",
+    ".ruff_cache",
+    ".sass-cache",
+    ".gradle",
+    ".tmp",
+    "logs",
+];
+
+/// Extensions of files made by tools rather than written by hand
+const DERIVED_EXTENSIONS: [&str; 8] = ["log", "tmp", "pyc", "pyo", "swp", "bak", "o", "orig"];
+
+/// Files left behind by file managers
+const DERIVED_FILES: [&str; 3] = [".DS_Store", "Thumbs.db", "desktop.ini"];
+
+/// A path worth excluding from its source, with why
+#[derive(Serialize)]
+pub struct Suggestion {
+    /// Path relative to rumkinst.toml, as written in `exclude`, ending in `/` for directories
+    path: String,
+    /// Packaged files under the path in the latest run
+    files: usize,
+    bytes: u64,
+    reasons: Vec<String>,
+}
+
+/// How a file packaged in the latest run looks across every run read
+struct FileHistory<'a> {
+    latest: &'a ArchiveEntry,
+    /// Runs the file was packaged in next to the run before, and how many of them it changed in
+    compared: usize,
+    changed: usize,
+}
+
+/// Suggest paths to exclude from the files packaged in `runs`, from oldest to newest
+///
+/// Files are suggested when they look generated, such as caches and logs, or when they are at
+/// least `min_size` bytes and changed in most of the runs, as those make archives big and
+/// different every time.
+pub fn suggest_excludes(runs: &[PathBuf], min_size: u64) -> Result<Vec<Suggestion>> {
+    let runs = runs
+        .iter()
+        .map(|dir| run_entries(dir))
+        .collect::<Result<Vec<_>>>()?;
+    let Some((latest, earlier)) = runs.split_last() else {
+        return Ok(vec![]);
+    };
+
+    let mut files = latest
+        .iter()
+        .map(|entry| {
+            let history = FileHistory {
+                latest: entry,
+                compared: 0,
+                changed: 0,
+            };
+            (entry.name.as_str(), history)
+        })
+        .collect::<BTreeMap<_, _>>();
+    let by_name = |entries: &[ArchiveEntry]| {
+        entries
+            .iter()
+            .map(|entry| (entry.name.clone(), entry.digest.clone()))
+            .collect::<BTreeMap<_, _>>()
+    };
+    for pair in runs.windows(2) {
+        let (before, after) = (by_name(&pair[0]), by_name(&pair[1]));
+        for (name, history) in &mut files {
+            if let (Some(before), Some(after)) = (before.get(*name), after.get(*name)) {
+                history.compared += 1;
+                history.changed += usize::from(before != after);
+            }
+        }
+    }
+    log::debug!(
+        "compared {} files across {} runs",
+        files.len(),
+        earlier.len() + 1
+    );
+
+    let mut derived = BTreeMap::<String, Suggestion>::new();
+    let mut suggestions = vec![];
+    for (name, history) in files {
+        if let Some((path, reason)) = derived_path(name) {
+            let suggestion = derived.entry(path.clone()).or_insert(Suggestion {
+                path,
+                files: 0,
+                bytes: 0,
+                reasons: vec![reason],
+            });
+            suggestion.files += 1;
+            suggestion.bytes += history.latest.size;
+            continue;
+        }
+
+        let mut reasons = vec![];
+        let changing = history.compared > 0 && history.changed * 2 > history.compared;
+        if changing {
+            reasons.push(format!(
+                "changed in {} of {} later runs",
+                history.changed, history.compared
+            ));
+        }
+        if history.latest.size >= min_size {
+            reasons.push(format!("{} large", HumanBytes(history.latest.size)));
+        }
+        // Large files alone are often what is being packaged, so only suggest them when they
+        // also change between runs, or when there is nothing to compare them with
+        if changing && reasons.len() == 2 || history.compared == 0 && reasons.len() == 1 {
+            suggestions.push(Suggestion {
+                path: format!("./{name}"),
+                files: 1,
+                bytes: history.latest.size,
+                reasons,
+            });
+        }
+    }
+
+    let mut suggestions = derived.into_values().chain(suggestions).collect::<Vec<_>>();
+    suggestions.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(a.path.cmp(&b.path)));
+    Ok(suggestions)
+}
+
+/// Entries packaged in the run directory `dir`, from the listing of its archive when there is
+/// one, as that is much faster than reading the archive
+fn run_entries(dir: &Path) -> Result<Vec<ArchiveEntry>> {
+    let archive = find_archive(dir)?;
+    let mut listing = archive.clone().into_os_string();
+    listing.push(".list");
+    let entries = match read_listing(Path::new(&listing)) {
+        Ok(entries) => entries,
+        Err(_) => read_archive_entries(&archive, &Sha256Digester)
+            .code_at(ErrorCode::ARCHIVE_READ, &archive)
+            .with_context(|| format!("failed to read archive {archive:?}"))?,
+    };
+    // The manifest rumkinst embeds is made by the build, never found in a source
+    Ok(entries
+        .into_iter()
+        .filter(|entry| !entry.name.starts_with(".rumkinst/"))
+        .collect())
+}
+
+/// The path to exclude for the generated file `name`, and why it looks generated, or `None` if
+/// it does not
+fn derived_path(name: &str) -> Option<(String, String)> {
+    let parts = name.split('/').collect::<Vec<_>>();
+    let (file, dirs) = parts.split_last()?;
+    if let Some(index) = dirs
+        .iter()
+        .position(|dir| DERIVED_DIRECTORIES.contains(dir))
+    {
+        return Some((
+            format!("./{}/", dirs[..=index].join("/")),
+            format!("`{}` directories hold generated files", dirs[index]),
+        ));
+    }
+    let extension = file
+        .rsplit_once('.')
+        .filter(|(stem, _)| !stem.is_empty())
+        .map(|(_, extension)| extension.to_lowercase());
+    let reason = if let Some(extension) =
+        extension.filter(|ext| DERIVED_EXTENSIONS.contains(&ext.as_str()))
+    {
+        format!("`.{extension}` files are generated")
+    } else if DERIVED_FILES.contains(file) {
+        format!("`{file}` is left behind by file managers")
+    } else if file.ends_with('~') {
+        "editor backup".to_string()
+    } else {
+        return None;
+    };
+    Some((format!("./{name}"), reason))
+}
+
+pub fn print_suggestions(suggestions: &[Suggestion], runs: usize) {
+    if suggestions.is_empty() {
+        println!("Nothing to suggest excluding from the last {runs} runs");
+        return;
+    }
+    println!("Suggested excludes, from the last {runs} runs:");
+    for suggestion in suggestions {
+        let files = match suggestion.files {
+            1 => String::new(),
+            files => format!("{files} files, "),
+        };
+        println!(
+            "  {:>10}  {}  ({files}{})",
+            HumanBytes(suggestion.bytes).to_string(),
+            suggestion.path,
+            suggestion.reasons.join(", ")
+        );
+    }
+    println!(
+        "Add them to the `exclude` of the source they are in, unless that source has a \
+         `strip-prefix` or `archive-prefix`, whose files are archived under another path"
+    );
+}
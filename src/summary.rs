@@ -0,0 +1,92 @@
+use std::{fs, path::Path};
+
+use indicatif::HumanBytes;
+use rumkinst::{BuildOutput, config::ThemeType, tr};
+
+/// Log a summary of a finished run: its artifacts, warnings, skipped files and total time,
+/// drawn in the installer theme of the config
+pub fn print_run_summary(theme: &ThemeType, run_id: &str, output: &BuildOutput) {
+    let artifacts = [&output.archive, &output.delta, &output.deb]
+        .into_iter()
+        .flatten()
+        .chain(output.target_archives.iter().map(|target| &target.archive))
+        .chain(&output.windows_installer)
+        .collect::<Vec<_>>();
+
+    let mut rows = artifacts
+        .iter()
+        .map(|artifact| {
+            (
+                relative_name(output, &artifact.path),
+                file_size(&artifact.path),
+                format!("{}:{}", artifact.algorithm, artifact.digest),
+            )
+        })
+        .collect::<Vec<_>>();
+    rows.extend(output.plugin_artifacts.iter().map(|artifact| {
+        (
+            relative_name(output, &artifact.path),
+            file_size(&artifact.path),
+            tr!(
+                "run-plugin-artifact",
+                kind = artifact.kind.to_string(),
+                plugin = artifact.plugin.as_str()
+            ),
+        )
+    }));
+
+    let mut lines = vec![tr!("run-finished", run = run_id)];
+    match rows.is_empty() {
+        true => lines.push(tr!("run-no-artifacts")),
+        false => lines.push(tr!("run-artifacts")),
+    }
+    let name_width = rows.iter().map(|row| row.0.len()).max().unwrap_or_default();
+    let size_width = rows.iter().map(|row| row.1.len()).max().unwrap_or_default();
+    for (name, size, checksum) in rows {
+        lines.push(format!(
+            "  {name:<name_width$}  {size:>size_width$}  {checksum}"
+        ));
+    }
+    lines.push(tr!(
+        "run-files",
+        packaged = output.stats.files - output.stats.skipped.min(output.stats.files),
+        skipped = output.stats.skipped
+    ));
+    lines.push(tr!("run-warnings", count = output.stats.warnings));
+    lines.push(tr!(
+        "run-time",
+        seconds = format!("{:.2}", output.stats.elapsed.as_secs_f64())
+    ));
+
+    match theme {
+        ThemeType::Plain => lines.iter().for_each(|line| log::info!("{line}")),
+        // Figlet lettering is only drawn by installers, a summary of a few lines is boxed instead
+        ThemeType::Box | ThemeType::Figlet => {
+            let width = lines
+                .iter()
+                .map(|line| line.chars().count())
+                .max()
+                .unwrap_or_default();
+            log::info!("┌{}┐", "─".repeat(width + 2));
+            for line in &lines {
+                log::info!("│ {line:<width$} │");
+            }
+            log::info!("└{}┘", "─".repeat(width + 2));
+        }
+    }
+}
+
+/// `path` relative to the output directory of `output`
+fn relative_name(output: &BuildOutput, path: &Path) -> String {
+    path.strip_prefix(&output.out_dir)
+        .unwrap_or(path)
+        .display()
+        .to_string()
+}
+
+/// Size of the file at `path`, `-` if it can't be read
+fn file_size(path: &Path) -> String {
+    fs::metadata(path)
+        .map(|metadata| HumanBytes(metadata.len()).to_string())
+        .unwrap_or_else(|_| "-".to_string())
+}
@@ -0,0 +1,148 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use rumkinst::{
+    config::{Config, identifier::Identifier},
+    error_log::{ErrorCode, WithCode},
+};
+
+use crate::git::git;
+
+/// Create a new rumkinst directory at `dir_path` from the template repository at `url`
+///
+/// The template is cloned next to `dir_path` and only moved into place once it is filled in and
+/// its `rumkinst.toml` is valid, so a failed attempt leaves nothing behind. When `offline`, only
+/// templates on the local filesystem can be used.
+pub fn new_from_template(
+    name: Identifier,
+    dir_path: PathBuf,
+    url: &str,
+    offline: bool,
+) -> Result<()> {
+    log::trace!("running command logic for `new` with a template");
+    let local = Path::new(url).exists() || url.starts_with("file://");
+    if offline && !local {
+        return Err(ErrorCode::OFFLINE).context(format!(
+            "cloning the template {url} needs network access, but --offline was given"
+        ));
+    }
+    log::info!("Creating a new rumkinst directory from {url}...");
+
+    if dir_path.exists() {
+        return Err(ErrorCode::PROJECT_EXISTS.at(&dir_path)).with_context(|| {
+            format!("cannot create directory at {dir_path:?}, one already exists")
+        });
+    }
+    let file_name = dir_path
+        .file_name()
+        .expect("the project directory should always have a name")
+        .to_string_lossy();
+    let staging = dir_path.with_file_name(format!(".{file_name}.template"));
+    if staging.exists() {
+        fs::remove_dir_all(&staging)
+            .code_at(ErrorCode::PROJECT_CREATE, &staging)
+            .with_context(|| format!("failed to remove stale template clone {staging:?}"))?;
+    }
+
+    let result = fill_in_template(&name, &staging, url).and_then(|()| {
+        fs::rename(&staging, &dir_path)
+            .code_at(ErrorCode::PROJECT_CREATE, &dir_path)
+            .with_context(|| format!("failed to move the template to {dir_path:?}"))
+    });
+    if result.is_err() {
+        fs::remove_dir_all(&staging).ok();
+    }
+    result?;
+
+    log::info!("Successfully created new rumkinst directory at {dir_path:?}");
+    Ok(())
+}
+
+/// Clone `url` into `dir` without its git history, and fill in its placeholders
+fn fill_in_template(name: &Identifier, dir: &Path, url: &str) -> Result<()> {
+    let dir_arg = dir.to_string_lossy();
+    git(
+        Path::new(""),
+        &["clone", "--depth", "1", "--quiet", "--", url, &dir_arg],
+    )
+    .code(ErrorCode::PROJECT_CREATE)
+    .with_context(|| format!("failed to clone template {url}"))?;
+    fs::remove_dir_all(dir.join(".git"))
+        .code_at(ErrorCode::PROJECT_CREATE, dir)
+        .context("failed to remove the git history of the template")?;
+
+    let authors = git_author();
+    let placeholders = [("{{name}}", name.as_str()), ("{{authors}}", &authors)];
+    fill_in_dir(dir, &placeholders)?;
+
+    let config_path = dir.join("rumkinst.toml");
+    let config = fs::read(&config_path)
+        .code_at(ErrorCode::CONFIG_NOT_FOUND, &config_path)
+        .context("the template has no `rumkinst.toml` at its top level")?;
+    Config::read_named(config.as_slice(), "rumkinst.toml")
+        .context("the template's `rumkinst.toml` is invalid once filled in")?;
+    Ok(())
+}
+
+/// Replace `placeholders` in every UTF-8 file under `dir`, leaving symlinks and binary files alone
+fn fill_in_dir(dir: &Path, placeholders: &[(&str, &str)]) -> Result<()> {
+    let read_error = || format!("failed to read template directory {dir:?}");
+    for entry in fs::read_dir(dir)
+        .code_at(ErrorCode::PROJECT_CREATE, dir)
+        .with_context(read_error)?
+    {
+        let entry = entry
+            .code_at(ErrorCode::PROJECT_CREATE, dir)
+            .with_context(read_error)?;
+        let path = entry.path();
+        let file_type = entry
+            .file_type()
+            .code_at(ErrorCode::PROJECT_CREATE, &path)
+            .with_context(|| format!("failed to read template file {path:?}"))?;
+        if file_type.is_dir() {
+            fill_in_dir(&path, placeholders)?;
+            continue;
+        }
+        if !file_type.is_file() {
+            continue;
+        }
+
+        let bytes = fs::read(&path)
+            .code_at(ErrorCode::PROJECT_CREATE, &path)
+            .with_context(|| format!("failed to read template file {path:?}"))?;
+        let Ok(text) = String::from_utf8(bytes) else {
+            continue;
+        };
+        let filled = placeholders
+            .iter()
+            .fold(text.clone(), |text, (placeholder, value)| {
+                text.replace(placeholder, value)
+            });
+        if filled != text {
+            log::debug!("filled in placeholders in {path:?}");
+            fs::write(&path, filled)
+                .code_at(ErrorCode::PROJECT_CREATE, &path)
+                .with_context(|| format!("failed to write template file {path:?}"))?;
+        }
+    }
+    Ok(())
+}
+
+/// The git user as `Name <email>`, empty if git has none configured
+fn git_author() -> String {
+    let config = |key| {
+        git(Path::new(""), &["config", key])
+            .ok()
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+    match (config("user.name"), config("user.email")) {
+        (Some(name), Some(email)) => format!("{name} <{email}>"),
+        (Some(name), None) => name,
+        (None, Some(email)) => format!("<{email}>"),
+        (None, None) => String::new(),
+    }
+}
@@ -0,0 +1,77 @@
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+};
+
+/// The state of the git repository a build ran in
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct VcsInfo {
+    /// Full hash of the checked out commit
+    pub commit: String,
+    /// Tag pointing at the commit, `None` if there is none
+    pub tag: Option<String>,
+    /// Checked out branch, `None` when `HEAD` is detached
+    pub branch: Option<String>,
+    /// Whether tracked files have uncommitted changes, untracked files are ignored
+    pub dirty: bool,
+}
+
+impl VcsInfo {
+    /// Look for a git repository enclosing `dir`, `None` if there is none, it has no commits, or
+    /// `git` is not installed
+    pub fn detect(dir: &Path) -> Option<Self> {
+        let commit = git(dir, &["rev-parse", "--verify", "HEAD"])?;
+        let tag = git(dir, &["describe", "--tags", "--exact-match", "HEAD"]);
+        let branch = git(dir, &["symbolic-ref", "--quiet", "--short", "HEAD"]);
+        let dirty = git(dir, &["status", "--porcelain", "--untracked-files=no"])
+            .is_some_and(|status| !status.is_empty());
+
+        Some(Self {
+            commit,
+            tag,
+            branch,
+            dirty,
+        })
+    }
+
+    /// Environment variables describing the repository, as given to hooks
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        let mut vars = vec![
+            ("RUMKINST_GIT_COMMIT", self.commit.clone()),
+            ("RUMKINST_GIT_DIRTY", self.dirty.to_string()),
+        ];
+        if let Some(tag) = &self.tag {
+            vars.push(("RUMKINST_GIT_TAG", tag.clone()));
+        }
+        if let Some(branch) = &self.branch {
+            vars.push(("RUMKINST_GIT_BRANCH", branch.clone()));
+        }
+        vars
+    }
+}
+
+/// Trimmed stdout of a successful `git` command run in `dir`
+fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    let mut command = Command::new("git");
+    command
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null());
+    if !dir.as_os_str().is_empty() {
+        command.current_dir(dir);
+    }
+
+    let output = match command.output() {
+        Ok(output) => output,
+        Err(err) => {
+            log::debug!("failed to run `git`: {err}");
+            return None;
+        }
+    };
+    if !output.status.success() {
+        log::trace!("`git {}` {}", args.join(" "), output.status);
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
@@ -0,0 +1,175 @@
+//! A throwaway project the `rumkinst` binary is run against
+
+// Each test crate only uses some of the helpers
+#![allow(dead_code)]
+
+use std::{
+    env, fs,
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use flate2::read::GzDecoder;
+use rumkinst::config::Config;
+use sha2::{Digest, Sha256};
+
+/// Name of the package every test project is created with
+pub const PACKAGE: &str = "demo";
+
+static NEXT_PROJECT: AtomicUsize = AtomicUsize::new(0);
+
+/// A project made with `rumkinst new`, removed again once dropped
+pub struct Project {
+    dir: PathBuf,
+}
+
+impl Project {
+    /// A new project with `a.txt` and `etc/b.conf` in its `root/`
+    pub fn new() -> Self {
+        let dir = env::temp_dir().join(format!(
+            "rumkinst-it-{}-{}",
+            std::process::id(),
+            NEXT_PROJECT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir).expect("project directory should be created");
+        let project = Self { dir };
+
+        let created = project.rumkinst(&project.dir, &["new", PACKAGE]);
+        assert_success(&created);
+        project.write("root/a.txt", "a\n");
+        project.write("root/etc/b.conf", "b = 1\n");
+        project
+    }
+
+    /// The directory holding `rumkinst.toml`
+    pub fn path(&self) -> PathBuf {
+        self.dir.join("rumkinst")
+    }
+
+    /// Write `contents` to `path` inside the project, creating its parents
+    pub fn write(&self, path: &str, contents: &str) {
+        let path = self.path().join(path);
+        fs::create_dir_all(path.parent().expect("project files should have a parent"))
+            .expect("parent directory should be created");
+        fs::write(&path, contents).expect("project file should be written");
+    }
+
+    /// Append `toml` to `rumkinst.toml`
+    pub fn configure(&self, toml: &str) {
+        let path = self.path().join("rumkinst.toml");
+        let config = fs::read_to_string(&path).expect("rumkinst.toml should be readable");
+        fs::write(&path, config + "\n" + toml).expect("rumkinst.toml should be written");
+    }
+
+    /// The config of the project, to build it through the library
+    pub fn config(&self) -> Config {
+        let path = self.path().join("rumkinst.toml");
+        let file = fs::File::open(&path).expect("rumkinst.toml should be readable");
+        Config::read(file)
+            .expect("rumkinst.toml should be valid")
+            .with_base_dir(self.path())
+    }
+
+    /// Run `rumkinst` with `args` inside the project
+    pub fn run(&self, args: &[&str]) -> Output {
+        self.rumkinst(&self.path(), args)
+    }
+
+    /// Run `rumkinst` with `args` in `dir`, a directory next to the project that is created first
+    pub fn run_beside(&self, dir: &str, args: &[&str]) -> Output {
+        let dir = self.dir.join(dir);
+        fs::create_dir_all(&dir).expect("directory next to the project should be created");
+        self.rumkinst(&dir, args)
+    }
+
+    /// The run directories in `out/`, without `out/latest`
+    pub fn runs(&self) -> Vec<PathBuf> {
+        let Ok(entries) = fs::read_dir(self.path().join("out")) else {
+            return vec![];
+        };
+        entries
+            .map(|entry| entry.expect("out/ should be readable").path())
+            .filter(|path| {
+                path.file_name().is_some_and(|name| name != "latest")
+                    && fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_dir())
+            })
+            .collect()
+    }
+
+    /// The only run directory in `out/`
+    pub fn only_run(&self) -> PathBuf {
+        let runs = self.runs();
+        assert_eq!(runs.len(), 1, "expected one run in out/, found {runs:?}");
+        runs.into_iter().next().expect("there should be one run")
+    }
+
+    /// Run `rumkinst` with `args` in `dir`, caching into the project instead of the user's cache
+    fn rumkinst(&self, dir: &Path, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_rumkinst"))
+            .args(args)
+            .current_dir(dir)
+            .env("XDG_CACHE_HOME", self.dir.join("cache"))
+            .env_remove("RUST_LOG")
+            .env("NO_COLOR", "1")
+            .output()
+            .expect("rumkinst should run")
+    }
+}
+
+impl Drop for Project {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+/// Fail the test with the output of `output` unless it exited successfully
+#[track_caller]
+pub fn assert_success(output: &Output) {
+    assert!(
+        output.status.success(),
+        "rumkinst {}\nstderr:\n{}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// Hex encoded SHA-256 digest of the file at `path`
+pub fn sha256(path: &Path) -> String {
+    let contents = fs::read(path).unwrap_or_else(|err| panic!("failed to read {path:?}: {err}"));
+    Sha256::digest(contents)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// `file://` URL of `path`, which `curl` reads and writes without leaving the machine
+pub fn file_url(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    format!("file:///{}", path.trim_start_matches('/'))
+}
+
+/// Name and contents of every file in the gzipped tarball `archive`
+pub fn tarball_files(archive: impl Read) -> Vec<(String, String)> {
+    let mut files = vec![];
+    let mut tarball = tar::Archive::new(GzDecoder::new(archive));
+    for entry in tarball.entries().expect("archive should be a tarball") {
+        let mut entry = entry.expect("archive entry should be readable");
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry
+            .path()
+            .expect("archive entry should have a path")
+            .to_string_lossy()
+            .into_owned();
+        let mut contents = String::new();
+        entry
+            .read_to_string(&mut contents)
+            .expect("archive entry should be text");
+        files.push((name, contents));
+    }
+    files.sort();
+    files
+}
@@ -0,0 +1,34 @@
+mod common;
+
+use std::{fs, thread};
+
+use common::Project;
+use rumkinst::{BuildOptions, build};
+
+/// Build the project, returning how many warnings the build reported
+fn build_warnings(project: &Project) -> usize {
+    let output = build(
+        &project.config(),
+        BuildOptions::new(project.path().join("out")),
+    )
+    .expect("build should succeed");
+    output.stats.warnings
+}
+
+#[test]
+fn builds_only_count_their_own_warnings() {
+    let empty = Project::new();
+    fs::remove_dir_all(empty.path().join("root")).expect("root/ should be removed");
+    fs::create_dir(empty.path().join("root")).expect("root/ should be created");
+    let packaged = Project::new();
+
+    let (empty_warnings, packaged_warnings) = thread::scope(|scope| {
+        let empty = scope.spawn(|| build_warnings(&empty));
+        let packaged = scope.spawn(|| build_warnings(&packaged));
+        (empty.join().unwrap(), packaged.join().unwrap())
+    });
+    assert!(empty_warnings > 0, "an empty root/ should be warned about");
+    assert_eq!(packaged_warnings, 0);
+
+    assert_eq!(build_warnings(&packaged), 0);
+}
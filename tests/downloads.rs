@@ -0,0 +1,61 @@
+//! `[[sources.download]]` files, fetched with `curl` from `file://` URLs
+
+mod common;
+
+use std::fs;
+
+use common::{PACKAGE, Project, assert_success, file_url, sha256, tarball_files};
+
+/// `[[sources.download]]` of `upstream/tool.txt` next to the project, pinned to `sha256`
+fn download_pinned_to(project: &Project, sha256: &str) -> String {
+    let upstream = project.path().with_file_name("upstream").join("tool.txt");
+    format!(
+        "[[sources.download]]\nurl = \"{}\"\nsha256 = \"{sha256}\"\ndest = \"root/tool.txt\"\n",
+        file_url(&upstream)
+    )
+}
+
+/// Write `upstream/tool.txt` next to the project, returning its digest
+fn serve_tool(project: &Project) -> String {
+    let upstream = project.path().with_file_name("upstream");
+    fs::create_dir_all(&upstream).expect("upstream directory should be created");
+    fs::write(upstream.join("tool.txt"), "tool\n").expect("upstream file should be written");
+    sha256(&upstream.join("tool.txt"))
+}
+
+#[test]
+fn pinned_download_is_packaged() {
+    let project = Project::new();
+    let digest = serve_tool(&project);
+    project.configure(&download_pinned_to(&project, &digest));
+
+    let streamed = project.run(&["make", "--stdout"]);
+    assert_success(&streamed);
+    assert!(
+        tarball_files(streamed.stdout.as_slice())
+            .contains(&("root/tool.txt".to_string(), "tool\n".to_string())),
+        "the download should be packaged at its dest"
+    );
+}
+
+#[test]
+fn mismatched_pin_fails_the_run() {
+    let project = Project::new();
+    serve_tool(&project);
+    project.configure(&download_pinned_to(&project, &"0".repeat(64)));
+
+    let made = project.run(&["make"]);
+    let stderr = String::from_utf8_lossy(&made.stderr);
+    assert!(
+        !made.status.success() && stderr.contains("but 0000"),
+        "rumkinst {} should refuse a download that doesn't match its pin\nstderr:\n{stderr}",
+        made.status,
+    );
+    assert!(
+        !project
+            .runs()
+            .iter()
+            .any(|run| run.join(format!("{PACKAGE}.tar.gz")).exists()),
+        "no archive should be made"
+    );
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use std::fs::{self, File};
+
+use common::{PACKAGE, Project, assert_success, sha256, tarball_files};
+
+#[test]
+fn make_writes_archive_and_checksum() {
+    let project = Project::new();
+    assert_success(&project.run(&["make"]));
+
+    let run = project.only_run();
+    let archive = run.join(format!("{PACKAGE}.tar.gz"));
+    let files = tarball_files(File::open(&archive).expect("archive should be made"));
+    assert_eq!(
+        files,
+        [
+            ("root/a.txt".to_string(), "a\n".to_string()),
+            ("root/etc/b.conf".to_string(), "b = 1\n".to_string()),
+        ]
+    );
+
+    let checksum = fs::read_to_string(run.join(format!("{PACKAGE}.tar.gz.sha256")))
+        .expect("checksum should be made");
+    assert_eq!(
+        checksum.trim_end(),
+        format!("{}  {PACKAGE}.tar.gz", sha256(&archive))
+    );
+}
+
+#[test]
+fn make_verifies_reproducible_archive() {
+    let project = Project::new();
+    assert_success(&project.run(&["make", "--verify-reproducible"]));
+}
+
+#[test]
+fn locked_make_fails_when_files_change() {
+    let project = Project::new();
+    assert_success(&project.run(&["make"]));
+    assert_success(&project.run(&["make", "--locked"]));
+
+    project.write("root/a.txt", "changed\n");
+    let locked = project.run(&["make", "--locked"]);
+    assert!(
+        !locked.status.success(),
+        "--locked should fail after a file changed"
+    );
+}
+
+#[test]
+fn make_streams_archive_to_stdout() {
+    let project = Project::new();
+    let streamed = project.run(&["make", "--stdout"]);
+    assert_success(&streamed);
+
+    let files = tarball_files(streamed.stdout.as_slice());
+    let names = files
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["root/a.txt", "root/etc/b.conf"]);
+    assert!(
+        project.runs().is_empty(),
+        "--stdout should not make a run directory"
+    );
+}
+
+#[test]
+fn make_ignores_projects_in_parent_directories() {
+    let project = Project::new();
+    let made = project.run_beside("elsewhere/nested", &["make"]);
+    assert!(
+        !made.status.success(),
+        "a parent's rumkinst/ should not be searched"
+    );
+    assert!(project.runs().is_empty());
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use std::{fs, path::Path};
+
+use common::{PACKAGE, Project, assert_success, file_url, sha256};
+
+/// `[publish.http]` uploading into `dir` with `curl`, so nothing leaves the machine
+fn publish_to(dir: &Path) -> String {
+    format!("[publish.http]\nurl = \"{}/{{file}}\"\n", file_url(dir))
+}
+
+#[test]
+fn make_publishes_every_artifact() {
+    let project = Project::new();
+    let published = project.path().join("published");
+    fs::create_dir(&published).expect("publish directory should be created");
+    project.configure(&publish_to(&published));
+    assert_success(&project.run(&["make"]));
+
+    let run = project.only_run();
+    for name in [
+        format!("{PACKAGE}.tar.gz"),
+        format!("{PACKAGE}.tar.gz.sha256"),
+        format!("{PACKAGE}.tar.gz.list"),
+    ] {
+        assert_eq!(
+            sha256(&published.join(&name)),
+            sha256(&run.join(&name)),
+            "{name}"
+        );
+    }
+}
+
+#[test]
+fn no_publish_skips_publishing() {
+    let project = Project::new();
+    let published = project.path().join("published");
+    fs::create_dir(&published).expect("publish directory should be created");
+    project.configure(&publish_to(&published));
+    assert_success(&project.run(&["make", "--no-publish"]));
+
+    let uploads = fs::read_dir(&published).expect("publish directory should be readable");
+    assert_eq!(
+        uploads.count(),
+        0,
+        "--no-publish should not upload anything"
+    );
+    assert!(
+        project
+            .only_run()
+            .join(format!("{PACKAGE}.tar.gz"))
+            .is_file()
+    );
+}
+
+#[test]
+fn failed_publish_keeps_artifacts() {
+    let project = Project::new();
+    project.configure(&publish_to(&project.path().join("missing")));
+    let made = project.run(&["make"]);
+    assert!(
+        !made.status.success(),
+        "publishing to a missing directory should fail"
+    );
+
+    let run = project.only_run();
+    assert!(run.join(format!("{PACKAGE}.tar.gz")).is_file());
+    let stderr = String::from_utf8_lossy(&made.stderr);
+    assert!(stderr.contains("failed to publish"), "{stderr}");
+}
+
+#[test]
+fn offline_make_refuses_to_publish() {
+    let project = Project::new();
+    let published = project.path().join("published");
+    project.configure(&publish_to(&published));
+    let made = project.run(&["--offline", "make"]);
+    assert!(!made.status.success(), "--offline should refuse to publish");
+    assert!(!published.exists());
+}
+
+// Creating the link needs extra privileges on Windows
+#[cfg(unix)]
+#[test]
+fn failed_publish_keeps_latest_link() {
+    let project = Project::new();
+    assert_success(&project.run(&["make"]));
+    let latest = project.path().join("out/latest");
+    let succeeded = fs::read_link(&latest).expect("out/latest should link to the run");
+
+    project.configure(&publish_to(&project.path().join("missing")));
+    assert!(!project.run(&["make"]).status.success());
+    assert_eq!(project.runs().len(), 2);
+    assert_eq!(fs::read_link(&latest).unwrap(), succeeded);
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use std::{
+    error::Error,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use common::Project;
+use rumkinst::{BuildOptions, build, error_log::FileErrorPolicy, installer_gen::Signer};
+
+/// A signer that never makes the same signature twice
+#[derive(Default)]
+struct CountingSigner {
+    signed: AtomicU64,
+}
+
+impl Signer for CountingSigner {
+    fn extension(&self) -> &str {
+        "sig"
+    }
+
+    fn sign(&self, _: &Path, _: &[u8]) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let count = self.signed.fetch_add(1, Ordering::Relaxed);
+        Ok(count.to_le_bytes().to_vec())
+    }
+}
+
+#[test]
+fn signed_archives_are_reproducible() {
+    let project = Project::new();
+    let options = BuildOptions::new(project.path().join("out"))
+        .with_signer(Arc::new(CountingSigner::default()))
+        .with_reproducibility_check(true);
+    build(&project.config(), options).expect("signing should not make the archive differ");
+}
+
+#[cfg(unix)]
+#[test]
+fn reproducibility_check_skips_errors_once() {
+    let project = Project::new();
+    // Transforms run as files are archived, so the file is skipped on each pass
+    project.configure("[[transforms]]\nfiles = [\"root/a.txt\"]\ntransform = [\"false\"]\n");
+    let options = BuildOptions::new(project.path().join("out"))
+        .with_file_error_policy(FileErrorPolicy::KeepGoing)
+        .with_reproducibility_check(true);
+    let output = build(&project.config(), options).expect("the failing file should be skipped");
+    assert_eq!(output.stats.skipped, 1);
+}
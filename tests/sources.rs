@@ -0,0 +1,79 @@
+//! Sources leading outside of the project, which are only searched with `--allow-outside-sources`
+
+// Symlinks can't be made without extra privileges on Windows
+#![cfg(unix)]
+
+mod common;
+
+use std::{fs, os::unix::fs::symlink, path::PathBuf, process::Output};
+
+use common::{Project, assert_success, tarball_files};
+
+/// A directory next to the project holding `secret.txt`
+fn outside(project: &Project) -> PathBuf {
+    let outside = project.path().with_file_name("outside");
+    fs::create_dir_all(&outside).expect("outside directory should be created");
+    fs::write(outside.join("secret.txt"), "secret\n").expect("outside file should be written");
+    outside
+}
+
+#[track_caller]
+fn assert_escapes(output: &Output) {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        !output.status.success() && stderr.contains("resolves outside of"),
+        "rumkinst {} should refuse a source outside of the project\nstderr:\n{stderr}",
+        output.status,
+    );
+}
+
+/// Names and contents of the archive streamed by a make with `--allow-outside-sources`
+fn allowed_files(project: &Project) -> Vec<(String, String)> {
+    let streamed = project.run(&["make", "--stdout", "--allow-outside-sources"]);
+    assert_success(&streamed);
+    tarball_files(streamed.stdout.as_slice())
+}
+
+#[test]
+fn root_symlinked_outside_is_refused() {
+    let project = Project::new();
+    let outside = outside(&project);
+    let root = project.path().join("root");
+    fs::remove_dir_all(&root).expect("root/ should be removed");
+    symlink(&outside, &root).expect("root/ should be linked outside");
+
+    assert_escapes(&project.run(&["make", "--stdout"]));
+    assert_eq!(
+        allowed_files(&project),
+        [("root/secret.txt".to_string(), "secret\n".to_string())]
+    );
+}
+
+#[test]
+fn nested_symlink_escaping_root_is_refused() {
+    let project = Project::new();
+    symlink("../../rumkinst.toml", project.path().join("root/etc/link"))
+        .expect("symlink should be made");
+
+    assert_escapes(&project.run(&["make", "--stdout"]));
+    let config =
+        fs::read_to_string(project.path().join("rumkinst.toml")).expect("config should be read");
+    assert!(
+        allowed_files(&project).contains(&("root/etc/link".to_string(), config)),
+        "the symlink should be followed once allowed"
+    );
+}
+
+#[test]
+fn parent_directory_root_is_refused() {
+    let project = Project::new();
+    outside(&project);
+    // Archive entries can't have `..` components, so the prefix is stripped
+    project.configure("[root]\npath = \"../outside\"\nstrip-prefix = \"../outside\"\n");
+
+    assert_escapes(&project.run(&["make", "--stdout"]));
+    assert_eq!(
+        allowed_files(&project),
+        [("secret.txt".to_string(), "secret\n".to_string())]
+    );
+}